@@ -1,7 +1,25 @@
+use std::process::Command;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // compile with tonic-build for gRPC support
     tonic_build::compile_protos("proto/stunnel.proto")?;
 
+    // Build info reported by GetVersion
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=STUNNEL_SPACE_GIT_COMMIT={}", commit);
+    println!(
+        "cargo:rustc-env=STUNNEL_SPACE_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+
     println!("cargo:rerun-if-changed=proto/stunnel.proto");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
     Ok(())
 }
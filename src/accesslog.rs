@@ -0,0 +1,257 @@
+//! Logging every gRPC call.
+//!
+//! Each call to the manager, health checks included, ends with one event
+//! from this module's target naming the method, the peer address and user
+//! agent, how long the call took and the gRPC status it ended with, and
+//! for a failed call the status message as `detail`:
+//!
+//! ```text
+//! INFO stunnel_space::accesslog: RPC completed method="UpdateConfig" service="vfxstunnel.StunnelManager" peer=10.0.0.7:52114 user_agent="grpc-go/1.59.0" duration_ms=212 code=Ok
+//! ```
+//!
+//! Calls refused by authentication, rate limiting or maintenance mode are
+//! logged too, since the layer sits outside them. A call the client
+//! abandons before its status is sent is logged as `Cancelled`. Health
+//! checks are logged at debug level so probes do not flood the log; the
+//! whole log can be silenced with `LOG_LEVEL=info,stunnel_space::accesslog=warn`.
+//!
+//! Request and response messages are only logged when payload logging is
+//! turned on (see [`crate::StunnelServer::with_payload_logging`]), from
+//! inside the handlers, with secrets masked by [`payload`].
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{self, HeaderMap};
+use tonic::codegen::{Body as HttpBody, Bytes};
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::transport::Body;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+use tracing::Level;
+
+use crate::redact;
+
+/// Longest logged payload, in characters; longer ones are cut short.
+pub const MAX_PAYLOAD_CHARS: usize = 4096;
+
+// Calls to this service are logged at debug level.
+const HEALTH_SERVICE: &str = "grpc.health.v1.Health";
+
+/// A message as it is logged: its `Debug` form with secrets masked (see
+/// [`redact::redact_debug`]), cut short after [`MAX_PAYLOAD_CHARS`].
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::accesslog::payload;
+/// use stunnel_space::stunnel::UpdateConfigRequest;
+///
+/// let request = UpdateConfigRequest {
+///     config_content: "[web]\nkey = /etc/stunnel/web.key\n".to_string(),
+///     ..Default::default()
+/// };
+/// let logged = payload(&request);
+/// assert!(logged.contains(r#"config_content: "[web]\nkey = ********\n""#));
+/// assert!(!logged.contains("web.key"));
+/// ```
+pub fn payload(message: &impl Debug) -> String {
+    let mut text = redact::redact_debug(&format!("{:?}", message));
+    if let Some((cut, _)) = text.char_indices().nth(MAX_PAYLOAD_CHARS) {
+        let total = text.chars().count();
+        text.truncate(cut);
+        text.push_str(&format!(
+            "… ({} more characters)",
+            total - MAX_PAYLOAD_CHARS
+        ));
+    }
+    text
+}
+
+/// Logs the messages of a call to `method` by `caller`, as formatted by
+/// [`payload`]; `response` is `None` when the call failed or streams its
+/// response.
+pub fn log_payloads(method: &str, caller: &str, request: &str, response: Option<&str>) {
+    tracing::info!(method, caller, request, response, "RPC payload");
+}
+
+/// Logs every call passing through the server.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccessLogLayer;
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLog { inner }
+    }
+}
+
+/// Service logging each call once its response has been sent.
+#[derive(Debug, Clone)]
+pub struct AccessLog<S> {
+    inner: S,
+}
+
+impl<S> Service<http::Request<Body>> for AccessLog<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Display + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let mut call = Call::new(&request);
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    // Trailers-only responses, errors among them, carry the
+                    // status in the headers
+                    if let Some(status) = Status::from_header_map(response.headers()) {
+                        call.record(&status);
+                    }
+                    Ok(response.map(|inner| LoggedBody { inner, call }.boxed_unsync()))
+                }
+                Err(e) => {
+                    call.record(&Status::unknown(e.to_string()));
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+// What is logged about a call; logged when dropped, with the response body.
+struct Call {
+    service: String,
+    method: String,
+    peer: Option<SocketAddr>,
+    user_agent: String,
+    started: Instant,
+    status: Option<(Code, String)>,
+}
+
+impl Call {
+    fn new(request: &http::Request<Body>) -> Self {
+        let (service, method) = request
+            .uri()
+            .path()
+            .trim_start_matches('/')
+            .split_once('/')
+            .unwrap_or_default();
+        let extensions = request.extensions();
+        let peer = extensions
+            .get::<TcpConnectInfo>()
+            .and_then(|info| info.remote_addr())
+            .or_else(|| {
+                extensions
+                    .get::<TlsConnectInfo<TcpConnectInfo>>()
+                    .and_then(|info| info.get_ref().remote_addr())
+            });
+        let user_agent = request
+            .headers()
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            service: service.to_string(),
+            method: method.to_string(),
+            peer,
+            user_agent,
+            started: Instant::now(),
+            status: None,
+        }
+    }
+
+    fn record(&mut self, status: &Status) {
+        self.status = Some((status.code(), status.message().to_string()));
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        let (code, message) = self
+            .status
+            .take()
+            .unwrap_or_else(|| (Code::Cancelled, "Client went away".to_string()));
+        let peer = self
+            .peer
+            .map_or_else(|| "unknown".to_string(), |peer| peer.to_string());
+        let duration_ms = self.started.elapsed().as_millis() as u64;
+        macro_rules! log_call {
+            ($level:expr) => {
+                tracing::event!(
+                    $level,
+                    method = %self.method,
+                    service = %self.service,
+                    peer = %peer,
+                    user_agent = %self.user_agent,
+                    duration_ms,
+                    code = ?code,
+                    detail = (!message.is_empty()).then_some(message.as_str()),
+                    "RPC completed"
+                )
+            };
+        }
+        if self.service == HEALTH_SERVICE {
+            log_call!(Level::DEBUG);
+        } else {
+            log_call!(Level::INFO);
+        }
+    }
+}
+
+// Response body noting the status the call ends with.
+struct LoggedBody {
+    inner: BoxBody,
+    call: Call,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Err(status))) = &poll {
+            self.call.record(status);
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        let poll = Pin::new(&mut self.inner).poll_trailers(cx);
+        let status = match &poll {
+            Poll::Ready(Ok(Some(trailers))) => Status::from_header_map(trailers),
+            Poll::Ready(Err(status)) => Some(status.clone()),
+            _ => None,
+        };
+        if let Some(status) = status {
+            self.call.record(&status);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
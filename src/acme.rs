@@ -0,0 +1,843 @@
+//! ACME (RFC 8555) certificate issuance and renewal.
+//!
+//! Providers registered for ACME get a certificate for their domains from an
+//! ACME directory such as Let's Encrypt. The full chain is stored in the
+//! managed certificates directory as `<provider>.crt`, with its key as
+//! `<provider>.key`. HTTP-01 challenges are answered by writing files under
+//! a webroot served on port 80; DNS-01 challenges by running a hook that
+//! publishes the TXT record. Requests are signed with an RSA account key
+//! using the `openssl` command-line tool.
+//!
+//! Registrations are kept in `acme.json` in the managed directory.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::PublicKey;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::certs;
+use crate::certstore::{self, CertStore, KeyType, Subject, CERT_EXTENSION};
+
+/// Let's Encrypt's production directory.
+pub const LETS_ENCRYPT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Default number of days before expiry at which certificates are renewed.
+pub const DEFAULT_RENEW_DAYS: u32 = 30;
+
+/// Name of the managed private key used as the ACME account key.
+pub const ACCOUNT_KEY_NAME: &str = "acme-account";
+
+/// File in the managed directory listing ACME-managed providers.
+pub const REGISTRY_FILE: &str = "acme.json";
+
+// How often, and how many times, pending authorizations and orders are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 60;
+
+/// How domain control is proven to the ACME server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeType {
+    /// A file served over HTTP on port 80.
+    Http01,
+    /// A TXT record at `_acme-challenge.<domain>`.
+    Dns01,
+}
+
+impl ChallengeType {
+    /// Returns the ACME name of the challenge, e.g. `http-01`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChallengeType::Http01 => "http-01",
+            ChallengeType::Dns01 => "dns-01",
+        }
+    }
+}
+
+impl FromStr for ChallengeType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "http-01" => Ok(ChallengeType::Http01),
+            "dns-01" => Ok(ChallengeType::Dns01),
+            other => Err(format!("Unknown ACME challenge type: {}", other)),
+        }
+    }
+}
+
+/// Settings of the ACME subsystem.
+#[derive(Debug, Clone)]
+pub struct AcmeSettings {
+    pub directory_url: String,
+    /// Contact address registered with the account, if any.
+    pub contact_email: Option<String>,
+    pub challenge: ChallengeType,
+    /// Directory served at `http://<domain>/`; HTTP-01 tokens are written
+    /// under `.well-known/acme-challenge/`.
+    pub webroot: Option<PathBuf>,
+    /// Executable run as `<hook> present|cleanup <record name> <value>` to
+    /// publish and remove DNS-01 TXT records.
+    pub dns_hook: Option<PathBuf>,
+    /// Days before expiry at which certificates are renewed.
+    pub renew_days: u32,
+}
+
+/// An ACME-managed provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcmeRegistration {
+    /// Domains the certificate is issued for; the first is the subject.
+    pub domains: Vec<String>,
+}
+
+/// A certificate obtained from the ACME server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuedCertificate {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub not_after: DateTime<Utc>,
+}
+
+/// Outcome of one provider's renewal check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenewalResult {
+    pub provider: String,
+    /// Whether a new certificate was issued.
+    pub renewed: bool,
+    /// Expiry of the certificate now in place, if there is one.
+    pub not_after: Option<DateTime<Utc>>,
+    /// Why renewal failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Issues and renews certificates for ACME-managed providers.
+#[derive(Debug)]
+pub struct AcmeManager {
+    settings: AcmeSettings,
+    store: CertStore,
+    // Serializes issuance, so overlapping renewals never race on the same files
+    lock: Mutex<()>,
+}
+
+impl AcmeManager {
+    /// Creates a manager storing certificates in `store`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the challenge type's webroot or DNS hook is not set.
+    pub fn new(settings: AcmeSettings, store: CertStore) -> Result<Self, String> {
+        match settings.challenge {
+            ChallengeType::Http01 if settings.webroot.is_none() => {
+                return Err("HTTP-01 challenges require a webroot".to_string());
+            }
+            ChallengeType::Dns01 if settings.dns_hook.is_none() => {
+                return Err("DNS-01 challenges require a DNS hook".to_string());
+            }
+            _ => {}
+        }
+        Ok(Self {
+            settings,
+            store,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Returns the subsystem's settings.
+    pub fn settings(&self) -> &AcmeSettings {
+        &self.settings
+    }
+
+    /// Returns the ACME-managed providers, keyed by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry exists but cannot be read.
+    pub fn registrations(&self) -> Result<BTreeMap<String, AcmeRegistration>, String> {
+        let path = self.registry_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid ACME registry {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(format!(
+                "Failed to read ACME registry {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Marks `provider` as ACME-managed for `domains`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be read or written.
+    pub fn register(&self, provider: &str, domains: &[String]) -> Result<(), String> {
+        let mut registrations = self.registrations()?;
+        registrations.insert(
+            provider.to_string(),
+            AcmeRegistration {
+                domains: domains.to_vec(),
+            },
+        );
+        self.save_registrations(&registrations)
+    }
+
+    /// Stops managing `provider`, leaving its certificate files in place.
+    /// Returns whether it was registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be read or written.
+    pub fn unregister(&self, provider: &str) -> Result<bool, String> {
+        let mut registrations = self.registrations()?;
+        if registrations.remove(provider).is_none() {
+            return Ok(false);
+        }
+        self.save_registrations(&registrations)?;
+        Ok(true)
+    }
+
+    /// Obtains a certificate for `domains` and stores it as `provider`'s
+    /// managed certificate and key, replacing any previous ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a domain is invalid, a challenge fails or the
+    /// ACME server rejects the order.
+    pub async fn issue(
+        &self,
+        provider: &str,
+        domains: &[String],
+    ) -> Result<IssuedCertificate, String> {
+        if domains.is_empty() {
+            return Err("At least one domain is required".to_string());
+        }
+        if let Some(domain) = domains.iter().find(|domain| !valid_domain(domain)) {
+            return Err(format!("Invalid domain: {:?}", domain));
+        }
+        let cert_path = self.store.file_path(provider, CERT_EXTENSION)?;
+        let key_path = self.store.key_path(provider)?;
+
+        let _guard = self.lock.lock().await;
+        let mut client = AcmeClient::connect(&self.settings, &self.account_key().await?).await?;
+        client.register_account(&self.settings).await?;
+
+        // The new key is kept aside until the certificate is in hand, so a
+        // failed order leaves the current key and certificate untouched
+        let pending = format!("{}.pending", provider);
+        let subject = Subject {
+            common_name: domains[0].clone(),
+            ..Subject::default()
+        };
+        let sans: Vec<String> = domains
+            .iter()
+            .map(|domain| format!("DNS:{}", domain))
+            .collect();
+        let (pending_key, csr) =
+            self.store
+                .generate_csr(&pending, KeyType::EcP256, &subject, &sans, true)?;
+
+        let chain = match client.order(&self.settings, domains, &csr).await {
+            Ok(chain) => chain,
+            Err(e) => {
+                self.store.remove_key(&pending)?;
+                return Err(e);
+            }
+        };
+        let not_after = certs::parse_certificates(chain.as_bytes())?
+            .first()
+            .map(|leaf| leaf.not_after)
+            .ok_or_else(|| "ACME server returned no certificate".to_string())?;
+
+        let key = fs::read_to_string(&pending_key)
+            .map_err(|e| format!("Failed to read key {}: {}", pending_key.display(), e))?;
+        self.store.store(provider, &chain, Some(&key), None, true)?;
+        self.store.remove_key(&pending)?;
+        Ok(IssuedCertificate {
+            cert_path,
+            key_path,
+            not_after,
+        })
+    }
+
+    /// Renews every registered certificate that is missing or expires within
+    /// the renewal window, or all of them if `force` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be read.
+    pub async fn renew_due(&self, force: bool) -> Result<Vec<RenewalResult>, String> {
+        let mut results = Vec::new();
+        for (provider, registration) in self.registrations()? {
+            let current = self
+                .store
+                .file_path(&provider, CERT_EXTENSION)
+                .ok()
+                .and_then(|path| certs::read_certificates(&path.to_string_lossy()).ok())
+                .and_then(|certificates| certificates.first().map(|leaf| leaf.not_after));
+            let due = match current {
+                Some(not_after) => {
+                    force
+                        || (not_after - Utc::now()).num_days() < i64::from(self.settings.renew_days)
+                }
+                None => true,
+            };
+            if !due {
+                results.push(RenewalResult {
+                    provider,
+                    renewed: false,
+                    not_after: current,
+                    error: None,
+                });
+                continue;
+            }
+
+            let result = match self.issue(&provider, &registration.domains).await {
+                Ok(issued) => RenewalResult {
+                    provider,
+                    renewed: true,
+                    not_after: Some(issued.not_after),
+                    error: None,
+                },
+                Err(e) => RenewalResult {
+                    provider,
+                    renewed: false,
+                    not_after: current,
+                    error: Some(e),
+                },
+            };
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    fn registry_path(&self) -> PathBuf {
+        self.store.dir().join(REGISTRY_FILE)
+    }
+
+    fn save_registrations(
+        &self,
+        registrations: &BTreeMap<String, AcmeRegistration>,
+    ) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(registrations)
+            .map_err(|e| format!("Failed to serialize ACME registry: {}", e))?;
+        certstore::write_file(&self.registry_path(), content, certstore::CERT_FILE_MODE)
+    }
+
+    // Returns the account key path, generating the key on first use.
+    async fn account_key(&self) -> Result<PathBuf, String> {
+        let path = self.store.key_path(ACCOUNT_KEY_NAME)?;
+        if path.exists() {
+            return Ok(path);
+        }
+        let output = Command::new("openssl")
+            .args([
+                "genpkey",
+                "-algorithm",
+                "RSA",
+                "-pkeyopt",
+                "rsa_keygen_bits:2048",
+            ])
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run openssl: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "openssl failed to generate the ACME account key: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        self.store.write_key(ACCOUNT_KEY_NAME, &output.stdout)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    #[serde(default)]
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+// A session with the ACME server, signing requests with the account key.
+struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    account_key: PathBuf,
+    jwk: Value,
+    thumbprint: String,
+    nonce: Option<String>,
+    kid: Option<String>,
+}
+
+impl AcmeClient {
+    async fn connect(settings: &AcmeSettings, account_key: &Path) -> Result<Self, String> {
+        let http = reqwest::Client::new();
+        let directory: Directory = http
+            .get(&settings.directory_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch ACME directory: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid ACME directory: {}", e))?;
+        let (jwk, thumbprint) = account_jwk(account_key).await?;
+        Ok(Self {
+            http,
+            directory,
+            account_key: account_key.to_path_buf(),
+            jwk,
+            thumbprint,
+            nonce: None,
+            kid: None,
+        })
+    }
+
+    // Finds or creates the account, keeping its URL for later requests.
+    async fn register_account(&mut self, settings: &AcmeSettings) -> Result<(), String> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = &settings.contact_email {
+            payload["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&payload)).await?;
+        self.kid = Some(location(&response)?);
+        Ok(())
+    }
+
+    // Places an order for `domains`, completes its challenges and returns
+    // the issued PEM chain.
+    async fn order(
+        &mut self,
+        settings: &AcmeSettings,
+        domains: &[String],
+        csr_pem: &str,
+    ) -> Result<String, String> {
+        let identifiers: Vec<Value> = domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect();
+        let url = self.directory.new_order.clone();
+        let response = self
+            .post(&url, Some(&json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = location(&response)?;
+        let order: Order = parse_json(response).await?;
+
+        for authorization_url in &order.authorizations {
+            self.authorize(settings, authorization_url).await?;
+        }
+
+        let csr = pem_to_der(csr_pem)?;
+        self.post(
+            &order.finalize,
+            Some(&json!({ "csr": URL_SAFE_NO_PAD.encode(csr) })),
+        )
+        .await?;
+
+        for _ in 0..POLL_ATTEMPTS {
+            let order: Order = parse_json(self.post(&order_url, None).await?).await?;
+            match order.status.as_str() {
+                "valid" => {
+                    let url = order
+                        .certificate
+                        .ok_or_else(|| "Valid order has no certificate URL".to_string())?;
+                    return self
+                        .post(&url, None)
+                        .await?
+                        .text()
+                        .await
+                        .map_err(|e| format!("Failed to download certificate: {}", e));
+                }
+                "invalid" => {
+                    return Err(format!(
+                        "ACME order failed: {}",
+                        order.error.map(|e| e.detail).unwrap_or_default()
+                    ));
+                }
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+        Err("Timed out waiting for the certificate to be issued".to_string())
+    }
+
+    // Completes the configured challenge for one authorization.
+    async fn authorize(&mut self, settings: &AcmeSettings, url: &str) -> Result<(), String> {
+        let authorization: Authorization = parse_json(self.post(url, None).await?).await?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+        let domain = authorization.identifier.value;
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|challenge| challenge.kind == settings.challenge.as_str())
+            .ok_or_else(|| {
+                format!(
+                    "ACME server offers no {} challenge for {}",
+                    settings.challenge.as_str(),
+                    domain
+                )
+            })?;
+        let key_authorization = format!("{}.{}", challenge.token, self.thumbprint);
+
+        let response =
+            ChallengeResponse::present(settings, &domain, &challenge.token, &key_authorization)
+                .await?;
+        let result = self.complete_challenge(url, &challenge.url, &domain).await;
+        response.cleanup(settings).await;
+        result
+    }
+
+    async fn complete_challenge(
+        &mut self,
+        authorization_url: &str,
+        challenge_url: &str,
+        domain: &str,
+    ) -> Result<(), String> {
+        self.post(challenge_url, Some(&json!({}))).await?;
+        for _ in 0..POLL_ATTEMPTS {
+            let authorization: Authorization =
+                parse_json(self.post(authorization_url, None).await?).await?;
+            match authorization.status.as_str() {
+                "valid" => return Ok(()),
+                "pending" | "processing" => tokio::time::sleep(POLL_INTERVAL).await,
+                status => {
+                    let detail = authorization
+                        .challenges
+                        .into_iter()
+                        .find_map(|challenge| challenge.error)
+                        .map(|e| e.detail)
+                        .unwrap_or_default();
+                    return Err(format!(
+                        "Authorization for {} is {}: {}",
+                        domain, status, detail
+                    ));
+                }
+            }
+        }
+        Err(format!("Timed out validating {}", domain))
+    }
+
+    // Sends a signed request; a `None` payload is a POST-as-GET. A rejected
+    // nonce is retried once with a fresh one.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&Value>,
+    ) -> Result<reqwest::Response, String> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.new_nonce().await?,
+            };
+            let mut protected = json!({ "alg": "RS256", "nonce": nonce, "url": url });
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.jwk.clone(),
+            }
+            let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+            let payload = payload
+                .map(|payload| URL_SAFE_NO_PAD.encode(payload.to_string()))
+                .unwrap_or_default();
+            let signature = sign(&self.account_key, &format!("{}.{}", protected, payload)).await?;
+            let body = json!({
+                "protected": protected,
+                "payload": payload,
+                "signature": URL_SAFE_NO_PAD.encode(signature),
+            });
+
+            let response = self
+                .http
+                .post(url)
+                .header("Content-Type", "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|e| format!("ACME request to {} failed: {}", url, e))?;
+            self.nonce = response
+                .headers()
+                .get("Replay-Nonce")
+                .and_then(|nonce| nonce.to_str().ok())
+                .map(str::to_string);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let status = response.status();
+            let problem: Problem = response.json().await.unwrap_or(Problem {
+                kind: String::new(),
+                detail: String::new(),
+            });
+            if problem.kind.ends_with(":badNonce") && !retried {
+                retried = true;
+                continue;
+            }
+            return Err(format!(
+                "ACME request to {} failed ({}): {}",
+                url, status, problem.detail
+            ));
+        }
+    }
+
+    async fn new_nonce(&self) -> Result<String, String> {
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get ACME nonce: {}", e))?;
+        response
+            .headers()
+            .get("Replay-Nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| "ACME server returned no nonce".to_string())
+    }
+}
+
+// A published challenge response, removed once validation is over.
+enum ChallengeResponse {
+    File(PathBuf),
+    TxtRecord { name: String, value: String },
+}
+
+impl ChallengeResponse {
+    async fn present(
+        settings: &AcmeSettings,
+        domain: &str,
+        token: &str,
+        key_authorization: &str,
+    ) -> Result<Self, String> {
+        match settings.challenge {
+            ChallengeType::Http01 => {
+                if token.is_empty()
+                    || !token
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return Err(format!("Invalid HTTP-01 token: {:?}", token));
+                }
+                let dir = settings
+                    .webroot
+                    .as_deref()
+                    .unwrap_or(Path::new("."))
+                    .join(".well-known/acme-challenge");
+                fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+                let path = dir.join(token);
+                fs::write(&path, key_authorization)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                Ok(ChallengeResponse::File(path))
+            }
+            ChallengeType::Dns01 => {
+                let name = format!("_acme-challenge.{}", domain.trim_start_matches("*."));
+                let value = URL_SAFE_NO_PAD.encode(Sha256::digest(key_authorization));
+                run_dns_hook(settings, "present", &name, &value).await?;
+                Ok(ChallengeResponse::TxtRecord { name, value })
+            }
+        }
+    }
+
+    // Removes the response; failures are only logged.
+    async fn cleanup(self, settings: &AcmeSettings) {
+        match self {
+            ChallengeResponse::File(path) => {
+                let _ = fs::remove_file(path);
+            }
+            ChallengeResponse::TxtRecord { name, value } => {
+                if let Err(e) = run_dns_hook(settings, "cleanup", &name, &value).await {
+                    tracing::warn!("Failed to remove ACME TXT record {}: {}", name, e);
+                }
+            }
+        }
+    }
+}
+
+async fn run_dns_hook(
+    settings: &AcmeSettings,
+    action: &str,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    let hook = settings
+        .dns_hook
+        .as_deref()
+        .ok_or_else(|| "No DNS hook configured".to_string())?;
+    let output = Command::new(hook)
+        .args([action, name, value])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run DNS hook {}: {}", hook.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "DNS hook {} {} failed: {}",
+            hook.display(),
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// Returns the account key's JWK and its RFC 7638 thumbprint.
+async fn account_jwk(account_key: &Path) -> Result<(Value, String), String> {
+    let output = Command::new("openssl")
+        .args(["pkey", "-pubout", "-outform", "DER", "-in"])
+        .arg(account_key)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to read ACME account key {}: {}",
+            account_key.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let (_, spki) = SubjectPublicKeyInfo::from_der(&output.stdout)
+        .map_err(|e| format!("Invalid ACME account key: {}", e))?;
+    let Ok(PublicKey::RSA(rsa)) = spki.parsed() else {
+        return Err("ACME account key must be an RSA key".to_string());
+    };
+    let encode = |bytes: &[u8]| {
+        let start = bytes
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(bytes.len());
+        URL_SAFE_NO_PAD.encode(&bytes[start..])
+    };
+    let (e, n) = (encode(rsa.exponent), encode(rsa.modulus));
+
+    // The thumbprint hashes the required members in lexicographic order
+    let canonical = format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n);
+    let thumbprint = URL_SAFE_NO_PAD.encode(Sha256::digest(canonical));
+    Ok((json!({ "e": e, "kty": "RSA", "n": n }), thumbprint))
+}
+
+// Signs `data` with RSASSA-PKCS1-v1_5 SHA-256 (JWS `RS256`).
+async fn sign(account_key: &Path, data: &str) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("openssl")
+        .args(["dgst", "-sha256", "-binary", "-sign"])
+        .arg(account_key)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(data.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "openssl failed to sign the ACME request: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn location(response: &reqwest::Response) -> Result<String, String> {
+    response
+        .headers()
+        .get("Location")
+        .and_then(|location| location.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| format!("ACME response from {} has no Location", response.url()))
+}
+
+async fn parse_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, String> {
+    let url = response.url().to_string();
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid ACME response from {}: {}", url, e))
+}
+
+fn pem_to_der(pem: &str) -> Result<Vec<u8>, String> {
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    STANDARD
+        .decode(body.trim())
+        .map_err(|e| format!("Invalid CSR: {}", e))
+}
+
+// Accepts DNS names, with an optional leading wildcard label.
+fn valid_domain(domain: &str) -> bool {
+    let name = domain.strip_prefix("*.").unwrap_or(domain);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
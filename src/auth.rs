@@ -0,0 +1,464 @@
+//! Bearer-token authentication for the gRPC API.
+//!
+//! Every RPC must carry `authorization: Bearer <token>` metadata, where the
+//! token is the configured shared secret, an API key from the keys file, or
+//! a JWT signed by a key from a JWKS endpoint. JWTs must name this server's
+//! issuer and audience, and must carry an expiry; `exp` and `nbf` are
+//! checked with at most a minute of clock skew. Their `sub` claim
+//! identifies the caller in the config history. The JWKS is fetched at
+//! startup and refreshed in the background, since tonic interceptors cannot
+//! await.
+//!
+//! Each token carries a [`Role`]: API keys have the role the keys file
+//! gives them, JWTs the one in their `role` claim, the shared secret is an
+//! admin and JWTs without a `role` claim are read-only. Calls to methods
+//! the role does not allow fail with `PERMISSION_DENIED`. API keys and JWTs
+//! may also be limited to tenant namespaces, by the keys file or a
+//! `namespaces` claim. A claim that is present must be a non-empty array of
+//! namespace names; anything else rejects the token rather than leaving it
+//! unscoped.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::rbac::{self, ApiKeyStore, Role, RpcMethod};
+
+/// Metadata key carrying the bearer token.
+pub const AUTHORIZATION_METADATA_KEY: &str = "authorization";
+
+/// Default time between JWKS refreshes.
+pub const DEFAULT_JWKS_REFRESH: Duration = Duration::from_secs(10 * 60);
+
+// Clock skew tolerated when checking `exp` and `nbf`.
+const LEEWAY_SECS: i64 = 60;
+
+/// Identity of a caller authenticated by API key or JWT: the key's name or
+/// the JWT's `sub` claim. Added to the request extensions for handlers to
+/// read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedCaller(pub String);
+
+/// Namespaces an authenticated caller is limited to, added to the request
+/// extensions for handlers to enforce. Callers without one may act on
+/// every instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceScope(pub Vec<String>);
+
+impl NamespaceScope {
+    /// Whether the scope covers an instance in `namespace`; instances
+    /// outside any namespace are never covered.
+    pub fn allows(&self, namespace: Option<&str>) -> bool {
+        namespace.is_some_and(|namespace| self.0.iter().any(|allowed| allowed == namespace))
+    }
+}
+
+/// Who a bearer token identifies and what it may do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// API key name or JWT subject; `None` for the shared secret.
+    pub subject: Option<String>,
+    pub role: Role,
+    /// Namespaces the token is limited to, never empty; `None` for all
+    /// instances.
+    pub namespaces: Option<Vec<String>>,
+}
+
+// A verification key from a JWKS.
+#[derive(Debug, Clone)]
+enum Jwk {
+    Rsa {
+        kid: Option<String>,
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    Ec {
+        kid: Option<String>,
+        curve: String,
+        // Uncompressed SEC1 point
+        point: Vec<u8>,
+    },
+}
+
+impl Jwk {
+    fn kid(&self) -> Option<&str> {
+        match self {
+            Jwk::Rsa { kid, .. } | Jwk::Ec { kid, .. } => kid.as_deref(),
+        }
+    }
+
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        match (self, alg) {
+            (Jwk::Rsa { n, e, .. }, "RS256" | "RS384" | "RS512") => {
+                let params = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    _ => &signature::RSA_PKCS1_2048_8192_SHA512,
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(params, message, signature)
+                    .is_ok()
+            }
+            (Jwk::Ec { curve, point, .. }, "ES256" | "ES384") => {
+                let algorithm: &dyn VerificationAlgorithm = match (alg, curve.as_str()) {
+                    ("ES256", "P-256") => &signature::ECDSA_P256_SHA256_FIXED,
+                    ("ES384", "P-384") => &signature::ECDSA_P384_SHA384_FIXED,
+                    _ => return false,
+                };
+                UnparsedPublicKey::new(algorithm, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Keys fetched from a JWKS endpoint.
+#[derive(Debug)]
+pub struct JwksCache {
+    url: String,
+    keys: RwLock<Vec<Jwk>>,
+    http: reqwest::Client,
+}
+
+impl JwksCache {
+    /// Creates an empty cache for the JWKS at `url`.
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            keys: RwLock::new(Vec::new()),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetches the JWKS, replacing the cached keys. Keys of unsupported
+    /// types are skipped. Returns the number of keys cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JWKS cannot be fetched or parsed.
+    pub async fn refresh(&self) -> Result<usize, String> {
+        let body: Value = self
+            .http
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch JWKS from {}: {}", self.url, e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid JWKS from {}: {}", self.url, e))?;
+        let keys: Vec<Jwk> = body["keys"]
+            .as_array()
+            .ok_or_else(|| format!("JWKS from {} has no keys array", self.url))?
+            .iter()
+            .filter_map(parse_jwk)
+            .collect();
+        let count = keys.len();
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        Ok(count)
+    }
+
+    /// Refreshes the keys every `interval` in the background, logging
+    /// failures and keeping the previous keys.
+    pub fn spawn_refresh(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = cache.refresh().await {
+                    tracing::warn!("{}", e);
+                }
+            }
+        })
+    }
+
+    fn keys(&self) -> Vec<Jwk> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Interceptor rejecting requests without a valid bearer token.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator {
+    // SHA-256 of the shared secret, so comparisons take constant time
+    token_digest: Option<[u8; 32]>,
+    jwks: Option<Arc<JwksCache>>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl Authenticator {
+    /// Creates an authenticator accepting no tokens until one is configured.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts `token` as a shared secret.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token_digest = Some(Sha256::digest(token.as_bytes()).into());
+        self
+    }
+
+    /// Accepts JWTs signed by a key in `jwks`, once an issuer and audience
+    /// are also set.
+    pub fn with_jwks(mut self, jwks: Arc<JwksCache>) -> Self {
+        self.jwks = Some(jwks);
+        self
+    }
+
+    /// Accepts the API keys in `api_keys`, each with its role.
+    pub fn with_api_keys(mut self, api_keys: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = Some(api_keys);
+        self
+    }
+
+    /// Requires JWTs to carry `iss` equal to `issuer`.
+    pub fn with_issuer(mut self, issuer: String) -> Self {
+        self.issuer = Some(issuer);
+        self
+    }
+
+    /// Requires JWTs to list `audience` in `aud`.
+    pub fn with_audience(mut self, audience: String) -> Self {
+        self.audience = Some(audience);
+        self
+    }
+
+    /// Checks a bearer token and returns who it identifies.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message describing why the token was rejected.
+    pub fn authenticate(&self, token: &str) -> Result<Principal, String> {
+        if let Some(digest) = &self.token_digest {
+            let presented: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+            if presented == *digest {
+                return Ok(Principal {
+                    subject: None,
+                    role: Role::Admin,
+                    namespaces: None,
+                });
+            }
+        }
+        if let Some(key) = self.api_keys.as_ref().and_then(|keys| keys.lookup(token)) {
+            return Ok(Principal {
+                subject: Some(key.name),
+                role: key.role,
+                namespaces: key.namespaces,
+            });
+        }
+        match &self.jwks {
+            Some(jwks) => self.verify_jwt(jwks, token),
+            None => Err("Invalid token".to_string()),
+        }
+    }
+
+    fn verify_jwt(&self, jwks: &JwksCache, token: &str) -> Result<Principal, String> {
+        // Any token the IdP signs for any application would pass otherwise
+        let (Some(issuer), Some(audience)) = (&self.issuer, &self.audience) else {
+            return Err(
+                "JWTs are not accepted without a configured issuer and audience".to_string(),
+            );
+        };
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("Invalid token".to_string());
+        };
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| "Malformed JWT".to_string())
+        };
+        let header: Value =
+            serde_json::from_slice(&decode(header)?).map_err(|_| "Malformed JWT".to_string())?;
+        let alg = header["alg"].as_str().unwrap_or_default();
+        let kid = header["kid"].as_str();
+        let message = &token[..token.rfind('.').unwrap_or_default()];
+        let signature = decode(signature)?;
+        let verified = jwks
+            .keys()
+            .iter()
+            .filter(|key| kid.is_none() || key.kid() == kid)
+            .any(|key| key.verify(alg, message.as_bytes(), &signature));
+        if !verified {
+            return Err("JWT signature is not valid".to_string());
+        }
+
+        let claims: Value =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| "Malformed JWT".to_string())?;
+        let now = Utc::now().timestamp();
+        match claims["exp"].as_i64() {
+            Some(exp) if exp + LEEWAY_SECS >= now => {}
+            Some(_) => return Err("JWT has expired".to_string()),
+            None => return Err("JWT has no expiry".to_string()),
+        }
+        match &claims["nbf"] {
+            Value::Null => {}
+            nbf => match nbf.as_i64() {
+                Some(nbf) if nbf - LEEWAY_SECS > now => {
+                    return Err("JWT is not yet valid".to_string())
+                }
+                Some(_) => {}
+                None => return Err("Malformed JWT".to_string()),
+            },
+        }
+        if claims["iss"].as_str() != Some(issuer.as_str()) {
+            return Err("JWT issuer is not trusted".to_string());
+        }
+        let listed = match &claims["aud"] {
+            Value::String(aud) => aud == audience,
+            Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !listed {
+            return Err("JWT is not intended for this server".to_string());
+        }
+        let role = match claims["role"].as_str() {
+            Some(role) => role.parse::<Role>()?,
+            // A token that names no role may only read
+            None => Role::ReadOnly,
+        };
+        let namespaces = match &claims["namespaces"] {
+            Value::Null => None,
+            claim => Some(parse_namespaces(claim)?),
+        };
+        Ok(Principal {
+            subject: Some(claims["sub"].as_str().unwrap_or("jwt").to_string()),
+            role,
+            namespaces,
+        })
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = request
+            .metadata()
+            .get(AUTHORIZATION_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                value
+                    .strip_prefix("Bearer ")
+                    .or_else(|| value.strip_prefix("bearer "))
+            })
+            .map(str::trim)
+            .ok_or_else(|| Status::unauthenticated("Missing bearer token"))?;
+        let principal = self.authenticate(token).map_err(Status::unauthenticated)?;
+
+        // Without the method tag the method is unknown, so only admins pass
+        let method = request
+            .extensions()
+            .get::<RpcMethod>()
+            .map(|method| method.0.clone())
+            .unwrap_or_default();
+        let required = rbac::required_role(&method);
+        if principal.role < required {
+            return Err(Status::permission_denied(format!(
+                "The {} role may not call {}; {} is required",
+                principal.role, method, required
+            )));
+        }
+
+        if let Some(subject) = principal.subject {
+            request
+                .extensions_mut()
+                .insert(AuthenticatedCaller(subject));
+        }
+        if let Some(namespaces) = principal.namespaces {
+            request.extensions_mut().insert(NamespaceScope(namespaces));
+        }
+        Ok(request)
+    }
+}
+
+// The namespaces a JWT's `namespaces` claim confines it to. The issuer
+// meant to restrict the token, so a claim that is not a non-empty array of
+// non-empty names rejects it instead of leaving it unscoped.
+fn parse_namespaces(claim: &Value) -> Result<Vec<String>, String> {
+    let malformed = || "JWT namespaces claim must be a non-empty array of names".to_string();
+    let namespaces = claim
+        .as_array()
+        .filter(|namespaces| !namespaces.is_empty())
+        .ok_or_else(malformed)?;
+    namespaces
+        .iter()
+        .map(|namespace| {
+            namespace
+                .as_str()
+                .filter(|name| !name.trim().is_empty())
+                .map(str::to_string)
+                .ok_or_else(malformed)
+        })
+        .collect()
+}
+
+fn parse_jwk(key: &Value) -> Option<Jwk> {
+    let field = |name: &str| {
+        key[name]
+            .as_str()
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+    };
+    let kid = key["kid"].as_str().map(str::to_string);
+    match key["kty"].as_str()? {
+        "RSA" => Some(Jwk::Rsa {
+            kid,
+            n: field("n")?,
+            e: field("e")?,
+        }),
+        "EC" => {
+            let mut point = vec![0x04];
+            point.extend(field("x")?);
+            point.extend(field("y")?);
+            Some(Jwk::Ec {
+                kid,
+                curve: key["crv"].as_str()?.to_string(),
+                point,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn namespaces_claim_lists_names() {
+        assert_eq!(
+            parse_namespaces(&json!(["team-a", "team-b"])),
+            Ok(vec!["team-a".to_string(), "team-b".to_string()])
+        );
+    }
+
+    #[test]
+    fn malformed_namespaces_claim_rejects_the_token() {
+        for claim in [
+            json!([]),
+            json!("team-a"),
+            json!({"team-a": true}),
+            json!(42),
+            json!(true),
+            json!(["team-a", 7]),
+            json!(["team-a", null]),
+            json!(["team-a", ""]),
+        ] {
+            assert!(parse_namespaces(&claim).is_err(), "{}", claim);
+        }
+    }
+}
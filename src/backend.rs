@@ -0,0 +1,281 @@
+//! How the manager starts, stops and signals stunnel.
+//!
+//! By default stunnel is spawned directly and tracked through its PID file.
+//! On systemd hosts that fights the init system: a stunnel the manager
+//! spawned is not the unit systemd supervises, and a restart of the manager
+//! can take it down with it. The systemd backend instead drives a unit,
+//! e.g. `stunnel@main.service`, through `systemctl` and reads its state
+//! from `systemctl show`.
+
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::utils::{
+    find_stunnel_process, get_global_option, get_stunnel_pid, reload_stunnel,
+    start_stunnel_detached, stop_stunnel,
+};
+
+/// How long a directly started stunnel may take to write its PID file.
+pub const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The running stunnel as a backend found it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located {
+    pub pid: i32,
+    /// Whether the PID was found by scanning processes because the PID
+    /// file was missing or stale.
+    pub discovered: bool,
+}
+
+/// Starts, stops and signals stunnel.
+///
+/// Implementations block, e.g. while waiting for stunnel to exit, so async
+/// callers should run them on the blocking pool.
+pub trait ProcessBackend: fmt::Debug + Send + Sync {
+    /// Short name for logs and status, e.g. `direct`.
+    fn name(&self) -> &'static str;
+
+    /// Finds the running stunnel.
+    ///
+    /// # Errors
+    ///
+    /// Returns why stunnel is not considered running.
+    fn locate(&self) -> Result<Located, String>;
+
+    /// Starts stunnel with the config at `config_path`, returning its PID
+    /// once it is up.
+    ///
+    /// # Errors
+    ///
+    /// Returns why stunnel did not start.
+    fn start(&self, config_path: &str) -> Result<i32, String>;
+
+    /// Stops the stunnel running as `pid`, waiting up to `timeout` for it to
+    /// exit. Returns whether it exited without being killed.
+    ///
+    /// # Errors
+    ///
+    /// Returns why stunnel could not be stopped.
+    fn stop(&self, pid: i32, timeout: Duration) -> Result<bool, String>;
+
+    /// Makes the stunnel running as `pid` reload its config.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the reload could not be requested.
+    fn reload(&self, pid: i32) -> Result<(), String>;
+}
+
+/// Which [`ProcessBackend`] manages stunnel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    /// stunnel is spawned by the manager.
+    #[default]
+    Direct,
+    /// stunnel runs as a systemd unit.
+    Systemd,
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackendKind::Direct => "direct",
+            BackendKind::Systemd => "systemd",
+        })
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "direct" => Ok(BackendKind::Direct),
+            "systemd" => Ok(BackendKind::Systemd),
+            other => Err(format!("Unknown process backend: {}", other)),
+        }
+    }
+}
+
+/// Spawns stunnel as a child of the manager and tracks it through its PID
+/// file, falling back to a scan of `/proc` for a stunnel started with the
+/// managed config when the file is missing or stale.
+#[derive(Debug, Clone)]
+pub struct DirectBackend {
+    config_path: String,
+    pid_file: String,
+    repair_pid_file: bool,
+}
+
+impl DirectBackend {
+    /// Tracks stunnel through `pid_file`, recognizing it in `/proc` by
+    /// `config_path`.
+    pub fn new(config_path: &str, pid_file: &str) -> Self {
+        Self {
+            config_path: config_path.to_string(),
+            pid_file: pid_file.to_string(),
+            repair_pid_file: false,
+        }
+    }
+
+    /// Sets whether a PID found in `/proc` is written back to the PID file.
+    pub fn with_pid_file_repair(mut self, repair: bool) -> Self {
+        self.repair_pid_file = repair;
+        self
+    }
+}
+
+impl ProcessBackend for DirectBackend {
+    fn name(&self) -> &'static str {
+        "direct"
+    }
+
+    fn locate(&self) -> Result<Located, String> {
+        let error = match get_stunnel_pid(&self.pid_file) {
+            Ok(pid) => {
+                return Ok(Located {
+                    pid,
+                    discovered: false,
+                })
+            }
+            Err(e) => e.to_string(),
+        };
+        let Some(pid) = find_stunnel_process(&self.config_path) else {
+            return Err(error);
+        };
+        if self.repair_pid_file {
+            match std::fs::write(&self.pid_file, format!("{}\n", pid)) {
+                Ok(()) => tracing::info!(
+                    pid,
+                    "Rewrote {} with the running stunnel's PID",
+                    self.pid_file
+                ),
+                Err(e) => tracing::warn!("Failed to rewrite {}: {}", self.pid_file, e),
+            }
+        }
+        Ok(Located {
+            pid,
+            discovered: true,
+        })
+    }
+
+    fn start(&self, config_path: &str) -> Result<i32, String> {
+        let content = std::fs::read_to_string(config_path)
+            .map_err(|e| format!("Failed to read {}: {}", config_path, e))?;
+        let foreground = get_global_option(&content, "foreground")
+            .map(|value| !value.eq_ignore_ascii_case("no"))
+            .unwrap_or(false);
+        let pid_file = get_global_option(&content, "pid").unwrap_or_else(|| self.pid_file.clone());
+        start_stunnel_detached(config_path, &pid_file, foreground, STARTUP_TIMEOUT)
+            .map_err(|e| e.to_string())
+    }
+
+    fn stop(&self, pid: i32, timeout: Duration) -> Result<bool, String> {
+        stop_stunnel(pid, timeout).map_err(|e| e.to_string())
+    }
+
+    fn reload(&self, pid: i32) -> Result<(), String> {
+        reload_stunnel(pid).map_err(|e| e.to_string())
+    }
+}
+
+/// Drives stunnel as a systemd unit through `systemctl`. The unit must run
+/// stunnel with the managed config; its stop timeout is systemd's
+/// `TimeoutStopSec`, not the one requested.
+#[derive(Debug, Clone)]
+pub struct SystemdBackend {
+    unit: String,
+}
+
+impl SystemdBackend {
+    /// Manages `unit`, e.g. `stunnel@main.service`.
+    pub fn new(unit: &str) -> Self {
+        Self {
+            unit: unit.to_string(),
+        }
+    }
+
+    // Runs `systemctl <args> -- <unit>`, returning its stdout.
+    fn systemctl(&self, args: &[&str]) -> Result<String, String> {
+        let output = Command::new("systemctl")
+            .args(args)
+            .arg("--")
+            .arg(&self.unit)
+            .output()
+            .map_err(|e| format!("Failed to run systemctl: {}", e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!(
+                "systemctl {} {} failed: {}",
+                args.join(" "),
+                self.unit,
+                stderr.trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    // Reads unit properties with `systemctl show`, e.g. `MainPID`.
+    fn show(&self, properties: &[&str]) -> Result<Vec<(String, String)>, String> {
+        let mut args = vec!["show"];
+        for property in properties {
+            args.push("--property");
+            args.push(property);
+        }
+        Ok(self
+            .systemctl(&args)?
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect())
+    }
+
+    fn property(properties: &[(String, String)], key: &str) -> String {
+        properties
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl ProcessBackend for SystemdBackend {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn locate(&self) -> Result<Located, String> {
+        let properties = self.show(&["ActiveState", "MainPID"])?;
+        let state = Self::property(&properties, "ActiveState");
+        let pid = Self::property(&properties, "MainPID")
+            .parse::<i32>()
+            .unwrap_or(0);
+        if !matches!(state.as_str(), "active" | "reloading") || pid <= 0 {
+            return Err(format!("{} is {}", self.unit, state));
+        }
+        Ok(Located {
+            pid,
+            discovered: false,
+        })
+    }
+
+    #[tracing::instrument(skip_all, fields(unit = %self.unit))]
+    fn start(&self, _config_path: &str) -> Result<i32, String> {
+        self.systemctl(&["start"])?;
+        self.locate().map(|located| located.pid)
+    }
+
+    #[tracing::instrument(skip_all, fields(unit = %self.unit))]
+    fn stop(&self, _pid: i32, _timeout: Duration) -> Result<bool, String> {
+        self.systemctl(&["stop"])?;
+        let properties = self.show(&["Result"])?;
+        Ok(Self::property(&properties, "Result") != "timeout")
+    }
+
+    #[tracing::instrument(skip_all, fields(unit = %self.unit))]
+    fn reload(&self, _pid: i32) -> Result<(), String> {
+        self.systemctl(&["reload"]).map(|_| ())
+    }
+}
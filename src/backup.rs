@@ -0,0 +1,196 @@
+//! Timestamped configuration backups with retention.
+//!
+//! Every config mutation copies the current file to
+//! `{path}.backup.{timestamp}` before writing. Old copies are pruned according
+//! to a [`RetentionPolicy`] so backups never grow without bound, while a bad
+//! change can still be rolled back to any retained version.
+//!
+//! With a [`SecretCipher`] the copies are sealed, since configs carry
+//! protocol passwords and PSK settings inline.
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::secrets::{self, SecretCipher};
+
+// Separates the original file name from the backup timestamp.
+const BACKUP_INFIX: &str = ".backup.";
+
+// Sortable, filename-safe UTC timestamp used as the backup ID.
+const BACKUP_ID_FORMAT: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// Number of backups kept when no retention count is configured.
+pub const DEFAULT_RETENTION_COUNT: usize = 20;
+
+/// Limits on how many backups are kept and for how long.
+///
+/// A backup is pruned if it violates any configured limit; `None` disables
+/// that limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Keep at most this many of the newest backups.
+    pub max_count: Option<usize>,
+    /// Remove backups older than this.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_count: Some(DEFAULT_RETENTION_COUNT),
+            max_age: None,
+        }
+    }
+}
+
+/// Metadata about a timestamped backup created by [`backup_file`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Timestamp suffix identifying the backup.
+    pub id: String,
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+/// Creates a timestamped backup copy of a file and applies retention.
+///
+/// Copies the specified file to `{original_path}.backup.{timestamp}` if it
+/// exists, then prunes older backups that fall outside `policy`. The new
+/// backup is always the newest, so it survives pruning.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file to backup
+/// * `policy` - Retention limits enforced after the copy
+/// * `cipher` - Seals the copy if given
+///
+/// # Returns
+///
+/// Returns the path to the backup file on success.
+///
+/// # Errors
+///
+/// Returns an error if the file copy operation fails. Failing to prune old
+/// backups is logged but does not fail the backup.
+#[tracing::instrument(skip(policy, cipher))]
+pub fn backup_file(
+    path: &str,
+    policy: &RetentionPolicy,
+    cipher: Option<&SecretCipher>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let backup_id = Utc::now().format(BACKUP_ID_FORMAT).to_string();
+    let backup_path = format!("{}{}{}", path, BACKUP_INFIX, backup_id);
+    if Path::new(path).exists() {
+        match cipher {
+            Some(cipher) => {
+                let sealed = cipher.seal_text(&fs::read_to_string(path)?)?;
+                fs::write(&backup_path, sealed)?;
+            }
+            None => {
+                fs::copy(path, &backup_path)?;
+            }
+        }
+    }
+
+    if let Err(e) = prune_backups(path, policy) {
+        tracing::warn!("Failed to prune old backups of {}: {}", path, e);
+    }
+    Ok(backup_path)
+}
+
+/// Lists the timestamped backups of a file, newest first.
+///
+/// # Errors
+///
+/// Returns an error if the directory containing `path` cannot be read.
+pub fn list_backups(path: &str) -> io::Result<Vec<BackupInfo>> {
+    let target = Path::new(path);
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}{}",
+        target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        BACKUP_INFIX
+    );
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = file_name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let Ok(created_at) = NaiveDateTime::parse_from_str(id, BACKUP_ID_FORMAT) else {
+            continue;
+        };
+        backups.push(BackupInfo {
+            id: id.to_string(),
+            path: entry.path().to_string_lossy().into_owned(),
+            created_at: created_at.and_utc(),
+            size_bytes: entry.metadata()?.len(),
+        });
+    }
+
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Reads the content of the backup of `path` identified by `backup_id`,
+/// opening it with `cipher` if it is sealed.
+///
+/// # Errors
+///
+/// Returns an error if no backup with that ID exists, it cannot be read, or
+/// it is sealed and cannot be opened.
+pub fn read_backup(
+    path: &str,
+    backup_id: &str,
+    cipher: Option<&SecretCipher>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let backup = list_backups(path)?
+        .into_iter()
+        .find(|backup| backup.id == backup_id)
+        .ok_or_else(|| format!("Backup {} not found", backup_id))?;
+    Ok(secrets::open_text(
+        cipher,
+        &fs::read_to_string(backup.path)?,
+    )?)
+}
+
+/// Removes backups of `path` that fall outside `policy`.
+///
+/// # Returns
+///
+/// Returns the IDs of the removed backups.
+///
+/// # Errors
+///
+/// Returns an error if the backups cannot be listed or removed.
+pub fn prune_backups(
+    path: &str,
+    policy: &RetentionPolicy,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    let mut removed = Vec::new();
+
+    for (index, backup) in list_backups(path)?.into_iter().enumerate() {
+        let over_count = policy.max_count.is_some_and(|keep| index >= keep);
+        let too_old = policy
+            .max_age
+            .is_some_and(|age| now - backup.created_at > age);
+        if over_count || too_old {
+            fs::remove_file(&backup.path)?;
+            removed.push(backup.id);
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,526 @@
+//! X.509 certificate inspection.
+//!
+//! Certificates are read from PEM files, which may hold a whole chain, so
+//! operators can confirm which certificate a service is actually using:
+//! its subject and issuer, the names it is valid for, its validity window,
+//! key algorithm and SHA-256 fingerprint. Private keys and certificate
+//! chains are checked with the `openssl` command-line tool.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::pem::Pem;
+use x509_parser::public_key::PublicKey;
+
+use crate::parser::{ConfigOption, Section, StunnelConfig};
+
+// Options whose values are certificate files.
+const CERTIFICATE_OPTIONS: [&str; 2] = ["cert", "CAfile"];
+
+/// Where a certificate file is referenced in a config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CertificateReferences {
+    /// Whether the global section references the file.
+    pub global: bool,
+    /// Providers that set the file themselves.
+    pub providers: Vec<String>,
+}
+
+/// The certificate and private key a service presents.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KeyPair {
+    /// Section using the pair; `None` when only the global section sets it.
+    pub section: Option<String>,
+    pub cert: String,
+    /// The key file; the certificate file itself when `key` is unset.
+    pub key: String,
+}
+
+/// A service certificate and the trust anchors it is checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainCheck {
+    /// Section using the certificate; `None` when only the global section sets it.
+    pub section: Option<String>,
+    pub cert: String,
+    pub ca_file: Option<String>,
+    pub ca_path: Option<String>,
+}
+
+/// What happens when a config references a key file others can read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyPermissionPolicy {
+    /// Key file permissions are not checked.
+    Off,
+    /// Readable key files are logged but the config is applied.
+    #[default]
+    Warn,
+    /// Configs referencing readable key files are refused.
+    Enforce,
+}
+
+impl FromStr for KeyPermissionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(KeyPermissionPolicy::Off),
+            "warn" => Ok(KeyPermissionPolicy::Warn),
+            "enforce" => Ok(KeyPermissionPolicy::Enforce),
+            other => Err(format!("Unknown key permission policy: {}", other)),
+        }
+    }
+}
+
+/// Details of a single X.509 certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// Subject alternative names, e.g. `DNS:example.com` or `IP:10.0.0.1`.
+    pub sans: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Serial number as colon-separated hex.
+    pub serial: String,
+    /// Public key algorithm and size, e.g. `RSA 2048` or `EC 256`.
+    pub key_algorithm: String,
+    /// SHA-256 fingerprint of the DER encoding, as colon-separated hex.
+    pub fingerprint_sha256: String,
+}
+
+/// Reads every certificate in a PEM file, in file order.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, holds no certificate, or a
+/// certificate cannot be parsed.
+pub fn read_certificates(path: &str) -> Result<Vec<CertificateInfo>, String> {
+    let content =
+        fs::read(path).map_err(|e| format!("Failed to read certificate {}: {}", path, e))?;
+    parse_certificates(&content).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Parses every `CERTIFICATE` block in PEM content, skipping other blocks
+/// such as private keys in combined files.
+///
+/// # Errors
+///
+/// Returns an error if the content holds no certificate, or a certificate
+/// cannot be parsed.
+pub fn parse_certificates(pem_content: &[u8]) -> Result<Vec<CertificateInfo>, String> {
+    let mut certificates = Vec::new();
+    for pem in Pem::iter_from_buffer(pem_content) {
+        let pem = pem.map_err(|e| format!("Invalid PEM: {}", e))?;
+        if pem.label != "CERTIFICATE" {
+            continue;
+        }
+        let certificate = pem
+            .parse_x509()
+            .map_err(|e| format!("Invalid certificate: {}", e))?;
+        certificates.push(certificate_info(&certificate, &pem.contents));
+    }
+
+    if certificates.is_empty() {
+        return Err("No certificate found".to_string());
+    }
+    Ok(certificates)
+}
+
+/// Returns the subject common name of a DER-encoded certificate, such as a
+/// verified gRPC client certificate.
+pub fn common_name(der: &[u8]) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+    let common_name = certificate.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(str::to_string)
+}
+
+/// Returns every `cert` and `CAfile` path `config` references, keyed by
+/// path as written in the config.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::certs::referenced_certificates;
+/// use stunnel_space::parser::StunnelConfig;
+///
+/// let config = StunnelConfig::parse("cert = /etc/a.pem\n[web]\ncert = /etc/a.pem\nCAfile = /etc/ca.pem\n");
+/// let references = referenced_certificates(&config);
+/// assert!(references["/etc/a.pem"].global);
+/// assert_eq!(references["/etc/ca.pem"].providers, vec!["web"]);
+/// ```
+pub fn referenced_certificates(config: &StunnelConfig) -> BTreeMap<String, CertificateReferences> {
+    let mut references: BTreeMap<String, CertificateReferences> = BTreeMap::new();
+    for path in certificate_paths(&config.globals) {
+        references.entry(path).or_default().global = true;
+    }
+    for section in &config.sections {
+        for path in certificate_paths(&section.options) {
+            let providers = &mut references.entry(path).or_default().providers;
+            if !providers.contains(&section.name) {
+                providers.push(section.name.clone());
+            }
+        }
+    }
+    references
+}
+
+/// Verifies that the private key in `key_path` belongs to the first
+/// certificate in `cert_path`.
+///
+/// # Errors
+///
+/// Returns a message naming both files if the key does not match, or if
+/// either file cannot be read. Encrypted keys cannot be checked.
+pub fn verify_key_pair(cert_path: &str, key_path: &str) -> Result<(), String> {
+    let content = fs::read(cert_path)
+        .map_err(|e| format!("Failed to read certificate {}: {}", cert_path, e))?;
+    let pem = Pem::iter_from_buffer(&content)
+        .filter_map(Result::ok)
+        .find(|pem| pem.label == "CERTIFICATE")
+        .ok_or_else(|| format!("{}: No certificate found", cert_path))?;
+    let certificate = pem
+        .parse_x509()
+        .map_err(|e| format!("{}: Invalid certificate: {}", cert_path, e))?;
+
+    let output = Command::new("openssl")
+        .args([
+            "pkey", "-pubout", "-outform", "DER", "-passin", "pass:", "-in",
+        ])
+        .arg(key_path)
+        .output()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to read private key {}: {}",
+            key_path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    if output.stdout != certificate.public_key().raw {
+        return Err(format!(
+            "Private key {} does not match certificate {}",
+            key_path, cert_path
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the distinct certificate/key pairs the services in `config`
+/// present, resolving options inherited from the global section. Services
+/// whose key lives in an OpenSSL engine are skipped.
+pub fn key_pairs(config: &StunnelConfig) -> Vec<KeyPair> {
+    let mut pairs: Vec<KeyPair> = Vec::new();
+    let resolve = |section: Option<&Section>| {
+        let option = |key: &str| {
+            section
+                .and_then(|s| s.get(key))
+                .or_else(|| config.global(key))
+        };
+        if option("engineId").is_some() {
+            return None;
+        }
+        let cert = option("cert")?;
+        Some(KeyPair {
+            section: section.map(|s| s.name.clone()),
+            cert: cert.to_string(),
+            key: option("key").unwrap_or(cert).to_string(),
+        })
+    };
+
+    let candidates: Vec<Option<KeyPair>> = if config.sections.is_empty() {
+        vec![resolve(None)]
+    } else {
+        config
+            .sections
+            .iter()
+            .map(|section| resolve(Some(section)))
+            .collect()
+    };
+    for pair in candidates.into_iter().flatten() {
+        if !pairs
+            .iter()
+            .any(|p| p.cert == pair.cert && p.key == pair.key)
+        {
+            pairs.push(pair);
+        }
+    }
+    pairs
+}
+
+/// Checks every pair in `pairs` whose files exist, returning an error
+/// message for each key that does not match its certificate.
+pub fn check_key_pairs(pairs: &[KeyPair]) -> Vec<String> {
+    pairs
+        .iter()
+        .filter(|pair| Path::new(&pair.cert).exists() && Path::new(&pair.key).exists())
+        .filter_map(|pair| {
+            verify_key_pair(&pair.cert, &pair.key)
+                .err()
+                .map(|e| match &pair.section {
+                    Some(section) => format!("[{}] {}", section, e),
+                    None => e,
+                })
+        })
+        .collect()
+}
+
+/// Verifies that the first certificate in `cert_path` chains to a CA in
+/// `ca_file` or `ca_path`, using the remaining certificates in `cert_path`
+/// as intermediates.
+///
+/// # Errors
+///
+/// Returns openssl's explanation if the chain is incomplete or untrusted,
+/// or an error if no CA is given or a file cannot be read.
+pub fn verify_chain(
+    cert_path: &str,
+    ca_file: Option<&str>,
+    ca_path: Option<&str>,
+) -> Result<(), String> {
+    if ca_file.is_none() && ca_path.is_none() {
+        return Err("CAfile or CApath is required to verify a chain".to_string());
+    }
+    let content = fs::read_to_string(cert_path)
+        .map_err(|e| format!("Failed to read certificate {}: {}", cert_path, e))?;
+    let leaf = first_certificate_pem(&content)
+        .ok_or_else(|| format!("{}: No certificate found", cert_path))?;
+
+    let mut command = Command::new("openssl");
+    command.arg("verify");
+    if let Some(ca_file) = ca_file {
+        command.arg("-CAfile").arg(ca_file);
+    }
+    if let Some(ca_path) = ca_path {
+        command.arg("-CApath").arg(ca_path);
+    }
+    // The leaf is read from stdin; the file's other certificates are intermediates
+    let mut child = command
+        .arg("-untrusted")
+        .arg(cert_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(leaf.as_bytes())
+            .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let details: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+        .filter(|line| line.starts_with("error") && !line.starts_with("error stdin"))
+        .map(str::to_string)
+        .collect();
+    Err(format!(
+        "Certificate chain {} does not verify against {}: {}",
+        cert_path,
+        [ca_file, ca_path]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" and "),
+        if details.is_empty() {
+            "verification failed".to_string()
+        } else {
+            details.join("; ")
+        }
+    ))
+}
+
+/// Returns the distinct service certificates in `config` that have a
+/// `CAfile` or `CApath` to be checked against, resolving options inherited
+/// from the global section.
+pub fn chain_checks(config: &StunnelConfig) -> Vec<ChainCheck> {
+    let mut checks: Vec<ChainCheck> = Vec::new();
+    let resolve = |section: Option<&Section>| {
+        let option = |key: &str| {
+            section
+                .and_then(|s| s.get(key))
+                .or_else(|| config.global(key))
+                .map(str::to_string)
+        };
+        let check = ChainCheck {
+            section: section.map(|s| s.name.clone()),
+            cert: option("cert")?,
+            ca_file: option("CAfile"),
+            ca_path: option("CApath"),
+        };
+        (check.ca_file.is_some() || check.ca_path.is_some()).then_some(check)
+    };
+
+    let candidates: Vec<Option<ChainCheck>> = if config.sections.is_empty() {
+        vec![resolve(None)]
+    } else {
+        config
+            .sections
+            .iter()
+            .map(|section| resolve(Some(section)))
+            .collect()
+    };
+    for check in candidates.into_iter().flatten() {
+        if !checks.iter().any(|c| {
+            c.cert == check.cert && c.ca_file == check.ca_file && c.ca_path == check.ca_path
+        }) {
+            checks.push(check);
+        }
+    }
+    checks
+}
+
+/// Runs every check in `checks` whose files exist, returning an error
+/// message for each certificate whose chain does not verify.
+pub fn check_chains(checks: &[ChainCheck]) -> Vec<String> {
+    checks
+        .iter()
+        .filter(|check| {
+            Path::new(&check.cert).exists()
+                && check.ca_file.as_ref().is_none_or(|f| Path::new(f).exists())
+                && check.ca_path.as_ref().is_none_or(|p| Path::new(p).exists())
+        })
+        .filter_map(|check| {
+            verify_chain(
+                &check.cert,
+                check.ca_file.as_deref(),
+                check.ca_path.as_deref(),
+            )
+            .err()
+            .map(|e| match &check.section {
+                Some(section) => format!("[{}] {}", section, e),
+                None => e,
+            })
+        })
+        .collect()
+}
+
+fn first_certificate_pem(content: &str) -> Option<&str> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let start = content.find("-----BEGIN CERTIFICATE-----")?;
+    let end = start + content[start..].find(END)? + END.len();
+    Some(&content[start..end])
+}
+
+/// Returns an error message for each existing key file in `pairs` that is
+/// readable by its group or by others.
+pub fn check_key_permissions(pairs: &[KeyPair]) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for pair in pairs {
+        let Ok(metadata) = fs::metadata(&pair.key) else {
+            continue;
+        };
+        let mode = metadata.permissions().mode() & 0o777;
+        let problem = format!(
+            "Private key {} is readable by {} (mode {:03o}); run chmod 600 on it",
+            pair.key,
+            if mode & 0o004 != 0 {
+                "everyone"
+            } else {
+                "its group"
+            },
+            mode
+        );
+        if mode & 0o044 != 0 && !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    }
+    problems
+}
+
+fn certificate_paths(options: &[ConfigOption]) -> Vec<String> {
+    options
+        .iter()
+        .filter(|option| {
+            CERTIFICATE_OPTIONS
+                .iter()
+                .any(|key| option.key.eq_ignore_ascii_case(key))
+        })
+        .map(|option| option.value.clone())
+        .collect()
+}
+
+fn certificate_info(certificate: &X509Certificate, der: &[u8]) -> CertificateInfo {
+    let validity = certificate.validity();
+    CertificateInfo {
+        subject: certificate.subject().to_string(),
+        issuer: certificate.issuer().to_string(),
+        sans: subject_alternative_names(certificate),
+        not_before: to_utc(validity.not_before.timestamp()),
+        not_after: to_utc(validity.not_after.timestamp()),
+        serial: certificate.raw_serial_as_string(),
+        key_algorithm: key_algorithm(certificate),
+        fingerprint_sha256: hex_colon(&Sha256::digest(der)),
+    }
+}
+
+fn subject_alternative_names(certificate: &X509Certificate) -> Vec<String> {
+    let Ok(Some(extension)) = certificate.subject_alternative_name() else {
+        return Vec::new();
+    };
+    extension
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+            GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+            GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+            GeneralName::IPAddress(bytes) => ip_address(bytes).map(|ip| format!("IP:{}", ip)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+fn key_algorithm(certificate: &X509Certificate) -> String {
+    let public_key = certificate.public_key();
+    match public_key.parsed() {
+        Ok(PublicKey::RSA(key)) => format!("RSA {}", key.key_size()),
+        Ok(PublicKey::EC(point)) => format!("EC {}", point.key_size()),
+        Ok(PublicKey::DSA(_)) => "DSA".to_string(),
+        // Ed25519/Ed448 and anything else: fall back to the algorithm name
+        _ => {
+            let oid = &public_key.algorithm.algorithm;
+            oid2sn(oid, oid_registry())
+                .map(str::to_string)
+                .unwrap_or_else(|_| oid.to_id_string())
+        }
+    }
+}
+
+fn to_utc(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+}
+
+fn hex_colon(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,742 @@
+//! Managed certificate directory.
+//!
+//! Private keys generated or uploaded through the manager live in a single
+//! directory on the stunnel host, readable only by its owner, so key
+//! material never has to leave the host. Key generation and CSRs use the
+//! `openssl` command-line tool, which must be installed.
+//!
+//! With sealed keys enabled, the directory holds each key only encrypted, as
+//! `<name>.key.sealed`, and the plaintext stunnel reads is materialized in a
+//! separate runtime directory, typically a tmpfs, at startup and whenever a
+//! key is written.
+
+use chrono::{DateTime, Utc};
+use nix::unistd::{chown, Gid, Group, Uid, User};
+use std::collections::BTreeMap;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::certs::{self, CertificateReferences};
+use crate::parser::StunnelConfig;
+use crate::secrets::SecretCipher;
+
+/// Mode of private key files.
+pub const KEY_FILE_MODE: u32 = 0o600;
+
+/// Mode of certificate and chain files.
+pub const CERT_FILE_MODE: u32 = 0o644;
+
+/// Extensions of the files stored for each name.
+pub const CERT_EXTENSION: &str = "crt";
+pub const KEY_EXTENSION: &str = "key";
+pub const CHAIN_EXTENSION: &str = "chain.crt";
+pub const SEALED_KEY_EXTENSION: &str = "key.sealed";
+
+/// Private key types that can be generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Rsa2048,
+    Rsa4096,
+    EcP256,
+    EcP384,
+}
+
+impl KeyType {
+    // Argument for `openssl req -newkey`.
+    fn newkey_arg(&self) -> &'static str {
+        match self {
+            KeyType::Rsa2048 => "rsa:2048",
+            KeyType::Rsa4096 => "rsa:4096",
+            KeyType::EcP256 => "ec",
+            KeyType::EcP384 => "ec",
+        }
+    }
+
+    // Extra `-pkeyopt` arguments selecting the curve for EC keys.
+    fn pkeyopt_args(&self) -> &'static [&'static str] {
+        match self {
+            KeyType::Rsa2048 | KeyType::Rsa4096 => &[],
+            KeyType::EcP256 => &["-pkeyopt", "ec_paramgen_curve:prime256v1"],
+            KeyType::EcP384 => &["-pkeyopt", "ec_paramgen_curve:secp384r1"],
+        }
+    }
+}
+
+/// Distinguished name fields of a certificate signing request. Empty
+/// fields are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subject {
+    pub common_name: String,
+    pub organization: String,
+    pub organizational_unit: String,
+    pub locality: String,
+    pub state: String,
+    pub country: String,
+}
+
+impl Subject {
+    // Renders the subject in `openssl -subj` form, escaping separators.
+    fn to_openssl(&self) -> Result<String, String> {
+        let fields = [
+            ("C", &self.country),
+            ("ST", &self.state),
+            ("L", &self.locality),
+            ("O", &self.organization),
+            ("OU", &self.organizational_unit),
+            ("CN", &self.common_name),
+        ];
+        let mut subject = String::new();
+        for (key, value) in fields {
+            if value.is_empty() {
+                continue;
+            }
+            if value.contains(['\n', '\r', '\0']) {
+                return Err(format!("Subject {} must be a single line", key));
+            }
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('/', "\\/")
+                .replace('=', "\\=");
+            subject.push_str(&format!("/{}={}", key, escaped));
+        }
+        if subject.is_empty() {
+            return Err("Subject must have at least one field".to_string());
+        }
+        Ok(subject)
+    }
+}
+
+/// Paths of the files written by [`CertStore::store`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFiles {
+    pub cert_path: PathBuf,
+    /// Set when a key was uploaded.
+    pub key_path: Option<PathBuf>,
+    /// Set when a chain was uploaded.
+    pub chain_path: Option<PathBuf>,
+}
+
+/// A certificate file in the managed directory or referenced by a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateEntry {
+    /// Path as written in the config, or the managed path if unreferenced.
+    pub path: String,
+    /// Whether the file lives in the managed directory.
+    pub managed: bool,
+    pub references: CertificateReferences,
+    /// Subject of the first (leaf) certificate in the file.
+    pub subject: String,
+    pub not_after: Option<DateTime<Utc>>,
+    /// Why the file could not be inspected, if it could not.
+    pub error: Option<String>,
+}
+
+/// User and group that own private keys written to the managed directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyOwner {
+    pub uid: Uid,
+    /// Group to set; `None` keeps the file's group.
+    pub gid: Option<Gid>,
+}
+
+impl FromStr for KeyOwner {
+    type Err = String;
+
+    /// Parses `user` or `user:group`, by name or numeric ID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = match s.trim().split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (s.trim(), None),
+        };
+        let uid = match user.parse::<u32>() {
+            Ok(uid) => Uid::from_raw(uid),
+            Err(_) => {
+                User::from_name(user)
+                    .map_err(|e| format!("Failed to look up user {}: {}", user, e))?
+                    .ok_or_else(|| format!("Unknown user: {}", user))?
+                    .uid
+            }
+        };
+        let gid = match group {
+            None => None,
+            Some(group) => Some(match group.parse::<u32>() {
+                Ok(gid) => Gid::from_raw(gid),
+                Err(_) => {
+                    Group::from_name(group)
+                        .map_err(|e| format!("Failed to look up group {}: {}", group, e))?
+                        .ok_or_else(|| format!("Unknown group: {}", group))?
+                        .gid
+                }
+            }),
+        };
+        Ok(Self { uid, gid })
+    }
+}
+
+/// A directory of managed keys and certificates.
+#[derive(Debug, Clone)]
+pub struct CertStore {
+    dir: PathBuf,
+    key_owner: Option<KeyOwner>,
+    sealed_keys: Option<SealedKeys>,
+}
+
+// Where plaintext keys are materialized when keys are stored sealed.
+#[derive(Debug, Clone)]
+struct SealedKeys {
+    cipher: Arc<SecretCipher>,
+    runtime_dir: PathBuf,
+}
+
+impl CertStore {
+    /// Opens the managed certificate directory, creating it readable only by
+    /// its owner if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::certstore::CertStore;
+    ///
+    /// let store = CertStore::open("/etc/stunnel/certs").expect("Failed to open certs directory");
+    /// ```
+    pub fn open(dir: &str) -> io::Result<Self> {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            key_owner: None,
+            sealed_keys: None,
+        })
+    }
+
+    /// Stores private keys sealed with `cipher` and materializes the
+    /// plaintext stunnel reads in `runtime_dir`, which is created readable
+    /// only by its owner (the key owner, if set).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime directory cannot be created.
+    pub fn with_sealed_keys(
+        mut self,
+        cipher: Arc<SecretCipher>,
+        runtime_dir: &str,
+    ) -> Result<Self, String> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(runtime_dir)
+            .map_err(|e| format!("Failed to create key directory {}: {}", runtime_dir, e))?;
+        if let Some(owner) = self.key_owner {
+            chown(runtime_dir, Some(owner.uid), owner.gid)
+                .map_err(|e| format!("Failed to change owner of {}: {}", runtime_dir, e))?;
+        }
+        self.sealed_keys = Some(SealedKeys {
+            cipher,
+            runtime_dir: PathBuf::from(runtime_dir),
+        });
+        Ok(self)
+    }
+
+    /// Hands the directory and every private key written to it to `owner`,
+    /// e.g. the user stunnel drops privileges to, so it can re-read keys on
+    /// reload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory's owner cannot be changed.
+    pub fn with_key_owner(mut self, owner: KeyOwner) -> Result<Self, String> {
+        chown(&self.dir, Some(owner.uid), owner.gid)
+            .map_err(|e| format!("Failed to change owner of {}: {}", self.dir.display(), e))?;
+        self.key_owner = Some(owner);
+        Ok(self)
+    }
+
+    /// Returns the managed directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of file `name` with `extension` in the directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` cannot be used as a file name.
+    pub fn file_path(&self, name: &str, extension: &str) -> Result<PathBuf, String> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+            return Err(format!("Name {:?} cannot be used as a file name", name));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, extension)))
+    }
+
+    /// Returns the path stunnel reads private key `name` from: the runtime
+    /// directory with sealed keys, else the managed directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` cannot be used as a file name.
+    pub fn key_path(&self, name: &str) -> Result<PathBuf, String> {
+        let path = self.file_path(name, KEY_EXTENSION)?;
+        Ok(match &self.sealed_keys {
+            Some(sealed) => sealed
+                .runtime_dir
+                .join(path.file_name().unwrap_or_default()),
+            None => path,
+        })
+    }
+
+    /// Decrypts every sealed key into the runtime directory, e.g. after a
+    /// reboot cleared it, and returns the materialized paths. Does nothing
+    /// unless keys are sealed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sealed key cannot be read, opened or written.
+    pub fn materialize_keys(&self) -> Result<Vec<PathBuf>, String> {
+        let Some(sealed) = &self.sealed_keys else {
+            return Ok(Vec::new());
+        };
+        let suffix = format!(".{}", SEALED_KEY_EXTENSION);
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("Failed to read {}: {}", self.dir.display(), e))?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let Some(name) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(&suffix))
+                .filter(|name| !name.starts_with('.'))
+            else {
+                continue;
+            };
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let key_pem = sealed
+                .cipher
+                .open(&content)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            let key_path = self.key_path(name)?;
+            write_file(&key_path, key_pem, KEY_FILE_MODE)?;
+            self.set_key_owner(&key_path)?;
+            paths.push(key_path);
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Removes private key `name`, sealed copy included. Missing files are
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` cannot be used as a file name.
+    pub fn remove_key(&self, name: &str) -> Result<(), String> {
+        let _ = fs::remove_file(self.key_path(name)?);
+        let _ = fs::remove_file(self.file_path(name, SEALED_KEY_EXTENSION)?);
+        Ok(())
+    }
+
+    // Writes private key `name`, sealing it first if keys are sealed, and
+    // returns the path stunnel reads it from.
+    pub(crate) fn write_key(&self, name: &str, key_pem: &[u8]) -> Result<PathBuf, String> {
+        if let Some(sealed) = &self.sealed_keys {
+            let content = sealed.cipher.seal(key_pem)?;
+            write_file(
+                &self.file_path(name, SEALED_KEY_EXTENSION)?,
+                content,
+                KEY_FILE_MODE,
+            )?;
+        }
+        let path = self.key_path(name)?;
+        write_file(&path, key_pem, KEY_FILE_MODE)?;
+        self.set_key_owner(&path)?;
+        Ok(path)
+    }
+
+    /// Generates private key `<name>.key` in the directory and returns its
+    /// path with a PEM certificate signing request for it.
+    ///
+    /// SANs may be given as `DNS:name` or `IP:address`; bare values are
+    /// treated as IP addresses if they parse as one and DNS names otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key exists and `overwrite` is false, the
+    /// subject or SANs are invalid, or `openssl` fails.
+    pub fn generate_csr(
+        &self,
+        name: &str,
+        key_type: KeyType,
+        subject: &Subject,
+        sans: &[String],
+        overwrite: bool,
+    ) -> Result<(PathBuf, String), String> {
+        let key_path = self.key_path(name)?;
+        if key_path.exists() && !overwrite {
+            return Err(format!("Key {} already exists", key_path.display()));
+        }
+        let subject = subject.to_openssl()?;
+        let sans = sans
+            .iter()
+            .map(|san| san_entry(san))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Have openssl write the key into a temp file created private, then
+        // move it into place. The temp file sits next to the final key, so
+        // a sealed store never holds the plaintext
+        let tmp_path = key_path.with_file_name(format!(".{}.key.tmp.{}", name, std::process::id()));
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(KEY_FILE_MODE)
+            .open(&tmp_path)
+            .and_then(|file| file.set_permissions(fs::Permissions::from_mode(KEY_FILE_MODE)))
+            .map_err(|e| format!("Failed to create key file: {}", e))?;
+        let mut command = Command::new("openssl");
+        command
+            .args(["req", "-new", "-nodes", "-newkey", key_type.newkey_arg()])
+            .args(key_type.pkeyopt_args())
+            .arg("-keyout")
+            .arg(&tmp_path)
+            .args(["-subj", &subject]);
+        if !sans.is_empty() {
+            command.args(["-addext", &format!("subjectAltName={}", sans.join(","))]);
+        }
+        let output = command
+            .output()
+            .map_err(|e| format!("Failed to run openssl: {}", e))?;
+        if !output.status.success() {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(format!(
+                "openssl failed to generate the CSR: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        self.seal_generated_key(name, &tmp_path)
+            .and_then(|_| self.set_key_owner(&tmp_path))
+            .and_then(|_| fs::rename(&tmp_path, &key_path).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp_path);
+                format!("Failed to store key {}: {}", key_path.display(), e)
+            })?;
+        Ok((
+            key_path,
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+        ))
+    }
+
+    /// Splits a PKCS#12 bundle into PEM certificate, key and chain files
+    /// stored as with [`CertStore::store`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the passphrase is wrong, the bundle holds no
+    /// certificate or key, or the files cannot be stored.
+    pub fn import_pkcs12(
+        &self,
+        name: &str,
+        bundle: &[u8],
+        passphrase: &str,
+        overwrite: bool,
+    ) -> Result<StoredFiles, String> {
+        // openssl reads the bundle from a private temp file, removed afterwards
+        let bundle_path = self.file_path(name, "p12")?.with_file_name(format!(
+            ".{}.p12.tmp.{}",
+            name,
+            std::process::id()
+        ));
+        write_file(&bundle_path, bundle, KEY_FILE_MODE)?;
+        let extract = |args: &[&str]| pkcs12_extract(&bundle_path, passphrase, args);
+        let result = extract(&["-nokeys", "-clcerts"]).and_then(|cert| {
+            let key = extract(&["-nocerts", "-nodes"])?;
+            let chain = extract(&["-nokeys", "-cacerts"])?;
+            Ok((cert, key, chain))
+        });
+        let _ = fs::remove_file(&bundle_path);
+        let (cert, key, chain) = result?;
+
+        // openssl may print bag attributes even when there is no key
+        if !key.contains("PRIVATE KEY-----") {
+            return Err("PKCS#12 bundle holds no private key".to_string());
+        }
+        let chain = Some(chain.as_str()).filter(|chain| chain.contains("BEGIN CERTIFICATE"));
+        self.store(name, &cert, Some(&key), chain, overwrite)
+    }
+
+    /// Returns the certificate files (`*.crt`, chains included) in the
+    /// directory, sorted by path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be read.
+    pub fn list_certificates(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| name.starts_with('.'));
+            if !hidden
+                && path.is_file()
+                && path.extension().and_then(|ext| ext.to_str()) == Some(CERT_EXTENSION)
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Stores a PEM certificate as `<name>.crt`, with an optional private key
+    /// (`<name>.key`, mode 600, sealed if enabled) and chain
+    /// (`<name>.chain.crt`). Each file is replaced atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificate exists and `overwrite` is false,
+    /// the PEM content is not a certificate or key, or a file cannot be
+    /// written.
+    pub fn store(
+        &self,
+        name: &str,
+        cert_pem: &str,
+        key_pem: Option<&str>,
+        chain_pem: Option<&str>,
+        overwrite: bool,
+    ) -> Result<StoredFiles, String> {
+        let cert_path = self.file_path(name, CERT_EXTENSION)?;
+        if cert_path.exists() && !overwrite {
+            return Err(format!(
+                "Certificate {} already exists",
+                cert_path.display()
+            ));
+        }
+        certs::parse_certificates(cert_pem.as_bytes())
+            .map_err(|e| format!("Invalid certificate: {}", e))?;
+        if let Some(chain_pem) = chain_pem {
+            certs::parse_certificates(chain_pem.as_bytes())
+                .map_err(|e| format!("Invalid chain: {}", e))?;
+        }
+        if let Some(key_pem) = key_pem {
+            if !key_pem.contains("PRIVATE KEY-----") {
+                return Err("Key must be a PEM-encoded private key".to_string());
+            }
+        }
+
+        // Write the key first, so a certificate never points at a missing key
+        let key_path = match key_pem {
+            Some(key_pem) => Some(self.write_key(name, key_pem.as_bytes())?),
+            None => None,
+        };
+        let chain_path = match chain_pem {
+            Some(chain_pem) => {
+                let path = self.file_path(name, CHAIN_EXTENSION)?;
+                write_file(&path, chain_pem, CERT_FILE_MODE)?;
+                Some(path)
+            }
+            None => None,
+        };
+        write_file(&cert_path, cert_pem, CERT_FILE_MODE)?;
+
+        Ok(StoredFiles {
+            cert_path,
+            key_path,
+            chain_path,
+        })
+    }
+
+    // Stores a sealed copy of the key openssl generated at `path`, if keys
+    // are sealed.
+    fn seal_generated_key(&self, name: &str, path: &Path) -> Result<(), String> {
+        let Some(sealed) = &self.sealed_keys else {
+            return Ok(());
+        };
+        let key_pem = fs::read(path).map_err(|e| e.to_string())?;
+        write_file(
+            &self.file_path(name, SEALED_KEY_EXTENSION)?,
+            sealed.cipher.seal(&key_pem)?,
+            KEY_FILE_MODE,
+        )
+    }
+
+    // Gives key file `path` to the configured key owner, if any.
+    fn set_key_owner(&self, path: &Path) -> Result<(), String> {
+        match self.key_owner {
+            Some(owner) => chown(path, Some(owner.uid), owner.gid)
+                .map_err(|e| format!("Failed to change owner of {}: {}", path.display(), e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Lists every certificate file referenced by `config` or stored in
+/// `store`, with the providers referencing it. Paths are matched after
+/// resolving symlinks and relative paths, so a managed file the config
+/// references appears once.
+///
+/// # Errors
+///
+/// Returns an error if the managed directory cannot be read.
+pub fn inventory(
+    config: &StunnelConfig,
+    store: Option<&CertStore>,
+) -> Result<Vec<CertificateEntry>, String> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let mut entries: BTreeMap<PathBuf, (String, bool, CertificateReferences)> = BTreeMap::new();
+    for (path, references) in certs::referenced_certificates(config) {
+        entries.insert(canonical(Path::new(&path)), (path, false, references));
+    }
+    if let Some(store) = store {
+        let managed = store
+            .list_certificates()
+            .map_err(|e| format!("Failed to read certificates directory: {}", e))?;
+        for path in managed {
+            entries
+                .entry(canonical(&path))
+                .or_insert_with(|| {
+                    (
+                        path.to_string_lossy().into_owned(),
+                        false,
+                        CertificateReferences::default(),
+                    )
+                })
+                .1 = true;
+        }
+    }
+
+    Ok(entries
+        .into_values()
+        .map(|(path, managed, references)| {
+            let mut entry = CertificateEntry {
+                path,
+                managed,
+                references,
+                subject: String::new(),
+                not_after: None,
+                error: None,
+            };
+            match certs::read_certificates(&entry.path) {
+                Ok(certificates) => {
+                    if let Some(leaf) = certificates.first() {
+                        entry.subject = leaf.subject.clone();
+                        entry.not_after = Some(leaf.not_after);
+                    }
+                }
+                Err(e) => entry.error = Some(e),
+            }
+            entry
+        })
+        .collect())
+}
+
+// Runs `openssl pkcs12` on a bundle, returning the PEM it prints. Bundles
+// using legacy algorithms (RC2, 3DES) need OpenSSL 3's legacy provider.
+fn pkcs12_extract(bundle_path: &Path, passphrase: &str, args: &[&str]) -> Result<String, String> {
+    let run = |legacy: bool| {
+        let mut command = Command::new("openssl");
+        command
+            .arg("pkcs12")
+            .arg("-in")
+            .arg(bundle_path)
+            .args(["-passin", "env:PKCS12_PASSPHRASE"])
+            .args(args)
+            .env("PKCS12_PASSPHRASE", passphrase);
+        if legacy {
+            command.arg("-legacy");
+        }
+        command.output()
+    };
+
+    let mut output = run(false).map_err(|e| format!("Failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        output = run(true).map_err(|e| format!("Failed to run openssl: {}", e))?;
+    }
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to read PKCS#12 bundle (wrong passphrase?): {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(pem_blocks(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Keeps only the PEM blocks of openssl output, dropping "Bag Attributes".
+fn pem_blocks(text: &str) -> String {
+    let mut pem = String::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        if line.starts_with("-----BEGIN ") {
+            in_block = true;
+        }
+        if in_block {
+            pem.push_str(line);
+            pem.push('\n');
+        }
+        if line.starts_with("-----END ") {
+            in_block = false;
+        }
+    }
+    pem
+}
+
+// Atomically replaces `path` with `content`, creating it with exactly `mode`.
+pub(crate) fn write_file(path: &Path, content: impl AsRef<[u8]>, mode: u32) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.tmp.{}", file_name, std::process::id()));
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp_path)
+        .and_then(|mut file| {
+            // The umask or a stale temp file must not loosen the mode
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+            file.write_all(content.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    result.map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("Failed to write {}: {}", path.display(), e)
+    })
+}
+
+// Normalizes a SAN to openssl's `DNS:`/`IP:` form.
+fn san_entry(san: &str) -> Result<String, String> {
+    let san = san.trim();
+    if san.is_empty() || san.contains([',', '\n', '\r', '\0']) {
+        return Err(format!("Invalid subject alternative name: {:?}", san));
+    }
+    if let Some((kind, value)) = san.split_once(':') {
+        if kind.eq_ignore_ascii_case("DNS") {
+            return Ok(format!("DNS:{}", value));
+        }
+        if kind.eq_ignore_ascii_case("IP") {
+            return value
+                .parse::<IpAddr>()
+                .map(|ip| format!("IP:{}", ip))
+                .map_err(|_| format!("Invalid IP address in SAN: {}", value));
+        }
+    }
+    Ok(match san.parse::<IpAddr>() {
+        Ok(ip) => format!("IP:{}", ip),
+        Err(_) => format!("DNS:{}", san),
+    })
+}
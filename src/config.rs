@@ -6,6 +6,45 @@
 use std::env;
 use std::error::Error;
 use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::acme::{ChallengeType, DEFAULT_RENEW_DAYS};
+use crate::backend::BackendKind;
+use crate::backup::{RetentionPolicy, DEFAULT_RETENTION_COUNT};
+use crate::certs::KeyPermissionPolicy;
+use crate::certstore::KeyOwner;
+use crate::connlog::DEFAULT_RETENTION_DAYS;
+use crate::dns::DnsCheckPolicy;
+use crate::expiry::DEFAULT_WARNING_DAYS;
+use crate::idempotency;
+use crate::logging::{self, LogFormat, LogTarget};
+use crate::ports::PortRange;
+use crate::readiness;
+use crate::retry::{self, RetryPolicy};
+use crate::statsd;
+use crate::supervisor;
+use crate::telemetry;
+use crate::validation::{ValidationPipeline, DEFAULT_VALIDATORS};
+
+// Seconds between ACME renewal checks when ACME_CHECK_INTERVAL_SECS is unset.
+const DEFAULT_ACME_CHECK_INTERVAL_SECS: u64 = 12 * 60 * 60;
+
+// Seconds between Vault refresh checks when VAULT_REFRESH_INTERVAL_SECS is unset.
+const DEFAULT_VAULT_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+
+// Seconds between health checks when HEALTH_CHECK_INTERVAL_SECS is unset.
+const DEFAULT_HEALTH_CHECK_INTERVAL_SECS: u64 = 10;
+
+// Seconds allowed for draining RPCs when SHUTDOWN_TIMEOUT_SECS is unset.
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 20;
+
+// Where decrypted managed keys are written when SECRETS_KEY_DIR is unset.
+const DEFAULT_SECRETS_KEY_DIR: &str = "/run/stunnel-space/keys";
 
 /// Configuration for the stunnel-space gRPC server.
 ///
@@ -19,24 +58,181 @@ pub struct Config {
     pub pid_file: String,
     pub grpc_host: String,
     pub grpc_port: String,
+    /// PEM certificate served by the gRPC endpoint; `None` serves plaintext.
+    pub grpc_tls_cert: Option<String>,
+    /// PEM private key for `grpc_tls_cert`.
+    pub grpc_tls_key: Option<String>,
+    /// PEM CA bundle client certificates are verified against; `None` disables client authentication.
+    pub grpc_tls_client_ca: Option<String>,
+    /// Whether clients without a certificate are still accepted when a client CA is set.
+    pub grpc_tls_client_auth_optional: bool,
+    /// Shared secret clients send as a bearer token.
+    pub auth_token: Option<String>,
+    /// JWKS endpoint whose keys sign accepted JWT bearer tokens.
+    pub jwks_url: Option<String>,
+    /// Required `iss` claim of JWTs.
+    pub jwt_issuer: Option<String>,
+    /// Required `aud` entry of JWTs.
+    pub jwt_audience: Option<String>,
+    /// File mapping API keys to roles, reloaded when it changes.
+    pub api_keys_file: Option<String>,
+    /// Config mutations each client may make per minute; `None` is unlimited.
+    pub rate_limit_per_client: Option<u32>,
+    /// Config mutations all clients together may make per minute; `None` is unlimited.
+    pub rate_limit_global: Option<u32>,
+    /// Whether the health service also requires stunnel to be running.
+    pub health_check_stunnel: bool,
+    /// Seconds between health checks.
+    pub health_check_interval_secs: u64,
+    /// Address serving OpenMetrics over HTTP; `None` disables the endpoint.
+    pub metrics_address: Option<SocketAddr>,
+    /// StatsD server (`host:port`) metrics are pushed to; `None` disables pushing.
+    pub statsd_address: Option<String>,
+    /// Seconds between pushes to StatsD.
+    pub statsd_interval_secs: u64,
+    /// Prefix of the metric names pushed to StatsD.
+    pub statsd_prefix: Option<String>,
+    /// Whether stunnel is restarted when it dies without being stopped.
+    pub supervise_stunnel: bool,
+    /// Seconds between checks of whether a supervised stunnel is still running.
+    pub supervisor_interval_secs: u64,
+    /// Whether stunnel is stopped when the manager shuts down.
+    pub shutdown_stop_stunnel: bool,
+    /// Seconds allowed for in-flight RPCs, and stunnel if stopped, to finish on shutdown.
+    pub shutdown_timeout_secs: u64,
+    /// Seconds to wait for services to listen after a start or reload; 0 skips the check.
+    pub readiness_timeout_secs: u64,
+    /// Attempts StartStunnel makes to start stunnel, including the first.
+    pub start_retry_attempts: u32,
+    /// Milliseconds before the second start attempt, doubled for each further one.
+    pub start_retry_backoff_ms: u64,
+    /// Whether a missing or stale PID file is rewritten with the PID of a stunnel found in /proc.
+    pub repair_pid_file: bool,
+    /// How stunnel is started, stopped and signalled.
+    pub process_backend: BackendKind,
+    /// systemd unit running stunnel, for the systemd process backend.
+    pub systemd_unit: String,
+    /// Whether the manager starts in maintenance mode, refusing changes.
+    pub maintenance_mode: bool,
+    /// Files and directories clients may name as `config_path` besides the managed config.
+    pub config_path_allowlist: Vec<String>,
+    /// Seconds responses to calls with an idempotency key are remembered.
+    pub idempotency_ttl_secs: u64,
     pub log_level: String,
+    /// Where the manager's log is written.
+    pub log_target: LogTarget,
+    /// How events written to stderr are formatted.
+    pub log_format: LogFormat,
+    /// Whether request and response messages are logged with each call.
+    pub log_payloads: bool,
+    /// OTLP/gRPC collector spans are exported to; `None` disables exporting.
+    pub otlp_endpoint: Option<String>,
+    /// Service name spans are exported under.
+    pub otel_service_name: String,
+    /// Number of config backups to keep; `None` keeps every backup.
+    pub backup_retention_count: Option<usize>,
+    /// Days after which config backups are pruned; `None` disables age-based pruning.
+    pub backup_retention_days: Option<u32>,
+    /// Path of the SQLite config history database; `None` disables history.
+    pub history_db_path: Option<String>,
+    /// Path of the SQLite connection history database; `None` disables it.
+    pub connection_log_db_path: Option<String>,
+    /// Days connections are kept in the connection history.
+    pub connection_log_retention_days: u32,
+    /// Path of the MaxMind country (or city) database; `None` disables country lookups.
+    pub geoip_country_db: Option<String>,
+    /// Path of the MaxMind ASN database; `None` disables ASN lookups.
+    pub geoip_asn_db: Option<String>,
+    /// Whether config changes are committed to a Git repository in the config's directory.
+    pub git_versioning: bool,
+    /// Directory holding one config file per provider; `None` keeps providers in the main config.
+    pub providers_dir: Option<String>,
+    /// Directory persisting provider templates; `None` keeps templates in memory.
+    pub templates_dir: Option<String>,
+    /// Directory persisting configs staged for approval; `None` keeps them in memory.
+    pub staging_dir: Option<String>,
+    /// JSON file persisting instances created with CreateInstance; `None` keeps them in memory.
+    pub instances_file: Option<String>,
+    /// YAML file listing tenant namespaces; `None` disables namespaces.
+    pub namespaces_file: Option<String>,
+    /// Validators run on candidate configs, as a `VALIDATORS` list.
+    pub validators: String,
+    /// Range AddProvider allocates accept ports from when none is given; `None` requires one.
+    pub accept_port_range: Option<PortRange>,
+    /// Seconds between background certificate expiry checks; `None` disables them.
+    pub cert_check_interval_secs: Option<u64>,
+    /// Days before expiry at which certificates are flagged.
+    pub cert_expiry_warning_days: u32,
+    /// URL receiving a JSON POST for each expiring certificate; `None` only logs.
+    pub cert_expiry_webhook_url: Option<String>,
+    /// Directory holding generated keys and uploaded certificates; `None` disables them.
+    pub certs_dir: Option<String>,
+    /// ACME directory URL; `None` disables ACME certificate management.
+    pub acme_directory_url: Option<String>,
+    /// Contact address registered with the ACME account.
+    pub acme_contact_email: Option<String>,
+    /// How ACME domain control is proven.
+    pub acme_challenge: ChallengeType,
+    /// Webroot served on port 80 for HTTP-01 challenges.
+    pub acme_webroot: Option<String>,
+    /// Hook publishing and removing DNS-01 TXT records.
+    pub acme_dns_hook: Option<String>,
+    /// Days before expiry at which ACME certificates are renewed.
+    pub acme_renew_days: u32,
+    /// Seconds between ACME renewal checks.
+    pub acme_check_interval_secs: u64,
+    /// How configs referencing group- or world-readable key files are handled.
+    pub key_permission_policy: KeyPermissionPolicy,
+    /// Whether connect hosts of added and updated providers must resolve.
+    pub dns_check: DnsCheckPolicy,
+    /// Owner of private keys written to the managed directory; `None` keeps the server's user.
+    pub key_file_owner: Option<KeyOwner>,
+    /// Whether sensitive option values are masked in returned config content.
+    pub redact_secrets: bool,
+    /// Whether referenced certificate and key files are watched, reloading stunnel on change.
+    pub cert_watch: bool,
+    /// Vault server address; `None` disables the Vault backend.
+    pub vault_addr: Option<String>,
+    /// Token authenticating to Vault.
+    pub vault_token: Option<String>,
+    /// Vault Enterprise namespace, if any.
+    pub vault_namespace: Option<String>,
+    /// Seconds between Vault refresh checks.
+    pub vault_refresh_interval_secs: u64,
+    /// Base64 key sealing backups, history and managed keys at rest.
+    pub secrets_key: Option<String>,
+    /// Command printing the secrets key, e.g. a KMS decrypt call.
+    pub secrets_key_command: Option<String>,
+    /// Directory plaintext keys are materialized in when keys are sealed.
+    pub secrets_key_dir: String,
 }
 
-/// Error type returned when required configuration variables are missing.
+/// Error type returned when configuration variables are missing or invalid.
 ///
-/// Contains a list of all missing environment variable names.
+/// Contains a list of all missing environment variable names and of any
+/// variables whose values could not be parsed.
 #[derive(Debug)]
 pub struct ConfigError {
     missing_vars: Vec<String>,
+    invalid_vars: Vec<String>,
 }
 
 impl fmt::Display for ConfigError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Missing required environment variables: {}",
-            self.missing_vars.join(", ")
-        )
+        let mut problems = Vec::new();
+        if !self.missing_vars.is_empty() {
+            problems.push(format!(
+                "Missing required environment variables: {}",
+                self.missing_vars.join(", ")
+            ));
+        }
+        if !self.invalid_vars.is_empty() {
+            problems.push(format!(
+                "Invalid environment variables: {}",
+                self.invalid_vars.join(", ")
+            ));
+        }
+        write!(f, "{}", problems.join("; "))
     }
 }
 
@@ -54,11 +250,85 @@ impl Config {
     /// # Optional Environment Variables
     ///
     /// - `GRPC_HOST`: gRPC server host (default: "0.0.0.0")
-    /// - `LOG_LEVEL`: Log level (default: "info")
+    /// - `GRPC_TLS_CERT`: PEM certificate enabling TLS on the gRPC endpoint; requires `GRPC_TLS_KEY` (default: unset, plaintext)
+    /// - `GRPC_TLS_KEY`: PEM private key for `GRPC_TLS_CERT`
+    /// - `GRPC_TLS_CLIENT_CA`: PEM CA bundle; clients must present a certificate it issued (default: unset)
+    /// - `GRPC_TLS_CLIENT_AUTH_OPTIONAL`: Also accept clients without a certificate (default: false)
+    /// - `STUNNEL_MGR_AUTH_TOKEN`: Shared secret required as a bearer token on every RPC (default: unset)
+    /// - `STUNNEL_MGR_JWKS_URL`: JWKS endpoint; JWTs signed by its keys are accepted as bearer tokens (default: unset)
+    /// - `STUNNEL_MGR_JWT_ISSUER`: Required `iss` claim of JWTs; required with `STUNNEL_MGR_JWKS_URL`
+    /// - `STUNNEL_MGR_JWT_AUDIENCE`: Required `aud` entry of JWTs; required with `STUNNEL_MGR_JWKS_URL`
+    /// - `STUNNEL_MGR_API_KEYS_FILE`: YAML file mapping API keys to `read-only`, `operator` or `admin` roles (default: unset)
+    /// - `RATE_LIMIT_PER_CLIENT`: Config mutations per minute allowed to each client, 0 for unlimited (default: unlimited)
+    /// - `RATE_LIMIT_GLOBAL`: Config mutations per minute allowed across all clients, 0 for unlimited (default: unlimited)
+    /// - `HEALTH_CHECK_STUNNEL`: Report NOT_SERVING on the gRPC health service while stunnel is not running (default: false)
+    /// - `HEALTH_CHECK_INTERVAL_SECS`: Seconds between health checks (default: 10)
+    /// - `METRICS_ADDRESS`: `host:port` serving OpenMetrics at `/metrics` over plain HTTP (default: unset, disabled)
+    /// - `STATSD_ADDRESS`: `host:port` of a StatsD server metrics are pushed to over UDP with DogStatsD tags (default: unset, disabled)
+    /// - `STATSD_INTERVAL_SECS`: Seconds between pushes to StatsD (default: 10)
+    /// - `STATSD_PREFIX`: Prefix of the metric names pushed to StatsD, e.g. `edge.` (default: unset)
+    /// - `SUPERVISE_STUNNEL`: Restart stunnel, with exponential backoff, when it dies without being stopped (default: false)
+    /// - `SUPERVISOR_INTERVAL_SECS`: Seconds between checks of a supervised stunnel (default: 2)
+    /// - `SHUTDOWN_STOP_STUNNEL`: Stop stunnel when the manager receives SIGTERM or SIGINT (default: false, left running)
+    /// - `SHUTDOWN_TIMEOUT_SECS`: Seconds to wait for in-flight RPCs, and stunnel if stopped, on shutdown (default: 20)
+    /// - `READINESS_TIMEOUT_SECS`: Seconds to wait for services to listen after a start or reload, 0 to skip the check (default: 5)
+    /// - `START_RETRY_ATTEMPTS`: Attempts to start stunnel before StartStunnel fails, 1 to never retry (default: 3)
+    /// - `START_RETRY_BACKOFF_MS`: Milliseconds before retrying a failed start, doubled after each failure up to 30s (default: 500)
+    /// - `REPAIR_PID_FILE`: Rewrite a missing or stale PID file when a running stunnel is found by its config path (default: false)
+    /// - `PROCESS_BACKEND`: `direct` to spawn stunnel, `systemd` to drive it through a unit with systemctl (default: direct)
+    /// - `STUNNEL_SYSTEMD_UNIT`: Unit running stunnel for the systemd backend and GetJournalLogs (default: stunnel.service)
+    /// - `CONFIG_PATH_ALLOWLIST`: Comma-separated absolute files and directories clients may pass as `config_path` (default: unset, only the managed config)
+    /// - `IDEMPOTENCY_TTL_SECS`: Seconds responses to mutating RPCs sent with `idempotency-key` metadata are replayed to retries (default: 86400)
+    /// - `MAINTENANCE_MODE`: Start in maintenance mode, refusing mutating RPCs until an admin disables it (default: false)
+    /// - `LOG_LEVEL`: Log level, or `tracing` filter directives such as `info,stunnel_space::server=debug` (default: "info")
+    /// - `LOG_FORMAT`: `text`, `json` or `pretty` for events written to stderr (default: text)
+    /// - `LOG_PAYLOADS`: Log each call's request and response messages, secrets masked (default: false)
+    /// - `LOG_TARGET`: `stderr`, `journald`, or `auto` for journald when run by systemd (default: auto)
+    /// - `OTEL_EXPORTER_OTLP_ENDPOINT`: OTLP/gRPC collector spans of RPCs and stunnel operations are exported to, e.g. `http://localhost:4317` (default: unset, disabled)
+    /// - `OTEL_SERVICE_NAME`: Service name of the exported spans (default: stunnel-space)
+    /// - `BACKUP_RETENTION_COUNT`: Config backups to keep, 0 for unlimited (default: 20)
+    /// - `BACKUP_RETENTION_DAYS`: Prune config backups older than this many days (default: unset)
+    /// - `HISTORY_DB_PATH`: SQLite database recording config revisions (default: unset, disabled)
+    /// - `CONNECTION_LOG_DB_PATH`: SQLite database recording the connections stunnel logs (default: unset, disabled)
+    /// - `CONNECTION_LOG_RETENTION_DAYS`: Days connections are kept in the connection history (default: 7)
+    /// - `GEOIP_COUNTRY_DB`: MaxMind GeoLite2 Country or City database for peer countries (default: unset, disabled)
+    /// - `GEOIP_ASN_DB`: MaxMind GeoLite2 ASN database for peer autonomous systems (default: unset, disabled)
+    /// - `GIT_VERSIONING`: Commit config changes to a Git repository, `true` or `false` (default: false)
+    /// - `PROVIDERS_DIR`: Store each added provider in its own file in this directory (default: unset)
+    /// - `TEMPLATES_DIR`: Directory persisting provider templates (default: unset, in memory)
+    /// - `STAGING_DIR`: Directory persisting configs staged with StageConfig (default: unset, in memory)
+    /// - `INSTANCES_FILE`: JSON file persisting instances created with CreateInstance (default: unset, in memory)
+    /// - `NAMESPACES_FILE`: YAML file listing tenant namespaces (default: unset, disabled)
+    /// - `ACCEPT_PORT_RANGE`: Ports, as `start-end`, allocated to providers added without an accept port (default: unset, disabled)
+    /// - `VALIDATORS`: Config validators as `name[:blocking|advisory[:severity]]`, comma-separated (default: stunnel,parser,lint:advisory)
+    /// - `CERT_CHECK_INTERVAL_SECS`: Seconds between certificate expiry checks, 0 to disable (default: unset, disabled)
+    /// - `CERT_EXPIRY_WARNING_DAYS`: Flag certificates expiring within this many days (default: 30)
+    /// - `CERT_EXPIRY_WEBHOOK_URL`: POST a JSON warning here for each expiring certificate (default: unset)
+    /// - `CERTS_DIR`: Managed directory for generated keys and uploaded certificates (default: unset, disabled)
+    /// - `ACME_DIRECTORY_URL`: ACME directory for managed certificates; requires `CERTS_DIR` (default: unset, disabled)
+    /// - `ACME_CONTACT_EMAIL`: Contact address of the ACME account (default: unset)
+    /// - `ACME_CHALLENGE`: `http-01` or `dns-01` (default: `http-01`)
+    /// - `ACME_WEBROOT`: Webroot for HTTP-01 challenge files
+    /// - `ACME_DNS_HOOK`: Hook publishing DNS-01 TXT records
+    /// - `ACME_RENEW_DAYS`: Renew ACME certificates within this many days of expiry (default: 30)
+    /// - `ACME_CHECK_INTERVAL_SECS`: Seconds between ACME renewal checks (default: 43200)
+    /// - `KEY_PERMISSION_POLICY`: `off`, `warn` or `enforce` for group/world-readable key files (default: warn)
+    /// - `KEY_FILE_OWNER`: `user[:group]` owning keys written to `CERTS_DIR` (default: unset)
+    /// - `DNS_CHECK`: `off`, `warn` or `enforce` for provider connect hosts that do not resolve (default: off)
+    /// - `REDACT_SECRETS`: Mask passwords, PSK and key paths in returned config content (default: true)
+    /// - `CERT_WATCH`: Reload stunnel when a referenced cert, key, CA or CRL file is replaced (default: false)
+    /// - `VAULT_ADDR`: Vault server for Vault-backed certificates; requires `CERTS_DIR` and `VAULT_TOKEN` (default: unset, disabled)
+    /// - `VAULT_TOKEN`: Token authenticating to Vault
+    /// - `VAULT_NAMESPACE`: Vault Enterprise namespace (default: unset)
+    /// - `VAULT_REFRESH_INTERVAL_SECS`: Seconds between Vault refresh checks (default: 300)
+    /// - `SECRETS_KEY`: Base64 32-byte key encrypting backups, history and managed keys at rest (default: unset, disabled)
+    /// - `SECRETS_KEY_COMMAND`: Command printing the secrets key instead, e.g. a KMS decrypt (default: unset)
+    /// - `SECRETS_KEY_DIR`: Directory, ideally a tmpfs, holding decrypted managed keys (default: /run/stunnel-space/keys)
     ///
     /// # Errors
     ///
-    /// Returns `ConfigError` if any required variables are missing.
+    /// Returns `ConfigError` if any required variables are missing or any
+    /// numeric variables cannot be parsed.
     ///
     /// # Example
     ///
@@ -73,6 +343,7 @@ impl Config {
     /// ```
     pub fn from_env() -> Result<Self, ConfigError> {
         let mut missing_vars = Vec::new();
+        let mut invalid_vars = Vec::new();
 
         // Get config path - REQUIRED
         let config_path = match env::var("STUNNEL_CONF_PATH") {
@@ -104,12 +375,351 @@ impl Config {
         // Get gRPC host - OPTIONAL with default (bind all interfaces)
         let grpc_host = env::var("GRPC_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
 
+        // Get gRPC TLS - OPTIONAL, certificate and key must be set together
+        let grpc_tls_cert = env::var("GRPC_TLS_CERT")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+        let grpc_tls_key = env::var("GRPC_TLS_KEY")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+        let grpc_tls_client_ca = env::var("GRPC_TLS_CLIENT_CA")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+        let grpc_tls_client_auth_optional =
+            parse_optional::<bool>("GRPC_TLS_CLIENT_AUTH_OPTIONAL", &mut invalid_vars)
+                .unwrap_or(false);
+        if grpc_tls_cert.is_some() && grpc_tls_key.is_none() {
+            missing_vars.push("GRPC_TLS_KEY".to_string());
+        }
+        // Client certificates can only be requested over TLS
+        if grpc_tls_cert.is_none() && (grpc_tls_key.is_some() || grpc_tls_client_ca.is_some()) {
+            missing_vars.push("GRPC_TLS_CERT".to_string());
+        }
+
+        // Get API authentication - OPTIONAL, unset leaves the API open
+        let auth_token = env::var("STUNNEL_MGR_AUTH_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        let jwks_url = env::var("STUNNEL_MGR_JWKS_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+        let jwt_issuer = env::var("STUNNEL_MGR_JWT_ISSUER")
+            .ok()
+            .filter(|issuer| !issuer.trim().is_empty());
+        let jwt_audience = env::var("STUNNEL_MGR_JWT_AUDIENCE")
+            .ok()
+            .filter(|audience| !audience.trim().is_empty());
+        if jwks_url.is_some() {
+            // Without both, tokens the IdP issues for other applications pass
+            if jwt_issuer.is_none() {
+                missing_vars.push("STUNNEL_MGR_JWT_ISSUER".to_string());
+            }
+            if jwt_audience.is_none() {
+                missing_vars.push("STUNNEL_MGR_JWT_AUDIENCE".to_string());
+            }
+        }
+        let api_keys_file = env::var("STUNNEL_MGR_API_KEYS_FILE")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+
+        // Get mutation rate limits - OPTIONAL, 0 or unset is unlimited
+        let rate_limit_per_client =
+            parse_optional::<u32>("RATE_LIMIT_PER_CLIENT", &mut invalid_vars)
+                .filter(|limit| *limit > 0);
+        let rate_limit_global = parse_optional::<u32>("RATE_LIMIT_GLOBAL", &mut invalid_vars)
+            .filter(|limit| *limit > 0);
+
+        // Get health checks - OPTIONAL, only the config path is checked by default
+        let health_check_stunnel =
+            parse_optional::<bool>("HEALTH_CHECK_STUNNEL", &mut invalid_vars).unwrap_or(false);
+        let health_check_interval_secs =
+            parse_optional::<u64>("HEALTH_CHECK_INTERVAL_SECS", &mut invalid_vars)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL_SECS);
+
+        // Get metrics endpoint - OPTIONAL, unset disables it
+        let metrics_address = parse_optional::<SocketAddr>("METRICS_ADDRESS", &mut invalid_vars);
+
+        // Get StatsD push - OPTIONAL, unset disables it
+        let statsd_address = env::var("STATSD_ADDRESS")
+            .ok()
+            .filter(|address| !address.is_empty());
+        let statsd_interval_secs = parse_optional::<u64>("STATSD_INTERVAL_SECS", &mut invalid_vars)
+            .filter(|secs| *secs > 0)
+            .unwrap_or(statsd::DEFAULT_INTERVAL.as_secs());
+        let statsd_prefix = env::var("STATSD_PREFIX")
+            .ok()
+            .filter(|prefix| !prefix.is_empty());
+
+        // Get crash supervision - OPTIONAL, disabled by default
+        let supervise_stunnel =
+            parse_optional::<bool>("SUPERVISE_STUNNEL", &mut invalid_vars).unwrap_or(false);
+        let supervisor_interval_secs =
+            parse_optional::<u64>("SUPERVISOR_INTERVAL_SECS", &mut invalid_vars)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(supervisor::DEFAULT_INTERVAL.as_secs());
+
+        // Get shutdown behaviour - OPTIONAL, stunnel is left running by default
+        let shutdown_stop_stunnel =
+            parse_optional::<bool>("SHUTDOWN_STOP_STUNNEL", &mut invalid_vars).unwrap_or(false);
+        let shutdown_timeout_secs =
+            parse_optional::<u64>("SHUTDOWN_TIMEOUT_SECS", &mut invalid_vars)
+                .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+
+        // Get readiness checks - OPTIONAL with default, 0 disables them
+        let readiness_timeout_secs =
+            parse_optional::<u64>("READINESS_TIMEOUT_SECS", &mut invalid_vars)
+                .unwrap_or(readiness::DEFAULT_TIMEOUT.as_secs());
+
+        // Get start retries - OPTIONAL with defaults, at least one attempt is made
+        let start_retry_attempts = parse_optional::<u32>("START_RETRY_ATTEMPTS", &mut invalid_vars)
+            .filter(|attempts| *attempts > 0)
+            .unwrap_or(retry::DEFAULT_ATTEMPTS);
+        let start_retry_backoff_ms =
+            parse_optional::<u64>("START_RETRY_BACKOFF_MS", &mut invalid_vars)
+                .unwrap_or(retry::DEFAULT_INITIAL_BACKOFF.as_millis() as u64);
+
+        // Get PID file repair - OPTIONAL, a discovered PID is only used by default
+        let repair_pid_file =
+            parse_optional::<bool>("REPAIR_PID_FILE", &mut invalid_vars).unwrap_or(false);
+
+        // Get process backend - OPTIONAL, stunnel is spawned directly by default
+        let process_backend =
+            parse_optional::<BackendKind>("PROCESS_BACKEND", &mut invalid_vars).unwrap_or_default();
+        let systemd_unit =
+            env::var("STUNNEL_SYSTEMD_UNIT").unwrap_or_else(|_| "stunnel.service".to_string());
+
+        // Get config path overrides - OPTIONAL, disabled by default
+        let config_path_allowlist: Vec<String> = env::var("CONFIG_PATH_ALLOWLIST")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect();
+        if config_path_allowlist
+            .iter()
+            .any(|path| !Path::new(path).is_absolute())
+        {
+            invalid_vars.push("CONFIG_PATH_ALLOWLIST".to_string());
+        }
+
+        // Get idempotency key retention - OPTIONAL with default
+        let idempotency_ttl_secs = parse_optional::<u64>("IDEMPOTENCY_TTL_SECS", &mut invalid_vars)
+            .filter(|secs| *secs > 0)
+            .unwrap_or(idempotency::DEFAULT_TTL.as_secs());
+
+        // Get maintenance mode - OPTIONAL, disabled by default
+        let maintenance_mode =
+            parse_optional::<bool>("MAINTENANCE_MODE", &mut invalid_vars).unwrap_or(false);
+
         // Get log level - OPTIONAL with default
         let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
+        if logging::parse_level(&log_level).is_err() {
+            invalid_vars.push("LOG_LEVEL".to_string());
+        }
+
+        // Get log format - OPTIONAL, one line of text per event by default
+        let log_format =
+            parse_optional::<LogFormat>("LOG_FORMAT", &mut invalid_vars).unwrap_or_default();
+
+        // Get payload logging - OPTIONAL, disabled by default
+        let log_payloads =
+            parse_optional::<bool>("LOG_PAYLOADS", &mut invalid_vars).unwrap_or(false);
+
+        // Get log target - OPTIONAL, journald when systemd connects stderr to the journal
+        let log_target =
+            parse_optional::<LogTarget>("LOG_TARGET", &mut invalid_vars).unwrap_or_default();
+
+        // Get span export - OPTIONAL, unset disables it
+        let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty());
+        let otel_service_name = env::var("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| telemetry::DEFAULT_SERVICE_NAME.to_string());
+
+        // Get backup retention - OPTIONAL, 0 disables the count limit
+        let backup_retention_count =
+            match parse_optional::<usize>("BACKUP_RETENTION_COUNT", &mut invalid_vars) {
+                Some(0) => None,
+                Some(count) => Some(count),
+                None => Some(DEFAULT_RETENTION_COUNT),
+            };
+        let backup_retention_days =
+            parse_optional::<u32>("BACKUP_RETENTION_DAYS", &mut invalid_vars)
+                .filter(|days| *days > 0);
+
+        // Get history database path - OPTIONAL, unset disables history
+        let history_db_path = env::var("HISTORY_DB_PATH")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+
+        // Get connection history database path - OPTIONAL, unset disables it
+        let connection_log_db_path = env::var("CONNECTION_LOG_DB_PATH")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+
+        // Get connection history retention - OPTIONAL, defaults to a week
+        let connection_log_retention_days =
+            parse_optional::<u32>("CONNECTION_LOG_RETENTION_DAYS", &mut invalid_vars)
+                .filter(|days| *days > 0)
+                .unwrap_or(DEFAULT_RETENTION_DAYS);
+
+        // Get GeoIP database paths - OPTIONAL, unset disables each lookup
+        let geoip_country_db = env::var("GEOIP_COUNTRY_DB")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+        let geoip_asn_db = env::var("GEOIP_ASN_DB")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+
+        // Get Git versioning - OPTIONAL, disabled by default
+        let git_versioning =
+            parse_optional::<bool>("GIT_VERSIONING", &mut invalid_vars).unwrap_or(false);
+
+        // Get providers directory - OPTIONAL, unset keeps providers in the main config
+        let providers_dir = env::var("PROVIDERS_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty());
+
+        // Get templates directory - OPTIONAL, unset keeps templates in memory
+        let templates_dir = env::var("TEMPLATES_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty());
+
+        // Get staging directory - OPTIONAL, unset keeps staged configs in memory
+        let staging_dir = env::var("STAGING_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty());
+
+        // Get instances file - OPTIONAL, unset keeps created instances in memory
+        let instances_file = env::var("INSTANCES_FILE")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
 
-        // If any required variables are missing, return error
-        if !missing_vars.is_empty() {
-            return Err(ConfigError { missing_vars });
+        // Get namespaces file - OPTIONAL, unset disables tenant namespaces
+        let namespaces_file = env::var("NAMESPACES_FILE")
+            .ok()
+            .filter(|path| !path.trim().is_empty());
+
+        // Get config validators - OPTIONAL, defaults to stunnel and parser checks plus advisory lint
+        let validators = env::var("VALIDATORS")
+            .ok()
+            .filter(|spec| !spec.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_VALIDATORS.to_string());
+        if ValidationPipeline::from_spec(&validators).is_err() {
+            invalid_vars.push("VALIDATORS".to_string());
+        }
+
+        // Get accept port pool - OPTIONAL, unset requires every provider to name its port
+        let accept_port_range = parse_optional::<PortRange>("ACCEPT_PORT_RANGE", &mut invalid_vars);
+
+        // Get certificate expiry monitoring - OPTIONAL, disabled by default
+        let cert_check_interval_secs =
+            parse_optional::<u64>("CERT_CHECK_INTERVAL_SECS", &mut invalid_vars)
+                .filter(|secs| *secs > 0);
+        let cert_expiry_warning_days =
+            parse_optional::<u32>("CERT_EXPIRY_WARNING_DAYS", &mut invalid_vars)
+                .unwrap_or(DEFAULT_WARNING_DAYS);
+        let cert_expiry_webhook_url = env::var("CERT_EXPIRY_WEBHOOK_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+
+        // Get managed certificates directory - OPTIONAL, unset disables key management
+        let certs_dir = env::var("CERTS_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty());
+
+        // Get ACME certificate management - OPTIONAL, disabled by default
+        let acme_directory_url = env::var("ACME_DIRECTORY_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+        let acme_contact_email = env::var("ACME_CONTACT_EMAIL")
+            .ok()
+            .filter(|email| !email.trim().is_empty());
+        let acme_challenge = parse_optional::<ChallengeType>("ACME_CHALLENGE", &mut invalid_vars)
+            .unwrap_or(ChallengeType::Http01);
+        let acme_webroot = env::var("ACME_WEBROOT")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty());
+        let acme_dns_hook = env::var("ACME_DNS_HOOK")
+            .ok()
+            .filter(|hook| !hook.trim().is_empty());
+        let acme_renew_days = parse_optional::<u32>("ACME_RENEW_DAYS", &mut invalid_vars)
+            .unwrap_or(DEFAULT_RENEW_DAYS);
+        let acme_check_interval_secs =
+            parse_optional::<u64>("ACME_CHECK_INTERVAL_SECS", &mut invalid_vars)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(DEFAULT_ACME_CHECK_INTERVAL_SECS);
+
+        // Get key file policy - OPTIONAL, warns about readable keys by default
+        let key_permission_policy =
+            parse_optional::<KeyPermissionPolicy>("KEY_PERMISSION_POLICY", &mut invalid_vars)
+                .unwrap_or_default();
+        let key_file_owner = env::var("KEY_FILE_OWNER")
+            .ok()
+            .filter(|owner| !owner.trim().is_empty())
+            .and_then(|owner| match owner.parse::<KeyOwner>() {
+                Ok(owner) => Some(owner),
+                Err(_) => {
+                    invalid_vars.push("KEY_FILE_OWNER".to_string());
+                    None
+                }
+            });
+
+        // Get DNS check policy - OPTIONAL, connect hosts are not resolved by default
+        let dns_check =
+            parse_optional::<DnsCheckPolicy>("DNS_CHECK", &mut invalid_vars).unwrap_or_default();
+
+        // Get secret redaction - OPTIONAL, enabled by default
+        let redact_secrets =
+            parse_optional::<bool>("REDACT_SECRETS", &mut invalid_vars).unwrap_or(true);
+
+        // Get certificate file watching - OPTIONAL, disabled by default
+        let cert_watch = parse_optional::<bool>("CERT_WATCH", &mut invalid_vars).unwrap_or(false);
+
+        // Get Vault certificate backend - OPTIONAL, disabled by default
+        let vault_addr = env::var("VAULT_ADDR")
+            .ok()
+            .filter(|addr| !addr.trim().is_empty());
+        let vault_token = env::var("VAULT_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        let vault_namespace = env::var("VAULT_NAMESPACE")
+            .ok()
+            .filter(|namespace| !namespace.trim().is_empty());
+        let vault_refresh_interval_secs =
+            parse_optional::<u64>("VAULT_REFRESH_INTERVAL_SECS", &mut invalid_vars)
+                .filter(|secs| *secs > 0)
+                .unwrap_or(DEFAULT_VAULT_REFRESH_INTERVAL_SECS);
+        if vault_addr.is_some() && vault_token.is_none() {
+            missing_vars.push("VAULT_TOKEN".to_string());
+        }
+
+        // Get encryption at rest - OPTIONAL, disabled by default
+        let secrets_key = env::var("SECRETS_KEY")
+            .ok()
+            .filter(|key| !key.trim().is_empty());
+        let secrets_key_command = env::var("SECRETS_KEY_COMMAND")
+            .ok()
+            .filter(|command| !command.trim().is_empty());
+        if secrets_key.is_some() && secrets_key_command.is_some() {
+            // Only one key source may be given
+            invalid_vars.push("SECRETS_KEY_COMMAND".to_string());
+        }
+        let secrets_key_dir = env::var("SECRETS_KEY_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_SECRETS_KEY_DIR.to_string());
+
+        // If any required variables are missing or invalid, return error
+        if !missing_vars.is_empty() || !invalid_vars.is_empty() {
+            return Err(ConfigError {
+                missing_vars,
+                invalid_vars,
+            });
         }
 
         Ok(Config {
@@ -117,7 +727,80 @@ impl Config {
             pid_file,
             grpc_host,
             grpc_port,
+            grpc_tls_cert,
+            grpc_tls_key,
+            grpc_tls_client_ca,
+            grpc_tls_client_auth_optional,
+            auth_token,
+            jwks_url,
+            jwt_issuer,
+            jwt_audience,
+            api_keys_file,
+            rate_limit_per_client,
+            rate_limit_global,
+            health_check_stunnel,
+            health_check_interval_secs,
+            metrics_address,
+            statsd_address,
+            statsd_interval_secs,
+            statsd_prefix,
+            supervise_stunnel,
+            supervisor_interval_secs,
+            shutdown_stop_stunnel,
+            shutdown_timeout_secs,
+            readiness_timeout_secs,
+            start_retry_attempts,
+            start_retry_backoff_ms,
+            repair_pid_file,
+            process_backend,
+            systemd_unit,
+            maintenance_mode,
+            config_path_allowlist,
+            idempotency_ttl_secs,
             log_level,
+            log_target,
+            log_format,
+            log_payloads,
+            otlp_endpoint,
+            otel_service_name,
+            backup_retention_count,
+            backup_retention_days,
+            history_db_path,
+            connection_log_db_path,
+            connection_log_retention_days,
+            geoip_country_db,
+            geoip_asn_db,
+            git_versioning,
+            providers_dir,
+            templates_dir,
+            staging_dir,
+            instances_file,
+            namespaces_file,
+            validators,
+            accept_port_range,
+            cert_check_interval_secs,
+            cert_expiry_warning_days,
+            cert_expiry_webhook_url,
+            certs_dir,
+            acme_directory_url,
+            acme_contact_email,
+            acme_challenge,
+            acme_webroot,
+            acme_dns_hook,
+            acme_renew_days,
+            acme_check_interval_secs,
+            key_permission_policy,
+            dns_check,
+            key_file_owner,
+            redact_secrets,
+            cert_watch,
+            vault_addr,
+            vault_token,
+            vault_namespace,
+            vault_refresh_interval_secs,
+            secrets_key,
+            secrets_key_command,
+            secrets_key_dir,
         })
     }
 
@@ -140,16 +823,347 @@ impl Config {
         format!("{}:{}", self.grpc_host, self.grpc_port)
     }
 
-    /// Prints the current configuration to stdout.
+    /// Returns the backup retention policy described by this configuration.
+    pub fn backup_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_count: self.backup_retention_count,
+            max_age: self
+                .backup_retention_days
+                .map(|days| chrono::Duration::days(i64::from(days))),
+        }
+    }
+
+    /// Returns how StartStunnel retries a failed start under this configuration.
+    pub fn start_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            attempts: self.start_retry_attempts,
+            initial_backoff: Duration::from_millis(self.start_retry_backoff_ms),
+        }
+    }
+
+    /// Logs the current configuration at info level, one event per setting.
     ///
     /// Useful for debugging and verifying configuration on startup.
-    pub fn print_config(&self) {
-        println!("=== Server Configuration ===");
-        println!("gRPC Host: {}", self.grpc_host);
-        println!("gRPC Port: {}", self.grpc_port);
-        println!("Config Path: {}", self.config_path);
-        println!("PID File: {}", self.pid_file);
-        println!("Log Level: {}", self.log_level);
-        println!("===========================");
+    pub fn log_config(&self) {
+        info!("Server configuration:");
+        info!("gRPC Host: {}", self.grpc_host);
+        info!("gRPC Port: {}", self.grpc_port);
+        info!(
+            "gRPC TLS: {}",
+            match (&self.grpc_tls_cert, &self.grpc_tls_client_ca) {
+                (Some(cert), Some(ca)) => format!(
+                    "enabled ({}), client certificates {} by {}",
+                    cert,
+                    if self.grpc_tls_client_auth_optional {
+                        "verified if presented"
+                    } else {
+                        "required"
+                    },
+                    ca
+                ),
+                (Some(cert), None) => format!("enabled ({})", cert),
+                _ => "disabled (plaintext)".to_string(),
+            }
+        );
+        // The shared secret is never printed
+        let mut methods = Vec::new();
+        if self.auth_token.is_some() {
+            methods.push("shared token".to_string());
+        }
+        if let Some(path) = &self.api_keys_file {
+            methods.push(format!("API keys ({})", path));
+        }
+        if let Some(url) = &self.jwks_url {
+            methods.push(format!("JWT (JWKS {})", url));
+        }
+        info!(
+            "API Authentication: {}",
+            if methods.is_empty() {
+                "disabled".to_string()
+            } else {
+                methods.join(", ")
+            }
+        );
+        let per_minute = |limit: Option<u32>| {
+            limit
+                .map(|limit| format!("{}/min", limit))
+                .unwrap_or_else(|| "unlimited".to_string())
+        };
+        info!(
+            "Mutation Rate Limits: {} per client, {} overall",
+            per_minute(self.rate_limit_per_client),
+            per_minute(self.rate_limit_global)
+        );
+        info!(
+            "Health Checks: config path{}, every {}s",
+            if self.health_check_stunnel {
+                " and stunnel process"
+            } else {
+                ""
+            },
+            self.health_check_interval_secs
+        );
+        info!(
+            "Metrics Endpoint: {}",
+            self.metrics_address
+                .map(|addr| format!("http://{}/metrics", addr))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        match &self.statsd_address {
+            Some(address) => info!(
+                "StatsD Push: {} every {}s{}",
+                address,
+                self.statsd_interval_secs,
+                self.statsd_prefix
+                    .as_ref()
+                    .map(|prefix| format!(", prefix {}", prefix))
+                    .unwrap_or_default()
+            ),
+            None => info!("StatsD Push: disabled"),
+        }
+        if self.supervise_stunnel {
+            info!(
+                "Crash Supervision: every {}s",
+                self.supervisor_interval_secs
+            );
+        } else {
+            info!("Crash Supervision: disabled");
+        }
+        info!(
+            "Shutdown: {} stunnel, {}s timeout",
+            if self.shutdown_stop_stunnel {
+                "stop"
+            } else {
+                "leave running"
+            },
+            self.shutdown_timeout_secs
+        );
+        if self.readiness_timeout_secs == 0 {
+            info!("Readiness Checks: disabled");
+        } else {
+            info!("Readiness Checks: {}s timeout", self.readiness_timeout_secs);
+        }
+        if self.start_retry_attempts > 1 {
+            info!(
+                "Start Retries: {} attempts, {}ms initial backoff",
+                self.start_retry_attempts, self.start_retry_backoff_ms
+            );
+        } else {
+            info!("Start Retries: disabled");
+        }
+        info!(
+            "Config Path Overrides: {}",
+            if self.config_path_allowlist.is_empty() {
+                "disabled".to_string()
+            } else {
+                self.config_path_allowlist.join(", ")
+            }
+        );
+        info!("Idempotency Keys: kept {}s", self.idempotency_ttl_secs);
+        info!(
+            "Maintenance Mode: {}",
+            if self.maintenance_mode {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        info!("Config Path: {}", self.config_path);
+        info!("PID File: {}", self.pid_file);
+        info!(
+            "PID File Repair: {}",
+            if self.repair_pid_file {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        match self.process_backend {
+            BackendKind::Direct => info!("Process Backend: direct"),
+            BackendKind::Systemd => info!("Process Backend: systemd ({})", self.systemd_unit),
+        }
+        info!("Log Level: {}", self.log_level);
+        info!("Log Target: {}", self.log_target);
+        info!("Log Format: {}", self.log_format);
+        info!(
+            "Payload Logging: {}",
+            if self.log_payloads {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        match &self.otlp_endpoint {
+            Some(endpoint) => info!(
+                "Span Export: OTLP to {} as {}",
+                endpoint, self.otel_service_name
+            ),
+            None => info!("Span Export: disabled"),
+        }
+        info!(
+            "Backup Retention: {} backups, {}",
+            self.backup_retention_count
+                .map(|count| count.to_string())
+                .unwrap_or_else(|| "unlimited".to_string()),
+            self.backup_retention_days
+                .map(|days| format!("{} days", days))
+                .unwrap_or_else(|| "no age limit".to_string())
+        );
+        info!(
+            "History Database: {}",
+            self.history_db_path.as_deref().unwrap_or("disabled")
+        );
+        info!(
+            "Connection History: {}",
+            self.connection_log_db_path
+                .as_deref()
+                .map(|path| format!("{} ({} days)", path, self.connection_log_retention_days))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        info!(
+            "GeoIP Country Database: {}",
+            self.geoip_country_db.as_deref().unwrap_or("disabled")
+        );
+        info!(
+            "GeoIP ASN Database: {}",
+            self.geoip_asn_db.as_deref().unwrap_or("disabled")
+        );
+        info!(
+            "Git Versioning: {}",
+            if self.git_versioning {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        info!(
+            "Providers Directory: {}",
+            self.providers_dir.as_deref().unwrap_or("disabled")
+        );
+        info!(
+            "Templates Directory: {}",
+            self.templates_dir.as_deref().unwrap_or("in memory")
+        );
+        info!(
+            "Staging Directory: {}",
+            self.staging_dir.as_deref().unwrap_or("in memory")
+        );
+        info!(
+            "Instances File: {}",
+            self.instances_file.as_deref().unwrap_or("in memory")
+        );
+        info!(
+            "Namespaces File: {}",
+            self.namespaces_file.as_deref().unwrap_or("not set")
+        );
+        info!("Validators: {}", self.validators);
+        info!(
+            "Accept Port Range: {}",
+            self.accept_port_range
+                .map(|range| range.to_string())
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        info!(
+            "Certificate Expiry Checks: {}",
+            self.cert_check_interval_secs
+                .map(|secs| format!(
+                    "every {}s, warning at {} days",
+                    secs, self.cert_expiry_warning_days
+                ))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        info!(
+            "Certificate Expiry Webhook: {}",
+            self.cert_expiry_webhook_url
+                .as_deref()
+                .unwrap_or("disabled")
+        );
+        info!(
+            "Certificates Directory: {}",
+            self.certs_dir.as_deref().unwrap_or("disabled")
+        );
+        info!(
+            "ACME: {}",
+            self.acme_directory_url
+                .as_deref()
+                .map(|url| format!(
+                    "{} ({}, renew at {} days, checked every {}s)",
+                    url,
+                    self.acme_challenge.as_str(),
+                    self.acme_renew_days,
+                    self.acme_check_interval_secs
+                ))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        info!(
+            "Key Permission Policy: {:?}{}",
+            self.key_permission_policy,
+            self.key_file_owner
+                .map(|owner| format!(
+                    ", keys owned by uid {}{}",
+                    owner.uid,
+                    owner
+                        .gid
+                        .map(|gid| format!(" gid {}", gid))
+                        .unwrap_or_default()
+                ))
+                .unwrap_or_default()
+        );
+        info!("DNS Check: {}", self.dns_check);
+        info!(
+            "Secret Redaction: {}",
+            if self.redact_secrets {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        info!(
+            "Certificate File Watching: {}",
+            if self.cert_watch {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        // The token is never printed
+        info!(
+            "Vault: {}",
+            self.vault_addr
+                .as_deref()
+                .map(|addr| format!(
+                    "{} (refreshed every {}s)",
+                    addr, self.vault_refresh_interval_secs
+                ))
+                .unwrap_or_else(|| "disabled".to_string())
+        );
+        // The key itself is never printed
+        info!(
+            "Encryption at Rest: {}",
+            if self.secrets_key.is_some() {
+                format!("enabled (keys decrypted to {})", self.secrets_key_dir)
+            } else if self.secrets_key_command.is_some() {
+                format!(
+                    "enabled, key from command (keys decrypted to {})",
+                    self.secrets_key_dir
+                )
+            } else {
+                "disabled".to_string()
+            }
+        );
+        info!("===========================");
+    }
+}
+
+// Parses an optional environment variable, recording it as invalid if it is
+// set but cannot be parsed.
+fn parse_optional<T: FromStr>(name: &str, invalid_vars: &mut Vec<String>) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            invalid_vars.push(name.to_string());
+            None
+        }
     }
 }
@@ -0,0 +1,377 @@
+//! Connection history stored in SQLite.
+//!
+//! When enabled, every connection the traffic follower sees stunnel accept
+//! is recorded with its service, peer address and open time, and completed
+//! with its close time and byte counts once stunnel logs that it closed.
+//! Connections opened longer ago than the retention period are pruned as
+//! new ones are recorded, so the database stays bounded without log
+//! shipping. Times are kept as Unix seconds, the resolution of stunnel's
+//! log.
+//!
+//! Besides listing connections, the store ranks the addresses they came
+//! from per service ([`ConnectionLog::top_talkers`]), so a client flooding
+//! a service stands out without exporting the history. With a [`GeoIp`]
+//! set, each connection also records the country and autonomous system of
+//! its peer as they were when it was opened.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, Row};
+
+use crate::geoip::GeoIp;
+use crate::traffic::ConnectionEvent;
+
+/// Days connections are kept when no retention is configured.
+pub const DEFAULT_RETENTION_DAYS: u32 = 7;
+
+// Columns added after the table was introduced, with their types, added to
+// databases created before them.
+const ADDED_COLUMNS: [(&str, &str); 3] =
+    [("country", "TEXT"), ("asn", "INTEGER"), ("as_org", "TEXT")];
+
+// Conditions selecting the connections a [`ConnectionQuery`] matches, bound
+// to its instance, service, since and until as ?1 to ?4.
+const MATCHING: &str = "instance = ?1
+    AND (?2 IS NULL OR service = ?2)
+    AND (?3 IS NULL OR closed_at IS NULL OR closed_at >= ?3)
+    AND (?4 IS NULL OR opened_at <= ?4)";
+
+// The address of a peer without its port. stunnel logs peers as
+// `host:port`, so the port is the trailing digits and the colon before them;
+// brackets around IPv6 hosts are dropped too.
+const PEER_ADDRESS: &str = "trim(substr(rtrim(peer, '0123456789'), 1,
+    length(rtrim(peer, '0123456789')) - 1), '[]')";
+
+/// A recorded connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionRecord {
+    pub instance: String,
+    pub service: String,
+    pub peer: String,
+    pub opened_at: DateTime<Utc>,
+    /// `None` while the connection is open, or if its close was never
+    /// logged.
+    pub closed_at: Option<DateTime<Utc>>,
+    pub bytes_to_tls: u64,
+    pub bytes_to_socket: u64,
+    /// ISO country code of the peer, if GeoIP lookups were enabled.
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+/// Which connections [`ConnectionLog::query`] returns.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionQuery {
+    pub instance: String,
+    /// Only connections of this service.
+    pub service: Option<String>,
+    /// Only connections still open at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only connections opened at or before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Connections to return, or addresses per service when ranking.
+    pub limit: u32,
+}
+
+/// What [`ConnectionLog::top_talkers`] ranks addresses by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TalkerOrder {
+    #[default]
+    Connections,
+    /// Bytes forwarded either way.
+    Bytes,
+}
+
+/// The connections one address made to one service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Talker {
+    pub service: String,
+    /// Peer address without the port.
+    pub address: String,
+    pub connections: u64,
+    pub bytes_to_tls: u64,
+    pub bytes_to_socket: u64,
+    /// When the latest of the connections was opened.
+    pub last_opened_at: DateTime<Utc>,
+    /// ISO country code of the address, if GeoIP lookups were enabled.
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+/// SQLite-backed rolling store of connections.
+///
+/// The connection is guarded by a mutex so the store can be shared between
+/// the traffic follower and concurrent RPC handlers.
+#[derive(Debug)]
+pub struct ConnectionLog {
+    conn: Mutex<Connection>,
+    retention: Duration,
+    geoip: Option<Arc<GeoIp>>,
+}
+
+impl ConnectionLog {
+    /// Opens (or creates) the connection database at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or its schema
+    /// cannot be created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::connlog::ConnectionLog;
+    ///
+    /// let log = ConnectionLog::open("/var/lib/stunnel-space/connections.db")
+    ///     .expect("Failed to open connection database");
+    /// ```
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS connections (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                instance TEXT NOT NULL,
+                connection TEXT NOT NULL,
+                service TEXT NOT NULL,
+                peer TEXT NOT NULL,
+                opened_at INTEGER NOT NULL,
+                closed_at INTEGER,
+                bytes_to_tls INTEGER NOT NULL DEFAULT 0,
+                bytes_to_socket INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS connections_opened_at
+                ON connections (instance, opened_at);",
+        )?;
+        let columns = conn
+            .prepare("SELECT name FROM pragma_table_info('connections')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<HashSet<_>>>()?;
+        for (column, kind) in ADDED_COLUMNS {
+            if !columns.contains(column) {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE connections ADD COLUMN {} {};",
+                    column, kind
+                ))?;
+            }
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            retention: Duration::from_secs(u64::from(DEFAULT_RETENTION_DAYS) * 24 * 60 * 60),
+            geoip: None,
+        })
+    }
+
+    /// Records the country and autonomous system of each new connection's
+    /// peer, looked up in `geoip`.
+    pub fn with_geoip(mut self, geoip: Arc<GeoIp>) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    /// Keeps connections for `retention` after they were opened.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the connections `instance`'s stunnel opened and closed, then
+    /// prunes those past the retention period.
+    ///
+    /// A close completes the latest open connection with the same number;
+    /// one whose opening was not recorded is recorded whole.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a write fails; nothing is recorded then.
+    pub fn record(&self, instance: &str, events: &[ConnectionEvent]) -> rusqlite::Result<()> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        for event in events {
+            let location = self
+                .geoip
+                .as_ref()
+                .map(|geoip| geoip.lookup_peer(&event.peer))
+                .unwrap_or_default();
+            let Some(closed) = &event.closed else {
+                tx.execute(
+                    "INSERT INTO connections (instance, connection, service, peer, opened_at,
+                                              country, asn, as_org)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        instance,
+                        event.connection,
+                        event.service,
+                        event.peer,
+                        event.opened_at.timestamp(),
+                        location.country,
+                        location.asn,
+                        location.as_org
+                    ],
+                )?;
+                continue;
+            };
+            let completed = tx.execute(
+                "UPDATE connections SET closed_at = ?1, bytes_to_tls = ?2, bytes_to_socket = ?3
+                 WHERE id = (SELECT id FROM connections
+                             WHERE instance = ?4 AND connection = ?5 AND closed_at IS NULL
+                             ORDER BY id DESC LIMIT 1)",
+                params![
+                    closed.closed_at.timestamp(),
+                    closed.bytes_to_tls as i64,
+                    closed.bytes_to_socket as i64,
+                    instance,
+                    event.connection
+                ],
+            )?;
+            if completed == 0 {
+                tx.execute(
+                    "INSERT INTO connections (instance, connection, service, peer, opened_at,
+                                              closed_at, bytes_to_tls, bytes_to_socket,
+                                              country, asn, as_org)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        instance,
+                        event.connection,
+                        event.service,
+                        event.peer,
+                        event.opened_at.timestamp(),
+                        closed.closed_at.timestamp(),
+                        closed.bytes_to_tls as i64,
+                        closed.bytes_to_socket as i64,
+                        location.country,
+                        location.asn,
+                        location.as_org
+                    ],
+                )?;
+            }
+        }
+        let cutoff = Utc::now().timestamp() - self.retention.as_secs() as i64;
+        tx.execute(
+            "DELETE FROM connections WHERE opened_at < ?1",
+            params![cutoff],
+        )?;
+        tx.commit()
+    }
+
+    /// Returns up to `query.limit` connections matching `query`, newest
+    /// first. A connection matches a time range if it was open at any
+    /// point within it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub fn query(&self, query: &ConnectionQuery) -> rusqlite::Result<Vec<ConnectionRecord>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT instance, service, peer, opened_at, closed_at, bytes_to_tls, bytes_to_socket,
+                    country, asn, as_org
+             FROM connections WHERE {}
+             ORDER BY opened_at DESC, id DESC LIMIT ?5",
+            MATCHING
+        ))?;
+        let records = stmt
+            .query_map(
+                params![
+                    query.instance,
+                    query.service,
+                    query.since.map(|since| since.timestamp()),
+                    query.until.map(|until| until.timestamp()),
+                    query.limit
+                ],
+                record_from_row,
+            )?
+            .collect();
+        records
+    }
+
+    /// Returns, for each service, the `query.limit` addresses that made the
+    /// most connections matching `query`, or forwarded the most bytes over
+    /// them. Services are in name order, their addresses highest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub fn top_talkers(
+        &self,
+        query: &ConnectionQuery,
+        order: TalkerOrder,
+    ) -> rusqlite::Result<Vec<Talker>> {
+        let rank_by = match order {
+            TalkerOrder::Connections => "connections DESC, bytes DESC",
+            TalkerOrder::Bytes => "bytes DESC, connections DESC",
+        };
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "WITH talkers AS (
+                SELECT service, {} AS address, COUNT(*) AS connections,
+                       SUM(bytes_to_tls) AS bytes_to_tls,
+                       SUM(bytes_to_socket) AS bytes_to_socket,
+                       SUM(bytes_to_tls + bytes_to_socket) AS bytes,
+                       MAX(opened_at) AS last_opened_at,
+                       MAX(country) AS country, MAX(asn) AS asn, MAX(as_org) AS as_org
+                FROM connections WHERE {}
+                GROUP BY service, address
+             ), ranked AS (
+                SELECT *, ROW_NUMBER() OVER (
+                    PARTITION BY service ORDER BY {}, address
+                ) AS rank
+                FROM talkers
+             )
+             SELECT service, address, connections, bytes_to_tls, bytes_to_socket, last_opened_at,
+                    country, asn, as_org
+             FROM ranked WHERE rank <= ?5 ORDER BY service, rank",
+            PEER_ADDRESS, MATCHING, rank_by
+        ))?;
+        let talkers = stmt
+            .query_map(
+                params![
+                    query.instance,
+                    query.service,
+                    query.since.map(|since| since.timestamp()),
+                    query.until.map(|until| until.timestamp()),
+                    query.limit
+                ],
+                |row| {
+                    Ok(Talker {
+                        service: row.get(0)?,
+                        address: row.get(1)?,
+                        connections: row.get::<_, i64>(2)? as u64,
+                        bytes_to_tls: row.get::<_, i64>(3)? as u64,
+                        bytes_to_socket: row.get::<_, i64>(4)? as u64,
+                        last_opened_at: DateTime::from_timestamp(row.get(5)?, 0)
+                            .unwrap_or_default(),
+                        country: row.get(6)?,
+                        asn: row.get(7)?,
+                        as_org: row.get(8)?,
+                    })
+                },
+            )?
+            .collect();
+        talkers
+    }
+}
+
+fn record_from_row(row: &Row<'_>) -> rusqlite::Result<ConnectionRecord> {
+    let time = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap_or_default();
+    Ok(ConnectionRecord {
+        instance: row.get(0)?,
+        service: row.get(1)?,
+        peer: row.get(2)?,
+        opened_at: time(row.get(3)?),
+        closed_at: row.get::<_, Option<i64>>(4)?.map(time),
+        bytes_to_tls: row.get::<_, i64>(5)? as u64,
+        bytes_to_socket: row.get::<_, i64>(6)? as u64,
+        country: row.get(7)?,
+        asn: row.get(8)?,
+        as_org: row.get(9)?,
+    })
+}
@@ -0,0 +1,80 @@
+//! Comparison of stunnel configurations.
+//!
+//! This module produces unified diffs between two versions of a config and
+//! summarizes which `[service]` sections were added, removed, or modified.
+
+use similar::TextDiff;
+
+use crate::parser::StunnelConfig;
+
+/// Section-level summary of the differences between two configurations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SectionChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+    /// Whether any option before the first section changed.
+    pub globals_changed: bool,
+}
+
+impl SectionChanges {
+    /// Returns `true` if nothing changed at the option level.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && !self.globals_changed
+    }
+}
+
+/// Renders a unified diff (3 lines of context) from `old` to `new`.
+///
+/// Returns an empty string if the contents are identical.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::diff::unified_diff;
+///
+/// let diff = unified_diff("debug = 5\n", "debug = 7\n", "current", "proposed");
+/// assert!(diff.contains("-debug = 5"));
+/// assert!(diff.contains("+debug = 7"));
+/// ```
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+/// Compares the parsed sections of two configurations.
+///
+/// Sections are matched by name; a section counts as modified when its
+/// options differ in value or order. Comments and blank lines are ignored.
+pub fn section_changes(old: &StunnelConfig, new: &StunnelConfig) -> SectionChanges {
+    let mut changes = SectionChanges {
+        globals_changed: old.globals != new.globals,
+        ..Default::default()
+    };
+
+    for section in &new.sections {
+        match old.section(&section.name) {
+            None => changes.added.push(section.name.clone()),
+            Some(previous) if previous.options != section.options => {
+                changes.modified.push(section.name.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    for section in &old.sections {
+        if new.section(&section.name).is_none() {
+            changes.removed.push(section.name.clone());
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,94 @@
+//! DNS checks of provider connect hosts.
+//!
+//! A typo in a provider's `connect_host` only shows up once stunnel starts
+//! logging connect failures at runtime. With `DNS_CHECK` set to `warn` or
+//! `enforce`, the connect hosts of added and updated providers are resolved
+//! first, and a host that does not resolve is logged or refused.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::net::lookup_host;
+
+/// How long a single host may take to resolve.
+pub const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happens when a provider's connect host does not resolve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DnsCheckPolicy {
+    /// Connect hosts are not resolved.
+    #[default]
+    Off,
+    /// Hosts that do not resolve are logged but the change is applied.
+    Warn,
+    /// Changes with hosts that do not resolve are refused.
+    Enforce,
+}
+
+impl fmt::Display for DnsCheckPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DnsCheckPolicy::Off => "off",
+            DnsCheckPolicy::Warn => "warn",
+            DnsCheckPolicy::Enforce => "enforce",
+        })
+    }
+}
+
+impl FromStr for DnsCheckPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(DnsCheckPolicy::Off),
+            "warn" => Ok(DnsCheckPolicy::Warn),
+            "enforce" => Ok(DnsCheckPolicy::Enforce),
+            other => Err(format!("Unknown DNS check policy: {}", other)),
+        }
+    }
+}
+
+/// Resolves `host`, an IP address (optionally bracketed) or hostname.
+///
+/// IP addresses are accepted without a lookup.
+///
+/// # Errors
+///
+/// Returns why the host does not resolve, including when the lookup takes
+/// longer than [`RESOLVE_TIMEOUT`].
+///
+/// # Example
+///
+/// ```no_run
+/// use stunnel_space::dns::resolve;
+///
+/// # async fn example() {
+/// if let Err(e) = resolve("db.internal.example.com").await {
+///     eprintln!("{}", e);
+/// }
+/// # }
+/// ```
+pub async fn resolve(host: &str) -> Result<(), String> {
+    let host = host.trim();
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if unbracketed.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    match tokio::time::timeout(RESOLVE_TIMEOUT, lookup_host((host, 0))).await {
+        Err(_) => Err(format!(
+            "{} did not resolve within {} seconds",
+            host,
+            RESOLVE_TIMEOUT.as_secs()
+        )),
+        Ok(Err(e)) => Err(format!("{} does not resolve: {}", host, e)),
+        Ok(Ok(mut addresses)) => match addresses.next() {
+            Some(_) => Ok(()),
+            None => Err(format!("{} resolves to no addresses", host)),
+        },
+    }
+}
@@ -0,0 +1,71 @@
+//! Connection draining before a provider is removed.
+//!
+//! Reloading stunnel without a service closes its listening socket but
+//! leaves the connections it already accepted open until their peers hang
+//! up. Waiting for those to finish, rather than reporting the removal as
+//! done straight away, lets callers know when it is safe to take the
+//! backend down.
+
+use std::time::{Duration, Instant};
+
+use crate::utils::established_connections;
+
+/// How long to wait for connections to close when no timeout is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Delay between counts of the open connections.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connections still open on a drained port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainStatus {
+    pub open_connections: usize,
+    /// Time since draining started.
+    pub elapsed: Duration,
+}
+
+impl DrainStatus {
+    /// Whether every connection has closed.
+    pub fn drained(&self) -> bool {
+        self.open_connections == 0
+    }
+}
+
+/// Waits until no established connection remains on local `port` or
+/// `timeout` passes, calling `progress` with each count along the way, and
+/// returns the last count.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use stunnel_space::drain::drain;
+///
+/// # async fn example() {
+/// let status = drain(8443, Duration::from_secs(60), |status| {
+///     println!("{} connections open", status.open_connections);
+/// })
+/// .await;
+/// if !status.drained() {
+///     eprintln!("{} connections still open", status.open_connections);
+/// }
+/// # }
+/// ```
+pub async fn drain(
+    port: u16,
+    timeout: Duration,
+    mut progress: impl FnMut(&DrainStatus),
+) -> DrainStatus {
+    let started = Instant::now();
+    loop {
+        let status = DrainStatus {
+            open_connections: established_connections(port),
+            elapsed: started.elapsed(),
+        };
+        progress(&status);
+        if status.drained() || status.elapsed >= timeout {
+            return status;
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(timeout - status.elapsed)).await;
+    }
+}
@@ -0,0 +1,172 @@
+//! Certificate expiry monitoring.
+//!
+//! Every `cert` and `CAfile` referenced by the config is inspected and its
+//! days until expiry computed. For chain and CA bundle files the earliest
+//! expiring certificate counts. Files expiring within the warning threshold
+//! are logged and, if a webhook is configured, posted to it as JSON.
+
+use chrono::{DateTime, Utc};
+use std::sync::RwLock;
+
+use crate::certs;
+use crate::parser::StunnelConfig;
+
+/// Default number of days before expiry at which certificates are flagged.
+pub const DEFAULT_WARNING_DAYS: u32 = 30;
+
+/// Expiry state of one referenced certificate file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateStatus {
+    pub path: String,
+    /// Providers that set this file themselves.
+    pub providers: Vec<String>,
+    /// Whether the global section references this file.
+    pub global: bool,
+    /// Subject of the earliest expiring certificate in the file.
+    pub subject: String,
+    pub not_after: Option<DateTime<Utc>>,
+    /// Whole days until `not_after`; negative once expired.
+    pub days_until_expiry: i64,
+    /// Why the file could not be inspected, if it could not.
+    pub error: Option<String>,
+}
+
+impl CertificateStatus {
+    /// Whether the certificate expires within `warning_days` (or already has).
+    pub fn is_expiring(&self, warning_days: u32) -> bool {
+        self.error.is_none() && self.days_until_expiry < i64::from(warning_days)
+    }
+}
+
+/// Result of one expiry check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiryReport {
+    pub checked_at: DateTime<Utc>,
+    pub certificates: Vec<CertificateStatus>,
+}
+
+/// Checks certificate expiry and keeps the latest report.
+#[derive(Debug)]
+pub struct ExpiryMonitor {
+    warning_days: u32,
+    webhook_url: Option<String>,
+    latest: RwLock<Option<ExpiryReport>>,
+}
+
+impl Default for ExpiryMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_WARNING_DAYS)
+    }
+}
+
+impl ExpiryMonitor {
+    /// Creates a monitor flagging certificates within `warning_days` of expiry.
+    pub fn new(warning_days: u32) -> Self {
+        Self {
+            warning_days,
+            webhook_url: None,
+            latest: RwLock::new(None),
+        }
+    }
+
+    /// Posts a JSON warning to `url` for every expiring certificate.
+    pub fn with_webhook(mut self, url: String) -> Self {
+        self.webhook_url = Some(url);
+        self
+    }
+
+    /// Returns the warning threshold in days.
+    pub fn warning_days(&self) -> u32 {
+        self.warning_days
+    }
+
+    /// Inspects every certificate file `config` references and stores the
+    /// result as the latest report.
+    pub fn check(&self, config: &StunnelConfig) -> ExpiryReport {
+        let report = ExpiryReport {
+            checked_at: Utc::now(),
+            certificates: check_config(config, Utc::now()),
+        };
+        *self.latest.write().unwrap_or_else(|e| e.into_inner()) = Some(report.clone());
+        report
+    }
+
+    /// Returns the latest report, if a check has run.
+    pub fn latest(&self) -> Option<ExpiryReport> {
+        self.latest
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Logs a warning for each expiring certificate in `report` and posts it
+    /// to the webhook, if one is configured. Webhook failures are only logged.
+    pub async fn notify(&self, report: &ExpiryReport) {
+        for status in report
+            .certificates
+            .iter()
+            .filter(|status| status.is_expiring(self.warning_days))
+        {
+            tracing::warn!(
+                "Certificate {} ({}) expires in {} days",
+                status.path,
+                status.subject,
+                status.days_until_expiry
+            );
+            if let Some(url) = &self.webhook_url {
+                if let Err(e) = post_warning(url, status).await {
+                    tracing::error!("Failed to post certificate warning to {}: {}", url, e);
+                }
+            }
+        }
+    }
+}
+
+/// Computes the expiry status of every certificate file `config` references,
+/// sorted by path.
+pub fn check_config(config: &StunnelConfig, now: DateTime<Utc>) -> Vec<CertificateStatus> {
+    certs::referenced_certificates(config)
+        .into_iter()
+        .map(|(path, references)| {
+            let mut status = CertificateStatus {
+                path,
+                providers: references.providers,
+                global: references.global,
+                subject: String::new(),
+                not_after: None,
+                days_until_expiry: 0,
+                error: None,
+            };
+            match certs::read_certificates(&status.path) {
+                Ok(certificates) => {
+                    if let Some(earliest) = certificates.iter().min_by_key(|cert| cert.not_after) {
+                        status.subject = earliest.subject.clone();
+                        status.not_after = Some(earliest.not_after);
+                        status.days_until_expiry = (earliest.not_after - now).num_days();
+                    }
+                }
+                Err(e) => status.error = Some(e),
+            }
+            status
+        })
+        .collect()
+}
+
+async fn post_warning(url: &str, status: &CertificateStatus) -> Result<(), reqwest::Error> {
+    let payload = serde_json::json!({
+        "event": "certificate_expiring",
+        "path": status.path,
+        "subject": status.subject,
+        "not_after": status.not_after.map(|not_after| not_after.to_rfc3339()),
+        "days_until_expiry": status.days_until_expiry,
+        "providers": status.providers,
+        "global": status.global,
+    });
+    reqwest::Client::new()
+        .post(url)
+        .json(&payload)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
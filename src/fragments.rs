@@ -0,0 +1,167 @@
+//! conf.d-style provider layout.
+//!
+//! In this mode each provider lives in its own `<name>.conf` file under a
+//! providers directory, and the main config pulls them in with stunnel's
+//! `include` option. Adding or removing a provider then creates or deletes a
+//! single file instead of rewriting the monolithic config.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::parser::StunnelConfig;
+
+/// Extension of provider fragment files.
+pub const FRAGMENT_EXTENSION: &str = "conf";
+
+/// A directory holding one config fragment per provider.
+#[derive(Debug, Clone)]
+pub struct FragmentDir {
+    dir: PathBuf,
+}
+
+impl FragmentDir {
+    /// Uses `dir` for provider fragments, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::fragments::FragmentDir;
+    ///
+    /// let fragments = FragmentDir::open("/etc/stunnel/conf.d")
+    ///     .expect("Failed to open providers directory");
+    /// ```
+    pub fn open(dir: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: PathBuf::from(dir),
+        })
+    }
+
+    /// Returns the providers directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the fragment file for provider `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` cannot be used as a file name.
+    pub fn fragment_path(&self, name: &str) -> io::Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Provider name {:?} cannot be used as a file name", name),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, FRAGMENT_EXTENSION)))
+    }
+
+    /// Returns whether a fragment exists for provider `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.fragment_path(name)
+            .map(|path| path.is_file())
+            .unwrap_or(false)
+    }
+
+    /// Returns the concatenated content of every fragment, in file name order.
+    ///
+    /// This mirrors the order in which stunnel reads an included directory.
+    /// Hidden files, such as fragments still being written, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a fragment cannot be read.
+    pub fn read_all(&self) -> io::Result<String> {
+        let mut content = String::new();
+        for (_, fragment) in self.read_each()? {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(&fragment);
+        }
+        Ok(content)
+    }
+
+    /// Returns the path and content of every fragment, in the order
+    /// [`FragmentDir::read_all`] concatenates them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a fragment cannot be read.
+    pub fn read_each(&self) -> io::Result<Vec<(PathBuf, String)>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && !path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with('.'))
+            })
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| fs::read_to_string(&path).map(|content| (path, content)))
+            .collect()
+    }
+
+    /// Writes a new fragment for provider `name` and returns its path.
+    ///
+    /// The fragment is written to a temporary file and renamed into place so
+    /// stunnel never reads a partial file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AlreadyExists` if the provider already has a
+    /// fragment, or any I/O error from writing it.
+    pub fn create(&self, name: &str, content: &str) -> io::Result<PathBuf> {
+        let path = self.fragment_path(name)?;
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+
+        // Hidden temp name so a partially written fragment is never read as a provider
+        let tmp_path = self
+            .dir
+            .join(format!(".{}.tmp.{}", name, std::process::id()));
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok(path)
+    }
+
+    /// Deletes the fragment for provider `name`, returning its former content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if the provider has no fragment.
+    pub fn remove(&self, name: &str) -> io::Result<String> {
+        let path = self.fragment_path(name)?;
+        let content = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+        Ok(content)
+    }
+
+    /// Returns whether `main_config` includes this directory.
+    pub fn is_included_by(&self, main_config: &str) -> bool {
+        StunnelConfig::parse(main_config)
+            .globals
+            .iter()
+            .any(|option| {
+                option.key.eq_ignore_ascii_case("include") && Path::new(&option.value) == self.dir
+            })
+    }
+}
@@ -0,0 +1,134 @@
+//! Country and autonomous system of remote peers.
+//!
+//! Peers are looked up in MaxMind GeoLite2 (or GeoIP2) databases: a Country
+//! or City database for the country, and an ASN database for the network
+//! it belongs to. Either may be configured alone. The databases are read
+//! into memory once at startup; MaxMind publishes updates weekly, so
+//! restart the manager after replacing them.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use maxminddb::{geoip2, Reader};
+
+/// Where a peer address is, as far as the databases know.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 country code, e.g. `DE`.
+    pub country: Option<String>,
+    /// Autonomous system number.
+    pub asn: Option<u32>,
+    /// Organization operating the autonomous system.
+    pub as_org: Option<String>,
+}
+
+/// MaxMind databases to look peers up in.
+pub struct GeoIp {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+// The readers hold whole databases, so only their types are shown.
+impl fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let database_type = |reader: &Option<Reader<Vec<u8>>>| {
+            reader
+                .as_ref()
+                .map(|reader| reader.metadata.database_type.clone())
+        };
+        f.debug_struct("GeoIp")
+            .field("country", &database_type(&self.country))
+            .field("asn", &database_type(&self.asn))
+            .finish()
+    }
+}
+
+impl GeoIp {
+    /// Opens the country database at `country_db` and the ASN database at
+    /// `asn_db`, skipping those that are `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database cannot be read or is not in the
+    /// MaxMind DB format.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::geoip::GeoIp;
+    ///
+    /// let geoip = GeoIp::open(
+    ///     Some("/usr/share/GeoIP/GeoLite2-Country.mmdb"),
+    ///     Some("/usr/share/GeoIP/GeoLite2-ASN.mmdb"),
+    /// )
+    /// .expect("Failed to open GeoIP databases");
+    /// ```
+    pub fn open(country_db: Option<&str>, asn_db: Option<&str>) -> Result<Self, String> {
+        let open = |path: &str| {
+            Reader::open_readfile(path)
+                .map_err(|e| format!("Failed to open GeoIP database {}: {}", path, e))
+        };
+        Ok(Self {
+            country: country_db.map(open).transpose()?,
+            asn: asn_db.map(open).transpose()?,
+        })
+    }
+
+    /// Looks up `ip`. Addresses a database does not cover, such as private
+    /// ones, leave its fields unset.
+    pub fn lookup(&self, ip: IpAddr) -> Location {
+        let ip = ip.to_canonical();
+        let country = self.country.as_ref().and_then(|reader| {
+            let record = reader.lookup::<geoip2::Country>(ip).ok()?;
+            record
+                .country
+                .and_then(|country| country.iso_code)
+                .or_else(|| {
+                    record
+                        .registered_country
+                        .and_then(|country| country.iso_code)
+                })
+                .map(str::to_string)
+        });
+        let asn = self
+            .asn
+            .as_ref()
+            .and_then(|reader| reader.lookup::<geoip2::Asn>(ip).ok());
+        Location {
+            country,
+            asn: asn.as_ref().and_then(|asn| asn.autonomous_system_number),
+            as_org: asn
+                .and_then(|asn| asn.autonomous_system_organization)
+                .map(str::to_string),
+        }
+    }
+
+    /// Looks up the address of a peer given as `host:port`; see
+    /// [`peer_ip`].
+    pub fn lookup_peer(&self, peer: &str) -> Location {
+        peer_ip(peer).map(|ip| self.lookup(ip)).unwrap_or_default()
+    }
+}
+
+/// The IP address of a peer given as `host:port`, the way stunnel logs
+/// peers, or as `[host]:port`. Returns `None` if the host is not an IP
+/// address.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::geoip::peer_ip;
+///
+/// assert_eq!(peer_ip("10.0.0.2:51234"), Some("10.0.0.2".parse().unwrap()));
+/// assert_eq!(peer_ip("2001:db8::10:443"), Some("2001:db8::10".parse().unwrap()));
+/// assert_eq!(peer_ip("[2001:db8::10]:443"), Some("2001:db8::10".parse().unwrap()));
+/// assert_eq!(peer_ip("localhost:443"), None);
+/// ```
+pub fn peer_ip(peer: &str) -> Option<IpAddr> {
+    let (host, port) = peer.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
@@ -0,0 +1,144 @@
+//! Git-backed versioning of the stunnel configuration.
+//!
+//! When enabled, the directory holding the managed config is treated as a
+//! local Git repository and every successful change is committed to it, so
+//! teams that keep stunnel configs as code get a regular Git history. The
+//! `git` command-line tool must be installed.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Identity used for commits made by the manager; the caller goes in the message.
+const COMMITTER_NAME: &str = "stunnel-space";
+const COMMITTER_EMAIL: &str = "stunnel-space@localhost";
+
+/// A local Git repository holding a managed config file.
+#[derive(Debug, Clone)]
+pub struct GitVersioning {
+    repo_dir: PathBuf,
+}
+
+impl GitVersioning {
+    /// Opens the repository containing `config_path`, initializing one in the
+    /// config's directory if none exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be determined or `git init` fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::git::GitVersioning;
+    ///
+    /// let git = GitVersioning::open("/etc/stunnel/stunnel.conf")
+    ///     .expect("Failed to open config repository");
+    /// ```
+    pub fn open(config_path: &str) -> Result<Self, Box<dyn Error>> {
+        let repo_dir = Path::new(config_path)
+            .canonicalize()?
+            .parent()
+            .ok_or("Config path has no parent directory")?
+            .to_path_buf();
+        let git = Self { repo_dir };
+
+        if git.run(&["rev-parse", "--git-dir"]).is_err() {
+            git.run(&["init", "--quiet"])?;
+        }
+        Ok(git)
+    }
+
+    /// Commits the current content of `file` and returns the new commit hash.
+    ///
+    /// Returns `Ok(None)` when the file is unchanged since the last commit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is outside the repository or a `git`
+    /// command fails.
+    pub fn commit_file(&self, file: &str, message: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let relative = self.relative_path(file)?;
+        self.run(&["add", "--", &relative])?;
+
+        // `diff --cached --quiet` succeeds when nothing is staged for the file
+        if self
+            .run(&["diff", "--cached", "--quiet", "--", &relative])
+            .is_ok()
+        {
+            return Ok(None);
+        }
+
+        self.run(&[
+            "-c",
+            &format!("user.name={}", COMMITTER_NAME),
+            "-c",
+            &format!("user.email={}", COMMITTER_EMAIL),
+            "commit",
+            "--quiet",
+            "-m",
+            message,
+            "--",
+            &relative,
+        ])?;
+        Ok(Some(self.run(&["rev-parse", "HEAD"])?))
+    }
+
+    /// Returns the content of `file` as of `commit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `commit` does not name a commit or the file does
+    /// not exist in it.
+    pub fn file_at(&self, commit: &str, file: &str) -> Result<String, Box<dyn Error>> {
+        let relative = self.relative_path(file)?;
+        let hash = self.resolve(commit)?;
+        let output = Command::new("git")
+            .current_dir(&self.repo_dir)
+            .args(["show", &format!("{}:./{}", hash, relative)])
+            .output()?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// Resolves `commit` to its full hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `commit` does not name a commit.
+    pub fn resolve(&self, commit: &str) -> Result<String, Box<dyn Error>> {
+        // Refuse anything git could parse as an option
+        if commit.is_empty() || commit.starts_with('-') {
+            return Err(format!("Invalid commit: {}", commit).into());
+        }
+        self.run(&["rev-parse", "--verify", &format!("{}^{{commit}}", commit)])
+    }
+
+    // Returns the path of `file` relative to the config directory.
+    fn relative_path(&self, file: &str) -> Result<String, Box<dyn Error>> {
+        let absolute = Path::new(file).canonicalize()?;
+        let relative = absolute
+            .strip_prefix(&self.repo_dir)
+            .map_err(|_| format!("{} is outside the config repository", file))?;
+        Ok(relative.to_string_lossy().into_owned())
+    }
+
+    // Runs a git command in the repository, returning trimmed stdout.
+    fn run(&self, args: &[&str]) -> Result<String, Box<dyn Error>> {
+        let output = Command::new("git")
+            .current_dir(&self.repo_dir)
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
@@ -0,0 +1,182 @@
+//! Config revision history stored in SQLite.
+//!
+//! When enabled, every config change made through the manager is recorded as
+//! a revision holding the full content, a unified diff against the previous
+//! version, the RPC that made the change, and the caller that requested it.
+//! This provides an audit trail and lets earlier revisions be restored.
+//! Content and diffs are sealed when a [`SecretCipher`] is set; revisions
+//! recorded before that are read back as they are.
+
+use chrono::Utc;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::sync::{Arc, Mutex};
+
+use crate::secrets::{self, SecretCipher};
+
+/// A recorded config revision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub id: i64,
+    /// RFC 3339 time the revision was recorded.
+    pub created_at: String,
+    pub config_path: String,
+    /// Name of the RPC that produced the revision.
+    pub rpc: String,
+    /// Identity of the client that requested the change.
+    pub caller: String,
+    pub content: String,
+    /// Unified diff from the previous content.
+    pub diff: String,
+}
+
+/// SQLite-backed store of config revisions.
+///
+/// The connection is guarded by a mutex so the store can be shared between
+/// concurrent RPC handlers.
+#[derive(Debug)]
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+    cipher: Option<Arc<SecretCipher>>,
+}
+
+impl HistoryStore {
+    /// Opens (or creates) the history database at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or its schema
+    /// cannot be created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::history::HistoryStore;
+    ///
+    /// let store = HistoryStore::open("/var/lib/stunnel-space/history.db")
+    ///     .expect("Failed to open history database");
+    /// ```
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS revisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at TEXT NOT NULL,
+                config_path TEXT NOT NULL,
+                rpc TEXT NOT NULL,
+                caller TEXT NOT NULL,
+                content TEXT NOT NULL,
+                diff TEXT NOT NULL
+            );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            cipher: None,
+        })
+    }
+
+    /// Seals the content and diff of revisions recorded from now on.
+    pub fn with_cipher(mut self, cipher: Arc<SecretCipher>) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Records a new revision and returns its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert fails.
+    pub fn record(
+        &self,
+        config_path: &str,
+        rpc: &str,
+        caller: &str,
+        content: &str,
+        diff: &str,
+    ) -> rusqlite::Result<i64> {
+        let seal = |text: &str| {
+            secrets::seal_text(self.cipher.as_deref(), text)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))
+        };
+        let (content, diff) = (seal(content)?, seal(diff)?);
+        let conn = self.lock();
+        conn.execute(
+            "INSERT INTO revisions (created_at, config_path, rpc, caller, content, diff)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                Utc::now().to_rfc3339(),
+                config_path,
+                rpc,
+                caller,
+                content,
+                diff
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Returns up to `limit` revisions of `config_path`, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub fn list(&self, config_path: &str, limit: u32) -> rusqlite::Result<Vec<Revision>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, created_at, config_path, rpc, caller, content, diff
+             FROM revisions WHERE config_path = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let revisions = stmt
+            .query_map(params![config_path, limit], revision_from_row)?
+            .map(|revision| self.open_revision(revision?))
+            .collect();
+        revisions
+    }
+
+    /// Returns the revision with the given ID, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub fn get(&self, id: i64) -> rusqlite::Result<Option<Revision>> {
+        self.lock()
+            .query_row(
+                "SELECT id, created_at, config_path, rpc, caller, content, diff
+                 FROM revisions WHERE id = ?1",
+                params![id],
+                revision_from_row,
+            )
+            .optional()?
+            .map(|revision| self.open_revision(revision))
+            .transpose()
+    }
+
+    // Opens the sealed content and diff of a stored revision.
+    fn open_revision(&self, mut revision: Revision) -> rusqlite::Result<Revision> {
+        let open = |column: usize, text: &str| {
+            secrets::open_text(self.cipher.as_deref(), text).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(column, Type::Text, e.into())
+            })
+        };
+        revision.content = open(5, &revision.content)?;
+        revision.diff = open(6, &revision.diff)?;
+        Ok(revision)
+    }
+
+    // A panic while holding the lock cannot leave SQLite inconsistent, so recover from poisoning.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn revision_from_row(row: &Row) -> rusqlite::Result<Revision> {
+    Ok(Revision {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        config_path: row.get(2)?,
+        rpc: row.get(3)?,
+        caller: row.get(4)?,
+        content: row.get(5)?,
+        diff: row.get(6)?,
+    })
+}
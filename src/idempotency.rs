@@ -0,0 +1,299 @@
+//! Idempotency keys for mutating RPCs.
+//!
+//! A client that times out waiting for AddProvider cannot tell whether the
+//! provider was added, and a blind retry fails with "already exists". Sending
+//! the same `idempotency-key` metadata on the original call and every retry
+//! makes the server run the call once and replay its response afterwards.
+//!
+//! Responses are remembered per method, key and credential (bearer token or
+//! client certificate), so one client cannot replay another's response. The
+//! service wraps the StunnelManager service inside its interceptors, so a
+//! retry is authenticated, rate limited and refused in maintenance mode
+//! before anything is replayed, and a revoked credential gets no replays.
+//! Only calls that completed with gRPC status OK are remembered; a refused
+//! call runs again on retry. A retry arriving while the original call is
+//! still running waits for it. Reusing a key for a different request fails
+//! with `INVALID_ARGUMENT`. Streaming methods are never keyed, since their
+//! responses would have to be buffered whole.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{self, HeaderMap, StatusCode};
+use tonic::codegen::{Body as HttpBody, Bytes};
+use tonic::server::NamedService;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::transport::Body;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+use crate::auth::AUTHORIZATION_METADATA_KEY;
+use crate::rbac::{self, Role};
+use crate::stunnel::stunnel_manager_server::StunnelManagerServer;
+use crate::StunnelServer;
+
+/// Metadata key carrying the client-chosen idempotency key.
+pub const IDEMPOTENCY_KEY_METADATA_KEY: &str = "idempotency-key";
+
+/// How long responses are remembered when no TTL is configured.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Methods whose responses stream, left alone even with a key.
+const STREAMING_METHODS: [&str; 2] = ["RemoveProviderWithProgress", "WatchCertificateChanges"];
+
+// Longest idempotency key accepted.
+const MAX_KEY_LEN: usize = 255;
+
+// Remembered responses are swept of expired ones once this many are held.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+// A response as sent, so it can be sent again.
+#[derive(Debug, Clone)]
+struct Recorded {
+    request_digest: [u8; 32],
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    trailers: Option<HeaderMap>,
+}
+
+impl Recorded {
+    fn replay(&self) -> http::Response<BoxBody> {
+        let mut response = http::Response::new(
+            Replay {
+                body: Some(self.body.clone()),
+                trailers: self.trailers.clone(),
+            }
+            .boxed_unsync(),
+        );
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+// Response body replaying a recorded body and trailers.
+struct Replay {
+    body: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl HttpBody for Replay {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Status>>> {
+        Poll::Ready(self.body.take().filter(|body| !body.is_empty()).map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+}
+
+// A key's slot, locked while its first call runs.
+struct Entry {
+    created: Instant,
+    slot: Arc<tokio::sync::Mutex<Option<Recorded>>>,
+}
+
+/// Remembered responses, shared by every connection.
+#[derive(Clone)]
+pub struct IdempotencyLayer {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<[u8; 32], Entry>>>,
+}
+
+impl IdempotencyLayer {
+    /// Remembers responses to keyed calls for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::default(),
+        }
+    }
+
+    // Returns the slot for `scope`, replacing an expired one.
+    fn slot(&self, scope: [u8; 32]) -> Arc<tokio::sync::Mutex<Option<Recorded>>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= SWEEP_THRESHOLD {
+            entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+        }
+        let entry = entries.entry(scope).or_insert_with(|| Entry {
+            created: now,
+            slot: Arc::default(),
+        });
+        if now.duration_since(entry.created) >= self.ttl {
+            *entry = Entry {
+                created: now,
+                slot: Arc::default(),
+            };
+        }
+        entry.slot.clone()
+    }
+}
+
+impl<S> Layer<S> for IdempotencyLayer {
+    type Service = Idempotency<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Idempotency {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service replaying the remembered response to keyed mutating calls.
+#[derive(Clone)]
+pub struct Idempotency<S> {
+    inner: S,
+    layer: IdempotencyLayer,
+}
+
+impl<S: NamedService> NamedService for Idempotency<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<Body>> for Idempotency<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // The ready service handles this call; its clone waits for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Some(scope) = scope(&request) else {
+            return Box::pin(inner.call(request));
+        };
+        let slot = match scope {
+            Ok(scope) => self.layer.slot(scope),
+            Err(status) => return Box::pin(async move { Ok(status.to_http()) }),
+        };
+
+        Box::pin(async move {
+            let (parts, mut body) = request.into_parts();
+            let mut content = Vec::new();
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => content.extend_from_slice(&chunk),
+                    Err(e) => {
+                        return Ok(
+                            Status::internal(format!("Failed to read request: {}", e)).to_http()
+                        )
+                    }
+                }
+            }
+            let request_digest: [u8; 32] = Sha256::digest(&content).into();
+
+            // A retry racing the original call waits here for its response
+            let mut slot = slot.lock().await;
+            if let Some(recorded) = &*slot {
+                if recorded.request_digest != request_digest {
+                    return Ok(Status::invalid_argument(
+                        "Idempotency key was already used for a different request",
+                    )
+                    .to_http());
+                }
+                return Ok(recorded.replay());
+            }
+
+            let response = inner
+                .call(http::Request::from_parts(parts, Body::from(content)))
+                .await?;
+            let (head, mut body) = response.into_parts();
+            let mut content = Vec::new();
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => content.extend_from_slice(&chunk),
+                    Err(status) => return Ok(status.to_http()),
+                }
+            }
+            let trailers = match body.trailers().await {
+                Ok(trailers) => trailers,
+                Err(status) => return Ok(status.to_http()),
+            };
+
+            let recorded = Recorded {
+                request_digest,
+                status: head.status,
+                headers: head.headers,
+                body: Bytes::from(content),
+                trailers,
+            };
+            // Trailers-only responses carry the status in the headers
+            let status = recorded
+                .trailers
+                .as_ref()
+                .and_then(Status::from_header_map)
+                .or_else(|| Status::from_header_map(&recorded.headers));
+            let response = recorded.replay();
+            if status.is_some_and(|status| status.code() == Code::Ok) {
+                *slot = Some(recorded);
+            }
+            Ok(response)
+        })
+    }
+}
+
+// Identifies a keyed mutating call by method, key and credential. Returns
+// `None` for calls to leave alone and an error for an unusable key.
+fn scope(request: &http::Request<Body>) -> Option<Result<[u8; 32], Status>> {
+    let key = request.headers().get(IDEMPOTENCY_KEY_METADATA_KEY)?;
+    let path = request.uri().path();
+    let service = <StunnelManagerServer<StunnelServer> as NamedService>::NAME;
+    let method = path
+        .strip_prefix('/')
+        .and_then(|path| path.strip_prefix(service))
+        .and_then(|path| path.strip_prefix('/'))?;
+    if rbac::required_role(method) == Role::ReadOnly || STREAMING_METHODS.contains(&method) {
+        return None;
+    }
+    let key = key.as_bytes();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Some(Err(Status::invalid_argument(format!(
+            "{} must be 1 to {} bytes",
+            IDEMPOTENCY_KEY_METADATA_KEY, MAX_KEY_LEN
+        ))));
+    }
+
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION_METADATA_KEY)
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    let peer_cert = request
+        .extensions()
+        .get::<TlsConnectInfo<TcpConnectInfo>>()
+        .and_then(|info| info.peer_certs())
+        .and_then(|certs| certs.first().map(|cert| cert.as_ref().to_vec()))
+        .unwrap_or_default();
+    let mut digest = Sha256::new();
+    for part in [path.as_bytes(), key, authorization, &peer_cert] {
+        digest.update((part.len() as u64).to_be_bytes());
+        digest.update(part);
+    }
+    Some(Ok(digest.finalize().into()))
+}
@@ -0,0 +1,290 @@
+//! Named stunnel instances managed alongside the default one.
+//!
+//! Hosts often run several stunnels, each with its own config and PID file.
+//! The instance configured through `STUNNEL_CONF_PATH` is always present as
+//! [`DEFAULT_INSTANCE`]; others are registered at run time by
+//! CreateInstance and, when an instances file is configured, survive a
+//! restart. Every RPC names the instance it acts on in `instance_id`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{DirectBackend, ProcessBackend, SystemdBackend};
+
+/// Name of the instance configured at startup, also used when a request
+/// leaves `instance_id` empty.
+pub const DEFAULT_INSTANCE: &str = "default";
+
+/// The only systemd unit the instance `id` may run as: its instance of the
+/// `stunnel@.service` template, so no other host service can be bound to it.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::instances::systemd_unit_for;
+///
+/// assert_eq!(systemd_unit_for("edge"), "stunnel@edge.service");
+/// ```
+pub fn systemd_unit_for(id: &str) -> String {
+    format!("stunnel@{}.service", id)
+}
+
+/// Checks that `unit` is the one the instance `id` may run as (see
+/// [`systemd_unit_for`]).
+///
+/// # Errors
+///
+/// Returns an error naming the expected unit otherwise.
+pub fn check_systemd_unit(id: &str, unit: &str) -> Result<(), String> {
+    validate_id(id)?;
+    let expected = systemd_unit_for(id);
+    if unit != expected {
+        return Err(format!(
+            "Instance {} may only run as systemd unit {}, not {:?}",
+            id, expected, unit
+        ));
+    }
+    Ok(())
+}
+
+/// Where an instance keeps its config and how its stunnel is run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceSpec {
+    pub config_path: String,
+    pub pid_file: String,
+    /// Runs stunnel as this systemd unit rather than starting it directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_unit: Option<String>,
+    /// Tenant namespace the instance belongs to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl InstanceSpec {
+    /// The systemd unit stunnel runs as, if any. An instance in a namespace
+    /// is always started directly, so a tenant never controls a host unit.
+    pub fn unit(&self) -> Option<&str> {
+        self.systemd_unit
+            .as_deref()
+            .filter(|_| self.namespace.is_none())
+    }
+
+    /// Builds the process backend running this instance's stunnel.
+    pub fn process_backend(&self, repair_pid_file: bool) -> Arc<dyn ProcessBackend> {
+        match self.unit() {
+            Some(unit) => Arc::new(SystemdBackend::new(unit)),
+            None => Arc::new(
+                DirectBackend::new(&self.config_path, &self.pid_file)
+                    .with_pid_file_repair(repair_pid_file),
+            ),
+        }
+    }
+}
+
+/// Instances registered besides the default one, optionally persisted to a
+/// JSON file.
+#[derive(Debug, Default)]
+pub struct InstanceRegistry {
+    file: Option<PathBuf>,
+    repair_pid_file: bool,
+    instances: RwLock<BTreeMap<String, InstanceSpec>>,
+}
+
+impl InstanceRegistry {
+    /// Creates a registry that keeps instances in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a registry persisted to `path`, loading the instances in it.
+    /// A missing file is treated as empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::instances::InstanceRegistry;
+    ///
+    /// let registry = InstanceRegistry::open("/var/lib/stunnel-space/instances.json")
+    ///     .expect("Failed to load instances");
+    /// ```
+    pub fn open(path: &str) -> io::Result<Self> {
+        let instances = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            file: Some(PathBuf::from(path)),
+            repair_pid_file: false,
+            instances: RwLock::new(instances),
+        })
+    }
+
+    /// Sets whether directly started instances write a PID found by
+    /// scanning processes back to their PID file.
+    pub fn with_pid_file_repair(mut self, repair: bool) -> Self {
+        self.repair_pid_file = repair;
+        self
+    }
+
+    /// Builds the process backend running `spec`'s stunnel.
+    pub fn process_backend(&self, spec: &InstanceSpec) -> Arc<dyn ProcessBackend> {
+        spec.process_backend(self.repair_pid_file)
+    }
+
+    /// Returns the instance `id`.
+    pub fn get(&self, id: &str) -> Option<InstanceSpec> {
+        self.instances
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+    }
+
+    /// Returns every instance, by name.
+    pub fn list(&self) -> Vec<(String, InstanceSpec)> {
+        self.instances
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(id, spec)| (id.clone(), spec.clone()))
+            .collect()
+    }
+
+    /// Registers the instance `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `id` is not a valid name or is taken, another
+    /// instance uses the same config or PID file, or the registry cannot be
+    /// persisted.
+    pub fn insert(&self, id: &str, spec: InstanceSpec) -> Result<(), String> {
+        validate_id(id)?;
+        let mut instances = self.instances.write().unwrap_or_else(|e| e.into_inner());
+        if instances.contains_key(id) {
+            return Err(format!("Instance {} already exists", id));
+        }
+        if let Some((other, _)) = instances.iter().find(|(_, other)| {
+            other.config_path == spec.config_path || other.pid_file == spec.pid_file
+        }) {
+            return Err(format!(
+                "Instance {} already uses that config or pid file",
+                other
+            ));
+        }
+        let mut updated = instances.clone();
+        updated.insert(id.to_string(), spec);
+        self.persist(&updated)
+            .map_err(|e| format!("Failed to save instances: {}", e))?;
+        *instances = updated;
+        Ok(())
+    }
+
+    /// Unregisters the instance `id`, returning it if it existed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be persisted.
+    pub fn remove(&self, id: &str) -> Result<Option<InstanceSpec>, String> {
+        let mut instances = self.instances.write().unwrap_or_else(|e| e.into_inner());
+        if !instances.contains_key(id) {
+            return Ok(None);
+        }
+        let mut updated = instances.clone();
+        let removed = updated.remove(id);
+        self.persist(&updated)
+            .map_err(|e| format!("Failed to save instances: {}", e))?;
+        *instances = updated;
+        Ok(removed)
+    }
+
+    // Replaces the instances file, if any, with `instances`.
+    fn persist(&self, instances: &BTreeMap<String, InstanceSpec>) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(instances).map_err(io::Error::other)?;
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)?;
+        let tmp_path = dir.join(format!(".instances.tmp.{}", std::process::id()));
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+// Instance names travel in every request and appear in logs, so they are
+// kept simple.
+fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid instance name {:?}: use letters, digits, '-' and '_'",
+            id
+        ));
+    }
+    if id == DEFAULT_INSTANCE {
+        return Err(format!("{} is the instance configured at startup", id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_instance_template_unit() {
+        assert!(check_systemd_unit("edge", "stunnel@edge.service").is_ok());
+    }
+
+    #[test]
+    fn rejects_other_units() {
+        for unit in [
+            "sshd.service",
+            "docker.service",
+            "stunnel@other.service",
+            "stunnel.service",
+            "--no-block",
+            "-H",
+        ] {
+            assert!(check_systemd_unit("edge", unit).is_err(), "{}", unit);
+        }
+    }
+
+    #[test]
+    fn namespaced_instances_run_no_unit() {
+        let spec = InstanceSpec {
+            config_path: "/etc/stunnel/tenants/a/a.conf".to_string(),
+            pid_file: "/etc/stunnel/tenants/a/a.pid".to_string(),
+            systemd_unit: Some("sshd.service".to_string()),
+            namespace: Some("team-a".to_string()),
+        };
+        assert_eq!(spec.unit(), None);
+        assert_eq!(spec.process_backend(false).name(), "direct");
+    }
+
+    #[test]
+    fn rejects_invalid_instance_names() {
+        assert!(check_systemd_unit("a b", "stunnel@a b.service").is_err());
+    }
+}
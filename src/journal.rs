@@ -0,0 +1,123 @@
+//! Reading stunnel's log from the systemd journal.
+//!
+//! A stunnel run as a systemd unit usually logs to syslog or stderr, both
+//! of which end up in the journal rather than in a file named by `output`.
+//! Entries are read with `journalctl --output json`, one JSON object per
+//! line.
+
+use std::process::Command;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+
+/// Entries returned when no count is requested.
+pub const DEFAULT_LINES: u32 = 100;
+
+/// Most entries returned by one query.
+pub const MAX_LINES: u32 = 5000;
+
+/// Which journal entries to read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JournalQuery {
+    /// Most recent entries to return; 0 for [`DEFAULT_LINES`].
+    pub lines: u32,
+    /// Oldest entry time, in any form `journalctl --since` accepts, e.g.
+    /// `-1h` or `2024-01-31 12:00:00`.
+    pub since: Option<String>,
+    /// Least severe priority to include, e.g. `warning` or `4`.
+    pub priority: Option<String>,
+}
+
+/// One journal entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Syslog priority, 0 (emerg) to 7 (debug).
+    pub priority: u8,
+    pub message: String,
+    pub pid: Option<i32>,
+    /// Unit the logging process belonged to, if any.
+    pub unit: Option<String>,
+}
+
+/// Reads the most recent entries of `unit` matching `query`, oldest first.
+///
+/// # Errors
+///
+/// Returns an error if `journalctl` cannot be run or fails.
+pub fn query(unit: &str, query: &JournalQuery) -> Result<Vec<JournalEntry>, String> {
+    let lines = match query.lines {
+        0 => DEFAULT_LINES,
+        lines => lines.min(MAX_LINES),
+    };
+    let mut command = Command::new("journalctl");
+    command
+        .arg(format!("--unit={}", unit))
+        .arg(format!("--lines={}", lines))
+        .args(["--output=json", "--no-pager", "--quiet"]);
+    if let Some(since) = &query.since {
+        command.arg(format!("--since={}", since));
+    }
+    if let Some(priority) = &query.priority {
+        command.arg(format!("--priority={}", priority));
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run journalctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "journalctl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_entry)
+        .collect())
+}
+
+/// Parses one line of `journalctl --output json`, or returns `None` if it
+/// is not a journal entry.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::journal::parse_entry;
+///
+/// let line = r#"{"__REALTIME_TIMESTAMP":"1706702400000000","PRIORITY":"3","_PID":"812","_SYSTEMD_UNIT":"stunnel@main.service","MESSAGE":"Error binding service [a] to 0.0.0.0:443"}"#;
+/// let entry = parse_entry(line).unwrap();
+/// assert_eq!(entry.priority, 3);
+/// assert_eq!(entry.pid, Some(812));
+/// assert_eq!(entry.timestamp.to_rfc3339(), "2024-01-31T12:00:00+00:00");
+/// ```
+pub fn parse_entry(line: &str) -> Option<JournalEntry> {
+    let fields: Value = serde_json::from_str(line).ok()?;
+    let micros = field(&fields, "__REALTIME_TIMESTAMP")?
+        .parse::<i64>()
+        .ok()?;
+    Some(JournalEntry {
+        timestamp: Utc.timestamp_micros(micros).single()?,
+        priority: field(&fields, "PRIORITY")
+            .and_then(|priority| priority.parse().ok())
+            .unwrap_or(6),
+        message: field(&fields, "MESSAGE").unwrap_or_default(),
+        pid: field(&fields, "_PID").and_then(|pid| pid.parse().ok()),
+        unit: field(&fields, "_SYSTEMD_UNIT"),
+    })
+}
+
+// journalctl writes a field as a string, or as an array of bytes when it is
+// not valid UTF-8.
+fn field(fields: &Value, name: &str) -> Option<String> {
+    match fields.get(name)? {
+        Value::String(value) => Some(value.clone()),
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => None,
+    }
+}
@@ -9,7 +9,7 @@
 //! ## Features
 //!
 //! - **Configuration Management**: Validate, update, and generate stunnel configurations
-//! - **Dynamic Provider Management**: Add or remove service providers at runtime
+//! - **Dynamic Provider Management**: Add, remove, or list service providers at runtime
 //! - **Status Monitoring**: Check stunnel process status and active connections
 //! - **gRPC API**: Modern, efficient API for remote stunnel management
 //!
@@ -35,10 +35,64 @@
 //! }
 //! ```
 
+pub mod accesslog;
+pub mod acme;
+pub mod auth;
+pub mod backend;
+pub mod backup;
+pub mod certs;
+pub mod certstore;
 pub mod config;
+pub mod connlog;
+pub mod diff;
+pub mod dns;
+pub mod drain;
+pub mod expiry;
+pub mod fragments;
+pub mod geoip;
+pub mod git;
+pub mod history;
+pub mod idempotency;
+pub mod instances;
+pub mod journal;
+pub mod lint;
+pub mod logging;
+pub mod logs;
+pub mod maintenance;
+pub mod metrics;
+pub mod namespaces;
+pub mod operations;
+pub mod parser;
+pub mod platform;
+pub mod ports;
+pub mod probe;
+pub mod ratelimit;
+pub mod rbac;
+pub mod readiness;
+pub mod redact;
+pub mod retry;
+pub mod secrets;
 pub mod server;
+pub mod sockdiag;
+pub mod staging;
+pub mod statsd;
+pub mod structured;
+pub mod supervisor;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+pub mod telemetry;
+pub mod templates;
+pub mod tls;
+pub mod traffic;
+pub mod usage;
 pub mod utils;
+pub mod validation;
+pub mod vault;
+pub mod version;
+pub mod watcher;
 
+// Generated code: oneofs holding a whole Provider are much larger than their siblings
+#[allow(clippy::large_enum_variant)]
 pub mod stunnel {
     tonic::include_proto!("vfxstunnel");
 }
@@ -0,0 +1,199 @@
+//! Security linting of stunnel configurations.
+//!
+//! The linter inspects a parsed config for risky settings such as disabled
+//! peer verification, legacy protocol versions, weak ciphers, key files
+//! other users can read and verbose debug logging. Each finding carries a
+//! severity and a remediation hint.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::parser::{ConfigOption, Section, StunnelConfig};
+
+/// How serious a lint finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A single problem found in a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Stable identifier of the rule that produced the finding.
+    pub code: &'static str,
+    /// Section the finding applies to; `None` for the global section.
+    pub section: Option<String>,
+    pub message: String,
+    pub remediation: String,
+}
+
+// Protocol versions that are broken outright or deprecated by RFC 8996.
+const BROKEN_PROTOCOLS: [&str; 2] = ["SSLv2", "SSLv3"];
+const DEPRECATED_PROTOCOLS: [&str; 2] = ["TLSv1", "TLSv1.1"];
+
+// Cipher string components that enable weak or unauthenticated suites.
+const WEAK_CIPHERS: [&str; 9] = [
+    "NULL", "eNULL", "aNULL", "EXPORT", "RC4", "DES", "3DES", "MD5", "ADH",
+];
+
+// Options that enable peer certificate verification in stunnel 5.
+const VERIFY_OPTIONS: [&str; 4] = ["verify", "verifyChain", "verifyPeer", "checkHost"];
+
+/// Lints a parsed config, returning findings ordered by decreasing severity.
+///
+/// Protocol, cipher and key file rules are checked for the global section
+/// and for each section that sets the option itself. Key file permissions
+/// are read from the local filesystem; missing files are skipped.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::lint::{lint, Severity};
+/// use stunnel_space::parser::StunnelConfig;
+///
+/// let config = StunnelConfig::parse("sslVersion = SSLv3\n[db]\nclient = yes\nconnect = db:5432\n");
+/// let findings = lint(&config);
+/// assert!(findings.iter().any(|f| f.code == "insecure-protocol" && f.severity == Severity::Error));
+/// assert!(findings.iter().any(|f| f.code == "missing-verify"));
+/// ```
+pub fn lint(config: &StunnelConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    check_debug(config, &mut findings);
+    check_options(None, &config.globals, &mut findings);
+    for section in &config.sections {
+        check_options(Some(&section.name), &section.options, &mut findings);
+        check_verify(config, section, &mut findings);
+    }
+
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings
+}
+
+// Flags the most verbose debug level, which logs connection details.
+fn check_debug(config: &StunnelConfig, findings: &mut Vec<Finding>) {
+    let Some(debug) = config.global("debug") else {
+        return;
+    };
+    // The level may be prefixed with a syslog facility, e.g. `daemon.7`
+    let level = debug.rsplit('.').next().unwrap_or(debug).trim();
+    if level == "7" || level.eq_ignore_ascii_case("debug") {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            code: "debug-level",
+            section: None,
+            message: format!("debug = {} logs verbose connection details", debug),
+            remediation: "Use debug = 5 (notice) or lower in production".to_string(),
+        });
+    }
+}
+
+// Checks protocol, cipher and key file options set directly in one section.
+fn check_options(section: Option<&str>, options: &[ConfigOption], findings: &mut Vec<Finding>) {
+    for option in options {
+        let key = option.key.as_str();
+        let value = option.value.as_str();
+
+        if key.eq_ignore_ascii_case("sslVersion") || key.eq_ignore_ascii_case("sslVersionMin") {
+            let severity = if value.eq_ignore_ascii_case("all")
+                || BROKEN_PROTOCOLS
+                    .iter()
+                    .any(|p| p.eq_ignore_ascii_case(value))
+            {
+                Some(Severity::Error)
+            } else if DEPRECATED_PROTOCOLS
+                .iter()
+                .any(|p| p.eq_ignore_ascii_case(value))
+            {
+                Some(Severity::Warning)
+            } else {
+                None
+            };
+            if let Some(severity) = severity {
+                findings.push(Finding {
+                    severity,
+                    code: "insecure-protocol",
+                    section: section.map(str::to_string),
+                    message: format!("{} = {} allows insecure protocol versions", key, value),
+                    remediation: "Set sslVersionMin = TLSv1.2".to_string(),
+                });
+            }
+        }
+
+        if key.eq_ignore_ascii_case("ciphers") {
+            let weak: Vec<&str> = value
+                .split([':', ',', ' '])
+                .filter(|part| !part.starts_with(['!', '-']))
+                .filter(|part| part.split('-').any(|piece| WEAK_CIPHERS.contains(&piece)))
+                .collect();
+            if !weak.is_empty() {
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    code: "weak-ciphers",
+                    section: section.map(str::to_string),
+                    message: format!("ciphers enables weak suites: {}", weak.join(", ")),
+                    remediation: "Use an AEAD-only cipher list such as a Mozilla intermediate profile, or exclude them with !"
+                        .to_string(),
+                });
+            }
+        }
+
+        // Without `key`, stunnel reads the private key from the `cert` file
+        let is_key_file = key.eq_ignore_ascii_case("key")
+            || (key.eq_ignore_ascii_case("cert") && !has_option(options, "key"));
+        if is_key_file {
+            check_key_permissions(section, value, findings);
+        }
+    }
+}
+
+// Flags client services that never verify the server they connect to.
+fn check_verify(config: &StunnelConfig, section: &Section, findings: &mut Vec<Finding>) {
+    let is_client = section
+        .get("client")
+        .map(|value| value.eq_ignore_ascii_case("yes"))
+        .unwrap_or(false);
+    let verifies = VERIFY_OPTIONS
+        .iter()
+        .any(|key| section.get(key).is_some() || config.global(key).is_some());
+    if is_client && !verifies {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            code: "missing-verify",
+            section: Some(section.name.clone()),
+            message: "Client service does not verify the server certificate".to_string(),
+            remediation: "Set verifyChain = yes with CAfile, plus checkHost for the expected name"
+                .to_string(),
+        });
+    }
+}
+
+// Flags private keys that users other than the owner can read.
+fn check_key_permissions(section: Option<&str>, path: &str, findings: &mut Vec<Finding>) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    let mode = metadata.permissions().mode();
+    if mode & 0o004 != 0 {
+        findings.push(Finding {
+            severity: Severity::Error,
+            code: "key-permissions",
+            section: section.map(str::to_string),
+            message: format!(
+                "Private key {} is world-readable (mode {:o})",
+                path,
+                mode & 0o777
+            ),
+            remediation: format!("Run chmod 600 {}", path),
+        });
+    }
+}
+
+fn has_option(options: &[ConfigOption], key: &str) -> bool {
+    options
+        .iter()
+        .any(|option| option.key.eq_ignore_ascii_case(key))
+}
@@ -0,0 +1,200 @@
+//! Where the manager's own log goes.
+//!
+//! Events carry structured fields such as `rpc`, `provider` and `pid`. On
+//! stderr they are printed after the message, or as JSON keys; sent to
+//! journald they become journal fields (`RPC=`, `PROVIDER=`, `PID=`), so
+//! `journalctl PROVIDER=backend-a` finds everything logged about one
+//! provider. Which events are written is set by a level or `tracing` filter
+//! directives.
+//!
+//! Spans can also be exported over OTLP; see [`crate::telemetry`].
+
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+use opentelemetry::sdk::trace::Tracer;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter, Targets};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+// Identifier the manager logs under in the journal.
+const SYSLOG_IDENTIFIER: &str = "stunnel-space";
+
+/// Where log events are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogTarget {
+    /// journald when systemd connected stderr to the journal, else stderr.
+    #[default]
+    Auto,
+    Stderr,
+    Journald,
+}
+
+impl fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogTarget::Auto => "auto",
+            LogTarget::Stderr => "stderr",
+            LogTarget::Journald => "journald",
+        })
+    }
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(LogTarget::Auto),
+            "stderr" => Ok(LogTarget::Stderr),
+            "journald" | "journal" => Ok(LogTarget::Journald),
+            other => Err(format!("Unknown log target: {}", other)),
+        }
+    }
+}
+
+/// How events written to stderr are formatted. journald always receives
+/// structured fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One line per event, fields after the message.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields and enclosing spans, for
+    /// log shippers.
+    Json,
+    /// Multi-line, for reading during development.
+    Pretty,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+            LogFormat::Pretty => "pretty",
+        })
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" | "plain" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "pretty" => Ok(LogFormat::Pretty),
+            other => Err(format!("Unknown log format: {}", other)),
+        }
+    }
+}
+
+/// Parses `level`, either a level such as `debug` or `tracing` filter
+/// directives such as `info,stunnel_space::server=debug`. A lone word must
+/// be a level, so that a misspelt one is not taken for a target name.
+///
+/// # Errors
+///
+/// Returns why `level` is not a valid filter.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::logging::parse_level;
+///
+/// assert!(parse_level("warn").is_ok());
+/// assert!(parse_level("info,stunnel_space::server=debug").is_ok());
+/// assert!(parse_level("loud").is_err());
+/// assert!(parse_level("stunnel_space=loud").is_err());
+/// ```
+pub fn parse_level(level: &str) -> Result<EnvFilter, String> {
+    let invalid = |e: &dyn fmt::Display| format!("Invalid log level {}: {}", level, e);
+    if !level.contains(['=', ',']) {
+        level
+            .trim()
+            .parse::<LevelFilter>()
+            .map_err(|e| invalid(&e))?;
+    }
+    EnvFilter::builder()
+        .parse(level.trim())
+        .map_err(|e| invalid(&e))
+}
+
+/// Installs the global subscriber writing events that pass `level` (see
+/// [`parse_level`]) to `target` in `format`, and returns the target in
+/// effect: `Auto` resolves to journald when systemd passed
+/// `JOURNAL_STREAM`, and journald falls back to stderr when its socket
+/// cannot be reached. With a `tracer`, the manager's spans are exported
+/// through it too, whatever the level.
+///
+/// # Errors
+///
+/// Returns an error if `level` is invalid or a global subscriber is already
+/// installed.
+pub fn init(
+    target: LogTarget,
+    format: LogFormat,
+    level: &str,
+    tracer: Option<Tracer>,
+) -> Result<LogTarget, Box<dyn std::error::Error>> {
+    let filter = parse_level(level)?;
+    let wanted = match target {
+        LogTarget::Auto if env::var_os("JOURNAL_STREAM").is_some() => LogTarget::Journald,
+        LogTarget::Auto => LogTarget::Stderr,
+        other => other,
+    };
+    let (output, target) = match wanted {
+        LogTarget::Journald => match journald_layer() {
+            Ok(layer) => (layer.boxed(), LogTarget::Journald),
+            Err(e) => {
+                eprintln!("journald unavailable, logging to stderr: {}", e);
+                (stderr_layer(format), LogTarget::Stderr)
+            }
+        },
+        _ => (stderr_layer(format), LogTarget::Stderr),
+    };
+    tracing_subscriber::registry()
+        .with(output.with_filter(filter))
+        .with(otel_layer(tracer))
+        .try_init()?;
+    Ok(target)
+}
+
+// Writes events to stderr in `format`, colored on a terminal.
+fn stderr_layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(io::stderr);
+    match format {
+        LogFormat::Text => layer.with_ansi(io::stderr().is_terminal()).boxed(),
+        LogFormat::Json => layer.json().boxed(),
+        LogFormat::Pretty => layer.pretty().with_ansi(io::stderr().is_terminal()).boxed(),
+    }
+}
+
+// Exports the manager's own spans through `tracer`, if any. Spans of
+// dependencies, such as the exporter's own HTTP/2 connection, are left out.
+fn otel_layer<S>(tracer: Option<Tracer>) -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracer.map(|tracer| {
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::TRACE))
+    })
+}
+
+// Fields are sent without the default `F_` prefix so they can be matched
+// by the names used in code.
+fn journald_layer() -> io::Result<tracing_journald::Layer> {
+    Ok(tracing_journald::layer()?
+        .with_field_prefix(None)
+        .with_syslog_identifier(SYSLOG_IDENTIFIER.to_string()))
+}
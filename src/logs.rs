@@ -0,0 +1,248 @@
+//! Reading stunnel's log file.
+//!
+//! stunnel reports whether a reload worked only in its log: a SIGHUP is
+//! delivered either way, and a config stunnel cannot load is logged as
+//! rejected while the old services keep running. Reading the lines
+//! appended after the signal tells the two apart, provided the global
+//! `output` option points stunnel at a log file.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::parser::StunnelConfig;
+
+/// How long to wait for stunnel to log the outcome of a reload.
+pub const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Delay between reads of the log file.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Logged at level 5 once a config has been parsed and loaded.
+const RELOAD_APPLIED: &str = "Configuration successful";
+
+// Logged at level 3 when a reloaded config is rejected.
+const RELOAD_REJECTED: &str = "Failed to reload the configuration file";
+
+// Highest level, `LOG3` (err), counted as an error.
+const ERROR_LEVEL: u8 = 3;
+
+// Bytes read from the end of the log when looking for its last error.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+// Format of the timestamp stunnel puts before each line, in local time.
+const TIMESTAMP_FORMAT: &str = "%Y.%m.%d %H:%M:%S";
+
+/// One line of stunnel's log, e.g.
+/// `2024.01.31 12:00:00 LOG5[main]: Configuration successful`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// Syslog severity, 0 (emerg) to 7 (debug).
+    pub level: u8,
+    /// The thread or connection that logged the line, e.g. `main` or `3`.
+    pub thread: String,
+    pub message: String,
+    /// When the line was logged; `None` if it has no timestamp.
+    pub logged_at: Option<DateTime<Utc>>,
+}
+
+/// Parses a line in stunnel's log format, with or without the leading
+/// timestamp. Returns `None` for lines in any other format.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::logs::parse_line;
+///
+/// let line = parse_line("2024.01.31 12:00:00 LOG3[main]: Failed to reload the configuration file").unwrap();
+/// assert_eq!(line.level, 3);
+/// assert_eq!(line.thread, "main");
+/// assert_eq!(line.message, "Failed to reload the configuration file");
+/// assert!(line.logged_at.is_some());
+/// ```
+pub fn parse_line(line: &str) -> Option<LogLine> {
+    let start = line.find("LOG")?;
+    let rest = &line[start + 3..];
+    let level = rest.get(..1)?.parse::<u8>().ok()?;
+    let (thread, message) = rest[1..].strip_prefix('[')?.split_once("]: ")?;
+    let logged_at = NaiveDateTime::parse_from_str(line[..start].trim(), TIMESTAMP_FORMAT)
+        .ok()
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(|time| time.with_timezone(&Utc));
+    Some(LogLine {
+        level,
+        thread: thread.to_string(),
+        message: message.trim_end().to_string(),
+        logged_at,
+    })
+}
+
+/// Returns the log file `config` tells stunnel to write, if any. Logging to
+/// standard output or syslog alone leaves nothing to read.
+pub fn log_path(config: &StunnelConfig) -> Option<PathBuf> {
+    let output = config.global("output")?;
+    (!output.starts_with("/dev/")).then(|| PathBuf::from(output))
+}
+
+/// A read position in a log file, so only lines logged after it was taken
+/// are returned.
+#[derive(Debug, Clone)]
+pub struct LogCursor {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl LogCursor {
+    /// Starts at the current end of `path`; a file that does not exist yet
+    /// is read from its start once created.
+    pub fn at_end(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let offset = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        Self { path, offset }
+    }
+
+    /// The file being read.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the complete lines appended since the last read. A file that
+    /// shrank, e.g. after log rotation, is read again from its start.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read.
+    pub fn read_new(&mut self) -> io::Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        // A trailing partial line is left for the next read
+        let complete = appended
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+        self.offset += complete as u64;
+        Ok(String::from_utf8_lossy(&appended[..complete])
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Returns the last message logged at error level or above among the
+/// final lines of the log file at `path`, e.g. why stunnel exited.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn last_error(path: impl AsRef<Path>) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    Ok(String::from_utf8_lossy(&tail)
+        .lines()
+        .rev()
+        .filter_map(parse_line)
+        .find(|line| line.level <= ERROR_LEVEL)
+        .map(|line| line.message))
+}
+
+/// What stunnel did with a reloaded config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadOutcome {
+    /// The log did not show the outcome before the timeout.
+    Unknown,
+    /// The new config was loaded.
+    Applied,
+    /// The new config was rejected and the old one kept.
+    Rejected,
+}
+
+/// The outcome of a reload and the errors stunnel logged while reloading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadResult {
+    pub outcome: ReloadOutcome,
+    /// Messages logged at error level or above, e.g. the config line that
+    /// could not be parsed.
+    pub errors: Vec<String>,
+}
+
+/// The outcome a log line reports, if it reports one. stunnel logs an
+/// applied config at start as well as on reload.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::logs::{parse_line, reload_outcome, ReloadOutcome};
+///
+/// let line = parse_line("LOG5[main]: Configuration successful").unwrap();
+/// assert_eq!(reload_outcome(&line), Some(ReloadOutcome::Applied));
+/// ```
+pub fn reload_outcome(line: &LogLine) -> Option<ReloadOutcome> {
+    match line.message.as_str() {
+        RELOAD_APPLIED => Some(ReloadOutcome::Applied),
+        RELOAD_REJECTED => Some(ReloadOutcome::Rejected),
+        _ => None,
+    }
+}
+
+/// Reads the lines logged after `cursor` until stunnel reports whether a
+/// reload was applied or rejected, or `timeout` passes.
+///
+/// stunnel only logs a successful reload at `debug = 5` or higher (its
+/// default); below that a reload without errors is reported as
+/// [`ReloadOutcome::Unknown`].
+///
+/// # Example
+///
+/// ```no_run
+/// use stunnel_space::logs::{wait_for_reload, LogCursor, ReloadOutcome, RELOAD_TIMEOUT};
+///
+/// # async fn example() {
+/// let mut cursor = LogCursor::at_end("/var/log/stunnel.log");
+/// // ... send SIGHUP ...
+/// let result = wait_for_reload(&mut cursor, RELOAD_TIMEOUT).await;
+/// if result.outcome == ReloadOutcome::Rejected {
+///     eprintln!("Reload rejected: {}", result.errors.join("; "));
+/// }
+/// # }
+/// ```
+#[tracing::instrument(skip_all)]
+pub async fn wait_for_reload(cursor: &mut LogCursor, timeout: Duration) -> ReloadResult {
+    let deadline = Instant::now() + timeout;
+    let mut errors = Vec::new();
+    loop {
+        let lines = cursor.read_new().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read {}: {}", cursor.path.display(), e);
+            vec![]
+        });
+        for line in lines.iter().filter_map(|line| parse_line(line)) {
+            if let Some(outcome) = reload_outcome(&line) {
+                return ReloadResult { outcome, errors };
+            }
+            if line.level <= ERROR_LEVEL {
+                errors.push(line.message);
+            }
+        }
+        if Instant::now() >= deadline {
+            return ReloadResult {
+                outcome: ReloadOutcome::Unknown,
+                errors,
+            };
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -1,6 +1,99 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use stunnel_space::accesslog::AccessLogLayer;
+use stunnel_space::acme::{AcmeManager, AcmeSettings};
+use stunnel_space::auth::{self, Authenticator, JwksCache};
+use stunnel_space::backend::BackendKind;
+use stunnel_space::certstore::CertStore;
+use stunnel_space::connlog::ConnectionLog;
+use stunnel_space::expiry::ExpiryMonitor;
+use stunnel_space::fragments::FragmentDir;
+use stunnel_space::geoip::GeoIp;
+use stunnel_space::git::GitVersioning;
+use stunnel_space::history::HistoryStore;
+use stunnel_space::idempotency::IdempotencyLayer;
+use stunnel_space::instances::{InstanceRegistry, InstanceSpec};
+use stunnel_space::logging;
+use stunnel_space::maintenance::MaintenanceMode;
+use stunnel_space::metrics;
+use stunnel_space::namespaces::NamespaceSet;
+use stunnel_space::ratelimit::RateLimiter;
+use stunnel_space::rbac::{self, ApiKeyStore};
+use stunnel_space::secrets::SecretCipher;
+use stunnel_space::staging::StagingArea;
+use stunnel_space::statsd::StatsdSink;
 use stunnel_space::stunnel::stunnel_manager_server::StunnelManagerServer;
+use stunnel_space::supervisor::Supervisor;
+#[cfg(feature = "systemd")]
+use stunnel_space::systemd::Notifier;
+use stunnel_space::telemetry;
+use stunnel_space::templates::TemplateStore;
+use stunnel_space::traffic;
+use stunnel_space::validation::ValidationPipeline;
+use stunnel_space::vault::VaultManager;
+use stunnel_space::watcher;
 use stunnel_space::{Config, StunnelServer};
-use tonic::transport::Server;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::oneshot;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Body, Certificate, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Status};
+use tonic_health::ServingStatus;
+use tower::util::MapRequestLayer;
+use tower::Layer;
+use tracing::{info, warn};
+
+// Authenticates requests, refuses changes in maintenance mode, then applies
+// rate limits, so limits are counted per authenticated client and refused
+// changes do not use them up. Authentication and rate limits may be disabled.
+#[derive(Clone)]
+struct Interceptors {
+    authenticator: Option<Authenticator>,
+    maintenance: MaintenanceMode,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Interceptor for Interceptors {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let request = match &mut self.authenticator {
+            Some(authenticator) => authenticator.call(request)?,
+            None => request,
+        };
+        let request = self.maintenance.call(request)?;
+        match &mut self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.call(request),
+            None => Ok(request),
+        }
+    }
+}
+
+// Resolves with the name of the first SIGTERM or SIGINT received.
+async fn shutdown_signal() -> &'static str {
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+// The instance configured by STUNNEL_CONF_PATH, run by the process backend
+// selected by PROCESS_BACKEND.
+fn default_instance(config: &Config) -> InstanceSpec {
+    InstanceSpec {
+        config_path: config.config_path.clone(),
+        pid_file: config.pid_file.clone(),
+        systemd_unit: (config.process_backend == BackendKind::Systemd)
+            .then(|| config.systemd_unit.clone()),
+        namespace: None,
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -23,22 +116,423 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    // Print configuration
-    config.print_config();
+    // Send the manager's log, filtered by LOG_LEVEL, to journald or stderr,
+    // and spans to an OTLP collector if one is configured
+    let tracer = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| telemetry::otlp_tracer(endpoint, &config.otel_service_name))
+        .transpose()?;
+    let log_target = logging::init(
+        config.log_target,
+        config.log_format,
+        &config.log_level,
+        tracer,
+    )?;
+
+    // Log configuration
+    config.log_config();
+    info!("Logging to {}", log_target);
 
     // Parse gRPC address
-    let addr = config.get_grpc_address().parse()?;
+    let addr: SocketAddr = config.get_grpc_address().parse()?;
 
     // Create stunnel server with config values
-    let stunnel_server = StunnelServer::new(config.config_path.clone(), config.pid_file.clone());
+    let mut stunnel_server =
+        StunnelServer::new(config.config_path.clone(), config.pid_file.clone())
+            .with_backup_policy(config.backup_policy())
+            .with_key_permission_policy(config.key_permission_policy)
+            .with_dns_check_policy(config.dns_check)
+            .with_readiness_timeout(Duration::from_secs(config.readiness_timeout_secs))
+            .with_start_retry(config.start_retry_policy())
+            .with_process_backend(default_instance(&config).process_backend(config.repair_pid_file))
+            .with_journal_unit(&config.systemd_unit)
+            .with_secret_redaction(config.redact_secrets)
+            .with_payload_logging(config.log_payloads)
+            .with_allowed_config_paths(
+                config
+                    .config_path_allowlist
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            );
+
+    // Refuse changes while in maintenance mode, toggled by admins over RPC
+    let maintenance = MaintenanceMode::new();
+    if config.maintenance_mode {
+        maintenance.enable("enabled at startup by MAINTENANCE_MODE", "environment");
+    }
+    stunnel_server = stunnel_server.with_maintenance_mode(maintenance.clone());
+
+    // Encrypt backups, history and managed keys at rest if a key is configured
+    let secrets = match (&config.secrets_key, &config.secrets_key_command) {
+        (Some(key), _) => Some(SecretCipher::from_base64(key)?),
+        (None, Some(command)) => Some(SecretCipher::from_command(command)?),
+        (None, None) => None,
+    }
+    .map(Arc::new);
+    if let Some(cipher) = &secrets {
+        stunnel_server = stunnel_server.with_secrets(cipher.clone());
+    }
+
+    // Open the config history database if one is configured
+    if let Some(history_db_path) = &config.history_db_path {
+        let mut store = HistoryStore::open(history_db_path)
+            .map_err(|e| format!("Failed to open history database: {}", e))?;
+        if let Some(cipher) = &secrets {
+            store = store.with_cipher(cipher.clone());
+        }
+        stunnel_server = stunnel_server.with_history(store);
+    }
+
+    // Look up where peers are if GeoIP databases are configured
+    let geoip = match (&config.geoip_country_db, &config.geoip_asn_db) {
+        (None, None) => None,
+        (country_db, asn_db) => Some(Arc::new(GeoIp::open(
+            country_db.as_deref(),
+            asn_db.as_deref(),
+        )?)),
+    };
+    if let Some(geoip) = &geoip {
+        stunnel_server = stunnel_server.with_geoip(geoip.clone());
+    }
+
+    // Open the connection history database if one is configured
+    if let Some(connection_log_db_path) = &config.connection_log_db_path {
+        let mut log = ConnectionLog::open(connection_log_db_path)
+            .map_err(|e| format!("Failed to open connection history database: {}", e))?
+            .with_retention(Duration::from_secs(
+                u64::from(config.connection_log_retention_days) * 24 * 60 * 60,
+            ));
+        if let Some(geoip) = &geoip {
+            log = log.with_geoip(geoip.clone());
+        }
+        stunnel_server = stunnel_server.with_connection_log(log);
+    }
+
+    // Commit config changes to Git if enabled
+    if config.git_versioning {
+        let git = GitVersioning::open(&config.config_path)
+            .map_err(|e| format!("Failed to open config Git repository: {}", e))?;
+        stunnel_server = stunnel_server.with_git_versioning(git);
+    }
+
+    // Keep each added provider in its own file if a providers directory is set
+    if let Some(providers_dir) = &config.providers_dir {
+        let fragments = FragmentDir::open(providers_dir)
+            .map_err(|e| format!("Failed to open providers directory: {}", e))?;
+        stunnel_server = stunnel_server.with_providers_dir(fragments);
+    }
+
+    // Load persisted provider templates
+    if let Some(templates_dir) = &config.templates_dir {
+        let templates = TemplateStore::open(templates_dir)
+            .map_err(|e| format!("Failed to load templates: {}", e))?;
+        stunnel_server = stunnel_server.with_templates(templates);
+    }
+
+    // Load configs staged for approval
+    if let Some(staging_dir) = &config.staging_dir {
+        let staging = StagingArea::open(staging_dir)
+            .map_err(|e| format!("Failed to load staged configs: {}", e))?;
+        stunnel_server = stunnel_server.with_staging_area(staging);
+    }
+
+    // Manage further instances, created over RPC and optionally persisted
+    let mut instances = InstanceRegistry::new();
+    if let Some(instances_file) = &config.instances_file {
+        instances = InstanceRegistry::open(instances_file)
+            .map_err(|e| format!("Failed to load instances: {}", e))?;
+        info!(
+            "Loaded {} instance(s) from {}",
+            instances.list().len(),
+            instances_file
+        );
+    }
+    stunnel_server =
+        stunnel_server.with_instances(instances.with_pid_file_repair(config.repair_pid_file));
+
+    // Tenant namespaces scoping API keys to their own instances
+    if let Some(namespaces_file) = &config.namespaces_file {
+        let namespaces = NamespaceSet::open(namespaces_file)?;
+        info!(
+            "Loaded {} namespace(s) from {}",
+            namespaces.len(),
+            namespaces_file
+        );
+        stunnel_server = stunnel_server.with_namespaces(namespaces);
+    }
+
+    // Validators run on every candidate config
+    stunnel_server =
+        stunnel_server.with_validation_pipeline(ValidationPipeline::from_spec(&config.validators)?);
+
+    // Allocate accept ports for providers added without one
+    if let Some(range) = config.accept_port_range {
+        stunnel_server = stunnel_server.with_accept_port_range(range);
+    }
+
+    // Keep generated keys and uploaded certificates in a managed directory
+    if let Some(certs_dir) = &config.certs_dir {
+        let mut store = CertStore::open(certs_dir)
+            .map_err(|e| format!("Failed to open certificates directory: {}", e))?;
+        if let Some(owner) = config.key_file_owner {
+            store = store.with_key_owner(owner)?;
+        }
+        if let Some(cipher) = &secrets {
+            store = store.with_sealed_keys(cipher.clone(), &config.secrets_key_dir)?;
+            let keys = store.materialize_keys()?;
+            info!(
+                "Decrypted {} managed key(s) to {}",
+                keys.len(),
+                config.secrets_key_dir
+            );
+        }
+        stunnel_server = stunnel_server.with_cert_store(store.clone());
+
+        // Issue and renew certificates over ACME if a directory is configured
+        if let Some(directory_url) = &config.acme_directory_url {
+            let settings = AcmeSettings {
+                directory_url: directory_url.clone(),
+                contact_email: config.acme_contact_email.clone(),
+                challenge: config.acme_challenge,
+                webroot: config.acme_webroot.as_ref().map(PathBuf::from),
+                dns_hook: config.acme_dns_hook.as_ref().map(PathBuf::from),
+                renew_days: config.acme_renew_days,
+            };
+            let manager = AcmeManager::new(settings, store.clone())
+                .map_err(|e| format!("Failed to set up ACME: {}", e))?;
+            stunnel_server = stunnel_server.with_acme(manager);
+            stunnel_server.spawn_acme_renewal(Duration::from_secs(config.acme_check_interval_secs));
+        }
+
+        // Fetch certificates and keys from Vault if an address is configured
+        if let (Some(vault_addr), Some(vault_token)) = (&config.vault_addr, &config.vault_token) {
+            let mut manager = VaultManager::new(vault_addr, vault_token.clone(), store.clone());
+            if let Some(namespace) = &config.vault_namespace {
+                manager = manager.with_namespace(namespace.clone());
+            }
+            stunnel_server = stunnel_server.with_vault(manager);
+            stunnel_server
+                .spawn_vault_refresh(Duration::from_secs(config.vault_refresh_interval_secs));
+        }
+    } else if config.acme_directory_url.is_some() {
+        return Err("ACME_DIRECTORY_URL requires CERTS_DIR".into());
+    } else if config.vault_addr.is_some() {
+        return Err("VAULT_ADDR requires CERTS_DIR".into());
+    }
+
+    // Warn about expiring certificates
+    let mut expiry_monitor = ExpiryMonitor::new(config.cert_expiry_warning_days);
+    if let Some(webhook_url) = &config.cert_expiry_webhook_url {
+        expiry_monitor = expiry_monitor.with_webhook(webhook_url.clone());
+    }
+    stunnel_server = stunnel_server.with_expiry_monitor(expiry_monitor);
+    if let Some(secs) = config.cert_check_interval_secs {
+        stunnel_server.spawn_expiry_monitor(Duration::from_secs(secs));
+    }
+
+    // Reload stunnel when rotation tools replace certificate files
+    if config.cert_watch {
+        stunnel_server
+            .spawn_cert_watcher(watcher::DEFAULT_DEBOUNCE)
+            .map_err(|e| format!("Failed to watch certificate files: {}", e))?;
+    }
+
+    // Serve over TLS, verifying client certificates if a client CA is set
+    let mut server = Server::builder();
+    if let (Some(cert), Some(key)) = (&config.grpc_tls_cert, &config.grpc_tls_key) {
+        let read =
+            |path: &str| fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+        let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+        if let Some(client_ca) = &config.grpc_tls_client_ca {
+            tls = tls
+                .client_ca_root(Certificate::from_pem(read(client_ca)?))
+                .client_auth_optional(config.grpc_tls_client_auth_optional);
+        }
+        server = server
+            .tls_config(tls)
+            .map_err(|e| format!("Invalid gRPC TLS configuration: {}", e))?;
+    } else if !addr.ip().is_loopback() {
+        warn!(
+            "gRPC server on {} accepts plaintext connections from the network; set GRPC_TLS_CERT and GRPC_TLS_KEY to enable TLS",
+            addr
+        );
+    }
+
+    // Require a bearer token on every RPC if a shared secret, API keys or
+    // JWKS is set, and check the token's role against the method called
+    let mut authenticator = None;
+    if config.auth_token.is_some() || config.api_keys_file.is_some() || config.jwks_url.is_some() {
+        let mut auth = Authenticator::new();
+        if let Some(token) = &config.auth_token {
+            auth = auth.with_token(token);
+        }
+        if let Some(path) = &config.api_keys_file {
+            let keys = ApiKeyStore::open(path)?;
+            info!("Loaded {} API key(s) from {}", keys.len(), path);
+            auth = auth.with_api_keys(Arc::new(keys));
+        }
+        if let Some(jwks_url) = &config.jwks_url {
+            let jwks = Arc::new(JwksCache::new(jwks_url.clone()));
+            // Keep starting if the identity provider is down; JWTs are
+            // rejected until a refresh succeeds
+            match jwks.refresh().await {
+                Ok(count) => info!("Loaded {} JWT signing key(s) from {}", count, jwks_url),
+                Err(e) => warn!("{}", e),
+            }
+            jwks.spawn_refresh(auth::DEFAULT_JWKS_REFRESH);
+            auth = auth.with_jwks(jwks);
+        }
+        if let Some(issuer) = &config.jwt_issuer {
+            auth = auth.with_issuer(issuer.clone());
+        }
+        if let Some(audience) = &config.jwt_audience {
+            auth = auth.with_audience(audience.clone());
+        }
+        authenticator = Some(auth);
+    } else {
+        warn!("gRPC API authentication is disabled; set STUNNEL_MGR_AUTH_TOKEN, STUNNEL_MGR_API_KEYS_FILE or STUNNEL_MGR_JWKS_URL");
+    }
+
+    // Throttle config mutations if limits are configured
+    let mut rate_limiter = None;
+    if config.rate_limit_per_client.is_some() || config.rate_limit_global.is_some() {
+        let mut limiter = RateLimiter::new();
+        if let Some(limit) = config.rate_limit_per_client {
+            limiter = limiter.with_per_client_limit(limit);
+        }
+        if let Some(limit) = config.rate_limit_global {
+            limiter = limiter.with_global_limit(limit);
+        }
+        rate_limiter = Some(limiter);
+    }
+
+    // Restart stunnel if it crashes
+    if config.supervise_stunnel {
+        stunnel_server = stunnel_server.with_supervisor(Supervisor::new());
+        stunnel_server.spawn_supervisor(Duration::from_secs(config.supervisor_interval_secs));
+    }
+
+    // Add up per-provider traffic from stunnel's log for GetTrafficStats and
+    // record connections for QueryConnections
+    stunnel_server.spawn_traffic_follower(traffic::POLL_INTERVAL);
+
+    // Serve OpenMetrics over HTTP if an address is configured
+    if let Some(metrics_address) = config.metrics_address {
+        let manager = stunnel_server.clone();
+        metrics::serve(metrics_address, move || manager.render_metrics())?;
+        info!("Serving metrics on http://{}/metrics", metrics_address);
+    }
+
+    // Push the same metrics to StatsD if an address is configured
+    if let Some(statsd_address) = &config.statsd_address {
+        let mut sink = StatsdSink::connect(statsd_address)?;
+        if let Some(prefix) = &config.statsd_prefix {
+            sink = sink.with_prefix(prefix);
+        }
+        info!("Pushing metrics to StatsD at {}", sink.target());
+        stunnel_server.spawn_statsd_push(sink, Duration::from_secs(config.statsd_interval_secs));
+    }
+
+    // Serve grpc.health.v1.Health for load balancers and probes, outside
+    // authentication and rate limits
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    stunnel_server.spawn_health_reporter(
+        health_reporter.clone(),
+        config.health_check_stunnel,
+        Duration::from_secs(config.health_check_interval_secs),
+    );
+
+    // On SIGTERM or SIGINT, fail health checks and end streams, then stop
+    // accepting connections and let in-flight RPCs finish
+    let manager = stunnel_server.clone();
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let (draining_tx, draining_rx) = oneshot::channel();
+
+    // Under systemd, report readiness, watchdog keepalives and status
+    #[cfg(feature = "systemd")]
+    let notifier = Notifier::from_env()
+        .map_err(|e| format!("Invalid NOTIFY_SOCKET: {}", e))?
+        .map(Arc::new);
+
+    let signalled = {
+        let manager = manager.clone();
+        let mut health_reporter = health_reporter;
+        #[cfg(feature = "systemd")]
+        let notifier = notifier.clone();
+        async move {
+            let name = shutdown_signal().await;
+            #[cfg(feature = "systemd")]
+            if let Some(notifier) = &notifier {
+                let _ = notifier.stopping();
+            }
+            info!(
+                "Received {}, draining in-flight requests (up to {}s)",
+                name,
+                shutdown_timeout.as_secs()
+            );
+            health_reporter
+                .set_service_status("", ServingStatus::NotServing)
+                .await;
+            manager.begin_shutdown();
+            let _ = draining_tx.send(());
+        }
+    };
+
+    info!("Starting gRPC server on {}", addr);
+    let incoming = TcpIncoming::new(addr, false, None)
+        .map_err(|e| format!("Failed to bind gRPC server to {}: {}", addr, e))?;
+
+    #[cfg(feature = "systemd")]
+    if let Some(notifier) = notifier {
+        if let Err(e) = notifier.ready(&manager.status_summary()) {
+            warn!("Failed to notify systemd of readiness: {}", e);
+        }
+        let manager = manager.clone();
+        notifier.spawn_keepalive(move || manager.status_summary());
+    }
 
-    println!("\nStarting gRPC server on {}", addr);
+    // Start the gRPC server, logging every call and tagging requests with the
+    // method they call for the role check. Responses to retried keyed
+    // mutations are replayed only once the interceptors let the retry through
+    let mut server = server
+        .layer(AccessLogLayer)
+        .layer(MapRequestLayer::new(rbac::tag_method::<Body>));
+    let router = server.add_service(health_service);
+    let router = router.add_service(InterceptedService::new(
+        IdempotencyLayer::new(Duration::from_secs(config.idempotency_ttl_secs))
+            .layer(StunnelManagerServer::new(stunnel_server)),
+        Interceptors {
+            authenticator,
+            maintenance,
+            rate_limiter,
+        },
+    ));
+    let serve = router.serve_with_incoming_shutdown(incoming, signalled);
+    tokio::pin!(serve);
+    tokio::select! {
+        result = &mut serve => result?,
+        _ = async {
+            if draining_rx.await.is_ok() {
+                tokio::time::sleep(shutdown_timeout).await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        } => warn!(
+            "In-flight requests did not finish within {}s; exiting anyway",
+            shutdown_timeout.as_secs()
+        ),
+    }
 
-    // Start the gRPC server
-    Server::builder()
-        .add_service(StunnelManagerServer::new(stunnel_server))
-        .serve(addr)
-        .await?;
+    // Finish any config write still in progress, then leave stunnel running
+    // or stop it
+    let stop_stunnel = config.shutdown_stop_stunnel;
+    tokio::task::spawn_blocking(move || manager.shutdown(stop_stunnel, shutdown_timeout)).await??;
+    info!("Shutdown complete");
+    if config.otlp_endpoint.is_some() {
+        tokio::task::spawn_blocking(telemetry::shutdown).await?;
+    }
 
     Ok(())
 }
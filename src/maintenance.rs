@@ -0,0 +1,93 @@
+//! Maintenance (read-only) mode.
+//!
+//! While maintenance mode is on, every RPC that changes the config or
+//! signals stunnel (anything the read-only role may not call) is refused
+//! with `FAILED_PRECONDITION`, so a change freeze holds or an operator can
+//! hand-edit the config without an automation overwriting it. Status
+//! queries keep working. The mode can be set at startup with
+//! `MAINTENANCE_MODE` and toggled by admins with SetMaintenanceMode.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::rbac::{self, Role, RpcMethod};
+
+// The RPC toggling the mode, which stays callable while it is on.
+const SET_METHOD: &str = "SetMaintenanceMode";
+
+/// Why and since when the manager has been in maintenance mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Maintenance {
+    pub reason: String,
+    pub enabled_by: String,
+    pub since: DateTime<Utc>,
+}
+
+/// Shared maintenance mode switch, doubling as the interceptor enforcing it.
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceMode {
+    state: Arc<RwLock<Option<Maintenance>>>,
+}
+
+impl MaintenanceMode {
+    /// Creates a switch with maintenance mode off.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turns maintenance mode on, or updates its reason if already on.
+    pub fn enable(&self, reason: &str, enabled_by: &str) -> Maintenance {
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let since = state
+            .as_ref()
+            .map(|maintenance| maintenance.since)
+            .unwrap_or_else(Utc::now);
+        let maintenance = Maintenance {
+            reason: reason.to_string(),
+            enabled_by: enabled_by.to_string(),
+            since,
+        };
+        *state = Some(maintenance.clone());
+        maintenance
+    }
+
+    /// Turns maintenance mode off, returning whether it was on.
+    pub fn disable(&self) -> bool {
+        self.state
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .is_some()
+    }
+
+    /// Returns the current maintenance, if the mode is on.
+    pub fn current(&self) -> Option<Maintenance> {
+        self.state.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Interceptor for MaintenanceMode {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        // Untagged requests are treated as mutations
+        let method = request
+            .extensions()
+            .get::<RpcMethod>()
+            .map(|method| method.0.as_str())
+            .unwrap_or_default();
+        if method == SET_METHOD || rbac::required_role(method) == Role::ReadOnly {
+            return Ok(request);
+        }
+        match self.current() {
+            None => Ok(request),
+            Some(maintenance) if maintenance.reason.is_empty() => Err(Status::failed_precondition(
+                "The manager is in maintenance mode; changes are refused",
+            )),
+            Some(maintenance) => Err(Status::failed_precondition(format!(
+                "The manager is in maintenance mode ({}); changes are refused",
+                maintenance.reason
+            ))),
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Metrics in the OpenMetrics text format.
+//!
+//! When enabled, `GET /metrics` on a separate HTTP listener returns, for
+//! every instance, series labelled with the instance, the provider and the
+//! port it accepts on:
+//!
+//! - `stunnel_provider_active_connections`: connections open now
+//! - `stunnel_provider_connections_total`: connections closed since the
+//!   manager started, from stunnel's log (see [`crate::traffic`])
+//! - `stunnel_provider_bytes_to_tls_total` and
+//!   `stunnel_provider_bytes_to_socket_total`: what those forwarded
+//! - `stunnel_certificate_expiry_timestamp`: when each certificate file the
+//!   provider presents or trusts expires, as Unix seconds, so an alert can
+//!   fire on `stunnel_certificate_expiry_timestamp - time() < 30 * 86400`
+//!
+//! and, per instance, `stunnel_reloads_total` with an `outcome` label of
+//! `applied` or `rejected`: the configs stunnel logged loading since the
+//! manager started. The same samples can be pushed to StatsD instead; see
+//! [`crate::statsd`].
+//!
+//! The endpoint is plain HTTP without authentication; bind it to an
+//! address only the scraper can reach.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+
+use crate::expiry;
+use crate::parser::{split_host_port, Section, StunnelConfig};
+use crate::stunnel::Connection;
+use crate::traffic::{ReloadCounts, ServiceTraffic};
+
+/// Content type of the rendered metrics.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// What a series measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Gauge,
+    Counter,
+}
+
+// Every family the manager exports, in the order they are rendered.
+const FAMILIES: [(&str, Kind, &str); 6] = [
+    (
+        "stunnel_provider_active_connections",
+        Kind::Gauge,
+        "Connections to the provider open now.",
+    ),
+    (
+        "stunnel_provider_connections",
+        Kind::Counter,
+        "Connections to the provider closed since the manager started.",
+    ),
+    (
+        "stunnel_provider_bytes_to_tls",
+        Kind::Counter,
+        "Bytes the provider's closed connections sent over TLS.",
+    ),
+    (
+        "stunnel_provider_bytes_to_socket",
+        Kind::Counter,
+        "Bytes the provider's closed connections sent to the plaintext socket.",
+    ),
+    (
+        "stunnel_certificate_expiry_timestamp",
+        Kind::Gauge,
+        "When a certificate file the provider presents or trusts expires, in Unix seconds.",
+    ),
+    (
+        "stunnel_reloads",
+        Kind::Counter,
+        "Configs stunnel logged applying, at start or on reload, or rejecting since the manager started.",
+    ),
+];
+
+/// Label names and values of a sample.
+pub type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug)]
+struct Family {
+    name: &'static str,
+    kind: Kind,
+    help: &'static str,
+    samples: Vec<(Labels, f64)>,
+}
+
+/// Samples of the exported metric families, collected for one scrape.
+#[derive(Debug)]
+pub struct MetricSet {
+    families: Vec<Family>,
+}
+
+impl Default for MetricSet {
+    fn default() -> Self {
+        Self {
+            families: FAMILIES
+                .iter()
+                .map(|&(name, kind, help)| Family {
+                    name,
+                    kind,
+                    help,
+                    samples: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl MetricSet {
+    /// Creates a set without samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample of the family `name`; samples of unknown families are
+    /// dropped.
+    pub fn add(&mut self, name: &str, labels: Labels, value: f64) {
+        if let Some(family) = self.families.iter_mut().find(|family| family.name == name) {
+            family.samples.push((labels, value));
+        }
+    }
+
+    /// Every sample with the name and kind of its family, in render order.
+    pub fn samples(&self) -> impl Iterator<Item = (&'static str, Kind, &Labels, f64)> + '_ {
+        self.families.iter().flat_map(|family| {
+            family
+                .samples
+                .iter()
+                .map(move |(labels, value)| (family.name, family.kind, labels, *value))
+        })
+    }
+
+    /// Renders the samples in the OpenMetrics text format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::metrics::MetricSet;
+    ///
+    /// let mut metrics = MetricSet::new();
+    /// metrics.add(
+    ///     "stunnel_provider_connections",
+    ///     vec![("provider", "web".to_string()), ("accept_port", "443".to_string())],
+    ///     3.0,
+    /// );
+    /// let text = metrics.render();
+    /// assert!(text.contains("stunnel_provider_connections_total{provider=\"web\",accept_port=\"443\"} 3\n"));
+    /// assert!(text.ends_with("# EOF\n"));
+    /// ```
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for family in &self.families {
+            let (kind, suffix) = match family.kind {
+                Kind::Gauge => ("gauge", ""),
+                Kind::Counter => ("counter", "_total"),
+            };
+            let _ = writeln!(text, "# TYPE {} {}", family.name, kind);
+            let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+            for (labels, value) in &family.samples {
+                let labels = labels
+                    .iter()
+                    .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let _ = writeln!(text, "{}{}{{{}}} {}", family.name, suffix, labels, value);
+            }
+        }
+        text.push_str("# EOF\n");
+        text
+    }
+}
+
+/// Adds the series of one instance: per provider in `config`, its open
+/// `connections`, its `traffic` totals and the expiry of its certificates;
+/// and the instance's `reloads`.
+pub fn collect_instance(
+    metrics: &mut MetricSet,
+    instance: &str,
+    config: &StunnelConfig,
+    traffic: &BTreeMap<String, ServiceTraffic>,
+    reloads: ReloadCounts,
+    connections: &[Connection],
+    now: DateTime<Utc>,
+) {
+    let labels = |section: &Section| -> Labels {
+        vec![
+            ("instance", instance.to_string()),
+            ("provider", section.name.clone()),
+            ("accept_port", accept_port(section).unwrap_or_default()),
+        ]
+    };
+    for section in &config.sections {
+        let active = accept_port(section).map_or(0, |port| {
+            connections
+                .iter()
+                .filter(|connection| {
+                    connection
+                        .local_address
+                        .rsplit_once(':')
+                        .is_some_and(|(_, local)| local == port)
+                })
+                .count()
+        });
+        let totals = traffic.get(&section.name).copied().unwrap_or_default();
+        metrics.add(
+            "stunnel_provider_active_connections",
+            labels(section),
+            active as f64,
+        );
+        metrics.add(
+            "stunnel_provider_connections",
+            labels(section),
+            totals.connections as f64,
+        );
+        metrics.add(
+            "stunnel_provider_bytes_to_tls",
+            labels(section),
+            totals.bytes_to_tls as f64,
+        );
+        metrics.add(
+            "stunnel_provider_bytes_to_socket",
+            labels(section),
+            totals.bytes_to_socket as f64,
+        );
+    }
+    for status in expiry::check_config(config, now) {
+        let Some(not_after) = status.not_after else {
+            continue;
+        };
+        for section in config
+            .sections
+            .iter()
+            .filter(|section| uses_certificate(config, section, &status.path))
+        {
+            let mut labels = labels(section);
+            labels.push(("path", status.path.clone()));
+            metrics.add(
+                "stunnel_certificate_expiry_timestamp",
+                labels,
+                not_after.timestamp() as f64,
+            );
+        }
+    }
+    for (outcome, count) in [("applied", reloads.applied), ("rejected", reloads.rejected)] {
+        metrics.add(
+            "stunnel_reloads",
+            vec![
+                ("instance", instance.to_string()),
+                ("outcome", outcome.to_string()),
+            ],
+            count as f64,
+        );
+    }
+}
+
+/// Serves `render()` as `GET /metrics` on `addr` until the process exits.
+/// `render` may block; it runs on the blocking thread pool.
+///
+/// # Errors
+///
+/// Returns an error if `addr` cannot be bound.
+pub fn serve<F>(addr: SocketAddr, render: F) -> Result<tokio::task::JoinHandle<()>, String>
+where
+    F: Fn() -> String + Send + Sync + 'static,
+{
+    let render = Arc::new(render);
+    let builder = hyper::Server::try_bind(&addr)
+        .map_err(|e| format!("Failed to bind metrics endpoint to {}: {}", addr, e))?;
+    let make_service = make_service_fn(move |_| {
+        let render = render.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| respond(request, render.clone()))) }
+    });
+    let server = builder.serve(make_service);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            tracing::error!("Metrics endpoint failed: {}", e);
+        }
+    }))
+}
+
+async fn respond<F>(request: Request<Body>, render: Arc<F>) -> Result<Response<Body>, Infallible>
+where
+    F: Fn() -> String + Send + Sync + 'static,
+{
+    let reply = |status: StatusCode, content_type: &str, body: String| {
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        if let Ok(value) = header::HeaderValue::from_str(content_type) {
+            response.headers_mut().insert(header::CONTENT_TYPE, value);
+        }
+        response
+    };
+    if request.uri().path() != "/metrics" {
+        return Ok(reply(
+            StatusCode::NOT_FOUND,
+            "text/plain",
+            "Not found\n".to_string(),
+        ));
+    }
+    if request.method() != Method::GET {
+        return Ok(reply(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "text/plain",
+            "Use GET\n".to_string(),
+        ));
+    }
+    Ok(match tokio::task::spawn_blocking(move || render()).await {
+        Ok(text) => reply(StatusCode::OK, CONTENT_TYPE, text),
+        Err(e) => reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "text/plain",
+            format!("Metrics task failed: {}\n", e),
+        ),
+    })
+}
+
+// The port `section` accepts on, as a label value.
+fn accept_port(section: &Section) -> Option<String> {
+    let accept = section.get("accept")?;
+    split_host_port(accept).1.map(|port| port.to_string())
+}
+
+// Whether `section` presents or trusts the certificate file `path`: it sets
+// it itself, or the global section does and it inherits that option.
+fn uses_certificate(config: &StunnelConfig, section: &Section, path: &str) -> bool {
+    ["cert", "CAfile"].iter().any(|key| match section.get(key) {
+        Some(own) => own == path,
+        None => config.global(key) == Some(path),
+    })
+}
+
+// Escapes a label value: backslashes, double quotes and newlines.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,430 @@
+//! Tenant namespaces.
+//!
+//! A namespace groups the instances of one tenant. Namespaces are listed in
+//! a YAML (or JSON) file, each with the directory its configs and PID files
+//! must live in and, optionally, the accept ports its services may use:
+//!
+//! ```yaml
+//! namespaces:
+//!   - name: team-a
+//!     config_dir: /etc/stunnel/team-a
+//!     accept_ports: 21000-21099
+//!   - name: team-b
+//!     config_dir: /etc/stunnel/team-b
+//!     accept_ports: 21100-21199
+//! ```
+//!
+//! API keys and JWTs naming namespaces may only see and act on the
+//! instances in them; see [`crate::rbac`]. Their configs may not reach
+//! outside `config_dir`: only options on an allowlist may be set, which
+//! leaves out `exec`, `include`, `engine*`, `setuid`, `setgid` and
+//! `chroot` among others, and every file an option names must be inside
+//! it (see [`NamespacePaths`]).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::lint::Severity;
+use crate::parser::{split_host_port, ConfigOption, StunnelConfig};
+use crate::ports::PortRange;
+use crate::validation::{ConfigValidator, Problem};
+
+// Global options a tenant may set. Any other, such as `engine`,
+// `setuid`, `chroot` or `include`, could run code or act with the
+// manager's privileges and is refused.
+const GLOBAL_OPTIONS: &[&str] = &[
+    "debug",
+    "output",
+    "pid",
+    "foreground",
+    "syslog",
+    "compression",
+    "socket",
+    "RNDbytes",
+    "RNDfile",
+    "RNDoverwrite",
+    "EGD",
+    "taskbar",
+];
+
+// Service options a tenant may set. Any other, such as `exec`, `engineId`,
+// `pty` or `transparent`, is refused.
+const SERVICE_OPTIONS: &[&str] = &[
+    "accept",
+    "connect",
+    "client",
+    "cert",
+    "key",
+    "CAfile",
+    "CApath",
+    "CRLfile",
+    "CRLpath",
+    "checkEmail",
+    "checkHost",
+    "checkIP",
+    "ciphers",
+    "ciphersuites",
+    "curves",
+    "debug",
+    "delay",
+    "failover",
+    "ident",
+    "local",
+    "logId",
+    "OCSP",
+    "OCSPaia",
+    "OCSPflag",
+    "OCSPnonce",
+    "OCSPrequire",
+    "options",
+    "protocol",
+    "protocolAuthentication",
+    "protocolDomain",
+    "protocolHeader",
+    "protocolHost",
+    "protocolPassword",
+    "protocolUsername",
+    "PSKidentity",
+    "PSKsecrets",
+    "renegotiation",
+    "requireCert",
+    "reset",
+    "retry",
+    "securityLevel",
+    "sessionCacheSize",
+    "sessionCacheTimeout",
+    "sessionResume",
+    "sni",
+    "socket",
+    "sslVersion",
+    "sslVersionMax",
+    "sslVersionMin",
+    "TIMEOUTbusy",
+    "TIMEOUTclose",
+    "TIMEOUTconnect",
+    "TIMEOUTidle",
+    "TIMEOUTocsp",
+    "verify",
+    "verifyChain",
+    "verifyPeer",
+];
+
+// Options naming a file or directory stunnel reads or writes.
+const PATH_OPTIONS: [&str; 11] = [
+    "output",
+    "pid",
+    "cert",
+    "key",
+    "CAfile",
+    "CApath",
+    "CRLfile",
+    "CRLpath",
+    "RNDfile",
+    "EGD",
+    "PSKsecrets",
+];
+
+/// A tenant's namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    pub name: String,
+    /// Directory the configs and PID files of its instances must be in.
+    pub config_dir: PathBuf,
+    /// Ports its services may accept on; `None` allows any.
+    pub accept_ports: Option<PortRange>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamespacesFile {
+    #[serde(default)]
+    namespaces: Vec<NamespaceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamespaceEntry {
+    name: String,
+    config_dir: String,
+    #[serde(default)]
+    accept_ports: Option<String>,
+}
+
+/// The namespaces instances may be created in.
+#[derive(Debug, Default)]
+pub struct NamespaceSet {
+    namespaces: BTreeMap<String, Namespace>,
+}
+
+impl NamespaceSet {
+    /// Creates a set without namespaces.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the namespaces file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, a namespace
+    /// is listed twice, its directory is not absolute or its port range is
+    /// invalid.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read namespaces file {}: {}", path, e))?;
+        let file: NamespacesFile = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Invalid namespaces file {}: {}", path, e))?;
+        let mut namespaces = BTreeMap::new();
+        for entry in file.namespaces {
+            let config_dir = PathBuf::from(&entry.config_dir);
+            if !config_dir.is_absolute() {
+                return Err(format!(
+                    "Namespace {} in {}: config_dir must be absolute",
+                    entry.name, path
+                ));
+            }
+            let accept_ports = entry
+                .accept_ports
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| format!("Namespace {} in {}: {}", entry.name, path, e))?;
+            let namespace = Namespace {
+                name: entry.name.clone(),
+                config_dir,
+                accept_ports,
+            };
+            if namespaces.insert(entry.name.clone(), namespace).is_some() {
+                return Err(format!(
+                    "Namespace {} is listed twice in {}",
+                    entry.name, path
+                ));
+            }
+        }
+        Ok(Self { namespaces })
+    }
+
+    /// Returns the namespace `name`.
+    pub fn get(&self, name: &str) -> Option<&Namespace> {
+        self.namespaces.get(name)
+    }
+
+    /// Returns the number of namespaces.
+    pub fn len(&self) -> usize {
+        self.namespaces.len()
+    }
+
+    /// Whether there are no namespaces.
+    pub fn is_empty(&self) -> bool {
+        self.namespaces.is_empty()
+    }
+}
+
+/// Rejects services accepting on ports outside their namespace's range.
+#[derive(Debug, Clone)]
+pub struct NamespacePorts {
+    namespace: String,
+    range: PortRange,
+}
+
+impl NamespacePorts {
+    /// Checks configs of `namespace` against its `range`.
+    pub fn new(namespace: &str, range: PortRange) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            range,
+        }
+    }
+}
+
+impl ConfigValidator for NamespacePorts {
+    fn name(&self) -> &str {
+        "namespace"
+    }
+
+    fn validate(&self, _content: &str, config: &StunnelConfig) -> Result<Vec<Problem>, String> {
+        Ok(config
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let port = section
+                    .get("accept")
+                    .and_then(|accept| split_host_port(accept).1)?;
+                (!self.range.contains(port)).then(|| Problem {
+                    severity: Severity::Error,
+                    line: None,
+                    message: format!(
+                        "Service [{}] accepts on port {}, outside the {} ports of namespace {}",
+                        section.name, port, self.range, self.namespace
+                    ),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Rejects configs reaching outside their namespace's directory: only
+/// allowlisted options may be set, so none runs a program, loads an engine
+/// or includes other files, and every file or directory an option names,
+/// Unix sockets included, must resolve to a path inside it.
+#[derive(Debug, Clone)]
+pub struct NamespacePaths {
+    namespace: String,
+    config_dir: PathBuf,
+}
+
+impl NamespacePaths {
+    /// Checks configs of `namespace` against its `config_dir`.
+    pub fn new(namespace: &str, config_dir: &Path) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            config_dir: resolve(config_dir).unwrap_or_else(|| config_dir.to_path_buf()),
+        }
+    }
+
+    // Why `option`, global or in a service, is not allowed in the
+    // namespace, if it is not.
+    fn check(&self, option: &ConfigOption, global: bool) -> Option<String> {
+        let is = |keys: &[&str]| keys.iter().any(|key| option.key.eq_ignore_ascii_case(key));
+        if !is(if global {
+            GLOBAL_OPTIONS
+        } else {
+            SERVICE_OPTIONS
+        }) {
+            return Some(format!(
+                "{} is not allowed in namespace {}",
+                option.key, self.namespace
+            ));
+        }
+        // accept and connect name a path for Unix sockets
+        let socket = is(&["accept", "connect"]) && option.value.starts_with('/');
+        if !is(&PATH_OPTIONS) && !socket {
+            return None;
+        }
+        let inside = resolve(Path::new(&option.value))
+            .is_some_and(|path| path.starts_with(&self.config_dir));
+        (!inside).then(|| {
+            format!(
+                "{} = {} is outside {}, the directory of namespace {}",
+                option.key,
+                option.value,
+                self.config_dir.display(),
+                self.namespace
+            )
+        })
+    }
+}
+
+impl ConfigValidator for NamespacePaths {
+    fn name(&self) -> &str {
+        "namespace-paths"
+    }
+
+    fn validate(&self, _content: &str, config: &StunnelConfig) -> Result<Vec<Problem>, String> {
+        let globals = config
+            .globals
+            .iter()
+            .map(|option| ("the global section".to_string(), option, true));
+        let services = config.sections.iter().flat_map(|section| {
+            section
+                .options
+                .iter()
+                .map(move |option| (format!("service [{}]", section.name), option, false))
+        });
+        Ok(globals
+            .chain(services)
+            .filter_map(|(place, option, global)| {
+                self.check(option, global).map(|reason| Problem {
+                    severity: Severity::Error,
+                    line: None,
+                    message: format!("In {}: {}", place, reason),
+                })
+            })
+            .collect())
+    }
+}
+
+// Resolves an absolute `path` as the host would open it: `.` and `..` are
+// applied, then symlinks in the longest part of it that exists. `None` for
+// a relative path, which stunnel would resolve against its working
+// directory.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let mut lexical = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            Component::CurDir => {}
+            component => lexical.push(component),
+        }
+    }
+    let mut existing = lexical.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return Some(
+                rest.iter()
+                    .rev()
+                    .fold(canonical, |path, name| path.join(name)),
+            );
+        }
+        rest.push(existing.file_name()?.to_os_string());
+        existing = existing.parent()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(content: &str) -> Vec<String> {
+        let validator = NamespacePaths::new("team-a", Path::new("/srv/team-a"));
+        validator
+            .validate(content, &StunnelConfig::parse(content))
+            .unwrap()
+            .into_iter()
+            .map(|problem| problem.message)
+            .collect()
+    }
+
+    #[test]
+    fn allows_options_inside_the_directory() {
+        let content = "pid = /srv/team-a/a.pid\n\n[web]\naccept = 21000\nconnect = 127.0.0.1:80\ncert = /srv/team-a/web.pem\n";
+        assert!(problems(content).is_empty());
+    }
+
+    #[test]
+    fn rejects_options_off_the_allowlist() {
+        for content in [
+            "engine = pkcs11\n",
+            "engineCtrl = SO_PATH:/tmp/evil.so\n",
+            "setuid = root\n",
+            "setgid = root\n",
+            "chroot = /srv/team-a\n",
+            "include = /srv/team-a/more.conf\n",
+            "[web]\naccept = 21000\nexec = /bin/sh\n",
+            "[web]\naccept = 21000\nengineId = pkcs11\n",
+        ] {
+            assert_eq!(problems(content).len(), 1, "{}", content);
+        }
+        assert!(problems("engineCtrl = SO_PATH:/tmp/evil.so\n")[0]
+            .contains("engineCtrl is not allowed in namespace team-a"));
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_directory() {
+        for content in [
+            "pid = /run/stunnel.pid\n",
+            "output = relative.log\n",
+            "[web]\ncert = /srv/team-a/../team-b/web.pem\n",
+            "[web]\nconnect = /run/docker.sock\n",
+        ] {
+            assert_eq!(problems(content).len(), 1, "{}", content);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Long-running operations.
+//!
+//! Draining a provider, or restarting stunnel and waiting for its services
+//! to listen, can take longer than a client should hold a gRPC call open.
+//! RPCs that support it run in the background when asked and return an
+//! operation ID at once; GetOperation and ListOperations then report
+//! progress and the eventual result, and CancelOperation asks a running
+//! operation to stop at its next safe point.
+//!
+//! Operations are kept in memory: they end with the manager, and finished
+//! ones are forgotten after [`RETENTION`].
+
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long a finished operation can still be looked up.
+pub const RETENTION: Duration = Duration::from_secs(60 * 60);
+
+// Finished operations kept at most, oldest dropped first.
+const MAX_FINISHED: usize = 1000;
+
+// Random bytes in an operation ID.
+const ID_LEN: usize = 16;
+
+/// Where an operation is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationState {
+    Running,
+    Succeeded,
+    Failed,
+    /// Stopped early after CancelOperation.
+    Cancelled,
+}
+
+impl OperationState {
+    /// Whether the operation has ended.
+    pub fn is_finished(&self) -> bool {
+        *self != OperationState::Running
+    }
+}
+
+/// A background operation and, once it has finished, its result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation<R> {
+    pub id: String,
+    /// RPC that started the operation, e.g. `RemoveProvider`.
+    pub method: String,
+    pub started_by: String,
+    pub state: OperationState,
+    /// Latest progress while running, then the outcome.
+    pub message: String,
+    /// Whether cancellation was asked for, honoured or not.
+    pub cancel_requested: bool,
+    /// The response the RPC would have returned, once finished.
+    pub result: Option<R>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// How an operation ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome<R> {
+    pub state: OperationState,
+    pub message: String,
+    pub result: R,
+}
+
+#[derive(Debug)]
+struct Entry<R> {
+    operation: Operation<R>,
+    cancel: watch::Sender<bool>,
+}
+
+/// Background operations, shared by every clone.
+#[derive(Debug)]
+pub struct OperationStore<R> {
+    entries: Arc<Mutex<Vec<Entry<R>>>>,
+}
+
+impl<R> Clone for OperationStore<R> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Arc::clone(&self.entries),
+        }
+    }
+}
+
+impl<R> Default for OperationStore<R> {
+    fn default() -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<R: Clone> OperationStore<R> {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a new running operation for RPC `method`, returning the
+    /// handle its task reports through.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no operation ID can be generated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::operations::{OperationState, OperationStore, Outcome};
+    ///
+    /// let store: OperationStore<String> = OperationStore::new();
+    /// let handle = store.start("RestartStunnel", "ci").unwrap();
+    /// handle.set_progress("Stopping stunnel");
+    /// handle.finish(Outcome {
+    ///     state: OperationState::Succeeded,
+    ///     message: "Stunnel restarted".to_string(),
+    ///     result: "pid 4242".to_string(),
+    /// });
+    /// let operation = store.get(handle.id()).unwrap();
+    /// assert_eq!(operation.state, OperationState::Succeeded);
+    /// ```
+    pub fn start(&self, method: &str, started_by: &str) -> io::Result<OperationHandle<R>> {
+        let mut bytes = [0u8; ID_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| io::Error::other("Failed to generate an operation ID"))?;
+        let now = Utc::now();
+        let operation = Operation {
+            id: bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            method: method.to_string(),
+            started_by: started_by.to_string(),
+            state: OperationState::Running,
+            message: "Started".to_string(),
+            cancel_requested: false,
+            result: None,
+            started_at: now,
+            updated_at: now,
+            finished_at: None,
+        };
+        let (cancel, cancelled) = watch::channel(false);
+        let handle = OperationHandle {
+            id: operation.id.clone(),
+            store: self.clone(),
+            cancelled,
+        };
+
+        let mut entries = self.lock();
+        prune(&mut entries);
+        entries.push(Entry { operation, cancel });
+        Ok(handle)
+    }
+
+    /// Returns the operation with ID `id`, if it is known.
+    pub fn get(&self, id: &str) -> Option<Operation<R>> {
+        let mut entries = self.lock();
+        prune(&mut entries);
+        entries
+            .iter()
+            .find(|entry| entry.operation.id == id)
+            .map(|entry| entry.operation.clone())
+    }
+
+    /// Returns every known operation, newest first.
+    pub fn list(&self) -> Vec<Operation<R>> {
+        let mut entries = self.lock();
+        prune(&mut entries);
+        entries
+            .iter()
+            .rev()
+            .map(|entry| entry.operation.clone())
+            .collect()
+    }
+
+    /// Asks operation `id` to stop, returning it as it now stands, or
+    /// `None` if it is not known. Finished operations are left as they are.
+    pub fn cancel(&self, id: &str) -> Option<Operation<R>> {
+        let mut entries = self.lock();
+        let entry = entries.iter_mut().find(|entry| entry.operation.id == id)?;
+        if !entry.operation.state.is_finished() {
+            entry.operation.cancel_requested = true;
+            entry.operation.updated_at = Utc::now();
+            entry.cancel.send_replace(true);
+        }
+        Some(entry.operation.clone())
+    }
+
+    fn update(&self, id: &str, apply: impl FnOnce(&mut Operation<R>)) {
+        let mut entries = self.lock();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.operation.id == id) {
+            apply(&mut entry.operation);
+            entry.operation.updated_at = Utc::now();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry<R>>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Drops finished operations past their retention, and the oldest finished
+// ones beyond the cap. Running operations are always kept.
+fn prune<R>(entries: &mut Vec<Entry<R>>) {
+    let cutoff = Utc::now()
+        - chrono::Duration::from_std(RETENTION).unwrap_or_else(|_| chrono::Duration::zero());
+    entries.retain(|entry| {
+        entry
+            .operation
+            .finished_at
+            .is_none_or(|finished| finished > cutoff)
+    });
+    let mut excess = entries
+        .iter()
+        .filter(|entry| entry.operation.state.is_finished())
+        .count()
+        .saturating_sub(MAX_FINISHED);
+    entries.retain(|entry| {
+        if excess > 0 && entry.operation.state.is_finished() {
+            excess -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+/// The task side of a running operation.
+#[derive(Debug, Clone)]
+pub struct OperationHandle<R> {
+    id: String,
+    store: OperationStore<R>,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl<R: Clone> OperationHandle<R> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Replaces the operation's progress message.
+    pub fn set_progress(&self, message: impl Into<String>) {
+        let message = message.into();
+        self.store.update(&self.id, |operation| {
+            operation.message = message;
+        });
+    }
+
+    /// Whether CancelOperation was called for this operation.
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Completes once CancelOperation is called for this operation.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.clone();
+        // An error means the store dropped the operation; never resolve then
+        if cancelled.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Records how the operation ended.
+    pub fn finish(&self, outcome: Outcome<R>) {
+        self.store.update(&self.id, |operation| {
+            operation.state = outcome.state;
+            operation.message = outcome.message;
+            operation.result = Some(outcome.result);
+            operation.finished_at = Some(Utc::now());
+        });
+    }
+
+    /// Records that the operation failed without producing a result, e.g.
+    /// because its RPC returned an error status.
+    pub fn fail(&self, message: impl Into<String>) {
+        let message = message.into();
+        self.store.update(&self.id, |operation| {
+            operation.state = OperationState::Failed;
+            operation.message = message;
+            operation.finished_at = Some(Utc::now());
+        });
+    }
+}
@@ -0,0 +1,509 @@
+//! Parsing of stunnel configuration files.
+//!
+//! This module turns stunnel's INI-style configuration into a structured
+//! representation of global options and `[service]` sections, keeping track
+//! of the line ranges each section occupies so callers can edit it in place.
+//! Every config-mutating RPC goes through these functions rather than
+//! matching on raw strings, so comments such as `; [web-backup]` are never
+//! mistaken for section headers.
+
+use std::ops::Range;
+
+/// Prefix marking lines of a section disabled by the manager.
+///
+/// Disabled lines are ordinary comments to stunnel, so the section stops
+/// being served while its options stay in the file.
+pub const DISABLED_MARKER: &str = "; [disabled] ";
+
+/// A single `key = value` option line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigOption {
+    pub key: String,
+    pub value: String,
+}
+
+/// A `[name]` service section and the options it contains.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub options: Vec<ConfigOption>,
+    /// Text of the comment line directly above the header, if any.
+    pub comment: Option<String>,
+    /// Index of the line holding the `[name]` header.
+    pub start_line: usize,
+    /// Index one past the last line belonging to this section.
+    pub end_line: usize,
+}
+
+impl Section {
+    /// Returns the value of the first option named `key`.
+    ///
+    /// Option names are compared case-insensitively, matching stunnel.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        find_option(&self.options, key)
+    }
+
+    /// Returns the values of every option named `key`, in file order.
+    ///
+    /// Used for options stunnel allows more than once, such as `connect`.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|option| option.key.eq_ignore_ascii_case(key))
+            .map(|option| option.value.as_str())
+            .collect()
+    }
+}
+
+/// Parsed stunnel configuration.
+#[derive(Debug, Clone, Default)]
+pub struct StunnelConfig {
+    /// Options that appear before the first section header.
+    pub globals: Vec<ConfigOption>,
+    pub sections: Vec<Section>,
+}
+
+impl StunnelConfig {
+    /// Parses stunnel configuration content.
+    ///
+    /// Lines starting with `;` or `#` are treated as comments; a comment
+    /// directly above a section header is kept as that section's
+    /// [`Section::comment`]. Lines that are neither options, comments nor
+    /// section headers are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::parser::StunnelConfig;
+    ///
+    /// let config = StunnelConfig::parse("debug = 5\n[web]\naccept = 443\n");
+    /// assert_eq!(config.global("debug"), Some("5"));
+    /// assert_eq!(config.section("web").unwrap().get("accept"), Some("443"));
+    /// ```
+    pub fn parse(content: &str) -> Self {
+        let mut config = StunnelConfig::default();
+        let mut current: Option<Section> = None;
+        let mut line_count = 0;
+
+        let mut previous_comment: Option<&str> = None;
+
+        for (index, line) in content.lines().enumerate() {
+            line_count = index + 1;
+            let trimmed = line.trim();
+            let comment = parse_comment(trimmed);
+            // Lines of a disabled section never describe the section that follows
+            let description = comment.filter(|_| !trimmed.starts_with(DISABLED_MARKER.trim_end()));
+            let preceding_comment = std::mem::replace(&mut previous_comment, description);
+            if trimmed.is_empty() || comment.is_some() {
+                continue;
+            }
+
+            if let Some(name) = parse_section_header(trimmed) {
+                if let Some(mut section) = current.take() {
+                    section.end_line = index;
+                    config.sections.push(section);
+                }
+                current = Some(Section {
+                    name: name.to_string(),
+                    options: Vec::new(),
+                    comment: preceding_comment.map(str::to_string),
+                    start_line: index,
+                    end_line: index + 1,
+                });
+                continue;
+            }
+
+            if let Some((key, value)) = trimmed.split_once('=') {
+                let option = ConfigOption {
+                    key: key.trim().to_string(),
+                    value: value.trim().to_string(),
+                };
+                match current.as_mut() {
+                    Some(section) => section.options.push(option),
+                    None => config.globals.push(option),
+                }
+            }
+        }
+
+        if let Some(mut section) = current.take() {
+            section.end_line = line_count;
+            config.sections.push(section);
+        }
+
+        config
+    }
+
+    /// Returns the section named `name`, if present.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns the value of the first global option named `key`.
+    pub fn global(&self, key: &str) -> Option<&str> {
+        find_option(&self.globals, key)
+    }
+}
+
+/// Config content held line by line so edits can be written back losslessly.
+///
+/// Comments, blank lines, indentation, option ordering, line endings (`\n` or
+/// `\r\n`) and the presence of a final newline all survive a parse/render
+/// round trip, so only the lines an edit touches ever change.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::parser::Document;
+///
+/// let content = "; managed by hand\r\n[web]\r\naccept=443";
+/// assert_eq!(Document::parse(content).render(), content);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    lines: Vec<String>,
+    line_ending: &'static str,
+    trailing_newline: bool,
+}
+
+impl Document {
+    /// Splits `content` into lines, remembering how it was terminated.
+    pub fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(str::to_string).collect(),
+            line_ending: if content.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            },
+            trailing_newline: content.is_empty() || content.ends_with('\n'),
+        }
+    }
+
+    /// Returns the document's lines without their line endings.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Replaces the lines in `range` with `replacement`.
+    ///
+    /// The range is clamped to the document, so stale line indices never panic.
+    pub fn splice<I>(&mut self, range: Range<usize>, replacement: I)
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let end = range.end.min(self.lines.len());
+        let start = range.start.min(end);
+        self.lines.splice(start..end, replacement);
+    }
+
+    /// Appends the lines of `content`, converting them to this document's line endings.
+    pub fn append(&mut self, content: &str) {
+        self.lines.extend(content.lines().map(str::to_string));
+        self.trailing_newline = content.ends_with('\n');
+    }
+
+    /// Serializes the document using its original line ending style.
+    pub fn render(&self) -> String {
+        let mut content = self.lines.join(self.line_ending);
+        if self.trailing_newline && !self.lines.is_empty() {
+            content.push_str(self.line_ending);
+        }
+        content
+    }
+}
+
+/// Rewrites options of `section` within `content`, leaving every other line untouched.
+///
+/// Each update sets `key` to `Some(value)`, replacing the value of the first
+/// existing line for that key in place (and dropping any repeats) or appending
+/// it after the section's last option. `None` removes every line for the key.
+/// Repeating a key in `updates` writes one line per value, for options such
+/// as `options` that stunnel allows more than once.
+pub fn update_section(
+    content: &str,
+    section: &Section,
+    updates: &[(&str, Option<String>)],
+) -> String {
+    let mut document = Document::parse(content);
+    let body_range = section.start_line + 1..section.end_line;
+    let mut body = lines_in(&document, body_range.clone());
+    apply_updates(&mut body, updates, |body| {
+        body.iter()
+            .rposition(|line| option_key(line).is_some())
+            .map(|index| index + 1)
+            .unwrap_or(0)
+    });
+    document.splice(body_range, body);
+    document.render()
+}
+
+/// Sets or removes global (pre-section) options, leaving every other line untouched.
+///
+/// Each update sets `key` to `Some(value)`, replacing the value of the first
+/// existing global line for that key in place (and dropping any repeats) or
+/// appending it after the last global option. `None` removes every global
+/// line for the key.
+pub fn update_globals(content: &str, updates: &[(&str, Option<String>)]) -> String {
+    let mut document = Document::parse(content);
+    let global_end = StunnelConfig::parse(content)
+        .sections
+        .first()
+        .map(section_first_line)
+        .unwrap_or(document.lines().len());
+    let mut globals = lines_in(&document, 0..global_end);
+    apply_updates(&mut globals, updates, global_insert_index);
+    document.splice(0..global_end, globals);
+    document.render()
+}
+
+/// Adds a global `key = value` line after the last global option.
+///
+/// Unlike [`update_globals`], existing lines for `key` are kept, which suits
+/// options stunnel allows to repeat such as `include`.
+pub fn add_global(content: &str, key: &str, value: &str) -> String {
+    let mut document = Document::parse(content);
+    let global_end = StunnelConfig::parse(content)
+        .sections
+        .first()
+        .map(section_first_line)
+        .unwrap_or(document.lines().len());
+    let index = global_insert_index(&lines_in(&document, 0..global_end));
+    document.splice(index..index, [format!("{} = {}", key, value)]);
+    document.render()
+}
+
+/// Comments out `section` using [`DISABLED_MARKER`].
+///
+/// The header and every line up to the section's last option are marked;
+/// trailing blank lines and comments that precede the next section are kept.
+pub fn disable_section(content: &str, section: &Section) -> String {
+    let mut document = Document::parse(content);
+    let range = section.start_line..last_option_line(&document, section) + 1;
+    let marked: Vec<String> = lines_in(&document, range.clone())
+        .into_iter()
+        .map(|line| format!("{}{}", DISABLED_MARKER, line))
+        .collect();
+    document.splice(range, marked);
+    document.render()
+}
+
+/// Restores a section previously commented out by [`disable_section`].
+///
+/// Returns `None` if no disabled section named `name` exists.
+pub fn enable_section(content: &str, name: &str) -> Option<String> {
+    let mut document = Document::parse(content);
+    let lines = document.lines();
+    let start = lines.iter().position(|line| {
+        line.strip_prefix(DISABLED_MARKER)
+            .and_then(|rest| parse_section_header(rest.trim()))
+            == Some(name)
+    })?;
+
+    let mut restored = Vec::new();
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        match line.strip_prefix(DISABLED_MARKER) {
+            // A marked header after the first belongs to an adjacent disabled section
+            Some(original) if index == start || parse_section_header(original.trim()).is_none() => {
+                restored.push(original.to_string())
+            }
+            _ => break,
+        }
+    }
+    document.splice(start..start + restored.len(), restored);
+    Some(document.render())
+}
+
+/// Removes `section` from `content`, along with the comment directly above it.
+///
+/// Trailing blank lines and comments that precede the next section are kept,
+/// as is a blank line separating the removed section from the one before it.
+pub fn remove_section(content: &str, section: &Section) -> String {
+    let mut document = Document::parse(content);
+    let lines = document.lines();
+    let mut first = section_first_line(section);
+    let mut last = last_option_line(&document, section) + 1;
+
+    // Drop the blank separator before the section unless it is the only one left
+    let is_blank = |index: usize| lines.get(index).map(|line| line.trim().is_empty());
+    if first > 0 && is_blank(first - 1) == Some(true) {
+        if is_blank(last) != Some(false) {
+            first -= 1;
+        }
+    } else if is_blank(last) == Some(true) {
+        last += 1;
+    }
+
+    document.splice(first..last, Vec::new());
+    document.render()
+}
+
+/// Renames `section` to `new_name`, preserving every option line.
+///
+/// The `; <name> service` comment directly above the header is renamed too.
+pub fn rename_section(content: &str, section: &Section, new_name: &str) -> String {
+    let mut document = Document::parse(content);
+    if section.start_line >= document.lines().len() {
+        return content.to_string();
+    }
+
+    if section.comment.as_deref() == Some(format!("{} service", section.name).as_str()) {
+        document.splice(
+            section.start_line - 1..section.start_line,
+            [format!("; {} service", new_name)],
+        );
+    }
+    document.splice(
+        section.start_line..section.start_line + 1,
+        [format!("[{}]", new_name)],
+    );
+    document.render()
+}
+
+/// Joins a host and port into an `accept`/`connect` address.
+///
+/// An empty host yields a bare port, which stunnel binds on all interfaces.
+pub fn join_host_port(host: &str, port: u16) -> String {
+    if host.is_empty() {
+        port.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Splits an `accept`/`connect` address into host and port.
+///
+/// Accepts `port`, `host:port` and IPv6 forms such as `:::port` or
+/// `[::1]:port`. A bare port yields an empty host.
+pub fn split_host_port(address: &str) -> (String, Option<u16>) {
+    match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            (host.to_string(), port.trim().parse().ok())
+        }
+        None => (String::new(), address.trim().parse().ok()),
+    }
+}
+
+// Returns the section name if the line is a `[name]` header.
+fn parse_section_header(line: &str) -> Option<&str> {
+    line.strip_prefix('[')?.strip_suffix(']').map(str::trim)
+}
+
+// Returns the comment text if the line is a `;` or `#` comment.
+fn parse_comment(line: &str) -> Option<&str> {
+    line.strip_prefix(';')
+        .or_else(|| line.strip_prefix('#'))
+        .map(str::trim)
+}
+
+// Returns the first line of a section, including its leading comment.
+fn section_first_line(section: &Section) -> usize {
+    match section.comment {
+        Some(_) => section.start_line.saturating_sub(1),
+        None => section.start_line,
+    }
+}
+
+// Returns the index of the last option line in `section`, or its header if it has none.
+fn last_option_line(document: &Document, section: &Section) -> usize {
+    let lines = document.lines();
+    (section.start_line..section.end_line.min(lines.len()))
+        .rev()
+        .find(|&index| option_key(&lines[index]).is_some())
+        .unwrap_or(section.start_line)
+}
+
+// Copies the lines in `range`, clamped to the document.
+fn lines_in(document: &Document, range: Range<usize>) -> Vec<String> {
+    let lines = document.lines();
+    let end = range.end.min(lines.len());
+    lines[range.start.min(end)..end].to_vec()
+}
+
+// Applies option updates to a block of lines. Replaced options keep their
+// original key spelling and spacing; new options go where `insert_at` says.
+fn apply_updates(
+    block: &mut Vec<String>,
+    updates: &[(&str, Option<String>)],
+    insert_at: impl Fn(&[String]) -> usize,
+) {
+    let mut written: Vec<&str> = Vec::new();
+    for (key, value) in updates {
+        // Later values for a key set earlier in this batch are added alongside it
+        if let (Some(value), true) = (value, written.contains(key)) {
+            let index = block
+                .iter()
+                .rposition(|line| {
+                    option_key(line).is_some_and(|name| name.eq_ignore_ascii_case(key))
+                })
+                .map(|index| index + 1)
+                .unwrap_or_else(|| insert_at(block));
+            block.insert(index, format!("{} = {}", key, value));
+            continue;
+        }
+        if value.is_some() {
+            written.push(key);
+        }
+
+        let mut replaced = false;
+        block.retain_mut(|line| {
+            let matches = option_key(line)
+                .map(|name| name.eq_ignore_ascii_case(key))
+                .unwrap_or(false);
+            if !matches {
+                return true;
+            }
+            match value {
+                Some(value) if !replaced => {
+                    *line = replace_option_value(line, value);
+                    replaced = true;
+                    true
+                }
+                _ => false,
+            }
+        });
+
+        if let (Some(value), false) = (value, replaced) {
+            let index = insert_at(block);
+            block.insert(index, format!("{} = {}", key, value));
+        }
+    }
+}
+
+// Returns where a new global option goes: after the last global option or,
+// without one, before the blank lines leading into the first section.
+fn global_insert_index(globals: &[String]) -> usize {
+    globals
+        .iter()
+        .rposition(|line| option_key(line).is_some())
+        .or_else(|| globals.iter().rposition(|line| !line.trim().is_empty()))
+        .map(|index| index + 1)
+        .unwrap_or(0)
+}
+
+// Replaces the value of a `key = value` line, keeping everything up to it.
+fn replace_option_value(line: &str, value: &str) -> String {
+    match line.split_once('=') {
+        Some((key, rest)) => {
+            let spacing = &rest[..rest.len() - rest.trim_start().len()];
+            format!("{}={}{}", key, spacing, value)
+        }
+        None => line.to_string(),
+    }
+}
+
+// Returns the option name if the line is a `key = value` option.
+fn option_key(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    if trimmed.starts_with(';') || trimmed.starts_with('#') || trimmed.starts_with('[') {
+        return None;
+    }
+    trimmed.split_once('=').map(|(key, _)| key.trim())
+}
+
+fn find_option<'a>(options: &'a [ConfigOption], key: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|option| option.key.eq_ignore_ascii_case(key))
+        .map(|option| option.value.as_str())
+}
@@ -0,0 +1,773 @@
+//! Host-specific inspection of processes and sockets.
+//!
+//! Whether stunnel is alive, which ports are taken and which connections
+//! it holds are read from `/proc` on Linux. macOS and the BSDs have no
+//! `/proc`, so there the same questions are answered with `kill(pid, 0)`,
+//! `ps` (which reads the `kern.proc` sysctl) and the socket lister each
+//! system ships: `lsof` on macOS, `sockstat` on FreeBSD and `fstat` on
+//! OpenBSD.
+//! On Linux, byte counts, round-trip times and queues of connections come
+//! from [sock_diag](crate::sockdiag).
+//! Resource use of a process comes from `/proc/<pid>/stat` on Linux and
+//! from `ps` elsewhere.
+//! [`current`] returns the implementation for the host the manager was
+//! built for; the functions in [`crate::utils`] go through it.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use nix::errno::Errno;
+use nix::sys::signal;
+use nix::unistd::{self, Pid, SysconfVar};
+
+use crate::sockdiag;
+use crate::stunnel::Connection;
+use crate::utils::is_process_alive;
+
+/// A TCP socket listening on this host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListeningSocket {
+    pub port: u16,
+    /// Socket inode, matching the `socket:[inode]` links in `/proc/<pid>/fd`;
+    /// 0 where sockets have no inode, e.g. on macOS.
+    pub inode: u64,
+}
+
+/// Resource use of a running process.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProcessStats {
+    pub started_at: DateTime<Utc>,
+    /// Resident memory.
+    pub rss_bytes: u64,
+    /// CPU time used so far, user and system.
+    pub cpu_seconds: f64,
+}
+
+/// Answers questions about processes and sockets on one kind of host.
+pub trait Platform: fmt::Debug + Send + Sync {
+    /// Short name for logs, e.g. `linux`.
+    fn name(&self) -> &'static str;
+
+    /// Whether a process with this PID exists.
+    fn process_running(&self, pid: i32) -> bool;
+
+    /// The TCP sockets listening on this host, IPv4 and IPv6.
+    fn listening_sockets(&self) -> Vec<ListeningSocket>;
+
+    /// The PID and command name of the process listening on `port`, if it
+    /// can be seen; without root, other users' processes may not be.
+    fn port_owner(&self, port: u16) -> Option<(i32, String)>;
+
+    /// Established TCP connections whose local port is `port`.
+    fn established_connections(&self, port: u16) -> usize;
+
+    /// TCP connections held by stunnel processes.
+    fn stunnel_connections(&self) -> Vec<Connection>;
+
+    /// The most recently started stunnel (`stunnel` or `stunnel4`) running
+    /// with `config_path` as an argument.
+    fn find_stunnel_process(&self, config_path: &str) -> Option<i32>;
+
+    /// When `pid` started and the memory and CPU time it uses, if it can be
+    /// inspected.
+    fn process_stats(&self, pid: i32) -> Option<ProcessStats>;
+}
+
+/// The implementation for the host the manager was built for.
+pub fn current() -> &'static dyn Platform {
+    if cfg!(target_os = "macos") {
+        &MacOs
+    } else if cfg!(target_os = "freebsd") {
+        &FreeBsd
+    } else if cfg!(target_os = "openbsd") {
+        &OpenBsd
+    } else {
+        &Linux
+    }
+}
+
+// TCP state code of an established connection in /proc/net/tcp.
+const TCP_ESTABLISHED: &str = "01";
+
+// TCP state code of a listening socket in /proc/net/tcp.
+const TCP_LISTEN: &str = "0A";
+
+/// Reads `/proc`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Linux;
+
+impl Linux {
+    // The PID and command name of the process holding socket `inode`.
+    fn socket_owner(inode: u64) -> Option<(i32, String)> {
+        let link = format!("socket:[{}]", inode);
+        for (pid, path) in proc_entries() {
+            let Ok(fds) = fs::read_dir(path.join("fd")) else {
+                continue;
+            };
+            let holds = fds.flatten().any(|fd| {
+                fs::read_link(fd.path())
+                    .map(|target| target.as_os_str() == link.as_str())
+                    .unwrap_or(false)
+            });
+            if holds {
+                let name = fs::read_to_string(path.join("comm")).unwrap_or_default();
+                return Some((pid, name.trim().to_string()));
+            }
+        }
+        None
+    }
+
+    // The inodes of the sockets process `path` holds open, from the
+    // `socket:[inode]` links in its fd directory.
+    fn socket_inodes(path: &Path) -> HashSet<u64> {
+        let Ok(fds) = fs::read_dir(path.join("fd")) else {
+            return HashSet::new();
+        };
+        fds.flatten()
+            .filter_map(|fd| {
+                let target = fs::read_link(fd.path()).ok()?;
+                target
+                    .to_str()?
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    }
+
+    // The established connections of sockets `inodes`, from /proc/net/tcp
+    // and tcp6.
+    fn proc_net_connections(inodes: &HashSet<u64>) -> Vec<Connection> {
+        let mut connections = Vec::new();
+        for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            let Ok(content) = fs::read_to_string(table) else {
+                continue;
+            };
+            for line in content.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 10 || fields[3] != TCP_ESTABLISHED {
+                    continue;
+                }
+                if !fields[9].parse().is_ok_and(|inode| inodes.contains(&inode)) {
+                    continue;
+                }
+                if let (Some(local), Some(remote)) =
+                    (proc_net_address(fields[1]), proc_net_address(fields[2]))
+                {
+                    connections.push(Connection {
+                        service_name: String::new(),
+                        local_address: local.to_string(),
+                        remote_address: remote.to_string(),
+                        bytes_sent: 0,
+                        bytes_received: 0,
+                        rtt_us: 0,
+                        send_queue: 0,
+                        receive_queue: 0,
+                        country: String::new(),
+                        asn: 0,
+                        as_org: String::new(),
+                    });
+                }
+            }
+        }
+        connections
+    }
+
+    // Clock ticks after boot at which `pid` started, field 22 of its stat file.
+    fn process_start_time(pid: i32) -> Option<u64> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name may contain spaces, so count fields after it
+        let fields = &stat[stat.rfind(')')? + 2..];
+        fields.split_whitespace().nth(19)?.parse().ok()
+    }
+}
+
+impl Platform for Linux {
+    fn name(&self) -> &'static str {
+        "linux"
+    }
+
+    fn process_running(&self, pid: i32) -> bool {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    }
+
+    fn listening_sockets(&self) -> Vec<ListeningSocket> {
+        let mut sockets = Vec::new();
+        for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            let Ok(content) = fs::read_to_string(table) else {
+                continue;
+            };
+            // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+            for line in content.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                    continue;
+                }
+                let port = fields[1]
+                    .rsplit_once(':')
+                    .and_then(|(_, port)| u16::from_str_radix(port, 16).ok());
+                if let (Some(port), Ok(inode)) = (port, fields[9].parse()) {
+                    if !sockets.contains(&ListeningSocket { port, inode }) {
+                        sockets.push(ListeningSocket { port, inode });
+                    }
+                }
+            }
+        }
+        sockets
+    }
+
+    fn port_owner(&self, port: u16) -> Option<(i32, String)> {
+        self.listening_sockets()
+            .into_iter()
+            .filter(|socket| socket.port == port)
+            .find_map(|socket| Self::socket_owner(socket.inode))
+    }
+
+    fn established_connections(&self, port: u16) -> usize {
+        let mut count = 0;
+        for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            let Ok(content) = fs::read_to_string(table) else {
+                continue;
+            };
+            count += content
+                .lines()
+                .skip(1)
+                .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+                .filter(|fields| fields.len() >= 4 && fields[3] == TCP_ESTABLISHED)
+                .filter(|fields| {
+                    fields[1]
+                        .rsplit_once(':')
+                        .and_then(|(_, local)| u16::from_str_radix(local, 16).ok())
+                        == Some(port)
+                })
+                .count();
+        }
+        count
+    }
+
+    // Matches established sockets to the fds of processes named stunnel.
+    // Other users' fd directories are unreadable without root.
+    fn stunnel_connections(&self) -> Vec<Connection> {
+        let inodes: HashSet<u64> = proc_entries()
+            .into_iter()
+            .filter(|(_, path)| {
+                fs::read_to_string(path.join("comm"))
+                    .is_ok_and(|name| name.trim().starts_with("stunnel"))
+            })
+            .flat_map(|(_, path)| Self::socket_inodes(&path))
+            .collect();
+        if inodes.is_empty() {
+            return vec![];
+        }
+        match sockdiag::established_sockets() {
+            Ok(sockets) => sockets
+                .into_iter()
+                .filter(|socket| inodes.contains(&socket.inode))
+                .map(|socket| Connection {
+                    service_name: String::new(),
+                    local_address: socket.local.to_string(),
+                    remote_address: socket.remote.to_string(),
+                    bytes_sent: i64::try_from(socket.bytes_sent).unwrap_or(i64::MAX),
+                    bytes_received: i64::try_from(socket.bytes_received).unwrap_or(i64::MAX),
+                    rtt_us: u32::try_from(socket.rtt.as_micros()).unwrap_or(u32::MAX),
+                    send_queue: socket.send_queue,
+                    receive_queue: socket.receive_queue,
+                    country: String::new(),
+                    asn: 0,
+                    as_org: String::new(),
+                })
+                .collect(),
+            // Without sock_diag or ss, only the addresses are known
+            Err(_) => Self::proc_net_connections(&inodes),
+        }
+    }
+
+    fn find_stunnel_process(&self, config_path: &str) -> Option<i32> {
+        let mut found: Option<(u64, i32)> = None;
+        for (pid, path) in proc_entries() {
+            let Ok(cmdline) = fs::read(path.join("cmdline")) else {
+                continue;
+            };
+            let args: Vec<String> = cmdline
+                .split(|byte| *byte == 0)
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            if !is_stunnel_command(&args, config_path) || !is_process_alive(pid) {
+                continue;
+            }
+            let started = Self::process_start_time(pid).unwrap_or(0);
+            if found.is_none_or(|(latest, _)| started >= latest) {
+                found = Some((started, pid));
+            }
+        }
+        found.map(|(_, pid)| pid)
+    }
+
+    fn process_stats(&self, pid: i32) -> Option<ProcessStats> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // Fields from the state on, so utime is the 12th rather than the 14th
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split_whitespace().collect();
+        let field = |index: usize| fields.get(index)?.parse::<u64>().ok();
+        let ticks = unistd::sysconf(SysconfVar::CLK_TCK).ok()??.max(1) as f64;
+        let page_size = unistd::sysconf(SysconfVar::PAGE_SIZE).ok()??.max(0) as u64;
+        let boot_time = fs::read_to_string("/proc/stat")
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("btime ")?.trim().parse::<i64>().ok())?;
+        let started_after_boot = (field(19)? as f64 / ticks * 1000.0) as i64;
+        Some(ProcessStats {
+            started_at: Utc.timestamp_opt(boot_time, 0).single()?
+                + Duration::milliseconds(started_after_boot),
+            rss_bytes: field(21)? * page_size,
+            cpu_seconds: (field(11)? + field(12)?) as f64 / ticks,
+        })
+    }
+}
+
+/// Uses `kill(pid, 0)`, `ps` and `lsof`, which ship with macOS.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MacOs;
+
+impl MacOs {
+    // Runs `lsof -nP -F pcn` with `args`, returning one (pid, command,
+    // name) per socket. The name is e.g. `*:443` for a listening socket or
+    // `127.0.0.1:443->10.0.0.2:51234` for a connection.
+    fn lsof(args: &[&str]) -> Vec<(i32, String, String)> {
+        let Ok(output) = Command::new("lsof")
+            .args(["-nP", "-F", "pcn"])
+            .args(args)
+            .output()
+        else {
+            return vec![];
+        };
+        let mut sockets = Vec::new();
+        let (mut pid, mut command) = (0, String::new());
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let (tag, value) = line.split_at(line.len().min(1));
+            match tag {
+                "p" => pid = value.parse().unwrap_or(0),
+                "c" => command = value.to_string(),
+                "n" => sockets.push((pid, command.clone(), value.to_string())),
+                _ => {}
+            }
+        }
+        sockets
+    }
+}
+
+impl Platform for MacOs {
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    fn process_running(&self, pid: i32) -> bool {
+        signal_zero(pid)
+    }
+
+    fn listening_sockets(&self) -> Vec<ListeningSocket> {
+        let mut sockets = Vec::new();
+        for (_, _, name) in Self::lsof(&["-iTCP", "-sTCP:LISTEN"]) {
+            if let Some(port) = address_port(&name) {
+                let socket = ListeningSocket { port, inode: 0 };
+                if !sockets.contains(&socket) {
+                    sockets.push(socket);
+                }
+            }
+        }
+        sockets
+    }
+
+    fn port_owner(&self, port: u16) -> Option<(i32, String)> {
+        Self::lsof(&[&format!("-iTCP:{}", port), "-sTCP:LISTEN"])
+            .into_iter()
+            .map(|(pid, command, _)| (pid, command))
+            .next()
+    }
+
+    fn established_connections(&self, port: u16) -> usize {
+        Self::lsof(&[&format!("-iTCP:{}", port), "-sTCP:ESTABLISHED"])
+            .iter()
+            .filter(|(_, _, name)| {
+                name.split_once("->")
+                    .and_then(|(local, _)| address_port(local))
+                    == Some(port)
+            })
+            .count()
+    }
+
+    fn stunnel_connections(&self) -> Vec<Connection> {
+        Self::lsof(&["-a", "-c", "stunnel", "-iTCP", "-sTCP:ESTABLISHED"])
+            .into_iter()
+            .filter_map(|(_, _, name)| {
+                let (local, remote) = name.split_once("->")?;
+                Some(Connection {
+                    service_name: String::new(),
+                    local_address: local.to_string(),
+                    remote_address: remote.to_string(),
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    rtt_us: 0,
+                    send_queue: 0,
+                    receive_queue: 0,
+                    country: String::new(),
+                    asn: 0,
+                    as_org: String::new(),
+                })
+            })
+            .collect()
+    }
+
+    fn find_stunnel_process(&self, config_path: &str) -> Option<i32> {
+        ps_stunnel_process(config_path)
+    }
+
+    fn process_stats(&self, pid: i32) -> Option<ProcessStats> {
+        ps_process_stats(pid)
+    }
+}
+
+/// Uses `kill(pid, 0)`, `ps` and `sockstat`, which ship with FreeBSD.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FreeBsd;
+
+impl FreeBsd {
+    // Runs `sockstat -46 -P tcp` with `args`, returning one (pid, command,
+    // local address, foreign address) per socket and process holding it.
+    fn sockstat(args: &[&str]) -> Vec<(i32, String, String, String)> {
+        let Ok(output) = Command::new("sockstat")
+            .args(["-46", "-P", "tcp"])
+            .args(args)
+            .output()
+        else {
+            return vec![];
+        };
+        // USER COMMAND PID FD PROTO LOCAL-ADDRESS FOREIGN-ADDRESS
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 7 || fields[0] == "USER" {
+                    return None;
+                }
+                Some((
+                    fields[2].parse().unwrap_or(0),
+                    fields[1].to_string(),
+                    fields[5].to_string(),
+                    fields[6].to_string(),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl Platform for FreeBsd {
+    fn name(&self) -> &'static str {
+        "freebsd"
+    }
+
+    fn process_running(&self, pid: i32) -> bool {
+        signal_zero(pid)
+    }
+
+    fn listening_sockets(&self) -> Vec<ListeningSocket> {
+        let mut sockets = Vec::new();
+        for (_, _, local, _) in Self::sockstat(&["-l"]) {
+            if let Some(port) = address_port(&local) {
+                let socket = ListeningSocket { port, inode: 0 };
+                if !sockets.contains(&socket) {
+                    sockets.push(socket);
+                }
+            }
+        }
+        sockets
+    }
+
+    fn port_owner(&self, port: u16) -> Option<(i32, String)> {
+        Self::sockstat(&["-l", "-p", &port.to_string()])
+            .into_iter()
+            .find(|(pid, _, local, _)| *pid > 0 && address_port(local) == Some(port))
+            .map(|(pid, command, _, _)| (pid, command))
+    }
+
+    fn established_connections(&self, port: u16) -> usize {
+        let mut connections: Vec<(String, String)> = Self::sockstat(&["-c"])
+            .into_iter()
+            .filter(|(_, _, local, _)| address_port(local) == Some(port))
+            .map(|(_, _, local, foreign)| (local, foreign))
+            .collect();
+        // A connection is listed once per process holding it
+        connections.sort();
+        connections.dedup();
+        connections.len()
+    }
+
+    fn stunnel_connections(&self) -> Vec<Connection> {
+        let mut connections: Vec<(String, String)> = Self::sockstat(&["-c"])
+            .into_iter()
+            .filter(|(_, command, _, _)| command.starts_with("stunnel"))
+            .map(|(_, _, local, foreign)| (local, foreign))
+            .collect();
+        connections.sort();
+        connections.dedup();
+        connections
+            .into_iter()
+            .map(|(local, foreign)| Connection {
+                service_name: String::new(),
+                local_address: local,
+                remote_address: foreign,
+                bytes_sent: 0,
+                bytes_received: 0,
+                rtt_us: 0,
+                send_queue: 0,
+                receive_queue: 0,
+                country: String::new(),
+                asn: 0,
+                as_org: String::new(),
+            })
+            .collect()
+    }
+
+    fn find_stunnel_process(&self, config_path: &str) -> Option<i32> {
+        ps_stunnel_process(config_path)
+    }
+
+    fn process_stats(&self, pid: i32) -> Option<ProcessStats> {
+        ps_process_stats(pid)
+    }
+}
+
+/// Uses `kill(pid, 0)`, `ps` and `fstat`, which ship with OpenBSD.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenBsd;
+
+impl OpenBsd {
+    // Runs `fstat`, returning one (pid, command, local address, foreign
+    // address) per TCP socket. fstat shows no TCP state: a socket without a
+    // foreign address is taken to be listening, one with it connected.
+    fn tcp_sockets() -> Vec<(i32, String, String, Option<String>)> {
+        let Ok(output) = Command::new("fstat").output() else {
+            return vec![];
+        };
+        // USER CMD PID FD internet[6] stream tcp 0x... LOCAL [<-- | -->] FOREIGN
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let domain = fields
+                    .iter()
+                    .position(|field| *field == "internet" || *field == "internet6")?;
+                if fields.get(domain + 2) != Some(&"tcp") {
+                    return None;
+                }
+                let local = fields.get(domain + 4)?.to_string();
+                let foreign = fields.get(domain + 6).map(|foreign| foreign.to_string());
+                Some((
+                    fields.get(2)?.parse().ok()?,
+                    fields[1].to_string(),
+                    local,
+                    foreign,
+                ))
+            })
+            .collect()
+    }
+}
+
+impl Platform for OpenBsd {
+    fn name(&self) -> &'static str {
+        "openbsd"
+    }
+
+    fn process_running(&self, pid: i32) -> bool {
+        signal_zero(pid)
+    }
+
+    fn listening_sockets(&self) -> Vec<ListeningSocket> {
+        let mut sockets = Vec::new();
+        for (_, _, local, foreign) in Self::tcp_sockets() {
+            if let (Some(port), None) = (address_port(&local), foreign) {
+                let socket = ListeningSocket { port, inode: 0 };
+                if !sockets.contains(&socket) {
+                    sockets.push(socket);
+                }
+            }
+        }
+        sockets
+    }
+
+    fn port_owner(&self, port: u16) -> Option<(i32, String)> {
+        Self::tcp_sockets()
+            .into_iter()
+            .find(|(_, _, local, foreign)| foreign.is_none() && address_port(local) == Some(port))
+            .map(|(pid, command, _, _)| (pid, command))
+    }
+
+    fn established_connections(&self, port: u16) -> usize {
+        let mut connections: Vec<(String, String)> = Self::tcp_sockets()
+            .into_iter()
+            .filter(|(_, _, local, _)| address_port(local) == Some(port))
+            .filter_map(|(_, _, local, foreign)| Some((local, foreign?)))
+            .collect();
+        // A connection is listed once per process holding it
+        connections.sort();
+        connections.dedup();
+        connections.len()
+    }
+
+    fn stunnel_connections(&self) -> Vec<Connection> {
+        Self::tcp_sockets()
+            .into_iter()
+            .filter(|(_, command, _, _)| command.starts_with("stunnel"))
+            .filter_map(|(_, _, local, foreign)| {
+                Some(Connection {
+                    service_name: String::new(),
+                    local_address: local,
+                    remote_address: foreign?,
+                    bytes_sent: 0,
+                    bytes_received: 0,
+                    rtt_us: 0,
+                    send_queue: 0,
+                    receive_queue: 0,
+                    country: String::new(),
+                    asn: 0,
+                    as_org: String::new(),
+                })
+            })
+            .collect()
+    }
+
+    fn find_stunnel_process(&self, config_path: &str) -> Option<i32> {
+        ps_stunnel_process(config_path)
+    }
+
+    fn process_stats(&self, pid: i32) -> Option<ProcessStats> {
+        ps_process_stats(pid)
+    }
+}
+
+// Whether `pid` exists, by sending it signal 0. A process owned by another
+// user refuses the signal but exists.
+fn signal_zero(pid: i32) -> bool {
+    matches!(
+        signal::kill(Pid::from_raw(pid), None),
+        Ok(()) | Err(Errno::EPERM)
+    )
+}
+
+// Finds the stunnel running with `config_path` in `ps` output, preferring
+// the one with the shortest elapsed time. PIDs are no guide, since OpenBSD
+// assigns them at random.
+fn ps_stunnel_process(config_path: &str) -> Option<i32> {
+    let output = Command::new("ps")
+        .args(["-axww", "-o", "pid=,etime=,command="])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse::<i32>().ok()?;
+            let elapsed = elapsed_seconds(fields.next()?)?;
+            let args: Vec<String> = fields.map(str::to_string).collect();
+            (is_stunnel_command(&args, config_path) && is_process_alive(pid))
+                .then_some((elapsed, pid))
+        })
+        .min()
+        .map(|(_, pid)| pid)
+}
+
+// Reads the resource use of `pid` from `ps`, which reports resident memory
+// in KiB.
+fn ps_process_stats(pid: i32) -> Option<ProcessStats> {
+    let output = Command::new("ps")
+        .args(["-o", "rss=,time=,etime=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split_whitespace();
+    let rss_kib = fields.next()?.parse::<u64>().ok()?;
+    // CPU time has hundredths on macOS and the BSDs, e.g. `1:02.35`
+    let time = fields.next()?;
+    let (time, hundredths) = time.split_once('.').unwrap_or((time, "0"));
+    let cpu_seconds =
+        elapsed_seconds(time)? as f64 + hundredths.parse::<f64>().unwrap_or(0.0) / 100.0;
+    let elapsed = elapsed_seconds(fields.next()?)?;
+    Some(ProcessStats {
+        started_at: Utc::now() - Duration::seconds(i64::try_from(elapsed).ok()?),
+        rss_bytes: rss_kib * 1024,
+        cpu_seconds,
+    })
+}
+
+// Parses a `ps` elapsed time, `[[dd-]hh:]mm:ss`.
+fn elapsed_seconds(etime: &str) -> Option<u64> {
+    let (days, clock) = match etime.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, etime),
+    };
+    let mut seconds = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(days * 86_400 + seconds)
+}
+
+// Parses an address of /proc/net/tcp or tcp6, e.g. `0100007F:01BB` for
+// 127.0.0.1:443. The address is in 32-bit words in host byte order, the
+// port in hex. IPv4 peers of IPv6 sockets are shown as IPv4.
+fn proc_net_address(field: &str) -> Option<SocketAddr> {
+    let (address, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let words = (0..address.len() / 8)
+        .map(|i| u32::from_str_radix(address.get(i * 8..i * 8 + 8)?, 16).ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let ip = match words.as_slice() {
+        [word] => IpAddr::V4(Ipv4Addr::from(word.to_ne_bytes())),
+        [a, b, c, d] => {
+            let mut octets = [0u8; 16];
+            for (chunk, word) in octets.chunks_mut(4).zip([a, b, c, d]) {
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            let ip = Ipv6Addr::from(octets);
+            ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4)
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+// The numeric entries of /proc, i.e. its processes.
+fn proc_entries() -> Vec<(i32, PathBuf)> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<i32>().ok()?;
+            Some((pid, entry.path()))
+        })
+        .collect()
+}
+
+// Whether `args` run stunnel with `config_path`.
+fn is_stunnel_command(args: &[String], config_path: &str) -> bool {
+    let Some((program, rest)) = args.split_first() else {
+        return false;
+    };
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    (name == "stunnel" || name == "stunnel4") && rest.iter().any(|arg| arg == config_path)
+}
+
+// The port of an lsof address such as `*:443`, `127.0.0.1:443` or `[::1]:443`.
+fn address_port(address: &str) -> Option<u16> {
+    address.rsplit_once(':')?.1.parse().ok()
+}
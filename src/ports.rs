@@ -0,0 +1,91 @@
+//! Accept-port allocation.
+//!
+//! With `ACCEPT_PORT_RANGE` set, AddProvider may leave `accept_port` unset
+//! and the server picks the lowest port in the range that no provider
+//! accepts on and nothing on the host listens on, so tenants that do not
+//! care which local port they get need not coordinate one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parser::{split_host_port, StunnelConfig};
+use crate::utils::listening_sockets;
+
+/// An inclusive range of ports providers are allocated accept ports from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    /// Creates the range `start..=end`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `start` is 0 or greater than `end`.
+    pub fn new(start: u16, end: u16) -> Result<Self, String> {
+        if start == 0 || start > end {
+            return Err(format!(
+                "Invalid port range {}-{} (expected 1-65535, start <= end)",
+                start, end
+            ));
+        }
+        Ok(Self { start, end })
+    }
+
+    /// Returns the lowest port in the range that no section of `config`
+    /// accepts on and no socket on this host listens on, or `None` if every
+    /// port is taken.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::parser::StunnelConfig;
+    /// use stunnel_space::ports::PortRange;
+    ///
+    /// let range: PortRange = "20000-20999".parse().unwrap();
+    /// let config = StunnelConfig::parse("[web]\naccept = 20000\nconnect = 80\n");
+    /// assert_ne!(range.allocate(&config), Some(20000));
+    /// ```
+    pub fn allocate(&self, config: &StunnelConfig) -> Option<u16> {
+        let mut taken: Vec<u16> = config
+            .sections
+            .iter()
+            .filter_map(|section| section.get("accept"))
+            .filter_map(|accept| split_host_port(accept).1)
+            .collect();
+        taken.extend(listening_sockets().into_iter().map(|socket| socket.port));
+        (self.start..=self.end).find(|port| !taken.contains(port))
+    }
+
+    /// Whether `port` is in the range.
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    /// Parses `start-end`, e.g. `20000-20999`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid port range {} (expected start-end)", s))?;
+        let port = |value: &str| {
+            value
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid port {} in range {}", value.trim(), s))
+        };
+        Self::new(port(start)?, port(end)?)
+    }
+}
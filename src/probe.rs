@@ -0,0 +1,313 @@
+//! Reachability and TLS handshake probes of provider backends.
+//!
+//! When clients cannot reach a service through stunnel, the cause is either
+//! stunnel itself or the backend it connects to. Probing the backend
+//! directly, with a plain TCP connection from the manager's host, tells the
+//! two apart. For client-mode providers, whose backends speak TLS, a
+//! handshake with the `openssl` command-line tool additionally shows what
+//! the backend negotiates and whether its certificate verifies against the
+//! provider's CA settings.
+
+use std::net::{IpAddr, SocketAddr};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use crate::certs::{parse_certificates, CertificateInfo};
+
+/// How long a probe waits for a connection when no timeout is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Outcome of probing one connect target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    /// The target as configured, e.g. `db.internal:5432`.
+    pub target: String,
+    /// Address the connection was made to, if it succeeded.
+    pub address: Option<SocketAddr>,
+    /// Time taken to connect, or to fail.
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+impl Probe {
+    /// Whether the target accepted the connection.
+    pub fn reachable(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Opens a TCP connection to `host:port`, closing it straight away.
+///
+/// An empty `host` means `localhost`, as in stunnel's `connect = port`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use stunnel_space::probe::probe;
+///
+/// # async fn example() {
+/// let probe = probe("db.internal", 5432, Duration::from_secs(3)).await;
+/// if !probe.reachable() {
+///     eprintln!("{} is down: {}", probe.target, probe.error.unwrap_or_default());
+/// }
+/// # }
+/// ```
+pub async fn probe(host: &str, port: u16, timeout: Duration) -> Probe {
+    let (unbracketed, target) = target(host, port);
+    let started = Instant::now();
+    let result = tokio::time::timeout(timeout, TcpStream::connect((unbracketed, port))).await;
+    let latency = started.elapsed();
+    let (address, error) = match result {
+        Err(_) => (
+            None,
+            Some(format!("timed out after {} ms", timeout.as_millis())),
+        ),
+        Ok(Err(e)) => (None, Some(e.to_string())),
+        Ok(Ok(stream)) => (stream.peer_addr().ok(), None),
+    };
+    Probe {
+        target,
+        address,
+        latency,
+        error,
+    }
+}
+
+/// The TLS settings of a client-mode provider a handshake probe uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSettings {
+    pub ca_file: Option<String>,
+    pub ca_path: Option<String>,
+    /// Client certificate presented to the backend.
+    pub cert: Option<String>,
+    /// Key of `cert`; the certificate file itself when unset.
+    pub key: Option<String>,
+    /// Server name sent in the SNI extension; a hostname connect host is
+    /// sent when unset.
+    pub sni: Option<String>,
+    /// Host name the certificate must match, as stunnel's `checkHost`.
+    pub check_host: Option<String>,
+    pub ciphers: Option<String>,
+    pub ciphersuites: Option<String>,
+}
+
+/// Outcome of a TLS handshake with one connect target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    /// The target as configured, e.g. `db.internal:5432`.
+    pub target: String,
+    /// Negotiated protocol version, e.g. `TLSv1.3`.
+    pub protocol: Option<String>,
+    /// Negotiated cipher, e.g. `TLS_AES_256_GCM_SHA384`.
+    pub cipher: Option<String>,
+    /// The certificate the backend presented.
+    pub certificate: Option<CertificateInfo>,
+    /// Why the certificate does not verify, if it does not.
+    pub verify_error: Option<String>,
+    /// Time taken to complete the handshake, or to fail.
+    pub latency: Duration,
+    /// Why the handshake failed, if it did.
+    pub error: Option<String>,
+}
+
+impl Handshake {
+    /// Whether a TLS session was established.
+    pub fn completed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Whether the handshake completed with a certificate that verifies
+    /// against the CA settings, is within its validity period and, with
+    /// `check_host`, matches that host.
+    pub fn certificate_valid(&self) -> bool {
+        self.completed() && self.certificate.is_some() && self.verify_error.is_none()
+    }
+}
+
+/// Performs a TLS handshake with `host:port` using `settings`, then closes
+/// the connection.
+///
+/// The handshake completes even if the certificate does not verify, so the
+/// negotiated protocol and cipher are reported either way. As with stunnel,
+/// no system trust store is used: without `ca_file` or `ca_path` the
+/// certificate is reported as unverified.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use stunnel_space::probe::{handshake, TlsSettings};
+///
+/// # async fn example() {
+/// let settings = TlsSettings {
+///     ca_file: Some("/etc/stunnel/backend-ca.pem".to_string()),
+///     ..Default::default()
+/// };
+/// let handshake = handshake("db.internal", 5432, &settings, Duration::from_secs(3)).await;
+/// if let Some(e) = &handshake.verify_error {
+///     eprintln!("{} presents an untrusted certificate: {}", handshake.target, e);
+/// }
+/// # }
+/// ```
+pub async fn handshake(
+    host: &str,
+    port: u16,
+    settings: &TlsSettings,
+    timeout: Duration,
+) -> Handshake {
+    let (unbracketed, target) = target(host, port);
+    let mut handshake = Handshake {
+        target,
+        protocol: None,
+        cipher: None,
+        certificate: None,
+        verify_error: None,
+        latency: Duration::ZERO,
+        error: None,
+    };
+
+    let mut command = Command::new("openssl");
+    command
+        .arg("s_client")
+        .arg("-connect")
+        .arg(&handshake.target);
+    let server_name = settings
+        .sni
+        .as_deref()
+        .or_else(|| (unbracketed.parse::<IpAddr>().is_err()).then_some(unbracketed));
+    if let Some(server_name) = server_name {
+        command.arg("-servername").arg(server_name);
+    }
+    if let Some(ca_file) = &settings.ca_file {
+        command.arg("-CAfile").arg(ca_file);
+    }
+    if let Some(ca_path) = &settings.ca_path {
+        command.arg("-CApath").arg(ca_path);
+    }
+    if let Some(cert) = &settings.cert {
+        command
+            .arg("-cert")
+            .arg(cert)
+            .arg("-key")
+            .arg(settings.key.as_ref().unwrap_or(cert));
+    }
+    if let Some(check_host) = &settings.check_host {
+        command.arg("-verify_hostname").arg(check_host);
+    }
+    if let Some(ciphers) = &settings.ciphers {
+        command.arg("-cipher").arg(ciphers);
+    }
+    if let Some(ciphersuites) = &settings.ciphersuites {
+        command.arg("-ciphersuites").arg(ciphersuites);
+    }
+    // Closed stdin ends the session as soon as the handshake is done
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let started = Instant::now();
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            handshake.error = Some(format!("Failed to run openssl: {}", e));
+            return handshake;
+        }
+    };
+    let result = tokio::time::timeout(timeout, child.wait_with_output()).await;
+    handshake.latency = started.elapsed();
+    let output = match result {
+        Err(_) => {
+            handshake.error = Some(format!("timed out after {} ms", timeout.as_millis()));
+            return handshake;
+        }
+        Ok(Err(e)) => {
+            handshake.error = Some(format!("Failed to run openssl: {}", e));
+            return handshake;
+        }
+        Ok(Ok(output)) => output,
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // "New, TLSv1.3, Cipher is TLS_AES_256_GCM_SHA384", or "(NONE)" for both
+    let negotiated = stdout.lines().find_map(|line| {
+        let (protocol, cipher) = line.strip_prefix("New, ")?.split_once(", Cipher is ")?;
+        (cipher != "(NONE)").then(|| (protocol.to_string(), cipher.to_string()))
+    });
+    let Some((protocol, cipher)) = negotiated else {
+        handshake.error = Some(openssl_error(&String::from_utf8_lossy(&output.stderr)));
+        return handshake;
+    };
+    handshake.protocol = Some(protocol);
+    handshake.cipher = Some(cipher);
+    handshake.certificate = stdout
+        .find("-----BEGIN CERTIFICATE-----")
+        .and_then(|start| parse_certificates(stdout[start..].as_bytes()).ok())
+        .and_then(|certificates| certificates.into_iter().next());
+
+    // "Verify return code: 18 (self-signed certificate)"
+    let verify_result = stdout.lines().find_map(|line| {
+        let (code, reason) = line
+            .trim()
+            .strip_prefix("Verify return code: ")?
+            .split_once(' ')?;
+        Some((
+            code.to_string(),
+            reason.trim_matches(['(', ')']).to_string(),
+        ))
+    });
+    handshake.verify_error = if handshake.certificate.is_none() {
+        Some("no certificate presented".to_string())
+    } else if settings.ca_file.is_none() && settings.ca_path.is_none() {
+        Some("no CAfile or CApath to verify against".to_string())
+    } else {
+        match verify_result {
+            Some((code, _)) if code == "0" => None,
+            Some((_, reason)) => Some(reason),
+            None => Some("verification result unknown".to_string()),
+        }
+    };
+    handshake
+}
+
+// Helper: `host` with brackets removed, and `host:port` as a connect
+// target. An empty `host` means `localhost`, as in stunnel's `connect = port`.
+fn target(host: &str, port: u16) -> (&str, String) {
+    let host = if host.is_empty() { "localhost" } else { host };
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    let target = if unbracketed.contains(':') {
+        format!("[{}]:{}", unbracketed, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    (unbracketed, target)
+}
+
+// Helper: the reason of the first error `openssl` reported, e.g.
+// "wrong version number" from
+// "40A7...:error:0A00010B:SSL routines:tls_validate_record_header:wrong version number:...".
+fn openssl_error(stderr: &str) -> String {
+    stderr
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+            fields.nth(1).filter(|field| *field == "error")?;
+            fields.nth(3).map(str::to_string)
+        })
+        .or_else(|| {
+            stderr
+                .lines()
+                .map(str::trim)
+                .rfind(|line| !line.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "handshake failed".to_string())
+}
@@ -0,0 +1,173 @@
+//! Rate limits on config mutations.
+//!
+//! Every RPC that changes the config or signals stunnel (anything the
+//! read-only role may not call) draws from a token bucket per client and
+//! from one shared by all clients. A bucket holds up to its per-minute
+//! limit and refills continuously, so short bursts are allowed but a
+//! runaway automation loop is throttled with `RESOURCE_EXHAUSTED` instead
+//! of rewriting the config and reloading stunnel hundreds of times.
+//!
+//! Clients are told apart by authenticated identity (API key name, JWT
+//! subject or client certificate common name), falling back to their IP
+//! address. The self-reported `x-client-id` is not trusted for this.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::auth::AuthenticatedCaller;
+use crate::certs;
+use crate::rbac::{self, Role, RpcMethod};
+
+// Period the limits are expressed over.
+const WINDOW: Duration = Duration::from_secs(60);
+
+// Client buckets are swept once this many are tracked, dropping full ones.
+const SWEEP_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: u32, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit),
+            updated: now,
+        }
+    }
+
+    // Adds the tokens earned since the last update, up to `limit`.
+    fn refill(&mut self, limit: u32, now: Instant) {
+        let rate = f64::from(limit) / WINDOW.as_secs_f64();
+        let earned = now.duration_since(self.updated).as_secs_f64() * rate;
+        self.tokens = (self.tokens + earned).min(f64::from(limit));
+        self.updated = now;
+    }
+
+    // Time until a whole token is available.
+    fn wait(&self, limit: u32) -> Duration {
+        let rate = f64::from(limit) / WINDOW.as_secs_f64();
+        Duration::from_secs_f64(((1.0 - self.tokens) / rate).max(0.0))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    global: Option<Bucket>,
+    clients: HashMap<String, Bucket>,
+}
+
+/// Interceptor limiting how often mutation RPCs may be called.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    per_client: Option<u32>,
+    global: Option<u32>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter that allows everything until limits are set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows each client at most `limit` mutations per minute.
+    pub fn with_per_client_limit(mut self, limit: u32) -> Self {
+        self.per_client = Some(limit);
+        self
+    }
+
+    /// Allows all clients together at most `limit` mutations per minute.
+    pub fn with_global_limit(mut self, limit: u32) -> Self {
+        self.global = Some(limit);
+        self
+    }
+
+    /// Takes a token for a mutation by `client` from each configured
+    /// bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long to wait if any bucket is empty; no token is taken
+    /// then.
+    pub fn acquire(&self, client: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { global, clients } = &mut *buckets;
+
+        let mut wait = Duration::ZERO;
+        if let Some(limit) = self.global {
+            let bucket = global.get_or_insert_with(|| Bucket::full(limit, now));
+            bucket.refill(limit, now);
+            wait = wait.max(bucket.wait(limit));
+        }
+        if let Some(limit) = self.per_client {
+            if clients.len() >= SWEEP_THRESHOLD {
+                clients.retain(|_, bucket| {
+                    bucket.refill(limit, now);
+                    bucket.tokens < f64::from(limit)
+                });
+            }
+            let bucket = clients
+                .entry(client.to_string())
+                .or_insert_with(|| Bucket::full(limit, now));
+            bucket.refill(limit, now);
+            wait = wait.max(bucket.wait(limit));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        if let Some(bucket) = global {
+            bucket.tokens -= 1.0;
+        }
+        if let Some(bucket) = clients.get_mut(client) {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+impl Interceptor for RateLimiter {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        // Untagged requests are treated as mutations
+        let method = request
+            .extensions()
+            .get::<RpcMethod>()
+            .map(|method| method.0.as_str())
+            .unwrap_or_default();
+        if rbac::required_role(method) == Role::ReadOnly {
+            return Ok(request);
+        }
+        match self.acquire(&client_key(&request)) {
+            Ok(()) => Ok(request),
+            Err(wait) => Err(Status::resource_exhausted(format!(
+                "Rate limit for config changes exceeded; retry in {}s",
+                wait.as_secs_f64().ceil()
+            ))),
+        }
+    }
+}
+
+// Identifies the client for rate limiting by authenticated identity, else
+// by IP address, so reconnecting from a new port does not reset its limit.
+fn client_key<T>(request: &Request<T>) -> String {
+    request
+        .extensions()
+        .get::<AuthenticatedCaller>()
+        .map(|caller| caller.0.clone())
+        .or_else(|| {
+            request.peer_certs().and_then(|certs| {
+                certs
+                    .first()
+                    .and_then(|cert| certs::common_name(cert.as_ref()))
+            })
+        })
+        .or_else(|| request.remote_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
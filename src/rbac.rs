@@ -0,0 +1,398 @@
+//! Role-based access control for the gRPC API.
+//!
+//! API keys are listed in a YAML (or JSON) file, each mapped to a role:
+//!
+//! ```yaml
+//! keys:
+//!   - name: dashboard
+//!     key: 3f1c...            # or key_sha256: <hex digest of the key>
+//!     role: read-only
+//!   - name: deploy-bot
+//!     key_sha256: 9a0b...
+//!     role: operator
+//!   - name: team-a-ci
+//!     key_sha256: 47d2...
+//!     role: operator
+//!     namespaces: [team-a]
+//! ```
+//!
+//! Clients send the key as a bearer token. `read-only` keys may call
+//! methods that only inspect state, `operator` keys may also manage
+//! providers and the stunnel process, and `admin` keys may call anything,
+//! including raw config writes, restores and certificate management. A key
+//! listing `namespaces` may only see and act on the instances in those
+//! [namespaces](crate::namespaces), whatever its role. The file is re-read
+//! whenever its modification time changes, so keys can be added or revoked
+//! without a restart.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::SystemTime;
+use tonic::codegen::http;
+
+/// Access level granted to a caller. Roles are ordered, each including the
+/// permissions of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Role {
+    ReadOnly,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    /// Returns the name used in the keys file, e.g. `read-only`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "read-only" | "readonly" | "read_only" => Ok(Role::ReadOnly),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!(
+                "Unknown role: {} (expected read-only, operator or admin)",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// Methods that only inspect state.
+const READ_ONLY_METHODS: &[&str] = &[
+    "GetStatus",
+    "ListProviders",
+    "GetProvider",
+    "GetConfig",
+    "ValidateConfigContent",
+    "DiffConfig",
+    "ListBackups",
+    "GetHistory",
+    "GetRevision",
+    "ExportConfig",
+    "ListTemplates",
+    "LintConfig",
+    "GetCertificateInfo",
+    "GetCertificateStatus",
+    "ListCertificates",
+    "VerifyKeyPair",
+    "VerifyChain",
+    "WatchCertificateChanges",
+    "GetMaintenanceMode",
+    "ProbeBackend",
+    "GetJournalLogs",
+    "GetVersion",
+    "GetTrafficStats",
+    "QueryConnections",
+    "GetTopTalkers",
+    "ListInstances",
+    "GetOperation",
+    "ListOperations",
+];
+
+// Methods that manage providers and the stunnel process without replacing
+// the config wholesale or touching key material.
+const OPERATOR_METHODS: &[&str] = &[
+    "ReloadConfig",
+    "StartStunnel",
+    "StopStunnel",
+    "RestartStunnel",
+    "AddProvider",
+    "AddProviders",
+    "AddProviderFromTemplate",
+    "RemoveProvider",
+    "RemoveProviderWithProgress",
+    "CancelOperation",
+    "UpdateProvider",
+    "DisableProvider",
+    "EnableProvider",
+    "RenameProvider",
+    "SetDebugLevel",
+    "RenewAcmeCertificates",
+    "RefreshVaultCertificates",
+];
+
+/// Returns the least role allowed to call gRPC method `method`, e.g.
+/// `GetStatus`. Methods not listed require [`Role::Admin`].
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::rbac::{required_role, Role};
+///
+/// assert_eq!(required_role("ListProviders"), Role::ReadOnly);
+/// assert_eq!(required_role("RemoveProvider"), Role::Operator);
+/// assert_eq!(required_role("UpdateConfig"), Role::Admin);
+/// ```
+pub fn required_role(method: &str) -> Role {
+    if READ_ONLY_METHODS.contains(&method) {
+        Role::ReadOnly
+    } else if OPERATOR_METHODS.contains(&method) {
+        Role::Operator
+    } else {
+        Role::Admin
+    }
+}
+
+/// Name of the gRPC method a request calls, added to the request
+/// extensions by [`tag_method`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcMethod(pub String);
+
+/// Records the method an HTTP/2 gRPC request calls, taken from the last
+/// segment of its `/<package>.<Service>/<Method>` path, so interceptors,
+/// which do not see the path, can authorize it.
+pub fn tag_method<B>(mut request: http::Request<B>) -> http::Request<B> {
+    let method = request
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    request.extensions_mut().insert(RpcMethod(method));
+    request
+}
+
+/// An API key with its name and role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    pub role: Role,
+    /// Namespaces the key is limited to, never empty; `None` for all
+    /// instances.
+    pub namespaces: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeysFile {
+    #[serde(default)]
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyEntry {
+    name: String,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    key_sha256: Option<String>,
+    role: Role,
+    #[serde(default)]
+    namespaces: Option<Vec<String>>,
+}
+
+// Loaded keys, indexed by the SHA-256 digest of the key.
+#[derive(Debug, Default)]
+struct LoadedKeys {
+    modified: Option<SystemTime>,
+    keys: Vec<([u8; 32], ApiKey)>,
+}
+
+/// API keys loaded from a file, reloaded when the file changes.
+#[derive(Debug)]
+pub struct ApiKeyStore {
+    path: String,
+    loaded: RwLock<LoadedKeys>,
+}
+
+impl ApiKeyStore {
+    /// Loads the keys file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let store = Self {
+            path: path.to_string(),
+            loaded: RwLock::new(LoadedKeys::default()),
+        };
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        *store.loaded.write().unwrap_or_else(|e| e.into_inner()) = store.load(modified)?;
+        Ok(store)
+    }
+
+    /// Returns the number of keys currently loaded.
+    pub fn len(&self) -> usize {
+        self.loaded
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys
+            .len()
+    }
+
+    /// Whether no keys are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the key matching `key`, first reloading the file if it
+    /// changed. A file that fails to reload is logged and the previous keys
+    /// stay in effect.
+    pub fn lookup(&self, key: &str) -> Option<ApiKey> {
+        self.reload_if_changed();
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        self.loaded
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys
+            .iter()
+            .find(|(candidate, _)| *candidate == digest)
+            .map(|(_, key)| key.clone())
+    }
+
+    fn reload_if_changed(&self) {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified
+            == self
+                .loaded
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .modified
+        {
+            return;
+        }
+        match self.load(modified) {
+            Ok(loaded) => {
+                tracing::info!(
+                    "Reloaded {} API key(s) from {}",
+                    loaded.keys.len(),
+                    self.path
+                );
+                *self.loaded.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+            }
+            Err(e) => {
+                tracing::error!("{}; keeping previous API keys", e);
+                // Do not retry until the file changes again
+                self.loaded
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .modified = modified;
+            }
+        }
+    }
+
+    fn load(&self, modified: Option<SystemTime>) -> Result<LoadedKeys, String> {
+        let content = fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read API keys file {}: {}", self.path, e))?;
+        let file: KeysFile = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Invalid API keys file {}: {}", self.path, e))?;
+        let mut keys = Vec::new();
+        for entry in file.keys {
+            let digest = match (&entry.key, &entry.key_sha256) {
+                (Some(key), None) if !key.is_empty() => Sha256::digest(key.as_bytes()).into(),
+                (None, Some(hex)) => parse_digest(hex).ok_or_else(|| {
+                    format!(
+                        "API key {} in {}: key_sha256 must be 64 hex digits",
+                        entry.name, self.path
+                    )
+                })?,
+                _ => {
+                    return Err(format!(
+                        "API key {} in {} needs exactly one of key or key_sha256",
+                        entry.name, self.path
+                    ))
+                }
+            };
+            // An empty list would leave the key unscoped, not confined
+            if entry.namespaces.as_ref().is_some_and(|namespaces| {
+                namespaces.is_empty() || namespaces.iter().any(|name| name.trim().is_empty())
+            }) {
+                return Err(format!(
+                    "API key {} in {}: namespaces must list at least one namespace",
+                    entry.name, self.path
+                ));
+            }
+            keys.push((
+                digest,
+                ApiKey {
+                    name: entry.name,
+                    role: entry.role,
+                    namespaces: entry.namespaces,
+                },
+            ));
+        }
+        Ok(LoadedKeys { modified, keys })
+    }
+}
+
+fn parse_digest(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str, content: &str) -> Result<ApiKeyStore, String> {
+        let path = std::env::temp_dir().join(format!(
+            "stunnel-space-keys-{}-{}.yaml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content).unwrap();
+        let store = ApiKeyStore::open(&path.display().to_string());
+        fs::remove_file(&path).unwrap();
+        store
+    }
+
+    #[test]
+    fn keys_are_scoped_to_their_namespaces() {
+        let store = open(
+            "scoped",
+            "keys:\n  - name: ci\n    key: secret\n    role: operator\n    namespaces: [team-a]\n  - name: ops\n    key: other\n    role: admin\n",
+        )
+        .unwrap();
+        assert_eq!(
+            store.lookup("secret").unwrap().namespaces,
+            Some(vec!["team-a".to_string()])
+        );
+        assert_eq!(store.lookup("other").unwrap().namespaces, None);
+    }
+
+    #[test]
+    fn empty_namespaces_are_refused() {
+        for namespaces in ["[]", "[\"\"]"] {
+            let content = format!(
+                "keys:\n  - name: ci\n    key: secret\n    role: operator\n    namespaces: {}\n",
+                namespaces
+            );
+            assert!(open("empty", &content).is_err(), "{}", namespaces);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Readiness checks after stunnel starts or reloads.
+//!
+//! A SIGHUP only asks stunnel to reload: a config it cannot load is logged
+//! and the old services keep running, and a service whose port is taken
+//! fails to bind without stopping the others. Waiting for every configured
+//! accept port to be listening confirms the services are actually up.
+//!
+//! Ports that were already listening before a reload count as ready
+//! straight away, so the check catches new and moved services rather than
+//! changes to existing ones.
+
+use std::time::{Duration, Instant};
+
+use crate::parser::{split_host_port, StunnelConfig};
+use crate::utils::listening_sockets;
+
+/// How long to wait for services to listen when no timeout is configured.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Delay between reads of the listening socket tables.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether one service is listening on its accept port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceReadiness {
+    pub service: String,
+    pub port: u16,
+    pub listening: bool,
+}
+
+/// Returns each section of `config` that accepts on a TCP port, with that
+/// port. Sections accepting on a Unix socket are skipped.
+pub fn accept_ports(config: &StunnelConfig) -> Vec<(String, u16)> {
+    config
+        .sections
+        .iter()
+        .filter_map(|section| {
+            let port = split_host_port(section.get("accept")?).1?;
+            Some((section.name.clone(), port))
+        })
+        .collect()
+}
+
+/// Polls the host's listening TCP sockets until every accept port of
+/// `config` is listening or `timeout` passes, returning the readiness of
+/// each service as last observed.
+///
+/// # Example
+///
+/// ```no_run
+/// use stunnel_space::parser::StunnelConfig;
+/// use stunnel_space::readiness::{wait_until_listening, DEFAULT_TIMEOUT};
+///
+/// # async fn example() {
+/// let config = StunnelConfig::parse("[web]\naccept = 8443\nconnect = 80\n");
+/// for service in wait_until_listening(&config, DEFAULT_TIMEOUT).await {
+///     if !service.listening {
+///         eprintln!("{} is not listening on {}", service.service, service.port);
+///     }
+/// }
+/// # }
+/// ```
+pub async fn wait_until_listening(
+    config: &StunnelConfig,
+    timeout: Duration,
+) -> Vec<ServiceReadiness> {
+    let expected = accept_ports(config);
+    let deadline = Instant::now() + timeout;
+    loop {
+        let listening: Vec<u16> = listening_sockets()
+            .into_iter()
+            .map(|socket| socket.port)
+            .collect();
+        let readiness: Vec<ServiceReadiness> = expected
+            .iter()
+            .map(|(service, port)| ServiceReadiness {
+                service: service.clone(),
+                port: *port,
+                listening: listening.contains(port),
+            })
+            .collect();
+        if readiness.iter().all(|service| service.listening) || Instant::now() >= deadline {
+            return readiness;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Describes the services that are not listening, or returns `None` if
+/// all are, e.g. `1 of 2 services not listening after 5s: web (port 8443)`.
+pub fn summary(readiness: &[ServiceReadiness], timeout: Duration) -> Option<String> {
+    let down: Vec<String> = readiness
+        .iter()
+        .filter(|service| !service.listening)
+        .map(|service| format!("{} (port {})", service.service, service.port))
+        .collect();
+    if down.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} of {} services not listening after {}s: {}",
+        down.len(),
+        readiness.len(),
+        timeout.as_secs(),
+        down.join(", ")
+    ))
+}
@@ -0,0 +1,337 @@
+//! Masking of sensitive option values.
+//!
+//! Config content returned by the API can reveal protocol passwords, PSK
+//! secret files and private key locations. Responses carrying config content
+//! implement [`Redact`], which replaces those values with [`REDACTED`].
+//! Masking is line-based, so it also covers diffs, commented-out options and
+//! stunnel's own error output; JSON and YAML exports are masked before
+//! conversion. Messages written to the log are masked in their `Debug`
+//! form by [`redact_debug`].
+
+use crate::stunnel::{
+    AddProviderFromTemplateResponse, AddProviderResponse, AddProvidersResponse,
+    ApplyChangesResponse, BindVaultCertificateResponse, ConfigFragment, ConfigOption,
+    ConfigRevision, DiffConfigResponse, DisableProviderResponse, EnableAcmeResponse,
+    EnableProviderResponse, GenerateConfigResponse, GetConfigResponse, GetHistoryResponse,
+    GetProviderResponse, GetRevisionResponse, ImportConfigResponse, ImportPkcs12Response,
+    ListProvidersResponse, ListTemplatesResponse, Provider, ProviderTemplate,
+    RegisterTemplateResponse, RemoveProviderResponse, RenameProviderResponse,
+    RestoreBackupResponse, RollbackRevisionResponse, RollbackToCommitResponse, StageConfigResponse,
+    UpdateConfigResponse, UpdateProviderResponse, UploadCrlResponse,
+};
+
+/// Placeholder replacing sensitive values.
+pub const REDACTED: &str = "********";
+
+/// Options whose values are masked, matched case-insensitively.
+pub const SENSITIVE_OPTIONS: &[&str] = &["protocolPassword", "PSKsecrets", "key", "engineCtrl"];
+
+/// Message fields whose values [`redact_debug`] masks: passwords, key
+/// material and paths, PKCS#12 bundles and staging tokens.
+pub const SENSITIVE_FIELDS: &[&str] = &[
+    "key",
+    "protocol_password",
+    "engine_ctrl",
+    "key_pem",
+    "passphrase",
+    "bundle",
+    "token",
+];
+
+/// Whether option `key` holds a value that should not leave the host.
+pub fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_OPTIONS
+        .iter()
+        .any(|sensitive| key.eq_ignore_ascii_case(sensitive))
+}
+
+/// Masks the values of sensitive options in config content. Lines may carry
+/// a diff marker or comment prefix; everything else is left untouched.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::redact::redact_config;
+///
+/// let content = "[web]\nkey = /etc/web.key\nprotocolPassword=hunter2\n-key = /old.key\n";
+/// assert_eq!(
+///     redact_config(content),
+///     "[web]\nkey = ********\nprotocolPassword=********\n-key = ********\n"
+/// );
+/// ```
+pub fn redact_config(content: &str) -> String {
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let Some(eq) = line.find('=') else {
+                return line.to_string();
+            };
+            let key = line[..eq]
+                .trim_start_matches(|c: char| {
+                    matches!(c, '+' | '-' | ';' | '#') || c.is_whitespace()
+                })
+                .trim_end();
+            if !is_sensitive(key) {
+                return line.to_string();
+            }
+            let value = &line[eq + 1..];
+            let leading = value.len() - value.trim_start().len();
+            let ending = &value[value.trim_end_matches(['\r', '\n']).len()..];
+            if value.trim().is_empty() {
+                return line.to_string();
+            }
+            format!("{}{}{}", &line[..eq + 1 + leading], REDACTED, ending)
+        })
+        .collect()
+}
+
+/// Whether `content` sets a sensitive option to the [`REDACTED`]
+/// placeholder, as happens when redacted output is written back.
+pub fn contains_placeholder(content: &str) -> bool {
+    content.lines().any(|line| {
+        line.split_once('=')
+            .is_some_and(|(key, value)| is_sensitive(key.trim()) && value.trim() == REDACTED)
+    })
+}
+
+/// Masks secrets in the `Debug` form of a message: the values of
+/// [`SENSITIVE_FIELDS`], sensitive options in any string holding config
+/// content, and the `value` of a `key`/`value` pair naming a sensitive
+/// option. A `key` field holding an option name rather than a key path is
+/// left alone.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::redact::redact_debug;
+///
+/// let text = r#"Req { config_content: "[web]\nkey = /etc/web.key\n", protocol_password: "hunter2", options: [ConfigOption { key: "PSKsecrets", value: "/etc/psk" }], bundle: [48, 130] }"#;
+/// assert_eq!(
+///     redact_debug(text),
+///     r#"Req { config_content: "[web]\nkey = ********\n", protocol_password: "********", options: [ConfigOption { key: "PSKsecrets", value: "********" }], bundle: "********" }"#
+/// );
+/// ```
+pub fn redact_debug(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut mask_value = false;
+    while let Some(start) = rest.find(['"', '[']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let field = field_name(&out).to_string();
+        let sensitive = SENSITIVE_FIELDS.contains(&field.as_str());
+        if rest.starts_with('[') {
+            // A list is only skipped whole when it is a sensitive field's value
+            let end = if sensitive { rest.find(']') } else { None };
+            match end {
+                Some(end) => {
+                    out.push_str(&format!("{:?}", REDACTED));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('[');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+        let end = literal_end(rest);
+        let (literal, after) = rest.split_at(end);
+        rest = after;
+        let value = unescape(&literal[1..literal.len().saturating_sub(1).max(1)]);
+        let masked = match field.as_str() {
+            _ if value.is_empty() => None,
+            "key" if is_option_name(&value) => {
+                mask_value = is_sensitive(&value);
+                out.push_str(literal);
+                continue;
+            }
+            "value" if mask_value => Some(REDACTED.to_string()),
+            _ if sensitive => Some(REDACTED.to_string()),
+            _ => Some(redact_config(&value)).filter(|redacted| *redacted != value),
+        };
+        mask_value = false;
+        match masked {
+            Some(masked) => out.push_str(&format!("{:?}", masked)),
+            None => out.push_str(literal),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// The field whose value starts after `text`, which ends in `name: `.
+fn field_name(text: &str) -> &str {
+    let Some(text) = text.trim_end().strip_suffix(':') else {
+        return "";
+    };
+    let start = text
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |i| i + 1);
+    &text[start..]
+}
+
+// Where the string literal at the start of `text` ends, past its closing
+// quote.
+fn literal_end(text: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+// Undoes the escapes `Debug` writes in strings.
+fn unescape(literal: &str) -> String {
+    let mut value = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some('0') => value.push('\0'),
+            Some('u') => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                if let Some(c) = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    value.push(c);
+                }
+            }
+            Some(other) => value.push(other),
+            None => {}
+        }
+    }
+    value
+}
+
+// Whether `value` looks like a config option name rather than a path.
+fn is_option_name(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A message whose sensitive values can be masked.
+pub trait Redact {
+    /// Masks sensitive values in place.
+    fn redact(&mut self);
+}
+
+/// Returns `message`, masked if `enabled`.
+pub fn apply<T: Redact>(enabled: bool, mut message: T) -> T {
+    if enabled {
+        message.redact();
+    }
+    message
+}
+
+fn redact_value(value: &mut String) {
+    if !value.is_empty() {
+        *value = REDACTED.to_string();
+    }
+}
+
+impl Redact for String {
+    fn redact(&mut self) {
+        *self = redact_config(self);
+    }
+}
+
+impl<T: Redact> Redact for Option<T> {
+    fn redact(&mut self) {
+        if let Some(inner) = self {
+            inner.redact();
+        }
+    }
+}
+
+impl<T: Redact> Redact for Vec<T> {
+    fn redact(&mut self) {
+        self.iter_mut().for_each(Redact::redact);
+    }
+}
+
+impl Redact for Provider {
+    fn redact(&mut self) {
+        redact_value(&mut self.key);
+        redact_value(&mut self.protocol_password);
+    }
+}
+
+impl Redact for ConfigOption {
+    fn redact(&mut self) {
+        if is_sensitive(&self.key) {
+            redact_value(&mut self.value);
+        }
+    }
+}
+
+impl Redact for ConfigRevision {
+    fn redact(&mut self) {
+        self.content.redact();
+        self.diff.redact();
+    }
+}
+
+impl Redact for ConfigFragment {
+    fn redact(&mut self) {
+        self.content.redact();
+    }
+}
+
+impl Redact for ProviderTemplate {
+    fn redact(&mut self) {
+        self.content.redact();
+    }
+}
+
+// Implements `Redact` for messages by masking the listed fields.
+macro_rules! redact_fields {
+    ($($message:ty => [$($field:ident),+]),+ $(,)?) => {
+        $(
+            impl Redact for $message {
+                fn redact(&mut self) {
+                    $(self.$field.redact();)+
+                }
+            }
+        )+
+    };
+}
+
+redact_fields! {
+    GenerateConfigResponse => [config_content],
+    AddProviderResponse => [updated_config],
+    AddProvidersResponse => [updated_config],
+    ApplyChangesResponse => [updated_config],
+    UpdateConfigResponse => [updated_config],
+    RemoveProviderResponse => [updated_config],
+    UpdateProviderResponse => [updated_config],
+    DisableProviderResponse => [updated_config],
+    EnableProviderResponse => [updated_config],
+    RenameProviderResponse => [updated_config],
+    ImportConfigResponse => [updated_config],
+    AddProviderFromTemplateResponse => [updated_config],
+    UploadCrlResponse => [updated_config],
+    ImportPkcs12Response => [updated_config],
+    EnableAcmeResponse => [updated_config],
+    BindVaultCertificateResponse => [updated_config],
+    RestoreBackupResponse => [restored_config],
+    RollbackRevisionResponse => [restored_config],
+    RollbackToCommitResponse => [restored_config],
+    DiffConfigResponse => [unified_diff],
+    StageConfigResponse => [diff],
+    GetConfigResponse => [raw_config, global_options, providers, fragments],
+    GetProviderResponse => [provider],
+    ListProvidersResponse => [providers],
+    GetHistoryResponse => [revisions],
+    GetRevisionResponse => [revision],
+    RegisterTemplateResponse => [template],
+    ListTemplatesResponse => [templates],
+}
@@ -0,0 +1,150 @@
+//! Retrying stunnel startup.
+//!
+//! A stunnel that crashed can leave its accept ports in TIME_WAIT, and a
+//! restart in that window fails to bind even though a second attempt a
+//! moment later would succeed. Starting is therefore retried with an
+//! exponential backoff, and every attempt's error is kept so a final
+//! failure explains what went wrong each time.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+/// Attempts made when no retry policy is configured.
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// Delay after the first failed attempt when no retry policy is configured.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+// Longest delay between two attempts, however many have failed.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often, and how patiently, an operation is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first; 1 disables retries.
+    pub attempts: u32,
+    /// Delay after the first failure, doubled after each further one.
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: DEFAULT_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay after failed attempt `attempt`, counted from 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use stunnel_space::retry::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy {
+    ///     attempts: 4,
+    ///     initial_backoff: Duration::from_millis(500),
+    /// };
+    /// assert_eq!(policy.backoff(1), Duration::from_millis(500));
+    /// assert_eq!(policy.backoff(3), Duration::from_secs(2));
+    /// ```
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(MAX_BACKOFF)
+            .min(MAX_BACKOFF)
+    }
+
+    /// Runs `operation` until it succeeds or the attempts run out, sleeping
+    /// the backoff between attempts. On success, returns the value and the
+    /// attempt that produced it.
+    ///
+    /// # Errors
+    ///
+    /// Returns every attempt's error if none succeeded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::retry::RetryPolicy;
+    /// use stunnel_space::utils::start_stunnel;
+    ///
+    /// # async fn example() {
+    /// let started = RetryPolicy::default()
+    ///     .run(|_| async { start_stunnel("/etc/stunnel/stunnel.conf").map_err(|e| e.to_string()) })
+    ///     .await;
+    /// match started {
+    ///     Ok((pid, attempt)) => println!("Started PID {} on attempt {}", pid, attempt),
+    ///     Err(e) => eprintln!("{}", e),
+    /// }
+    /// # }
+    /// ```
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<(T, u32), RetryError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let attempts = self.attempts.max(1);
+        let mut errors = Vec::new();
+        for attempt in 1..=attempts {
+            match operation(attempt).await {
+                Ok(value) => return Ok((value, attempt)),
+                Err(e) => {
+                    if attempt < attempts {
+                        let delay = self.backoff(attempt);
+                        tracing::warn!(
+                            "Attempt {} of {} failed: {}; retrying in {}ms",
+                            attempt,
+                            attempts,
+                            e,
+                            delay.as_millis()
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    errors.push(e);
+                }
+            }
+        }
+        Err(RetryError { errors })
+    }
+}
+
+/// The errors of every attempt of an operation that never succeeded, in
+/// order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryError {
+    pub errors: Vec<String>,
+}
+
+impl RetryError {
+    /// Each error prefixed with its attempt, e.g. `attempt 2: Address already in use`.
+    pub fn history(&self) -> Vec<String> {
+        self.errors
+            .iter()
+            .enumerate()
+            .map(|(i, e)| format!("attempt {}: {}", i + 1, e))
+            .collect()
+    }
+}
+
+impl fmt::Display for RetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.as_slice() {
+            [only] => f.write_str(only),
+            _ => write!(
+                f,
+                "failed after {} attempts: {}",
+                self.errors.len(),
+                self.history().join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RetryError {}
@@ -0,0 +1,203 @@
+//! Encryption at rest for secrets the manager keeps.
+//!
+//! Config backups and history revisions carry protocol passwords and PSK
+//! settings inline, and the managed certificates directory holds private
+//! keys. With a secrets key configured these are stored sealed with
+//! AES-256-GCM, so a copied backup directory or database reveals nothing.
+//! Plaintext exists only where stunnel reads it: the live config and the
+//! runtime key directory, which should be a tmpfs outside any backup.
+//!
+//! The key is 32 bytes, given base64-encoded either directly or as the
+//! output of a command, e.g. a KMS decrypt call, so it never touches disk.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::process::Command;
+
+// Marks sealed content; the version allows the format to change.
+const SEALED_PREFIX: &str = "stunnel-space:sealed:v1:";
+
+/// Length of a secrets key in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Seals and opens secrets with one AES-256-GCM key.
+pub struct SecretCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl fmt::Debug for SecretCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretCipher").finish_non_exhaustive()
+    }
+}
+
+impl SecretCipher {
+    /// Creates a cipher from a base64-encoded 32-byte key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `key` is not base64 or not 32 bytes long.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::secrets::SecretCipher;
+    ///
+    /// let cipher = SecretCipher::from_base64("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=").unwrap();
+    /// let sealed = cipher.seal(b"protocolPassword = hunter2").unwrap();
+    /// assert!(!sealed.contains("hunter2"));
+    /// assert_eq!(cipher.open(&sealed).unwrap(), b"protocolPassword = hunter2");
+    /// ```
+    pub fn from_base64(key: &str) -> Result<Self, String> {
+        let key = BASE64
+            .decode(key.trim())
+            .map_err(|e| format!("Secrets key is not valid base64: {}", e))?;
+        if key.len() != KEY_LEN {
+            return Err(format!(
+                "Secrets key must be {} bytes, got {}",
+                KEY_LEN,
+                key.len()
+            ));
+        }
+        let key = UnboundKey::new(&AES_256_GCM, &key)
+            .map_err(|_| "Secrets key was rejected".to_string())?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Creates a cipher from the base64 key printed by shell command
+    /// `command`, such as a KMS or Vault transit decrypt of a wrapped key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or prints an invalid key.
+    pub fn from_command(command: &str) -> Result<Self, String> {
+        let output = Command::new("sh")
+            .args(["-c", command])
+            .output()
+            .map_err(|e| format!("Failed to run secrets key command: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Secrets key command failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Self::from_base64(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Encrypts `plaintext` with a fresh nonce and returns it as a single
+    /// line of text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no random nonce can be generated.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| "Failed to generate a nonce".to_string())?;
+        let mut data = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| "Failed to encrypt secret".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(data);
+        Ok(format!("{}{}", SEALED_PREFIX, BASE64.encode(sealed)))
+    }
+
+    /// Decrypts content produced by [`SecretCipher::seal`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `sealed` is malformed or was sealed with another
+    /// key.
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>, String> {
+        let encoded = sealed
+            .trim_end()
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| "Content is not sealed".to_string())?;
+        let mut data = BASE64
+            .decode(encoded)
+            .map_err(|_| "Sealed content is corrupt".to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("Sealed content is corrupt".to_string());
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&data[..NONCE_LEN]);
+        let plaintext = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut data[NONCE_LEN..],
+            )
+            .map_err(|_| {
+                "Failed to decrypt sealed content: wrong key or corrupt data".to_string()
+            })?;
+        Ok(plaintext.to_vec())
+    }
+
+    /// Seals text content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no random nonce can be generated.
+    pub fn seal_text(&self, plaintext: &str) -> Result<String, String> {
+        self.seal(plaintext.as_bytes())
+    }
+
+    /// Opens sealed text content, passing content stored before a key was
+    /// configured through unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sealed content cannot be decrypted or is not
+    /// UTF-8.
+    pub fn open_text(&self, content: &str) -> Result<String, String> {
+        if !is_sealed(content) {
+            return Ok(content.to_string());
+        }
+        String::from_utf8(self.open(content)?)
+            .map_err(|_| "Sealed content is not valid UTF-8".to_string())
+    }
+}
+
+/// Whether `content` was produced by [`SecretCipher::seal`].
+pub fn is_sealed(content: &str) -> bool {
+    content.starts_with(SEALED_PREFIX)
+}
+
+/// Seals `content` if a cipher is configured.
+///
+/// # Errors
+///
+/// Returns an error if sealing fails.
+pub fn seal_text(cipher: Option<&SecretCipher>, content: &str) -> Result<String, String> {
+    match cipher {
+        Some(cipher) => cipher.seal_text(content),
+        None => Ok(content.to_string()),
+    }
+}
+
+/// Opens `content` if it is sealed.
+///
+/// # Errors
+///
+/// Returns an error if `content` is sealed and no cipher is configured or
+/// decryption fails.
+pub fn open_text(cipher: Option<&SecretCipher>, content: &str) -> Result<String, String> {
+    match cipher {
+        Some(cipher) => cipher.open_text(content),
+        None if is_sealed(content) => {
+            Err("Content is encrypted but no secrets key is configured".to_string())
+        }
+        None => Ok(content.to_string()),
+    }
+}
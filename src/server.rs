@@ -1,566 +1,8381 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::{BroadcastStream, WatchStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::server::NamedService;
 use tonic::{Request, Response, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::{error, info, warn, Instrument, Span};
 
-use crate::stunnel::stunnel_manager_server::StunnelManager;
+use crate::accesslog;
+use crate::acme::{self, AcmeManager};
+use crate::auth::{AuthenticatedCaller, NamespaceScope};
+use crate::backend::{self, DirectBackend, ProcessBackend};
+use crate::backup::{self, backup_file, read_backup, RetentionPolicy};
+use crate::certs::{self, KeyPermissionPolicy};
+use crate::certstore::{self, CertStore, KeyType, Subject};
+use crate::connlog::{self, ConnectionLog, ConnectionQuery};
+use crate::diff::{section_changes, unified_diff};
+use crate::dns::{self, DnsCheckPolicy};
+use crate::drain;
+use crate::expiry::{self, ExpiryMonitor};
+use crate::fragments::FragmentDir;
+use crate::geoip::GeoIp;
+use crate::git::GitVersioning;
+use crate::history::{self, HistoryStore};
+use crate::instances::{check_systemd_unit, InstanceRegistry, InstanceSpec, DEFAULT_INSTANCE};
+use crate::journal::{self, JournalQuery};
+use crate::lint::{self, lint, Severity};
+use crate::logs::{self, LogCursor};
+use crate::maintenance::{Maintenance, MaintenanceMode};
+use crate::metrics::{self, MetricSet};
+use crate::namespaces::{NamespacePaths, NamespacePorts, NamespaceSet};
+use crate::operations::{self, OperationHandle, OperationStore, Outcome};
+use crate::parser::{
+    self, add_global, disable_section, enable_section, join_host_port, remove_section,
+    rename_section, split_host_port, update_globals, update_section, Document, Section,
+    StunnelConfig,
+};
+use crate::ports::PortRange;
+use crate::probe;
+use crate::readiness::{self, ServiceReadiness};
+use crate::redact::{self, redact_config};
+use crate::retry::RetryPolicy;
+use crate::secrets::{self, SecretCipher};
+use crate::staging::StagingArea;
+use crate::statsd::StatsdSink;
+use crate::structured::{Format, StructuredConfig};
+use crate::stunnel::operation::Result as OperationResult;
+use crate::stunnel::stunnel_manager_server::{StunnelManager, StunnelManagerServer};
 use crate::stunnel::{
-    AddProviderRequest, AddProviderResponse, GenerateConfigRequest, GenerateConfigResponse,
-    ReloadRequest, ReloadResponse, RemoveProviderRequest, RemoveProviderResponse, StatusRequest,
-    StatusResponse, UpdateConfigRequest, UpdateConfigResponse,
+    bind_vault_certificate_request, config_change, diff_config_request, remove_provider_progress,
+    rollback_revision_request, AcmeRenewal, AddProviderFromTemplateRequest,
+    AddProviderFromTemplateResponse, AddProviderRequest, AddProviderResponse, AddProvidersRequest,
+    AddProvidersResponse, ApplyChangesRequest, ApplyChangesResponse, BackendProbe, Backup,
+    BindVaultCertificateRequest, BindVaultCertificateResponse, CancelOperationRequest,
+    CancelOperationResponse, CertificateChangeEvent, CertificateEntry, CertificateInfo,
+    CertificateStatus, CommitConfigRequest, CommitConfigResponse, ConfigFormat, ConfigFragment,
+    ConfigOption, ConfigRevision, ConnectTarget, Connection, ConnectionRecord,
+    CreateInstanceRequest, CreateInstanceResponse, DeleteInstanceRequest, DeleteInstanceResponse,
+    DiffConfigRequest, DiffConfigResponse, DisableAcmeRequest, DisableAcmeResponse,
+    DisableProviderRequest, DisableProviderResponse, DiscardConfigRequest, DiscardConfigResponse,
+    DrainProgress, EnableAcmeRequest, EnableAcmeResponse, EnableProviderRequest,
+    EnableProviderResponse, ExportConfigRequest, ExportConfigResponse, FailoverStrategy,
+    GenerateConfigRequest, GenerateConfigResponse, GenerateCsrRequest, GenerateCsrResponse,
+    GetCertificateInfoRequest, GetCertificateInfoResponse, GetCertificateStatusRequest,
+    GetCertificateStatusResponse, GetConfigRequest, GetConfigResponse, GetHistoryRequest,
+    GetHistoryResponse, GetJournalLogsRequest, GetJournalLogsResponse, GetMaintenanceModeRequest,
+    GetMaintenanceModeResponse, GetOperationRequest, GetOperationResponse, GetProviderRequest,
+    GetProviderResponse, GetRevisionRequest, GetRevisionResponse, GetTopTalkersRequest,
+    GetTopTalkersResponse, GetTrafficStatsRequest, GetTrafficStatsResponse, GetVersionRequest,
+    GetVersionResponse, ImportConfigRequest, ImportConfigResponse, ImportPkcs12Request,
+    ImportPkcs12Response, Instance, JournalEntry, LintConfigRequest, LintConfigResponse,
+    LintFinding, LintSeverity, ListBackupsRequest, ListBackupsResponse, ListCertificatesRequest,
+    ListCertificatesResponse, ListInstancesRequest, ListInstancesResponse, ListOperationsRequest,
+    ListOperationsResponse, ListProvidersRequest, ListProvidersResponse, ListTemplatesRequest,
+    ListTemplatesResponse, MaintenanceState, ManagerVersion, Operation, OperationState,
+    ProbeBackendRequest, ProbeBackendResponse, ProcessUsage, Provider, ProviderTalkers,
+    ProviderTemplate, ProviderTraffic, PruneBackupsRequest, PruneBackupsResponse,
+    QueryConnectionsRequest, QueryConnectionsResponse, RefreshVaultCertificatesRequest,
+    RefreshVaultCertificatesResponse, RegisterTemplateRequest, RegisterTemplateResponse,
+    ReloadOutcome, ReloadRequest, ReloadResponse, RemoveProviderProgress, RemoveProviderRequest,
+    RemoveProviderResponse, RenameProviderRequest, RenameProviderResponse,
+    RenewAcmeCertificatesRequest, RenewAcmeCertificatesResponse, RestartRequest, RestartResponse,
+    RestoreBackupRequest, RestoreBackupResponse, RollbackRevisionRequest, RollbackRevisionResponse,
+    RollbackToCommitRequest, RollbackToCommitResponse, SetDebugLevelRequest, SetDebugLevelResponse,
+    SetMaintenanceModeRequest, SetMaintenanceModeResponse, StageConfigRequest, StageConfigResponse,
+    StartRequest, StartResponse, StatusRequest, StatusResponse, StopRequest, StopResponse,
+    StunnelVersion, SupervisorStatus, Talker, TalkerOrder, TlsHandshake, TlsProfile,
+    UnbindVaultCertificateRequest, UnbindVaultCertificateResponse, UpdateConfigRequest,
+    UpdateConfigResponse, UpdateProviderRequest, UpdateProviderResponse, UploadCertificateRequest,
+    UploadCertificateResponse, UploadCrlRequest, UploadCrlResponse, ValidateConfigContentRequest,
+    ValidateConfigContentResponse, ValidationError, VaultRefresh, VerifyChainRequest,
+    VerifyChainResponse, VerifyKeyPairRequest, VerifyKeyPairResponse,
+    WatchCertificateChangesRequest,
 };
+use crate::supervisor::Supervisor;
+use crate::telemetry;
+use crate::templates::{self, TemplateStore};
+use crate::tls;
+use crate::traffic::TrafficAccounting;
+use crate::usage::UsageTracker;
 use crate::utils::{
-    backup_file, get_active_connections, get_stunnel_pid, reload_stunnel, start_stunnel,
-    validate_stunnel_conf_path,
+    expand_env_vars, get_active_connections, get_global_option, launch_config_path,
+    listening_sockets, port_owner, process_running, process_stats, remove_pid_file,
+    set_global_option,
 };
+use crate::validation::{ConfigValidator, Enforcement, Report, ValidationPipeline};
+use crate::vault::{self, VaultManager, VaultSource};
+use crate::version;
+use crate::watcher::{self, CertificateChange, FileWatcher};
+
+// PEM header every uploaded CRL must carry.
+const CRL_PEM_HEADER: &str = "-----BEGIN X509 CRL-----";
+
+// Options naming certificate, key, CA and CRL files and directories, which
+// certificate RPCs may inspect.
+const CERTIFICATE_PATH_OPTIONS: [&str; 6] =
+    ["cert", "key", "CAfile", "CApath", "CRLfile", "CRLpath"];
+
+// Changes buffered per WatchCertificateChanges subscriber before it lags.
+const CERT_CHANGE_CHANNEL_CAPACITY: usize = 64;
+
+// Grace period between SIGTERM and SIGKILL when the client does not specify one.
+const DEFAULT_STOP_TIMEOUT_SECS: u64 = 10;
+
+// Revisions returned by GetHistory when the client does not set a limit.
+const DEFAULT_HISTORY_LIMIT: u32 = 50;
+
+// Connections returned by QueryConnections when the client does not set a
+// limit, and the most it returns.
+const DEFAULT_CONNECTION_LIMIT: u32 = 100;
+const MAX_CONNECTION_LIMIT: u32 = 10_000;
+
+// Addresses per provider returned by GetTopTalkers when the client does not
+// set a limit, and the most it returns.
+const DEFAULT_TALKER_LIMIT: u32 = 10;
+const MAX_TALKER_LIMIT: u32 = 1_000;
+
+// Metadata key clients may set to identify themselves in the config history.
+const CLIENT_ID_METADATA_KEY: &str = "x-client-id";
+
+// Metadata key local clients may set to "true" to receive unmasked secrets.
+const REVEAL_SECRETS_METADATA_KEY: &str = "x-reveal-secrets";
 
 #[derive(Debug, Clone)]
 pub struct StunnelServer {
     config_path: String,
     pid_file: String,
+    backup_policy: RetentionPolicy,
+    history: Option<Arc<HistoryStore>>,
+    git: Option<GitVersioning>,
+    fragments: Option<FragmentDir>,
+    templates: Arc<TemplateStore>,
+    staging: Arc<StagingArea>,
+    validation: ValidationPipeline,
+    accept_port_range: Option<PortRange>,
+    expiry: Arc<ExpiryMonitor>,
+    cert_store: Option<CertStore>,
+    acme: Option<Arc<AcmeManager>>,
+    vault: Option<Arc<VaultManager>>,
+    cert_changes: broadcast::Sender<CertificateChange>,
+    key_permission_policy: KeyPermissionPolicy,
+    dns_check: DnsCheckPolicy,
+    readiness_timeout: Duration,
+    backend: Arc<dyn ProcessBackend>,
+    journal_unit: String,
+    supervisor: Option<Supervisor>,
+    usage: UsageTracker,
+    traffic: TrafficAccounting,
+    connection_log: Option<Arc<ConnectionLog>>,
+    geoip: Option<Arc<GeoIp>>,
+    start_retry: RetryPolicy,
+    operations: OperationStore<OperationResult>,
+    redact_secrets: bool,
+    log_payloads: bool,
+    secrets: Option<Arc<SecretCipher>>,
+    maintenance: MaintenanceMode,
+    allowed_config_paths: Vec<PathBuf>,
+    instance_id: String,
+    namespace: Option<String>,
+    namespaces: Arc<NamespaceSet>,
+    instances: Arc<InstanceRegistry>,
+    // Servers for the registered instances, built on first use.
+    instance_servers: Arc<Mutex<HashMap<String, StunnelServer>>>,
+    // Held for the whole of each mutating RPC, so read-modify-write cycles
+    // never interleave.
+    mutations: Arc<tokio::sync::Mutex<()>>,
+    // Held while the config is written; `true` once shutdown refuses writes.
+    config_writes: Arc<Mutex<bool>>,
+    // StartStunnel's startup options for the current run, if it gave any.
+    launch_overrides: Arc<Mutex<Option<LaunchOverrides>>>,
+    shutting_down: watch::Sender<bool>,
 }
 
 impl StunnelServer {
     pub fn new(config_path: String, pid_file: String) -> Self {
+        let backend = Arc::new(DirectBackend::new(&config_path, &pid_file));
         Self {
             config_path,
             pid_file,
+            backup_policy: RetentionPolicy::default(),
+            history: None,
+            git: None,
+            fragments: None,
+            templates: Arc::new(TemplateStore::new()),
+            staging: Arc::new(StagingArea::new()),
+            validation: ValidationPipeline::default(),
+            accept_port_range: None,
+            expiry: Arc::new(ExpiryMonitor::default()),
+            cert_store: None,
+            acme: None,
+            vault: None,
+            cert_changes: broadcast::channel(CERT_CHANGE_CHANNEL_CAPACITY).0,
+            key_permission_policy: KeyPermissionPolicy::default(),
+            dns_check: DnsCheckPolicy::default(),
+            readiness_timeout: readiness::DEFAULT_TIMEOUT,
+            backend,
+            journal_unit: "stunnel.service".to_string(),
+            supervisor: None,
+            usage: UsageTracker::new(),
+            traffic: TrafficAccounting::new(),
+            connection_log: None,
+            geoip: None,
+            start_retry: RetryPolicy::default(),
+            operations: OperationStore::new(),
+            redact_secrets: true,
+            log_payloads: false,
+            secrets: None,
+            maintenance: MaintenanceMode::new(),
+            allowed_config_paths: Vec::new(),
+            instance_id: DEFAULT_INSTANCE.to_string(),
+            namespace: None,
+            namespaces: Arc::new(NamespaceSet::new()),
+            instances: Arc::new(InstanceRegistry::new()),
+            instance_servers: Arc::default(),
+            mutations: Arc::new(tokio::sync::Mutex::new(())),
+            config_writes: Arc::new(Mutex::new(false)),
+            launch_overrides: Arc::default(),
+            shutting_down: watch::channel(false).0,
         }
     }
-}
 
-// Helper: write atomically by writing to a temp file then renaming.
-fn atomic_write(path: &str, content: &str) -> io::Result<()> {
-    let tmp_path = format!("{}.tmp.{}", path, std::process::id());
-    {
-        let mut file = fs::File::create(&tmp_path)?;
-        file.write_all(content.as_bytes())?;
-        file.sync_all()?;
+    /// Sets the retention policy applied each time a config backup is taken.
+    pub fn with_backup_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.backup_policy = policy;
+        self
     }
-    fs::rename(&tmp_path, path)?;
-    Ok(())
-}
 
-// Helper: best-effort check if a process exists (works on Linux by checking /proc).
-fn process_running(pid: i32) -> bool {
-    Path::new(&format!("/proc/{}", pid)).exists()
-}
+    /// Records every config change in `store`, enabling the history RPCs.
+    pub fn with_history(mut self, store: HistoryStore) -> Self {
+        self.history = Some(Arc::new(store));
+        self
+    }
 
-#[tonic::async_trait]
-impl StunnelManager for StunnelServer {
-    async fn reload_config(
-        &self,
-        request: Request<ReloadRequest>,
-    ) -> Result<Response<ReloadResponse>, Status> {
-        let req = request.into_inner();
-        let config_path = if req.config_path.is_empty() {
-            self.config_path.clone()
-        } else {
-            req.config_path
-        };
+    /// Records the connections the traffic follower sees in `log`, enabling
+    /// QueryConnections.
+    pub fn with_connection_log(mut self, log: ConnectionLog) -> Self {
+        self.connection_log = Some(Arc::new(log));
+        self
+    }
 
-        // Validate only if requested
-        if req.validate_only {
-            match validate_stunnel_conf_path(&config_path) {
-                Ok(_) => {
-                    return Ok(Response::new(ReloadResponse {
-                        success: true,
-                        message: "Configuration is valid".to_string(),
-                        pid: 0,
-                    }));
+    /// Looks up the country and autonomous system of the remote address of
+    /// each connection GetStatus reports in `geoip`.
+    pub fn with_geoip(mut self, geoip: Arc<GeoIp>) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    /// Commits every successful config change to the Git repository `git`.
+    pub fn with_git_versioning(mut self, git: GitVersioning) -> Self {
+        self.git = Some(git);
+        self
+    }
+
+    /// Stores each added provider in its own file under `fragments`, which
+    /// the main config includes.
+    pub fn with_providers_dir(mut self, fragments: FragmentDir) -> Self {
+        self.fragments = Some(fragments);
+        self
+    }
+
+    /// Uses `templates` for provider templates instead of an in-memory store.
+    pub fn with_templates(mut self, templates: TemplateStore) -> Self {
+        self.templates = Arc::new(templates);
+        self
+    }
+
+    /// Keeps configs staged with StageConfig in `staging` instead of in
+    /// memory.
+    pub fn with_staging_area(mut self, staging: StagingArea) -> Self {
+        self.staging = Arc::new(staging);
+        self
+    }
+
+    /// Validates candidate configs with `pipeline` instead of the
+    /// [`DEFAULT_VALIDATORS`](crate::validation::DEFAULT_VALIDATORS).
+    pub fn with_validation_pipeline(mut self, pipeline: ValidationPipeline) -> Self {
+        self.validation = pipeline;
+        self
+    }
+
+    /// Lets AddProvider omit `accept_port`, allocating the lowest free port
+    /// in `range` instead.
+    pub fn with_accept_port_range(mut self, range: PortRange) -> Self {
+        self.accept_port_range = Some(range);
+        self
+    }
+
+    /// Uses `monitor` for certificate expiry checks instead of one with the
+    /// default threshold and no webhook.
+    pub fn with_expiry_monitor(mut self, monitor: ExpiryMonitor) -> Self {
+        self.expiry = Arc::new(monitor);
+        self
+    }
+
+    /// Keeps generated keys and uploaded certificates in `store`.
+    pub fn with_cert_store(mut self, store: CertStore) -> Self {
+        self.cert_store = Some(store);
+        self
+    }
+
+    /// Sets how configs referencing group- or world-readable key files are
+    /// handled when applied.
+    pub fn with_key_permission_policy(mut self, policy: KeyPermissionPolicy) -> Self {
+        self.key_permission_policy = policy;
+        self
+    }
+
+    /// Sets whether the connect hosts of added and updated providers must
+    /// resolve.
+    pub fn with_dns_check_policy(mut self, policy: DnsCheckPolicy) -> Self {
+        self.dns_check = policy;
+        self
+    }
+
+    /// Sets how long ReloadConfig, StartStunnel and RestartStunnel wait for
+    /// every service to listen on its accept port. Zero skips the check.
+    pub fn with_readiness_timeout(mut self, timeout: Duration) -> Self {
+        self.readiness_timeout = timeout;
+        self
+    }
+
+    /// Sets how stunnel is started, stopped and signalled. By default it is
+    /// spawned directly and tracked through the PID file.
+    pub fn with_process_backend(mut self, backend: Arc<dyn ProcessBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets the systemd unit whose journal GetJournalLogs reads.
+    pub fn with_journal_unit(mut self, unit: &str) -> Self {
+        self.journal_unit = unit.to_string();
+        self
+    }
+
+    /// Enables crash supervision: once [`StunnelServer::spawn_supervisor`]
+    /// runs, a stunnel that dies without being stopped is started again.
+    pub fn with_supervisor(mut self, supervisor: Supervisor) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+
+    /// Sets how StartStunnel retries a stunnel that fails to start, e.g.
+    /// because its accept ports are still in TIME_WAIT after a crash.
+    pub fn with_start_retry(mut self, policy: RetryPolicy) -> Self {
+        self.start_retry = policy;
+        self
+    }
+
+    /// Sets whether sensitive option values are masked in returned config
+    /// content. Masking is on by default.
+    pub fn with_secret_redaction(mut self, enabled: bool) -> Self {
+        self.redact_secrets = enabled;
+        self
+    }
+
+    /// Sets whether handlers log the request and response messages of each
+    /// call, with secrets masked (see [`accesslog::payload`]). Off by
+    /// default.
+    pub fn with_payload_logging(mut self, enabled: bool) -> Self {
+        self.log_payloads = enabled;
+        self
+    }
+
+    /// Seals config backups with `cipher`. The history store and certificate
+    /// store are given the cipher separately.
+    pub fn with_secrets(mut self, cipher: Arc<SecretCipher>) -> Self {
+        self.secrets = Some(cipher);
+        self
+    }
+
+    /// Reports and toggles `mode` through the maintenance mode RPCs. The
+    /// same switch must be installed as an interceptor to take effect.
+    pub fn with_maintenance_mode(mut self, mode: MaintenanceMode) -> Self {
+        self.maintenance = mode;
+        self
+    }
+
+    /// Lets clients name `paths` as the `config_path` of ReloadConfig,
+    /// UpdateConfig, StartStunnel and RestartStunnel instead of the managed
+    /// config. A directory allows every file below it. By default no other
+    /// path is allowed.
+    pub fn with_allowed_config_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.allowed_config_paths = paths;
+        self
+    }
+
+    /// Manages the instances in `registry` besides this one. Each is served
+    /// by a server sharing this one's policies, certificate store, templates
+    /// and config history; the providers directory, Git versioning, ACME,
+    /// Vault and staged configs belong to this instance only.
+    pub fn with_instances(mut self, registry: InstanceRegistry) -> Self {
+        self.instances = Arc::new(registry);
+        self
+    }
+
+    /// Lets instances be created in the tenant namespaces in `namespaces`.
+    /// An instance in a namespace may only use configs in the namespace's
+    /// directory and, if it has a port range, accept ports in it. It gets
+    /// its own templates and no certificate store, and cannot toggle
+    /// maintenance mode, so tenants cannot see or change each other's
+    /// files.
+    pub fn with_namespaces(mut self, namespaces: NamespaceSet) -> Self {
+        self.namespaces = Arc::new(namespaces);
+        self
+    }
+
+    /// Issues and renews certificates for ACME-managed providers with `manager`.
+    pub fn with_acme(mut self, manager: AcmeManager) -> Self {
+        self.acme = Some(Arc::new(manager));
+        self
+    }
+
+    /// Fetches and refreshes Vault-backed certificates with `manager`.
+    pub fn with_vault(mut self, manager: VaultManager) -> Self {
+        self.vault = Some(Arc::new(manager));
+        self
+    }
+
+    /// Spawns a task checking certificate expiry every `interval`, warning
+    /// about certificates close to expiry.
+    pub fn spawn_expiry_monitor(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let report = match server.read_providers_config() {
+                    Ok(content) => server.expiry.check(&StunnelConfig::parse(&content)),
+                    Err(e) => {
+                        error!("Certificate expiry check failed to read config: {}", e);
+                        continue;
+                    }
+                };
+                server.expiry.notify(&report).await;
+            }
+        })
+    }
+
+    /// Spawns a task renewing due ACME certificates every `interval`, and
+    /// reloading stunnel when any was renewed. Does nothing without ACME.
+    pub fn spawn_acme_renewal(&self, interval: Duration) -> Option<tokio::task::JoinHandle<()>> {
+        let acme = self.acme.clone()?;
+        let server = self.clone();
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let results = match acme.renew_due(false).await {
+                    Ok(results) => results,
+                    Err(e) => {
+                        error!("ACME renewal check failed: {}", e);
+                        continue;
+                    }
+                };
+                for result in &results {
+                    if let Some(error) = &result.error {
+                        error!(provider = %result.provider, "ACME renewal failed: {}", error);
+                    } else if result.renewed {
+                        info!(provider = %result.provider, "Renewed ACME certificate");
+                    }
                 }
-                Err(e) => {
-                    return Ok(Response::new(ReloadResponse {
-                        success: false,
-                        message: format!("Config validation failed: {}", e),
-                        pid: 0,
-                    }));
+                if results.iter().any(|result| result.renewed) {
+                    server.reload_if_running();
+                }
+            }
+        }))
+    }
+
+    /// Spawns a task refreshing due Vault certificates every `interval`, and
+    /// reloading stunnel when any changed. Does nothing without Vault.
+    pub fn spawn_vault_refresh(&self, interval: Duration) -> Option<tokio::task::JoinHandle<()>> {
+        let vault = self.vault.clone()?;
+        let server = self.clone();
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let results = match vault.refresh_due(false).await {
+                    Ok(results) => results,
+                    Err(e) => {
+                        error!("Vault refresh failed: {}", e);
+                        continue;
+                    }
+                };
+                for result in &results {
+                    if let Some(error) = &result.error {
+                        error!(provider = %result.provider, "Vault refresh failed: {}", error);
+                    } else if result.refreshed {
+                        info!(provider = %result.provider, "Refreshed Vault certificate");
+                    }
+                }
+                if results.iter().any(|result| result.refreshed) {
+                    server.reload_if_running();
                 }
             }
+        }))
+    }
+
+    /// Checks that the manager can serve requests: the config path must be
+    /// readable and, if `require_stunnel`, stunnel must be running.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the manager is unhealthy.
+    pub fn check_health(&self, require_stunnel: bool) -> Result<(), String> {
+        fs::read(&self.config_path)
+            .map_err(|e| format!("Cannot read config {}: {}", self.config_path, e))?;
+        if require_stunnel {
+            self.stunnel_pid()
+                .map_err(|e| format!("Stunnel is not running: {}", e))?;
         }
+        Ok(())
+    }
 
-        // Try to get existing PID and reload
-        match get_stunnel_pid(&self.pid_file) {
-            Ok(pid) => {
-                // Ensure process is actually running before attempting reload
-                if process_running(pid) {
-                    // Send SIGHUP to reload configuration
-                    match reload_stunnel(pid) {
-                        Ok(_) => Ok(Response::new(ReloadResponse {
-                            success: true,
-                            message: "Configuration reloaded successfully".to_string(),
-                            pid,
-                        })),
-                        Err(e) => Ok(Response::new(ReloadResponse {
-                            success: false,
-                            message: format!("Failed to reload stunnel: {}", e),
-                            pid: 0,
-                        })),
+    /// Summarizes stunnel's state in one line, e.g. `stunnel pid 1234
+    /// running, 3 providers`, for service managers to display.
+    pub fn status_summary(&self) -> String {
+        let providers = self
+            .read_providers_config()
+            .map(|content| StunnelConfig::parse(&content).sections.len())
+            .unwrap_or(0);
+        match self.stunnel_pid() {
+            Ok(pid) => format!("stunnel pid {} running, {} providers", pid, providers),
+            Err(_) => format!("stunnel not running, {} providers", providers),
+        }
+    }
+
+    /// Spawns a task running [`StunnelServer::check_health`] every
+    /// `interval` and publishing the result to the gRPC health service, both
+    /// for StunnelManager and for the server as a whole. Changes are logged.
+    pub fn spawn_health_reporter(
+        &self,
+        mut reporter: HealthReporter,
+        require_stunnel: bool,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let mut healthy = None;
+            loop {
+                ticker.tick().await;
+                let result = server.check_health(require_stunnel);
+                if healthy == Some(result.is_ok()) {
+                    continue;
+                }
+                let status = match &result {
+                    Ok(()) => {
+                        info!("Health check passed; reporting SERVING");
+                        ServingStatus::Serving
                     }
-                } else {
-                    // PID file exists but process not running - start new instance
-                    match start_stunnel(&config_path) {
-                        Ok(new_pid) => Ok(Response::new(ReloadResponse {
-                            success: true,
-                            message: "Stunnel restarted successfully (stale pid)".to_string(),
-                            pid: new_pid,
-                        })),
-                        Err(e) => Ok(Response::new(ReloadResponse {
-                            success: false,
-                            message: format!("Failed to start stunnel after stale pid: {}", e),
-                            pid: 0,
-                        })),
+                    Err(e) => {
+                        warn!("Health check failed: {}; reporting NOT_SERVING", e);
+                        ServingStatus::NotServing
+                    }
+                };
+                reporter.set_service_status("", status).await;
+                reporter
+                    .set_service_status(
+                        <StunnelManagerServer<StunnelServer> as NamedService>::NAME,
+                        status,
+                    )
+                    .await;
+                healthy = Some(result.is_ok());
+            }
+        })
+    }
+
+    /// Spawns a thread watching every certificate, key, CA and CRL file the
+    /// config references. When one is replaced, stunnel is reloaded and the
+    /// change is published to WatchCertificateChanges subscribers. Config
+    /// edits update the watched set without reloading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if inotify is unavailable.
+    pub fn spawn_cert_watcher(
+        &self,
+        debounce: Duration,
+    ) -> io::Result<std::thread::JoinHandle<()>> {
+        let mut file_watcher = FileWatcher::new()?;
+        let server = self.clone();
+        Ok(std::thread::spawn(move || loop {
+            let config = StunnelConfig::parse(&server.read_providers_config().unwrap_or_default());
+            let mut files = watcher::watched_files(&config);
+            files.insert(Path::new(&server.config_path).to_path_buf());
+            file_watcher.watch(files);
+
+            let changed = match file_watcher.wait_for_changes(debounce) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    error!("Certificate watcher stopped: {}", e);
+                    return;
+                }
+            };
+            let paths: Vec<String> = changed
+                .iter()
+                .filter(|path| **path != Path::new(&server.config_path))
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            if paths.is_empty() {
+                continue;
+            }
+
+            let mut providers: Vec<String> = Vec::new();
+            for path in &paths {
+                for section in watcher::referencing_sections(&config, Path::new(path)) {
+                    if !providers.contains(&section) {
+                        providers.push(section);
                     }
                 }
             }
-            Err(e) => {
-                // Start new stunnel instance
-                println!("Starting new stunnel instance: {}", e);
-                match start_stunnel(&config_path) {
-                    Ok(pid) => Ok(Response::new(ReloadResponse {
-                        success: true,
-                        message: "Stunnel started successfully".to_string(),
-                        pid,
-                    })),
-                    Err(e) => Ok(Response::new(ReloadResponse {
-                        success: false,
-                        message: format!("Failed to start stunnel: {}", e),
-                        pid: 0,
-                    })),
+            let reloaded = match server.stunnel_pid() {
+                Ok(pid) if process_running(pid) => server.reload_process(pid).is_ok(),
+                _ => false,
+            };
+            info!(
+                "Certificate files changed: {}{}",
+                paths.join(", "),
+                if reloaded { "; stunnel reloaded" } else { "" }
+            );
+            // Sending only fails when nobody is subscribed
+            let _ = server.cert_changes.send(CertificateChange {
+                paths,
+                providers,
+                detected_at: Utc::now(),
+                reloaded,
+            });
+        }))
+    }
+
+    /// Spawns a task checking on stunnel every `interval` and restarting it,
+    /// with exponential backoff, if it has died without being stopped. Every
+    /// registered instance is checked the same way. Does nothing without a
+    /// supervisor.
+    pub fn spawn_supervisor(&self, interval: Duration) -> Option<tokio::task::JoinHandle<()>> {
+        self.supervisor.as_ref()?;
+        let server = self.clone();
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if *server.shutting_down.borrow() {
+                    return;
+                }
+                server.supervise().await;
+                for (id, _) in server.instances.list() {
+                    if let Ok(instance) = server.instance(&id) {
+                        instance.supervise().await;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Follows the log file of every instance's config every `interval`,
+    /// adding up what the closed connections of each provider forwarded for
+    /// GetTrafficStats and recording connections in the connection history,
+    /// if enabled. Configs without an `output` file are skipped.
+    pub fn spawn_traffic_follower(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if *server.shutting_down.borrow() {
+                    return;
+                }
+                let _ = server.follow_traffic();
+                for (id, _) in server.instances.list() {
+                    if let Ok(instance) = server.instance(&id) {
+                        let _ = instance.follow_traffic();
+                    }
                 }
             }
+        })
+    }
+
+    /// Renders the metrics of this instance and every registered one in the
+    /// OpenMetrics text format. Blocks while configs, logs and certificates
+    /// are read.
+    pub fn render_metrics(&self) -> String {
+        self.gather_metrics().render()
+    }
+
+    /// Collects the metrics of this instance and every registered one.
+    /// Blocks while configs, logs and certificates are read.
+    pub fn gather_metrics(&self) -> MetricSet {
+        let connections = get_active_connections();
+        let now = Utc::now();
+        let mut metrics = MetricSet::new();
+        self.collect_metrics(&mut metrics, &connections, now);
+        for (id, _) in self.instances.list() {
+            if let Ok(instance) = self.instance(&id) {
+                instance.collect_metrics(&mut metrics, &connections, now);
+            }
         }
+        metrics
     }
 
-    async fn get_status(
+    /// Spawns a task pushing the metrics of every instance to StatsD
+    /// through `sink` every `interval`, until shutdown.
+    pub fn spawn_statsd_push(
         &self,
-        _request: Request<StatusRequest>,
-    ) -> Result<Response<StatusResponse>, Status> {
-        match get_stunnel_pid(&self.pid_file) {
+        sink: StatsdSink,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        let sink = Arc::new(sink);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if *server.shutting_down.borrow() {
+                    return;
+                }
+                let manager = server.clone();
+                let sink = sink.clone();
+                match tokio::task::spawn_blocking(move || sink.push(&manager.gather_metrics()))
+                    .await
+                {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => warn!("StatsD push failed: {}", e),
+                    Err(e) => error!("StatsD push task failed: {}", e),
+                }
+            }
+        })
+    }
+
+    // Adds this instance's series to `metrics`. An unreadable config adds
+    // none.
+    fn collect_metrics(
+        &self,
+        metrics: &mut MetricSet,
+        connections: &[Connection],
+        now: DateTime<Utc>,
+    ) {
+        let Ok(content) = self.read_providers_config() else {
+            return;
+        };
+        // Catch up with the log rather than wait for the follower
+        let _ = self.follow_traffic();
+        metrics::collect_instance(
+            metrics,
+            &self.instance_id,
+            &StunnelConfig::parse(&content),
+            &self.traffic.totals(),
+            self.traffic.reloads(),
+            connections,
+            now,
+        );
+    }
+
+    // Reads the lines stunnel logged since the last call into the traffic
+    // totals and the connection history.
+    fn follow_traffic(&self) -> Result<(), String> {
+        let path = self
+            .read_providers_config()
+            .ok()
+            .and_then(|content| logs::log_path(&StunnelConfig::parse(&content)))
+            .ok_or_else(|| {
+                "The config names no log file; set the global output option".to_string()
+            })?;
+        let events = self.traffic.follow(&path).map_err(|e| {
+            warn!(instance = %self.instance_id, "Failed to read {}: {}", path.display(), e);
+            format!("Failed to read {}: {}", path.display(), e)
+        })?;
+        if let Some(log) = self.connection_log.as_ref().filter(|_| !events.is_empty()) {
+            if let Err(e) = log.record(&self.instance_id, &events) {
+                warn!(instance = %self.instance_id, "Failed to record connections: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    // Restarts stunnel if it has died without being stopped and the
+    // supervisor's backoff allows it.
+    async fn supervise(&self) {
+        let Some(supervisor) = &self.supervisor else {
+            return;
+        };
+        // Restart and Stop hold the lock, so stunnel is never seen down
+        // halfway through one of them
+        let _mutation = self.lock_mutations().await;
+        if *self.shutting_down.borrow() {
+            return;
+        }
+        if let Ok(pid) = self.stunnel_pid() {
+            supervisor.observe_running(pid);
+            return;
+        }
+        if !supervisor.observe_down(|pid| self.crash_reason(pid)) {
+            return;
+        }
+        let started = match self.start_process(&self.config_path).await {
+            Ok(started) => started,
+            Err(status) => Err(status.message().to_string()),
+        };
+        match started {
             Ok(pid) => {
-                let connections = get_active_connections();
-                Ok(Response::new(StatusResponse {
-                    is_running: process_running(pid),
-                    pid,
-                    config_path: self.config_path.clone(),
-                    active_connections: connections,
-                }))
+                info!(pid, instance = %self.instance_id, "Supervisor restarted stunnel");
+                supervisor.restarted(pid);
             }
-            Err(_) => Ok(Response::new(StatusResponse {
-                is_running: false,
-                pid: 0,
-                config_path: self.config_path.clone(),
-                active_connections: vec![],
-            })),
+            Err(e) => {
+                error!(instance = %self.instance_id, "Supervisor failed to restart stunnel: {}", e);
+                supervisor.restart_failed(e);
+            }
+        }
+    }
+
+    /// Marks the manager as shutting down, ending WatchCertificateChanges
+    /// streams so in-flight RPCs can drain. Config writes are still allowed.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.send_replace(true);
+        for instance in self
+            .instance_servers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+        {
+            instance.begin_shutdown();
+        }
+    }
+
+    /// Finishes shutting down: waits for a config write in progress, refuses
+    /// any further writes and, if `stop`, stops stunnel, escalating
+    /// to SIGKILL after `timeout`. Otherwise stunnel is left running. Every
+    /// registered instance is shut down the same way.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an instance's stunnel could not be stopped.
+    pub fn shutdown(&self, stop: bool, timeout: Duration) -> Result<(), String> {
+        self.begin_shutdown();
+        let mut errors: Vec<String> = self
+            .instances
+            .list()
+            .into_iter()
+            .filter_map(|(id, _)| {
+                let instance = self.instance(&id).ok()?;
+                let result = instance.shutdown(stop, timeout);
+                result.err().map(|e| format!("Instance {}: {}", id, e))
+            })
+            .collect();
+        if let Err(e) = self.shutdown_instance(stop, timeout) {
+            errors.insert(0, e);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    // Shuts down this instance alone.
+    fn shutdown_instance(&self, stop: bool, timeout: Duration) -> Result<(), String> {
+        *self.config_writes.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        if !stop {
+            return Ok(());
+        }
+        if let Some(supervisor) = &self.supervisor {
+            supervisor.stopped();
+        }
+        let Ok(pid) = self.stunnel_pid() else {
+            return Ok(());
+        };
+        let exited_cleanly = self
+            .backend
+            .stop(pid, timeout)
+            .map_err(|e| format!("Failed to stop stunnel (PID {}): {}", pid, e))?;
+        if !exited_cleanly {
+            warn!(
+                pid,
+                "Stunnel did not exit within {}s and was killed",
+                timeout.as_secs()
+            );
+        }
+        self.clear_launch_overrides();
+        if let Err(e) = remove_pid_file(&self.pid_file) {
+            warn!("Failed to remove pid file {}: {}", self.pid_file, e);
+        }
+        info!(pid, "Stopped stunnel");
+        Ok(())
+    }
+
+    // The server for the instance named `id`, this one for an empty id or
+    // its own.
+    fn instance(&self, id: &str) -> Result<StunnelServer, String> {
+        if id.is_empty() || id == self.instance_id {
+            return Ok(self.clone());
+        }
+        let mut servers = self
+            .instance_servers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(server) = servers.get(id) {
+            return Ok(server.clone());
+        }
+        let spec = self
+            .instances
+            .get(id)
+            .ok_or_else(|| format!("Instance {} not found", id))?;
+        let server = self.for_instance(id, &spec);
+        servers.insert(id.to_string(), server.clone());
+        Ok(server)
+    }
+
+    // A server for another instance, sharing this one's policies and stores
+    // but with its own config, process, locks and operations. An instance in
+    // a namespace shares nothing another tenant could read or change.
+    fn for_instance(&self, id: &str, spec: &InstanceSpec) -> StunnelServer {
+        let mut server = Self {
+            instance_id: id.to_string(),
+            namespace: spec.namespace.clone(),
+            config_path: spec.config_path.clone(),
+            pid_file: spec.pid_file.clone(),
+            git: None,
+            fragments: None,
+            staging: Arc::new(StagingArea::new()),
+            acme: None,
+            vault: None,
+            backend: self.instances.process_backend(spec),
+            journal_unit: spec.unit().unwrap_or_default().to_string(),
+            supervisor: self.supervisor.as_ref().map(|_| Supervisor::new()),
+            usage: UsageTracker::new(),
+            traffic: TrafficAccounting::new(),
+            operations: OperationStore::new(),
+            instances: Arc::new(InstanceRegistry::new()),
+            instance_servers: Arc::default(),
+            mutations: Arc::new(tokio::sync::Mutex::new(())),
+            config_writes: Arc::new(Mutex::new(false)),
+            launch_overrides: Arc::default(),
+            shutting_down: watch::channel(false).0,
+            ..self.clone()
+        };
+        if let Some(namespace) = spec
+            .namespace
+            .as_deref()
+            .and_then(|name| self.namespaces.get(name))
+        {
+            server.allowed_config_paths = vec![namespace.config_dir.clone()];
+            server.accept_port_range = namespace.accept_ports;
+            server.validation = server.validation.with_validator(
+                NamespacePaths::new(&namespace.name, &namespace.config_dir),
+                Enforcement::Blocking,
+                None,
+            );
+            if let Some(range) = namespace.accept_ports {
+                server.validation = server.validation.with_validator(
+                    NamespacePorts::new(&namespace.name, range),
+                    Enforcement::Blocking,
+                    None,
+                );
+            }
+            server.templates = Arc::new(TemplateStore::new());
+            server.cert_store = None;
+            server.cert_changes = broadcast::channel(CERT_CHANGE_CHANNEL_CAPACITY).0;
+        }
+        server
+    }
+
+    // Checks and registers the instance a CreateInstance request describes,
+    // returning its server. The config must be on the allowlist, or in the
+    // namespace's directory along with the PID file, which StopStunnel
+    // removes and must be an absolute `.pid` path.
+    fn register_instance(
+        &self,
+        req: CreateInstanceRequest,
+        scope: Option<&NamespaceScope>,
+    ) -> Result<StunnelServer, String> {
+        if req.config_path.is_empty() || req.pid_file.is_empty() {
+            return Err("config_path and pid_file are required".to_string());
+        }
+        let namespace = Some(req.namespace.trim().to_string()).filter(|name| !name.is_empty());
+        if let Some(scope) = scope {
+            if !scope.allows(namespace.as_deref()) {
+                return Err(format!(
+                    "Instances must be created in namespace {}",
+                    scope.0.join(" or ")
+                ));
+            }
+        }
+        let config_path = match &namespace {
+            Some(name) => {
+                let namespace = self
+                    .namespaces
+                    .get(name)
+                    .ok_or_else(|| format!("Namespace {} not found", name))?;
+                let config_dir = normalize_path(&namespace.config_dir)
+                    .ok_or_else(|| format!("Namespace {} has no config_dir", name))?;
+                let inside = |path: &str| {
+                    normalize_path(Path::new(path))
+                        .is_some_and(|path| path.starts_with(&config_dir))
+                };
+                if !inside(&req.config_path) || !inside(&req.pid_file) {
+                    return Err(format!(
+                        "config_path and pid_file must be in {}",
+                        namespace.config_dir.display()
+                    ));
+                }
+                req.config_path
+            }
+            None => self.resolve_config_path(req.config_path)?,
+        };
+        let pid_file = Path::new(&req.pid_file);
+        if !pid_file.is_absolute()
+            || pid_file.extension().and_then(|ext| ext.to_str()) != Some("pid")
+        {
+            return Err(format!(
+                "pid_file {} must be an absolute path ending in .pid",
+                req.pid_file
+            ));
+        }
+        if config_path == self.config_path || req.pid_file == self.pid_file {
+            return Err(format!(
+                "Instance {} already uses that config or pid file",
+                DEFAULT_INSTANCE
+            ));
+        }
+        let spec = InstanceSpec {
+            config_path,
+            pid_file: req.pid_file,
+            systemd_unit: (!req.systemd_unit.is_empty()).then_some(req.systemd_unit),
+            namespace,
+        };
+        self.instances.insert(&req.instance_id, spec)?;
+        self.instance(&req.instance_id)
+    }
+
+    // Active stunnel connections for GetStatus, with the location of their
+    // peers if GeoIP is enabled. An instance in a namespace only sees those
+    // to its own accept ports.
+    fn instance_connections(&self) -> Vec<Connection> {
+        let mut connections = get_active_connections();
+        if self.namespace.is_some() {
+            let ports: Vec<u16> = match self.read_providers_config() {
+                Ok(content) => StunnelConfig::parse(&content)
+                    .sections
+                    .iter()
+                    .filter_map(accept_port)
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            connections.retain(|connection| {
+                connection
+                    .local_address
+                    .rsplit_once(':')
+                    .and_then(|(_, port)| port.parse().ok())
+                    .is_some_and(|port| ports.contains(&port))
+            });
+        }
+        if let Some(geoip) = &self.geoip {
+            for connection in &mut connections {
+                let location = geoip.lookup_peer(&connection.remote_address);
+                connection.country = location.country.unwrap_or_default();
+                connection.asn = location.asn.unwrap_or_default();
+                connection.as_org = location.as_org.unwrap_or_default();
+            }
+        }
+        connections
+    }
+
+    // Whether GetRevision may return a revision of the config at
+    // `config_path`. The history database is shared, so an instance in a
+    // namespace only reads revisions of its own configs.
+    fn may_read_revision(&self, config_path: &str) -> bool {
+        self.namespace.is_none() || self.resolve_config_path(config_path.to_string()).is_ok()
+    }
+
+    // The instance as ListInstances reports it.
+    fn proto_instance(&self, id: &str) -> Instance {
+        let pid = self.stunnel_pid().ok().filter(|&pid| process_running(pid));
+        Instance {
+            instance_id: id.to_string(),
+            config_path: self.config_path.clone(),
+            pid_file: self.pid_file.clone(),
+            process_backend: self.backend.name().to_string(),
+            systemd_unit: if self.backend.name() == "systemd" {
+                self.journal_unit.clone()
+            } else {
+                String::new()
+            },
+            is_running: pid.is_some(),
+            pid: pid.unwrap_or(0),
+            namespace: self.namespace.clone().unwrap_or_default(),
+        }
+    }
+
+    // Waits for other mutating RPCs to finish, then blocks new ones until the
+    // returned guard is dropped.
+    async fn lock_mutations(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.mutations.lock().await
+    }
+
+    // Serializes config writes with shutdown, so the manager never exits
+    // between writing a config and recording or restoring it.
+    fn begin_write(&self) -> Result<MutexGuard<'_, bool>, String> {
+        let closed = self.config_writes.lock().unwrap_or_else(|e| e.into_inner());
+        if *closed {
+            return Err("The manager is shutting down; config changes are refused".to_string());
+        }
+        Ok(closed)
+    }
+
+    // Resolves the config path a request names, defaulting to the managed
+    // config. Any other path must be on the allowlist, so clients cannot
+    // write or load arbitrary files.
+    fn resolve_config_path(&self, requested: String) -> Result<String, String> {
+        if requested.is_empty() || requested == self.config_path {
+            return Ok(self.config_path.clone());
+        }
+        let denied = || {
+            format!(
+                "config_path {} is not the managed config or on the allowlist",
+                requested
+            )
+        };
+        let path = normalize_path(Path::new(&requested)).ok_or_else(denied)?;
+        let allowed = normalize_path(Path::new(&self.config_path)) == Some(path.clone())
+            || self
+                .allowed_config_paths
+                .iter()
+                .filter_map(|allowed| normalize_path(allowed))
+                .any(|allowed| path == allowed || (allowed.is_dir() && path.starts_with(&allowed)));
+        if !allowed {
+            return Err(denied());
+        }
+        Ok(requested)
+    }
+
+    // Resolves a certificate, key or CA path a request names. Read-only
+    // callers could otherwise probe any file on the host, so the path must
+    // be one the managed config references, or be in the certificate store
+    // or on the config path allowlist.
+    fn resolve_certificate_path(&self, requested: &str) -> Result<String, String> {
+        let denied = || {
+            format!(
+                "{} is not referenced by the config, in the certificate store or on the allowlist",
+                requested
+            )
+        };
+        let path = normalize_path(Path::new(requested)).ok_or_else(denied)?;
+        let config = self
+            .read_providers_config()
+            .map(|content| StunnelConfig::parse(&content))
+            .unwrap_or_default();
+        let referenced = config
+            .globals
+            .iter()
+            .chain(config.sections.iter().flat_map(|section| &section.options))
+            .filter(|option| {
+                CERTIFICATE_PATH_OPTIONS
+                    .iter()
+                    .any(|key| option.key.eq_ignore_ascii_case(key))
+            })
+            .filter_map(|option| normalize_path(Path::new(&option.value)))
+            .any(|referenced| referenced == path);
+        let allowed = referenced
+            || self
+                .cert_store
+                .iter()
+                .map(|store| store.dir().to_path_buf())
+                .chain(self.allowed_config_paths.iter().cloned())
+                .filter_map(|allowed| normalize_path(&allowed))
+                .any(|allowed| path == allowed || (allowed.is_dir() && path.starts_with(&allowed)));
+        if !allowed {
+            return Err(denied());
+        }
+        Ok(requested.to_string())
+    }
+
+    // The key pairs and chains in `config` whose files ValidateConfigContent
+    // may open, as resolve_certificate_path allows them, so a read-only
+    // caller cannot probe other files through the checks. Each skipped one
+    // is returned as a note naming the path, without touching the file.
+    fn checkable_certificates(
+        &self,
+        config: &StunnelConfig,
+    ) -> (Vec<certs::KeyPair>, Vec<certs::ChainCheck>, Vec<String>) {
+        let mut unchecked = Vec::new();
+        let mut allowed = |section: &Option<String>, paths: &[&str]| {
+            let denied: Vec<String> = paths
+                .iter()
+                .filter_map(|path| self.resolve_certificate_path(path).err())
+                .collect();
+            for reason in &denied {
+                let note = match section {
+                    Some(section) => format!("[{}] Not checked: {}", section, reason),
+                    None => format!("Not checked: {}", reason),
+                };
+                if !unchecked.contains(&note) {
+                    unchecked.push(note);
+                }
+            }
+            denied.is_empty()
+        };
+        let pairs = certs::key_pairs(config)
+            .into_iter()
+            .filter(|pair| allowed(&pair.section, &[&pair.cert, &pair.key]))
+            .collect();
+        let chains = certs::chain_checks(config)
+            .into_iter()
+            .filter(|check| {
+                let paths: Vec<&str> = [
+                    Some(&check.cert),
+                    check.ca_file.as_ref(),
+                    check.ca_path.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect();
+                allowed(&check.section, &paths)
+            })
+            .collect();
+        (pairs, chains, unchecked)
+    }
+
+    // Returns the version of the config at `path`, for the managed config
+    // including its provider files, or an empty string if it is unreadable.
+    fn version_of(&self, path: &str) -> String {
+        let content = if path == self.config_path {
+            self.read_providers_config()
+        } else {
+            fs::read_to_string(path)
+        };
+        content
+            .map(|content| config_version(&content))
+            .unwrap_or_default()
+    }
+
+    // Refuses a change based on version `expected` of the config at `path`
+    // if the config has changed since. An empty `expected` skips the check.
+    fn check_version(&self, path: &str, expected: &str) -> Result<(), String> {
+        if expected.is_empty() {
+            return Ok(());
+        }
+        let current = self.version_of(path);
+        if current != expected {
+            return Err(format!(
+                "Config changed since version {}; it is now at version {}. Re-read it and retry",
+                expected, current
+            ));
+        }
+        Ok(())
+    }
+
+    // Backs up the managed config and atomically replaces it. Validation
+    // failures are only logged, since stunnel may not be installed locally.
+    fn write_managed_config(&self, content: &str, rpc: &str, caller: &str) -> Result<(), String> {
+        check_no_placeholders(content)?;
+        let _writing = self.begin_write()?;
+        let previous = fs::read_to_string(&self.config_path).unwrap_or_default();
+        check_new_certificates(&previous, content)?;
+        self.check_key_permissions(content)?;
+        backup_file(
+            &self.config_path,
+            &self.backup_policy,
+            self.secrets.as_deref(),
+        )
+        .map_err(|e| format!("Failed to backup config: {}", e))?;
+        atomic_write(&self.config_path, content)
+            .map_err(|e| format!("Failed to write updated config: {}", e))?;
+        self.record_revision(&self.config_path, rpc, caller, &previous, content);
+        self.warn_if_invalid(content);
+        Ok(())
+    }
+
+    // Backs up the managed config and atomically replaces it, like
+    // `write_managed_config`, but only if the new config passes validation.
+    fn write_validated_config(&self, content: &str, rpc: &str, caller: &str) -> Result<(), String> {
+        check_no_placeholders(content)?;
+        let _writing = self.begin_write()?;
+        let previous = fs::read_to_string(&self.config_path).unwrap_or_default();
+        check_new_certificates(&previous, content)?;
+        self.check_key_permissions(content)?;
+        let report = self.validation.validate(content);
+        if !report.is_valid() {
+            return Err(format!("Invalid configuration: {}", report.summary()));
+        }
+        backup_file(
+            &self.config_path,
+            &self.backup_policy,
+            self.secrets.as_deref(),
+        )
+        .map_err(|e| format!("Failed to backup config: {}", e))?;
+        if let Err(e) = atomic_write(&self.config_path, content) {
+            // Attempt to restore the previous content if write partially failed
+            let _ = atomic_write(&self.config_path, &previous);
+            return Err(format!("Failed to write updated config: {}", e));
+        }
+
+        self.record_revision(&self.config_path, rpc, caller, &previous, content);
+        Ok(())
+    }
+
+    // Records a config revision in the history store and Git repository,
+    // whichever are enabled. The change has already been written, so a
+    // recording failure is only logged.
+    #[tracing::instrument(skip(self, previous, content))]
+    fn record_revision(
+        &self,
+        config_path: &str,
+        rpc: &str,
+        caller: &str,
+        previous: &str,
+        content: &str,
+    ) {
+        if let Some(store) = &self.history {
+            let diff = unified_diff(previous, content, "previous", "current");
+            if let Err(e) = store.record(config_path, rpc, caller, content, &diff) {
+                error!(rpc, "Failed to record config revision: {}", e);
+            }
+        }
+
+        if let Some(git) = &self.git {
+            let message = format!("{} by {}", rpc, caller);
+            if let Err(e) = git.commit_file(config_path, &message) {
+                error!(rpc, "Failed to commit config to Git: {}", e);
+            }
+        }
+    }
+
+    // Returns the main config followed by every provider fragment, which is
+    // what stunnel sees once the fragments are included.
+    fn read_providers_config(&self) -> io::Result<String> {
+        let mut content = fs::read_to_string(&self.config_path)?;
+        if let Some(fragments) = &self.fragments {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(&fragments.read_all()?);
+        }
+        Ok(content)
+    }
+
+    // Why stunnel running as `pid` died, with the last error it logged if
+    // its config names a log file.
+    fn crash_reason(&self, pid: i32) -> String {
+        let logged = self
+            .read_providers_config()
+            .ok()
+            .and_then(|content| logs::log_path(&StunnelConfig::parse(&content)))
+            .and_then(|path| logs::last_error(path).ok().flatten());
+        match logged {
+            Some(error) => format!("stunnel (PID {}) exited; last logged error: {}", pid, error),
+            None => format!("stunnel (PID {}) exited", pid),
+        }
+    }
+
+    // The crash supervision state, for GetStatus.
+    fn proto_supervisor(&self) -> Option<SupervisorStatus> {
+        let state = self.supervisor.as_ref()?.state();
+        Some(SupervisorStatus {
+            enabled: true,
+            expected_running: state.expected_running,
+            crash_count: state.crash_count,
+            restart_count: state.restart_count,
+            last_crash_reason: state.last_crash_reason.unwrap_or_default(),
+            last_crash_at: state
+                .last_crash_at
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+            last_restart_error: state.last_restart_error.unwrap_or_default(),
+            next_restart_at: state
+                .next_restart_at
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+        })
+    }
+
+    // The resources the stunnel running as `pid` uses, for GetStatus.
+    fn proto_usage(&self, pid: i32) -> Option<ProcessUsage> {
+        let usage = self.usage.sample(pid, &process_stats(pid)?);
+        Some(ProcessUsage {
+            started_at: usage.started_at.to_rfc3339(),
+            uptime_secs: usage.uptime.as_secs(),
+            rss_bytes: usage.rss_bytes,
+            cpu_percent: usage.cpu_percent,
+        })
+    }
+
+    // The release of the stunnel binary, read once per running PID; empty
+    // if `stunnel -version` fails.
+    async fn stunnel_release(&self, pid: Option<i32>) -> Result<String, Status> {
+        if let Some(release) = pid.and_then(|pid| self.usage.release(pid)) {
+            return Ok(release);
+        }
+        let release = tokio::task::spawn_blocking(version::stunnel_version)
+            .await
+            .map_err(|e| Status::internal(format!("Version task failed: {}", e)))?
+            .map(|version| version.version)
+            .unwrap_or_default();
+        if let Some(pid) = pid.filter(|_| !release.is_empty()) {
+            self.usage.remember_release(pid, release.clone());
+        }
+        Ok(release)
+    }
+
+    // SIGHUPs stunnel if it is running; a stopped instance picks up the
+    // config on start.
+    #[tracing::instrument(skip_all)]
+    fn reload_if_running(&self) {
+        if let Ok(pid) = self.stunnel_pid() {
+            if process_running(pid) {
+                let _ = self.reload_process(pid);
+            }
+        }
+    }
+
+    // Makes the stunnel running as `pid` reload its config, first
+    // refreshing the launch copy it reads if StartStunnel gave startup
+    // options, so it picks up the config's changes along with them.
+    fn reload_process(&self, pid: i32) -> Result<(), String> {
+        self.check_namespace_paths(&self.config_path)?;
+        let launched = self
+            .launch_overrides
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|overrides| overrides.config_path.clone());
+        if let Some(config_path) = launched {
+            self.launch_config(&config_path)?;
+        }
+        self.backend.reload(pid)
+    }
+
+    // Refuses to run the config at `config_path` in a namespace if it
+    // reaches outside the namespace's directory, however it got there.
+    fn check_namespace_paths(&self, config_path: &str) -> Result<(), String> {
+        let Some(namespace) = self
+            .namespace
+            .as_deref()
+            .and_then(|name| self.namespaces.get(name))
+        else {
+            return Ok(());
+        };
+        let content = fs::read_to_string(config_path)
+            .map_err(|e| format!("Failed to read {}: {}", config_path, e))?;
+        let problems = NamespacePaths::new(&namespace.name, &namespace.config_dir)
+            .validate(&content, &StunnelConfig::parse(&content))?;
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(problems
+            .into_iter()
+            .map(|problem| problem.message)
+            .collect::<Vec<_>>()
+            .join("; "))
+    }
+
+    // The config stunnel is started from for `config_path`: a freshly
+    // written launch copy while StartStunnel's startup options apply to it,
+    // else the config itself.
+    fn launch_config(&self, config_path: &str) -> Result<String, String> {
+        let overrides = self
+            .launch_overrides
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let Some(overrides) = overrides.filter(|overrides| overrides.config_path == config_path)
+        else {
+            return Ok(config_path.to_string());
+        };
+        let content = fs::read_to_string(config_path)
+            .map_err(|e| format!("Failed to read {}: {}", config_path, e))?;
+        let launch_path = launch_config_path(config_path);
+        atomic_write(&launch_path, &overrides.apply(&content))
+            .map_err(|e| format!("Failed to write {}: {}", launch_path, e))?;
+        Ok(launch_path)
+    }
+
+    // Forgets StartStunnel's startup options once the run they were given
+    // for has ended, removing the launch copy.
+    fn clear_launch_overrides(&self) {
+        let overrides = self
+            .launch_overrides
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(overrides) = overrides {
+            let _ = fs::remove_file(launch_config_path(&overrides.config_path));
+        }
+    }
+
+    // The PID of the running stunnel, as the process backend finds it.
+    fn stunnel_pid(&self) -> Result<i32, String> {
+        self.backend.locate().map(|located| located.pid)
+    }
+
+    // Starts stunnel through the process backend on the blocking pool,
+    // since waiting for it to come up sleeps.
+    async fn start_process(&self, config_path: &str) -> Result<Result<i32, String>, Status> {
+        let backend = Arc::clone(&self.backend);
+        let config_path = match self
+            .check_namespace_paths(config_path)
+            .and_then(|()| self.launch_config(config_path))
+        {
+            Ok(config_path) => config_path,
+            Err(message) => return Ok(Err(message)),
+        };
+        let span = Span::current();
+        let started =
+            tokio::task::spawn_blocking(move || span.in_scope(|| backend.start(&config_path)))
+                .await
+                .map_err(|e| Status::internal(format!("Start task failed: {}", e)))?;
+        if started.is_ok() {
+            self.usage.record_start();
+        }
+        Ok(started)
+    }
+
+    // Stops stunnel through the process backend on the blocking pool, since
+    // waiting for it to exit sleeps.
+    async fn stop_process(
+        &self,
+        pid: i32,
+        timeout: Duration,
+    ) -> Result<Result<bool, String>, Status> {
+        let backend = Arc::clone(&self.backend);
+        let span = Span::current();
+        let stopped =
+            tokio::task::spawn_blocking(move || span.in_scope(|| backend.stop(pid, timeout)))
+                .await
+                .map_err(|e| Status::internal(format!("Stop task failed: {}", e)))?;
+        if stopped.is_ok() {
+            self.clear_launch_overrides();
+        }
+        Ok(stopped)
+    }
+
+    // Waits for the services of the config at `config_path` to listen on
+    // their accept ports; empty when readiness checks are disabled or the
+    // config cannot be read.
+    async fn wait_for_services(&self, config_path: &str) -> Vec<ServiceReadiness> {
+        if self.readiness_timeout.is_zero() {
+            return vec![];
+        }
+        let content = if config_path == self.config_path {
+            self.read_providers_config()
+        } else {
+            fs::read_to_string(config_path)
+        };
+        match content {
+            Ok(content) => {
+                readiness::wait_until_listening(
+                    &StunnelConfig::parse(&content),
+                    self.readiness_timeout,
+                )
+                .await
+            }
+            Err(e) => {
+                warn!("Failed to read {} for readiness checks: {}", config_path, e);
+                vec![]
+            }
+        }
+    }
+
+    // The TCP port provider `name` accepts on, so its connections can be
+    // drained once the section is gone.
+    fn drain_port(&self, name: &str) -> Option<u16> {
+        let content = self.read_providers_config().ok()?;
+        StunnelConfig::parse(&content)
+            .section(name)
+            .and_then(accept_port)
+    }
+
+    // Runs `run` as a background operation of RPC `method`, returning the
+    // operation's ID. `run` receives the request to handle, without the
+    // metadata that reveals secrets since other callers can read the result.
+    fn start_in_background<T, F, Fut>(
+        &self,
+        method: &str,
+        request: Request<T>,
+        run: F,
+    ) -> Result<String, String>
+    where
+        F: FnOnce(StunnelServer, Request<T>, OperationHandle<OperationResult>) -> Fut,
+        Fut:
+            std::future::Future<Output = Result<Outcome<OperationResult>, Status>> + Send + 'static,
+    {
+        let handle = self
+            .operations
+            .start(method, &caller_identity(&request))
+            .map_err(|e| e.to_string())?;
+        let id = handle.id().to_string();
+        let (mut metadata, extensions, message) = request.into_parts();
+        metadata.remove(REVEAL_SECRETS_METADATA_KEY);
+        let operation = run(
+            self.clone(),
+            Request::from_parts(metadata, extensions, message),
+            handle.clone(),
+        );
+        tokio::spawn(async move {
+            match operation.await {
+                Ok(outcome) => handle.finish(outcome),
+                Err(status) => handle.fail(status.message()),
+            }
+        });
+        info!(rpc = method, operation = %id, "Started background operation");
+        Ok(id)
+    }
+
+    // Resolves the certificate file a provider uses: its own `cert`, else the
+    // global one it inherits.
+    fn provider_cert_path(&self, name: &str) -> Result<String, String> {
+        let content = self
+            .read_providers_config()
+            .map_err(|e| format!("Failed to read config: {}", e))?;
+        let config = StunnelConfig::parse(&content);
+        let section = config
+            .section(name)
+            .ok_or_else(|| format!("Provider {} not found in config", name))?;
+        section
+            .get("cert")
+            .or_else(|| config.global("cert"))
+            .map(str::to_string)
+            .ok_or_else(|| format!("Provider {} has no certificate configured", name))
+    }
+
+    // Adds a rendered provider section. In conf.d mode the section gets its
+    // own file instead of a config rewrite.
+    // Returns the written content: the provider file or the updated config.
+    fn add_provider_section(
+        &self,
+        name: &str,
+        new_section: &str,
+        rpc: &str,
+        caller: &str,
+    ) -> Result<String, String> {
+        let existing_config = fs::read_to_string(&self.config_path)
+            .map_err(|e| format!("Failed to read existing config: {}", e))?;
+        self.check_new_provider(name, new_section)?;
+
+        if let Some(fragments) = &self.fragments {
+            let fragment = new_section.trim_start();
+            self.write_provider_fragment(fragments, name, fragment, &existing_config, rpc, caller)?;
+            return Ok(fragment.to_string());
+        }
+
+        // Backup and write new config atomically
+        let updated_config = append_to_config(&existing_config, new_section);
+        self.write_managed_config(&updated_config, rpc, caller)?;
+        Ok(updated_config)
+    }
+
+    // Picks a free accept port from `range` for a new provider.
+    fn allocate_accept_port(&self, range: PortRange) -> Result<u16, String> {
+        let content = self
+            .read_providers_config()
+            .map_err(|e| format!("Failed to read provider files: {}", e))?;
+        range
+            .allocate(&StunnelConfig::parse(&content))
+            .ok_or_else(|| format!("No free accept port left in {}", range))
+    }
+
+    // Refuses a new provider `name` that already exists, including in
+    // provider files, or whose rendered `new_section` accepts on a port that
+    // is already taken.
+    fn check_new_provider(&self, name: &str, new_section: &str) -> Result<(), String> {
+        let providers_config = self
+            .read_providers_config()
+            .map_err(|e| format!("Failed to read provider files: {}", e))?;
+        let config = StunnelConfig::parse(&providers_config);
+        if config.section(name).is_some() {
+            return Err(format!("Provider {} already exists in config", name));
+        }
+        let port = StunnelConfig::parse(new_section)
+            .section(name)
+            .and_then(accept_port);
+        if let Some(port) = port {
+            check_accept_port(&config, name, port)?;
+        }
+        Ok(())
+    }
+
+    // Previews `add_provider_section` without writing anything. Returns the
+    // content it would write (the provider file or the updated config) and
+    // the main config stunnel would then load.
+    fn preview_provider_section(
+        &self,
+        name: &str,
+        new_section: &str,
+    ) -> Result<(String, String), String> {
+        let existing_config = fs::read_to_string(&self.config_path)
+            .map_err(|e| format!("Failed to read existing config: {}", e))?;
+        self.check_new_provider(name, new_section)?;
+
+        match &self.fragments {
+            Some(fragments) => {
+                // The fragment is loaded through the directory include, so
+                // validate it as part of the main config
+                check_no_placeholders(new_section)?;
+                let main_config = if fragments.is_included_by(&existing_config) {
+                    existing_config
+                } else {
+                    add_global(
+                        &existing_config,
+                        "include",
+                        &fragments.dir().to_string_lossy(),
+                    )
+                };
+                Ok((
+                    new_section.trim_start().to_string(),
+                    append_to_config(&main_config, new_section),
+                ))
+            }
+            None => {
+                let updated_config = append_to_config(&existing_config, new_section);
+                Ok((updated_config.clone(), updated_config))
+            }
+        }
+    }
+
+    // Runs the checks a write of `content` over the managed config would,
+    // then the validation pipeline, for dry runs. Unlike those writes, a dry
+    // run fails when a blocking validator cannot run, since validation is
+    // its whole point.
+    fn dry_run_managed(&self, content: &str) -> Result<Report, String> {
+        let previous = fs::read_to_string(&self.config_path).unwrap_or_default();
+        check_no_placeholders(content)?;
+        check_new_certificates(&previous, content)?;
+        self.check_key_permissions(content)?;
+        Ok(self.validation.validate(content))
+    }
+
+    // Runs the validation pipeline on the config file at `path`.
+    fn validate_file(&self, path: &str) -> Result<(), String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let report = self.validation.validate(&content);
+        if !report.is_valid() {
+            return Err(report.summary());
+        }
+        Ok(())
+    }
+
+    // Logs why `content` fails validation. Used after writes that go ahead
+    // regardless, since stunnel may not be installed locally.
+    fn warn_if_invalid(&self, content: &str) {
+        let report = self.validation.validate(content);
+        if !report.is_valid() {
+            warn!(
+                "Config validation failed: {}",
+                redact_config(&report.summary())
+            );
+        }
+    }
+
+    // Writes a provider fragment, first adding the providers directory to the
+    // main config's includes if needed.
+    fn write_provider_fragment(
+        &self,
+        fragments: &FragmentDir,
+        name: &str,
+        content: &str,
+        main_config: &str,
+        rpc: &str,
+        caller: &str,
+    ) -> Result<(), String> {
+        check_no_placeholders(content)?;
+        if !fragments.is_included_by(main_config) {
+            let include = fragments.dir().to_string_lossy();
+            let updated_config = add_global(main_config, "include", &include);
+            self.write_managed_config(&updated_config, rpc, caller)?;
+        }
+
+        let path = fragments
+            .create(name, content)
+            .map_err(|e| format!("Failed to write provider file: {}", e))?;
+        self.record_revision(&path.to_string_lossy(), rpc, caller, "", content);
+
+        // The fragment is loaded through the main config's include
+        if let Ok(main_config) = fs::read_to_string(&self.config_path) {
+            self.warn_if_invalid(&main_config);
+        }
+        Ok(())
+    }
+
+    // Returns the history store, or a message explaining that history is disabled.
+    fn history_store(&self) -> Result<&HistoryStore, String> {
+        self.history
+            .as_deref()
+            .ok_or_else(|| "Config history is not enabled (set HISTORY_DB_PATH)".to_string())
+    }
+
+    // Returns the connection log, or a message explaining that it is disabled.
+    fn connection_log(&self) -> Result<&ConnectionLog, String> {
+        self.connection_log.as_deref().ok_or_else(|| {
+            "Connection history is not enabled (set CONNECTION_LOG_DB_PATH)".to_string()
+        })
+    }
+
+    fn cert_store(&self) -> Result<&CertStore, String> {
+        self.cert_store
+            .as_ref()
+            .ok_or_else(|| "Managed certificates are not enabled (set CERTS_DIR)".to_string())
+    }
+
+    // Whether responses to `request` are masked. Local callers may opt out
+    // with the reveal metadata key; remote callers cannot.
+    fn should_redact<T>(&self, request: &Request<T>) -> bool {
+        if !self.redact_secrets {
+            return false;
+        }
+        let reveal = request
+            .metadata()
+            .get(REVEAL_SECRETS_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        let local = request
+            .remote_addr()
+            .is_some_and(|addr| addr.ip().is_loopback());
+        !(reveal && local)
+    }
+
+    // Applies the DNS check policy to `hosts`, pairs of a field and the
+    // connect host it sets.
+    async fn check_connect_hosts(&self, hosts: Vec<(String, String)>) -> Result<(), String> {
+        if self.dns_check == DnsCheckPolicy::Off {
+            return Ok(());
+        }
+        let mut problems = Vec::new();
+        for (field, host) in hosts {
+            if let Err(e) = dns::resolve(&host).await {
+                problems.push(format!("{}: {}", field, e));
+            }
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        if self.dns_check == DnsCheckPolicy::Enforce {
+            return Err(problems.join("; "));
+        }
+        for problem in problems {
+            warn!("{}", problem);
+        }
+        Ok(())
+    }
+
+    // Applies the key permission policy to every key file `content` references.
+    fn check_key_permissions(&self, content: &str) -> Result<(), String> {
+        if self.key_permission_policy == KeyPermissionPolicy::Off {
+            return Ok(());
+        }
+        let problems =
+            certs::check_key_permissions(&certs::key_pairs(&StunnelConfig::parse(content)));
+        if problems.is_empty() {
+            return Ok(());
+        }
+        if self.key_permission_policy == KeyPermissionPolicy::Enforce {
+            return Err(problems.join("; "));
+        }
+        for problem in problems {
+            warn!("{}", problem);
+        }
+        Ok(())
+    }
+
+    fn vault(&self) -> Result<&VaultManager, String> {
+        self.vault
+            .as_deref()
+            .ok_or_else(|| "Vault is not enabled (set VAULT_ADDR and VAULT_TOKEN)".to_string())
+    }
+
+    fn acme(&self) -> Result<&AcmeManager, String> {
+        self.acme
+            .as_deref()
+            .ok_or_else(|| "ACME is not enabled (set ACME_DIRECTORY_URL)".to_string())
+    }
+}
+
+// Helper: whether the caller's namespace scope, if any, covers an instance
+// in `namespace`.
+fn in_scope<T>(request: &Request<T>, namespace: Option<&str>) -> bool {
+    request
+        .extensions()
+        .get::<NamespaceScope>()
+        .is_none_or(|scope| scope.allows(namespace))
+}
+
+// Helper: the error for an instance outside the caller's namespaces. Named
+// instances are reported as missing, so tenants cannot probe for each
+// other's.
+fn out_of_scope(id: &str) -> Status {
+    if id.is_empty() || id == DEFAULT_INSTANCE {
+        Status::permission_denied(
+            "This API key is limited to namespaces; set instance_id to one of their instances",
+        )
+    } else {
+        Status::not_found(format!("Instance {} not found", id))
+    }
+}
+
+impl StunnelServer {
+    // Helper: when payload logging is on, capture who called `handler` with
+    // what, returning a function that logs it together with the response.
+    fn payload_logger<T: std::fmt::Debug>(
+        &self,
+        handler: &str,
+        request: &Request<T>,
+    ) -> Option<impl FnOnce(Option<&str>)> {
+        if !self.log_payloads {
+            return None;
+        }
+        let method = telemetry::method_name(handler);
+        let caller = caller_identity(request);
+        let request = accesslog::payload(request.get_ref());
+        Some(move |response: Option<&str>| {
+            accesslog::log_payloads(&method, &caller, &request, response)
+        })
+    }
+}
+
+// Helper: identify the client making a request, preferring the common name
+// of a verified client certificate, then the subject of its JWT, then an
+// explicit client ID, then the peer address.
+fn caller_identity<T>(request: &Request<T>) -> String {
+    request
+        .peer_certs()
+        .and_then(|certs| {
+            certs
+                .first()
+                .and_then(|cert| certs::common_name(cert.as_ref()))
+        })
+        .or_else(|| {
+            request
+                .extensions()
+                .get::<AuthenticatedCaller>()
+                .map(|caller| caller.0.clone())
+        })
+        .or_else(|| {
+            request
+                .metadata()
+                .get(CLIENT_ID_METADATA_KEY)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        })
+        .or_else(|| request.remote_addr().map(|addr| addr.to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Helper: map the proto format enum onto the structured config format.
+fn structured_format(format: i32) -> Result<Format, String> {
+    match ConfigFormat::from_i32(format) {
+        Some(ConfigFormat::Json) => Ok(Format::Json),
+        Some(ConfigFormat::Yaml) => Ok(Format::Yaml),
+        None => Err(format!("Unknown config format: {}", format)),
+    }
+}
+
+// Helper: convert a template into its proto representation.
+fn proto_template(name: String, content: String) -> ProviderTemplate {
+    ProviderTemplate {
+        placeholders: templates::placeholders(&content),
+        name,
+        content,
+    }
+}
+
+// Helper: map a lint severity onto its proto representation.
+fn lint_severity(severity: Severity) -> LintSeverity {
+    match severity {
+        Severity::Info => LintSeverity::Info,
+        Severity::Warning => LintSeverity::Warning,
+        Severity::Error => LintSeverity::Error,
+    }
+}
+
+// Helper: convert a lint finding into its proto representation.
+fn proto_finding(finding: lint::Finding) -> LintFinding {
+    LintFinding {
+        severity: lint_severity(finding.severity) as i32,
+        code: finding.code.to_string(),
+        section: finding.section.unwrap_or_default(),
+        message: finding.message,
+        remediation: finding.remediation,
+    }
+}
+
+// Helper: map the proto TLS profile onto the profile it selects, if any.
+fn tls_profile(profile: i32) -> Result<Option<tls::TlsProfile>, String> {
+    match TlsProfile::from_i32(profile) {
+        Some(TlsProfile::Unspecified) => Ok(None),
+        Some(TlsProfile::Modern) => Ok(Some(tls::TlsProfile::Modern)),
+        Some(TlsProfile::Intermediate) => Ok(Some(tls::TlsProfile::Intermediate)),
+        Some(TlsProfile::Old) => Ok(Some(tls::TlsProfile::Old)),
+        None => Err(format!("Unknown TLS profile: {}", profile)),
+    }
+}
+
+// Helper: map a proto key type to the key generated for a CSR.
+fn key_type(key_type: i32) -> Result<KeyType, String> {
+    match crate::stunnel::KeyType::from_i32(key_type) {
+        Some(crate::stunnel::KeyType::Rsa2048) => Ok(KeyType::Rsa2048),
+        Some(crate::stunnel::KeyType::Rsa4096) => Ok(KeyType::Rsa4096),
+        Some(crate::stunnel::KeyType::EcP256) => Ok(KeyType::EcP256),
+        Some(crate::stunnel::KeyType::EcP384) => Ok(KeyType::EcP384),
+        None => Err(format!("Unknown key type {}", key_type)),
+    }
+}
+
+// Helper: convert a certificate inventory entry into its proto representation.
+fn proto_certificate_entry(entry: certstore::CertificateEntry) -> CertificateEntry {
+    CertificateEntry {
+        path: entry.path,
+        managed: entry.managed,
+        providers: entry.references.providers,
+        global: entry.references.global,
+        subject: entry.subject,
+        not_after: entry
+            .not_after
+            .map(|not_after| not_after.to_rfc3339())
+            .unwrap_or_default(),
+        error: entry.error.unwrap_or_default(),
+    }
+}
+
+// Helper: convert a certificate expiry status into its proto representation.
+fn proto_certificate_status(
+    status: expiry::CertificateStatus,
+    warning_days: u32,
+) -> CertificateStatus {
+    CertificateStatus {
+        expiring: status.is_expiring(warning_days),
+        path: status.path,
+        providers: status.providers,
+        global: status.global,
+        subject: status.subject,
+        not_after: status
+            .not_after
+            .map(|not_after| not_after.to_rfc3339())
+            .unwrap_or_default(),
+        days_until_expiry: status.days_until_expiry,
+        error: status.error.unwrap_or_default(),
+    }
+}
+
+// Helper: convert an ACME renewal outcome into its proto representation.
+fn proto_acme_renewal(result: acme::RenewalResult) -> AcmeRenewal {
+    AcmeRenewal {
+        provider: result.provider,
+        renewed: result.renewed,
+        not_after: result
+            .not_after
+            .map(|not_after| not_after.to_rfc3339())
+            .unwrap_or_default(),
+        error: result.error.unwrap_or_default(),
+    }
+}
+
+// Helper: convert a Vault refresh outcome into its proto representation.
+fn proto_vault_refresh(result: vault::RefreshResult) -> VaultRefresh {
+    VaultRefresh {
+        provider: result.provider,
+        refreshed: result.refreshed,
+        not_after: result
+            .not_after
+            .map(|not_after| not_after.to_rfc3339())
+            .unwrap_or_default(),
+        error: result.error.unwrap_or_default(),
+    }
+}
+
+// Helper: convert a requested Vault source, filling in default KV fields.
+fn vault_source(source: bind_vault_certificate_request::Source) -> Result<VaultSource, String> {
+    let or_default = |value: String, default: &str| {
+        if value.is_empty() {
+            default.to_string()
+        } else {
+            value
+        }
+    };
+    match source {
+        bind_vault_certificate_request::Source::Pki(pki) => {
+            if pki.mount.is_empty() || pki.role.is_empty() || pki.common_name.is_empty() {
+                return Err("pki requires mount, role and common_name".to_string());
+            }
+            Ok(VaultSource::Pki {
+                mount: pki.mount,
+                role: pki.role,
+                common_name: pki.common_name,
+                alt_names: pki.alt_names,
+                ttl: pki.ttl,
+            })
+        }
+        bind_vault_certificate_request::Source::Kv(kv) => {
+            if kv.mount.is_empty() || kv.path.is_empty() {
+                return Err("kv requires mount and path".to_string());
+            }
+            Ok(VaultSource::Kv {
+                mount: kv.mount,
+                path: kv.path,
+                cert_field: or_default(kv.cert_field, vault::DEFAULT_CERT_FIELD),
+                key_field: or_default(kv.key_field, vault::DEFAULT_KEY_FIELD),
+                chain_field: or_default(kv.chain_field, vault::DEFAULT_CHAIN_FIELD),
+            })
+        }
+    }
+}
+
+// Helper: convert a certificate file change into its proto representation.
+fn proto_certificate_change(change: CertificateChange) -> CertificateChangeEvent {
+    CertificateChangeEvent {
+        paths: change.paths,
+        providers: change.providers,
+        detected_at: change.detected_at.to_rfc3339(),
+        reloaded: change.reloaded,
+    }
+}
+
+// Helper: convert the maintenance mode into its proto representation.
+fn proto_maintenance(maintenance: Option<Maintenance>) -> MaintenanceState {
+    match maintenance {
+        Some(maintenance) => MaintenanceState {
+            enabled: true,
+            reason: maintenance.reason,
+            enabled_by: maintenance.enabled_by,
+            since: maintenance.since.to_rfc3339(),
+        },
+        None => MaintenanceState::default(),
+    }
+}
+
+// Helper: convert certificate details into their proto representation.
+fn proto_certificate(info: certs::CertificateInfo) -> CertificateInfo {
+    CertificateInfo {
+        subject: info.subject,
+        issuer: info.issuer,
+        sans: info.sans,
+        not_before: info.not_before.to_rfc3339(),
+        not_after: info.not_after.to_rfc3339(),
+        serial: info.serial,
+        key_algorithm: info.key_algorithm,
+        fingerprint_sha256: info.fingerprint_sha256,
+    }
+}
+
+// Helper: convert a stored revision into its proto representation.
+fn proto_revision(revision: history::Revision, include_content: bool) -> ConfigRevision {
+    ConfigRevision {
+        id: revision.id,
+        created_at: revision.created_at,
+        config_path: revision.config_path,
+        rpc: revision.rpc,
+        caller: revision.caller,
+        diff: revision.diff,
+        content: if include_content {
+            revision.content
+        } else {
+            String::new()
+        },
+    }
+}
+
+// Helper: convert a recorded connection into its proto representation.
+fn proto_connection(record: connlog::ConnectionRecord) -> ConnectionRecord {
+    ConnectionRecord {
+        provider_name: record.service,
+        peer: record.peer,
+        opened_at: record.opened_at.to_rfc3339(),
+        closed_at: record
+            .closed_at
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+        duration_secs: record
+            .closed_at
+            .map(|at| (at - record.opened_at).num_seconds().max(0) as u64)
+            .unwrap_or_default(),
+        bytes_to_tls: record.bytes_to_tls,
+        bytes_to_socket: record.bytes_to_socket,
+        country: record.country.unwrap_or_default(),
+        asn: record.asn.unwrap_or_default(),
+        as_org: record.as_org.unwrap_or_default(),
+    }
+}
+
+// Helper: convert a ranked address into its proto representation.
+fn proto_talker(talker: connlog::Talker) -> Talker {
+    Talker {
+        address: talker.address,
+        connections: talker.connections,
+        bytes_to_tls: talker.bytes_to_tls,
+        bytes_to_socket: talker.bytes_to_socket,
+        last_opened_at: talker.last_opened_at.to_rfc3339(),
+        country: talker.country.unwrap_or_default(),
+        asn: talker.asn.unwrap_or_default(),
+        as_org: talker.as_org.unwrap_or_default(),
+    }
+}
+
+// Helper: parse an optional RFC 3339 bound of a time range; empty means
+// unbounded.
+fn parse_time_bound(name: &str, value: &str) -> Result<Option<DateTime<Utc>>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| Some(time.with_timezone(&Utc)))
+        .map_err(|e| format!("Invalid {} time {:?}: {}", name, value, e))
+}
+
+// Helper: reject content that pairs a certificate with a private key that
+// does not match it, or whose chain does not verify against its CAfile or
+// CApath. Only pairs and chains that are new relative to `previous` are
+// checked, so an existing problem does not block unrelated edits.
+fn check_new_certificates(previous: &str, content: &str) -> Result<(), String> {
+    let (previous, content) = (
+        StunnelConfig::parse(previous),
+        StunnelConfig::parse(content),
+    );
+    let existing = certs::key_pairs(&previous);
+    let new_pairs: Vec<certs::KeyPair> = certs::key_pairs(&content)
+        .into_iter()
+        .filter(|pair| {
+            !existing
+                .iter()
+                .any(|old| old.cert == pair.cert && old.key == pair.key)
+        })
+        .collect();
+    let existing = certs::chain_checks(&previous);
+    let new_chains: Vec<certs::ChainCheck> = certs::chain_checks(&content)
+        .into_iter()
+        .filter(|check| {
+            !existing.iter().any(|old| {
+                old.cert == check.cert
+                    && old.ca_file == check.ca_file
+                    && old.ca_path == check.ca_path
+            })
+        })
+        .collect();
+
+    let mut problems = certs::check_key_pairs(&new_pairs);
+    problems.extend(certs::check_chains(&new_chains));
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+// Helper: refuse content carrying the redaction placeholder, which means a
+// client is writing back masked output.
+fn check_no_placeholders(content: &str) -> Result<(), String> {
+    if redact::contains_placeholder(content) {
+        return Err(format!(
+            "Config sets a sensitive option to the redaction placeholder {}; send the real value",
+            redact::REDACTED
+        ));
+    }
+    Ok(())
+}
+
+// Helper: resolve symlinks and `..` in an absolute path. A file that does
+// not exist yet is resolved through its directory.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+    if let Ok(path) = path.canonicalize() {
+        return Some(path);
+    }
+    let name = path.file_name()?;
+    Some(path.parent()?.canonicalize().ok()?.join(name))
+}
+
+// Helper: version of config content clients pass back as
+// `expected_version`, the hex SHA-256 of the content.
+fn config_version(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Helper: write atomically by writing to a temp file then renaming.
+#[tracing::instrument(skip(content))]
+fn atomic_write(path: &str, content: &str) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp.{}", path, std::process::id());
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Startup options StartStunnel gave for one run of stunnel, which reads
+// them from a launch copy of its config rather than the config itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LaunchOverrides {
+    config_path: String,
+    foreground: bool,
+    debug_level: Option<u32>,
+}
+
+impl LaunchOverrides {
+    // `content` with the options set in its global section.
+    fn apply(&self, content: &str) -> String {
+        let mut content = content.to_string();
+        if self.foreground {
+            content = set_global_option(&content, "foreground", "yes");
+        }
+        if let Some(level) = self.debug_level {
+            content = set_global_option(&content, "debug", &level.to_string());
+        }
+        content
+    }
+}
+
+// Helper: resolve the SIGTERM grace period requested by the client.
+fn stop_timeout(timeout_secs: u32) -> Duration {
+    if timeout_secs == 0 {
+        Duration::from_secs(DEFAULT_STOP_TIMEOUT_SECS)
+    } else {
+        Duration::from_secs(u64::from(timeout_secs))
+    }
+}
+
+// Helper: convert parsed options into their proto representation.
+fn proto_options(options: &[parser::ConfigOption]) -> Vec<ConfigOption> {
+    options
+        .iter()
+        .map(|option| ConfigOption {
+            key: option.key.clone(),
+            value: option.value.clone(),
+        })
+        .collect()
+}
+
+// Helper: convert a parsed `[section]` into the Provider message.
+fn provider_from_section(section: &Section) -> Provider {
+    let option = |key: &str| section.get(key).unwrap_or_default().to_string();
+    let numeric = |key: &str| section.get(key).and_then(|value| value.parse().ok());
+    let flag = |key: &str| section.get(key).and_then(parse_yes_no);
+    let accept_port = accept_port(section).unwrap_or(0);
+    let connect_targets: Vec<ConnectTarget> = section
+        .get_all("connect")
+        .into_iter()
+        .map(|connect| {
+            let (host, port) = split_host_port(connect);
+            ConnectTarget {
+                host,
+                port: i32::from(port.unwrap_or(0)),
+            }
+        })
+        .collect();
+    // The first target also fills the single-target fields
+    let (connect_host, connect_port) = connect_targets
+        .first()
+        .map(|target| (target.host.clone(), target.port))
+        .unwrap_or_default();
+
+    Provider {
+        name: section.name.clone(),
+        accept_port: i32::from(accept_port),
+        connect_host,
+        connect_port,
+        is_client: section
+            .get("client")
+            .map(|value| value.eq_ignore_ascii_case("yes"))
+            .unwrap_or(false),
+        cert: option("cert"),
+        key: option("key"),
+        ca_file: option("CAfile"),
+        ca_path: option("CApath"),
+        engine_id: option("engineId"),
+        crl_file: option("CRLfile"),
+        ocsp: option("OCSP"),
+        ocsp_aia: flag("OCSPaia"),
+        ocsp_flags: section
+            .get_all("OCSPflag")
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        ocsp_nonce: flag("OCSPnonce"),
+        ocsp_require: flag("OCSPrequire"),
+        verify: numeric("verify"),
+        protocol: option("protocol"),
+        protocol_host: option("protocolHost"),
+        protocol_username: option("protocolUsername"),
+        protocol_password: option("protocolPassword"),
+        ciphers: option("ciphers"),
+        ciphersuites: option("ciphersuites"),
+        ssl_version_min: option("sslVersionMin"),
+        ssl_version_max: option("sslVersionMax"),
+        options: section
+            .get_all("options")
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        connect_targets,
+        timeout_busy: numeric("TIMEOUTbusy"),
+        timeout_close: numeric("TIMEOUTclose"),
+        timeout_connect: numeric("TIMEOUTconnect"),
+        timeout_idle: numeric("TIMEOUTidle"),
+        session_cache_size: numeric("sessionCacheSize"),
+        session_cache_timeout: numeric("sessionCacheTimeout"),
+        exec: option("exec"),
+        exec_args: section
+            .get("execArgs")
+            .map(|args| args.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        failover: section
+            .get("failover")
+            .and_then(failover_strategy)
+            .unwrap_or(FailoverStrategy::Unspecified) as i32,
+    }
+}
+
+// Longest provider name accepted.
+const MAX_PROVIDER_NAME_LEN: usize = 64;
+
+// Helper: trim a provider's string fields and check that the fields `checked`
+// selects are safe to write into the config. Returns one `field: problem`
+// entry per invalid field, e.g. `accept_port: 0 is not between 1 and 65535`.
+fn normalize_provider(
+    mut provider: Provider,
+    checked: impl Fn(&str) -> bool,
+) -> Result<Provider, Vec<String>> {
+    for value in [
+        &mut provider.name,
+        &mut provider.connect_host,
+        &mut provider.cert,
+        &mut provider.key,
+        &mut provider.ca_file,
+        &mut provider.ca_path,
+        &mut provider.engine_id,
+        &mut provider.crl_file,
+        &mut provider.ocsp,
+        &mut provider.protocol,
+        &mut provider.protocol_host,
+        &mut provider.protocol_username,
+        &mut provider.ciphers,
+        &mut provider.ciphersuites,
+        &mut provider.ssl_version_min,
+        &mut provider.ssl_version_max,
+        &mut provider.exec,
+    ]
+    .into_iter()
+    .chain(provider.options.iter_mut())
+    .chain(provider.ocsp_flags.iter_mut())
+    .chain(provider.exec_args.iter_mut())
+    .chain(
+        provider
+            .connect_targets
+            .iter_mut()
+            .map(|target| &mut target.host),
+    ) {
+        *value = value.trim().to_string();
+    }
+
+    let mut errors = Vec::new();
+    if checked("name") {
+        let name = &provider.name;
+        if name.is_empty() {
+            errors.push("name: is required".to_string());
+        } else if name.len() > MAX_PROVIDER_NAME_LEN {
+            errors.push(format!(
+                "name: must be at most {} characters",
+                MAX_PROVIDER_NAME_LEN
+            ));
+        } else if name.starts_with('.')
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            errors.push(format!(
+                "name: {:?} may only contain letters, digits, '.', '_' and '-', and must not start with '.'",
+                name
+            ));
+        }
+    }
+    if checked("accept_port") {
+        if let Some(problem) = port_problem(provider.accept_port) {
+            errors.push(format!("accept_port: {}", problem));
+        }
+    }
+
+    // Connect targets are ignored when the service runs a program
+    let connects = provider.exec.is_empty();
+    if connects && !provider.connect_targets.is_empty() {
+        if checked("connect_targets") {
+            for (i, target) in provider.connect_targets.iter().enumerate() {
+                if let Some(problem) = host_problem(&target.host) {
+                    errors.push(format!("connect_targets[{}].host: {}", i, problem));
+                }
+                if let Some(problem) = port_problem(target.port) {
+                    errors.push(format!("connect_targets[{}].port: {}", i, problem));
+                }
+            }
+        }
+    } else if connects {
+        if checked("connect_host") {
+            if let Some(problem) = host_problem(&provider.connect_host) {
+                errors.push(format!("connect_host: {}", problem));
+            }
+        }
+        if checked("connect_port") {
+            if let Some(problem) = port_problem(provider.connect_port) {
+                errors.push(format!("connect_port: {}", problem));
+            }
+        }
+    }
+    if checked("failover") && FailoverStrategy::from_i32(provider.failover).is_none() {
+        errors.push(format!("failover: unknown strategy {}", provider.failover));
+    }
+    if checked("verify") && provider.verify.is_some_and(|level| level > 4) {
+        errors.push("verify: must be between 0 and 4".to_string());
+    }
+
+    // A line break in a value would inject options into the config
+    for (field, value) in [
+        ("cert", &provider.cert),
+        ("key", &provider.key),
+        ("ca_file", &provider.ca_file),
+        ("ca_path", &provider.ca_path),
+        ("engine_id", &provider.engine_id),
+        ("crl_file", &provider.crl_file),
+        ("ocsp", &provider.ocsp),
+        ("protocol", &provider.protocol),
+        ("protocol_host", &provider.protocol_host),
+        ("protocol_username", &provider.protocol_username),
+        ("protocol_password", &provider.protocol_password),
+        ("ciphers", &provider.ciphers),
+        ("ciphersuites", &provider.ciphersuites),
+        ("ssl_version_min", &provider.ssl_version_min),
+        ("ssl_version_max", &provider.ssl_version_max),
+        ("exec", &provider.exec),
+    ] {
+        if checked(field) && value.chars().any(char::is_control) {
+            errors.push(format!(
+                "{}: must not contain line breaks or control characters",
+                field
+            ));
+        }
+    }
+    for (field, values) in [
+        ("options", &provider.options),
+        ("ocsp_flags", &provider.ocsp_flags),
+        ("exec_args", &provider.exec_args),
+    ] {
+        if !checked(field) {
+            continue;
+        }
+        for (i, value) in values.iter().enumerate() {
+            if value.is_empty() {
+                errors.push(format!("{}[{}]: must not be empty", field, i));
+            } else if value.chars().any(char::is_whitespace) {
+                // execArgs is split on whitespace, and the others are single words
+                errors.push(format!("{}[{}]: must not contain whitespace", field, i));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(provider)
+    } else {
+        Err(errors)
+    }
+}
+
+// Helper: the port a section accepts connections on.
+fn accept_port(section: &Section) -> Option<u16> {
+    section
+        .get("accept")
+        .and_then(|accept| split_host_port(accept).1)
+}
+
+// Helper: refuse accept port `port` for provider `name` when another section
+// of `config` accepts on it, or when another process already listens on it.
+// Ports of sections in the config are not checked on the host, since stunnel
+// binds those itself.
+fn check_accept_port(config: &StunnelConfig, name: &str, port: u16) -> Result<(), String> {
+    let users: Vec<&str> = config
+        .sections
+        .iter()
+        .filter(|section| section.name != name && accept_port(section) == Some(port))
+        .map(|section| section.name.as_str())
+        .collect();
+    match users.as_slice() {
+        [] => {}
+        [user] => {
+            return Err(format!(
+                "Accept port {} is already used by provider {}",
+                port, user
+            ))
+        }
+        users => {
+            return Err(format!(
+                "Accept port {} is already used by providers {}",
+                port,
+                users.join(", ")
+            ))
+        }
+    }
+    if config
+        .sections
+        .iter()
+        .any(|section| accept_port(section) == Some(port))
+    {
+        return Ok(());
+    }
+
+    match listening_sockets()
+        .into_iter()
+        .find(|socket| socket.port == port)
+    {
+        None => Ok(()),
+        Some(_) => Err(match port_owner(port) {
+            Some((pid, command)) => format!(
+                "Accept port {} is already in use on this host by {} (pid {})",
+                port, command, pid
+            ),
+            None => format!(
+                "Accept port {} is already in use on this host by another process",
+                port
+            ),
+        }),
+    }
+}
+
+// Helper: describe the problems `normalize_provider` found.
+fn invalid_provider(problems: Vec<String>) -> String {
+    format!("Invalid provider: {}", problems.join("; "))
+}
+
+// Helper: normalize every provider of a batch, naming each invalid field by
+// the provider's position, e.g. `providers[1].connect_port`.
+fn normalize_providers(providers: Vec<Provider>) -> Result<Vec<Provider>, String> {
+    let mut normalized = Vec::with_capacity(providers.len());
+    let mut problems = Vec::new();
+    for (i, provider) in providers.into_iter().enumerate() {
+        match normalize_provider(provider, |_| true) {
+            Ok(provider) => normalized.push(provider),
+            Err(errors) => problems.extend(
+                errors
+                    .into_iter()
+                    .map(|error| format!("providers[{}].{}", i, error)),
+            ),
+        }
+    }
+    if problems.is_empty() {
+        Ok(normalized)
+    } else {
+        Err(format!("Invalid providers: {}", problems.join("; ")))
+    }
+}
+
+// Helper: the connect hosts of `provider` among the fields `checked`
+// selects, each with the field it came from.
+fn connect_hosts(provider: &Provider, checked: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+    if !provider.exec.is_empty() {
+        return vec![];
+    }
+    if !provider.connect_targets.is_empty() {
+        if !checked("connect_targets") {
+            return vec![];
+        }
+        return provider
+            .connect_targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| (format!("connect_targets[{}].host", i), target.host.clone()))
+            .collect();
+    }
+    if checked("connect_host") {
+        vec![("connect_host".to_string(), provider.connect_host.clone())]
+    } else {
+        vec![]
+    }
+}
+
+// Helper: probe every connect target of `provider`; none for a provider
+// that runs a program.
+async fn probe_backends(provider: &Provider, timeout: Duration) -> Vec<probe::Probe> {
+    if !provider.exec.is_empty() {
+        return vec![];
+    }
+    let mut probes = Vec::new();
+    for (host, port) in connect_targets(provider) {
+        probes.push(probe::probe(host, u16::try_from(port).unwrap_or_default(), timeout).await);
+    }
+    probes
+}
+
+// Helper: describe why a pre-apply backend probe failed, if no connect
+// target was reachable.
+fn unreachable_backends(probes: &[probe::Probe]) -> Option<String> {
+    if probes.is_empty() || probes.iter().any(probe::Probe::reachable) {
+        return None;
+    }
+    let failures: Vec<String> = probes
+        .iter()
+        .map(|probe| {
+            format!(
+                "{}: {}",
+                probe.target,
+                probe.error.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    Some(format!("Backend unreachable: {}", failures.join("; ")))
+}
+
+// Helper: convert a backend probe into its proto representation.
+fn proto_probe(probe: probe::Probe) -> BackendProbe {
+    BackendProbe {
+        reachable: probe.reachable(),
+        target: probe.target,
+        address: probe
+            .address
+            .map(|address| address.to_string())
+            .unwrap_or_default(),
+        latency_ms: u32::try_from(probe.latency.as_millis()).unwrap_or(u32::MAX),
+        error: probe.error.unwrap_or_default(),
+        tls: None,
+    }
+}
+
+// Helper: the TLS settings a client-mode section connects to its backend
+// with, falling back to the global section as stunnel does.
+fn tls_settings(config: &StunnelConfig, section: &Section) -> probe::TlsSettings {
+    let option = |key: &str| {
+        section
+            .get(key)
+            .or_else(|| config.global(key))
+            .map(str::to_string)
+    };
+    probe::TlsSettings {
+        ca_file: option("CAfile"),
+        ca_path: option("CApath"),
+        cert: option("cert"),
+        key: option("key"),
+        sni: option("sni"),
+        check_host: option("checkHost"),
+        ciphers: option("ciphers"),
+        ciphersuites: option("ciphersuites"),
+    }
+}
+
+// Helper: convert a TLS handshake probe into its proto representation.
+fn proto_handshake(handshake: probe::Handshake) -> TlsHandshake {
+    TlsHandshake {
+        completed: handshake.completed(),
+        certificate_valid: handshake.certificate_valid(),
+        protocol: handshake.protocol.unwrap_or_default(),
+        cipher: handshake.cipher.unwrap_or_default(),
+        certificate: handshake.certificate.map(proto_certificate),
+        verify_error: handshake.verify_error.unwrap_or_default(),
+        latency_ms: u32::try_from(handshake.latency.as_millis()).unwrap_or(u32::MAX),
+        error: handshake.error.unwrap_or_default(),
+    }
+}
+
+// Helper: convert a reload outcome read from stunnel's log into its proto
+// representation.
+fn proto_reload_outcome(outcome: logs::ReloadOutcome) -> ReloadOutcome {
+    match outcome {
+        logs::ReloadOutcome::Unknown => ReloadOutcome::Unknown,
+        logs::ReloadOutcome::Applied => ReloadOutcome::Applied,
+        logs::ReloadOutcome::Rejected => ReloadOutcome::Rejected,
+    }
+}
+
+// Helper: the drain timeout a request asks for; 0 means the default.
+fn drain_timeout(secs: u32) -> Duration {
+    match secs {
+        0 => drain::DEFAULT_TIMEOUT,
+        secs => Duration::from_secs(u64::from(secs)),
+    }
+}
+
+// Helper: wait for the connections a removed provider accepted on `port` to
+// close, recording the outcome in `response`. A provider without a TCP
+// accept port has nothing to drain.
+async fn drain_removed(
+    response: &mut RemoveProviderResponse,
+    port: Option<u16>,
+    timeout: Duration,
+    progress: impl FnMut(&drain::DrainStatus),
+) {
+    let Some(port) = port else {
+        response.drained = true;
+        return;
+    };
+    let status = drain::drain(port, timeout, progress).await;
+    response.drained = status.drained();
+    response.open_connections = u32::try_from(status.open_connections).unwrap_or(u32::MAX);
+    response.message = if status.drained() {
+        format!(
+            "{}; connections drained after {}s",
+            response.message,
+            status.elapsed.as_secs()
+        )
+    } else {
+        format!(
+            "{}; {} connection(s) still open after {}s",
+            response.message,
+            status.open_connections,
+            status.elapsed.as_secs()
+        )
+    };
+}
+
+// Helper: the outcome of a background operation whose RPC reported `success`.
+fn outcome(success: bool, message: String, result: OperationResult) -> Outcome<OperationResult> {
+    Outcome {
+        state: if success {
+            operations::OperationState::Succeeded
+        } else {
+            operations::OperationState::Failed
+        },
+        message,
+        result,
+    }
+}
+
+// Helper: convert a background operation into its proto representation.
+fn proto_operation(operation: operations::Operation<OperationResult>) -> Operation {
+    let state = match operation.state {
+        operations::OperationState::Running => OperationState::Running,
+        operations::OperationState::Succeeded => OperationState::Succeeded,
+        operations::OperationState::Failed => OperationState::Failed,
+        operations::OperationState::Cancelled => OperationState::Cancelled,
+    };
+    Operation {
+        id: operation.id,
+        method: operation.method,
+        started_by: operation.started_by,
+        state: state as i32,
+        message: operation.message,
+        cancel_requested: operation.cancel_requested,
+        started_at: operation.started_at.to_rfc3339(),
+        updated_at: operation.updated_at.to_rfc3339(),
+        finished_at: operation
+            .finished_at
+            .map(|finished| finished.to_rfc3339())
+            .unwrap_or_default(),
+        result: operation.result,
+    }
+}
+
+// Helper: convert a drain status into its proto representation.
+fn proto_drain_progress(status: &drain::DrainStatus, done: bool) -> DrainProgress {
+    DrainProgress {
+        open_connections: u32::try_from(status.open_connections).unwrap_or(u32::MAX),
+        elapsed_secs: u32::try_from(status.elapsed.as_secs()).unwrap_or(u32::MAX),
+        done,
+        drained: status.drained(),
+    }
+}
+
+// Helper: convert service readiness into its proto representation.
+fn proto_readiness(service: ServiceReadiness) -> crate::stunnel::ServiceReadiness {
+    crate::stunnel::ServiceReadiness {
+        service: service.service,
+        accept_port: i32::from(service.port),
+        listening: service.listening,
+    }
+}
+
+// Helper: describe what is wrong with a port, if anything.
+fn port_problem(port: i32) -> Option<String> {
+    (!(1..=65535).contains(&port)).then(|| format!("{} is not between 1 and 65535", port))
+}
+
+// Helper: describe what is wrong with a connect host, if anything. Accepts
+// IP addresses, optionally bracketed, and DNS hostnames.
+fn host_problem(host: &str) -> Option<String> {
+    if host.is_empty() {
+        return Some("is required".to_string());
+    }
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if unbracketed.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+    let valid_label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    };
+    let hostname = host.strip_suffix('.').unwrap_or(host);
+    if hostname.len() > 253 || !hostname.split('.').all(valid_label) {
+        return Some(format!("{:?} is not a valid hostname or IP address", host));
+    }
+    None
+}
+
+// Helper: render an AddProvider section. Certificate and verification
+// options are written only when the provider sets them; anything left unset
+// falls back to stunnel's global section.
+fn render_provider_section(provider: &Provider) -> String {
+    let mut new_section = String::new();
+    new_section.push_str(&format!("\n; {} service\n", provider.name));
+    new_section.push_str(&format!("[{}]\n", provider.name));
+
+    if provider.is_client {
+        new_section.push_str("client = yes\n");
+    }
+
+    new_section.push_str(&format!("accept = :::{}\n", provider.accept_port));
+    if provider.exec.is_empty() {
+        for (host, port) in connect_targets(provider) {
+            new_section.push_str(&format!("connect = {}:{}\n", host, port));
+        }
+        if let Some(failover) = failover_option(provider.failover) {
+            new_section.push_str(&format!("failover = {}\n", failover));
+        }
+    } else {
+        // inetd-style service: stunnel runs the program instead of connecting
+        new_section.push_str(&format!("exec = {}\n", provider.exec));
+        if !provider.exec_args.is_empty() {
+            new_section.push_str(&format!("execArgs = {}\n", provider.exec_args.join(" ")));
+        }
+    }
+
+    for (key, value) in [
+        ("cert", &provider.cert),
+        ("key", &provider.key),
+        ("CAfile", &provider.ca_file),
+        ("CApath", &provider.ca_path),
+        ("engineId", &provider.engine_id),
+    ] {
+        if !value.is_empty() {
+            new_section.push_str(&format!("{} = {}\n", key, value));
+        }
+    }
+    if let Some(level) = provider.verify {
+        new_section.push_str(&format!("verify = {}\n", level));
+    }
+    for (key, value) in [
+        ("protocol", &provider.protocol),
+        ("protocolHost", &provider.protocol_host),
+        ("protocolUsername", &provider.protocol_username),
+        ("protocolPassword", &provider.protocol_password),
+    ] {
+        if !value.is_empty() {
+            new_section.push_str(&format!("{} = {}\n", key, value));
+        }
+    }
+    if !provider.crl_file.is_empty() {
+        new_section.push_str(&format!("CRLfile = {}\n", provider.crl_file));
+    }
+    if !provider.ocsp.is_empty() {
+        new_section.push_str(&format!("OCSP = {}\n", provider.ocsp));
+    }
+    for flag in &provider.ocsp_flags {
+        new_section.push_str(&format!("OCSPflag = {}\n", flag));
+    }
+    for (key, value) in [
+        ("OCSPaia", provider.ocsp_aia),
+        ("OCSPnonce", provider.ocsp_nonce),
+        ("OCSPrequire", provider.ocsp_require),
+    ] {
+        if let Some(value) = value {
+            new_section.push_str(&format!("{} = {}\n", key, yes_no(value)));
+        }
+    }
+    for (key, value) in tuning_options(provider) {
+        if let Some(value) = value {
+            new_section.push_str(&format!("{} = {}\n", key, value));
+        }
+    }
+    new_section.push_str(&render_tls_options(provider));
+
+    new_section
+}
+
+// Helper: render a boolean config value.
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+// Helper: parse a boolean config value.
+fn parse_yes_no(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("yes") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("no") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Helper: the provider's optional timeout and session cache options, keyed by
+// config option name.
+fn tuning_options(provider: &Provider) -> [(&'static str, Option<u32>); 6] {
+    [
+        ("TIMEOUTbusy", provider.timeout_busy),
+        ("TIMEOUTclose", provider.timeout_close),
+        ("TIMEOUTconnect", provider.timeout_connect),
+        ("TIMEOUTidle", provider.timeout_idle),
+        ("sessionCacheSize", provider.session_cache_size),
+        ("sessionCacheTimeout", provider.session_cache_timeout),
+    ]
+}
+
+// Helper: the provider's connect targets, falling back to connect_host/connect_port.
+fn connect_targets(provider: &Provider) -> Vec<(&str, i32)> {
+    if provider.connect_targets.is_empty() {
+        vec![(provider.connect_host.as_str(), provider.connect_port)]
+    } else {
+        provider
+            .connect_targets
+            .iter()
+            .map(|target| (target.host.as_str(), target.port))
+            .collect()
+    }
+}
+
+// Helper: map a proto failover strategy to its config value (None = stunnel default).
+fn failover_option(failover: i32) -> Option<&'static str> {
+    match FailoverStrategy::from_i32(failover) {
+        Some(FailoverStrategy::Rr) => Some("rr"),
+        Some(FailoverStrategy::Prio) => Some("prio"),
+        _ => None,
+    }
+}
+
+// Helper: parse a `failover` config value.
+fn failover_strategy(value: &str) -> Option<FailoverStrategy> {
+    if value.eq_ignore_ascii_case("rr") {
+        Some(FailoverStrategy::Rr)
+    } else if value.eq_ignore_ascii_case("prio") {
+        Some(FailoverStrategy::Prio)
+    } else {
+        None
+    }
+}
+
+// Helper: render a provider's TLS protocol, cipher and OpenSSL option lines.
+fn render_tls_options(provider: &Provider) -> String {
+    let mut lines = String::new();
+    for (key, value) in [
+        ("sslVersionMin", &provider.ssl_version_min),
+        ("sslVersionMax", &provider.ssl_version_max),
+        ("ciphers", &provider.ciphers),
+        ("ciphersuites", &provider.ciphersuites),
+    ] {
+        if !value.is_empty() {
+            lines.push_str(&format!("{} = {}\n", key, value));
+        }
+    }
+    for option in &provider.options {
+        lines.push_str(&format!("options = {}\n", option));
+    }
+    lines
+}
+
+// Helper: append a rendered section using the config's own line endings.
+fn append_to_config(existing_config: &str, new_section: &str) -> String {
+    let mut document = Document::parse(existing_config);
+    document.append(new_section);
+    document.render()
+}
+
+// Provider fields that UpdateProvider accepts in its update mask.
+const UPDATABLE_PROVIDER_FIELDS: &[&str] = &[
+    "accept_port",
+    "connect_host",
+    "connect_port",
+    "connect_targets",
+    "failover",
+    "exec",
+    "exec_args",
+    "is_client",
+    "cert",
+    "key",
+    "ca_file",
+    "ca_path",
+    "engine_id",
+    "verify",
+    "crl_file",
+    "ocsp",
+    "ocsp_aia",
+    "ocsp_flags",
+    "ocsp_nonce",
+    "ocsp_require",
+    "protocol",
+    "protocol_host",
+    "protocol_username",
+    "protocol_password",
+    "timeout_busy",
+    "timeout_close",
+    "timeout_connect",
+    "timeout_idle",
+    "session_cache_size",
+    "session_cache_timeout",
+    "ciphers",
+    "ciphersuites",
+    "ssl_version_min",
+    "ssl_version_max",
+    "options",
+];
+
+// Helper: compute the option edits that apply `provider` to `section` for the masked fields.
+fn provider_section_updates(
+    section: &Section,
+    provider: &Provider,
+    paths: &[String],
+) -> Result<Vec<(&'static str, Option<String>)>, String> {
+    let masked = |field: &str| paths.iter().any(|path| path == field);
+    let current = provider_from_section(section);
+
+    // Optional overrides are removed from the section when cleared
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    let mut updates: Vec<(&'static str, Option<String>)> = Vec::new();
+
+    if masked("accept_port") {
+        let port = u16::try_from(provider.accept_port)
+            .map_err(|_| "accept_port is out of range".to_string())?;
+        let host = section
+            .get("accept")
+            .map(|accept| split_host_port(accept).0)
+            .unwrap_or_else(|| "::".to_string());
+        updates.push(("accept", Some(join_host_port(&host, port))));
+    }
+    if masked("connect_host") || masked("connect_port") {
+        let host = if masked("connect_host") {
+            &provider.connect_host
+        } else {
+            &current.connect_host
+        };
+        let port = if masked("connect_port") {
+            provider.connect_port
+        } else {
+            current.connect_port
+        };
+        let port = u16::try_from(port).map_err(|_| "connect_port is out of range".to_string())?;
+        updates.push(("connect", Some(join_host_port(host, port))));
+    }
+    if masked("connect_targets") {
+        // Replace every existing `connect` line with the requested targets
+        updates.push(("connect", None));
+        for target in &provider.connect_targets {
+            let port = u16::try_from(target.port)
+                .map_err(|_| format!("connect target {} port is out of range", target.host))?;
+            updates.push(("connect", Some(join_host_port(&target.host, port))));
+        }
+    }
+    if masked("exec") {
+        if !provider.exec.is_empty() {
+            // A service either runs a program or connects, never both
+            updates.push(("connect", None));
+        }
+        updates.push(("exec", non_empty(&provider.exec)));
+    }
+    if masked("exec_args") {
+        updates.push(("execArgs", non_empty(&provider.exec_args.join(" "))));
+    }
+    if masked("failover") {
+        updates.push((
+            "failover",
+            failover_option(provider.failover).map(str::to_string),
+        ));
+    }
+    if masked("is_client") {
+        updates.push(("client", provider.is_client.then(|| "yes".to_string())));
+    }
+    if masked("cert") {
+        updates.push(("cert", non_empty(&provider.cert)));
+    }
+    if masked("key") {
+        updates.push(("key", non_empty(&provider.key)));
+    }
+    if masked("ca_file") {
+        updates.push(("CAfile", non_empty(&provider.ca_file)));
+    }
+    if masked("engine_id") {
+        updates.push(("engineId", non_empty(&provider.engine_id)));
+    }
+    if masked("crl_file") {
+        updates.push(("CRLfile", non_empty(&provider.crl_file)));
+    }
+    if masked("ocsp") {
+        updates.push(("OCSP", non_empty(&provider.ocsp)));
+    }
+    if masked("ocsp_flags") {
+        updates.push(("OCSPflag", None));
+        for flag in &provider.ocsp_flags {
+            updates.push(("OCSPflag", Some(flag.clone())));
+        }
+    }
+    for (field, key, value) in [
+        ("ocsp_aia", "OCSPaia", provider.ocsp_aia),
+        ("ocsp_nonce", "OCSPnonce", provider.ocsp_nonce),
+        ("ocsp_require", "OCSPrequire", provider.ocsp_require),
+    ] {
+        if masked(field) {
+            updates.push((key, value.map(|value| yes_no(value).to_string())));
+        }
+    }
+    if masked("ca_path") {
+        updates.push(("CApath", non_empty(&provider.ca_path)));
+    }
+    if masked("verify") {
+        updates.push(("verify", provider.verify.map(|level| level.to_string())));
+    }
+    if masked("protocol") {
+        updates.push(("protocol", non_empty(&provider.protocol)));
+    }
+    if masked("protocol_host") {
+        updates.push(("protocolHost", non_empty(&provider.protocol_host)));
+    }
+    if masked("protocol_username") {
+        updates.push(("protocolUsername", non_empty(&provider.protocol_username)));
+    }
+    if masked("protocol_password") {
+        updates.push(("protocolPassword", non_empty(&provider.protocol_password)));
+    }
+    let tuning_fields = [
+        "timeout_busy",
+        "timeout_close",
+        "timeout_connect",
+        "timeout_idle",
+        "session_cache_size",
+        "session_cache_timeout",
+    ];
+    for (field, (key, value)) in tuning_fields.iter().zip(tuning_options(provider)) {
+        if masked(field) {
+            updates.push((key, value.map(|value| value.to_string())));
+        }
+    }
+    if masked("ciphers") {
+        updates.push(("ciphers", non_empty(&provider.ciphers)));
+    }
+    if masked("ciphersuites") {
+        updates.push(("ciphersuites", non_empty(&provider.ciphersuites)));
+    }
+    if masked("ssl_version_min") {
+        updates.push(("sslVersionMin", non_empty(&provider.ssl_version_min)));
+    }
+    if masked("ssl_version_max") {
+        updates.push(("sslVersionMax", non_empty(&provider.ssl_version_max)));
+    }
+    if masked("options") {
+        // Replace every existing `options` line with the requested set
+        updates.push(("options", None));
+        for option in &provider.options {
+            updates.push(("options", Some(option.clone())));
+        }
+    }
+
+    Ok(updates)
+}
+
+// Helper: the provider fields an update mask selects. An empty mask means a
+// full update of every updatable field.
+fn update_mask_paths(mask: Option<prost_types::FieldMask>) -> Result<Vec<String>, String> {
+    let paths: Vec<String> = match mask {
+        Some(mask) if !mask.paths.is_empty() => mask.paths,
+        _ => UPDATABLE_PROVIDER_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .collect(),
+    };
+    if let Some(unknown) = paths
+        .iter()
+        .find(|path| !UPDATABLE_PROVIDER_FIELDS.contains(&path.as_str()))
+    {
+        return Err(format!("Field {} cannot be updated", unknown));
+    }
+    Ok(paths)
+}
+
+// Helper: apply one ApplyChanges entry to config content in memory.
+// Providers kept in their own files under `fragments` cannot be edited this
+// way, since the batch is a single rewrite of the main config.
+fn apply_config_change(
+    content: &str,
+    change: Option<config_change::Change>,
+    fragments: Option<&FragmentDir>,
+) -> Result<String, String> {
+    let in_fragment = |name: &str| fragments.is_some_and(|fragments| fragments.contains(name));
+    let config = StunnelConfig::parse(content);
+    // Accept ports must not clash with provider files either
+    let check_port = |name: &str, port: i32| -> Result<(), String> {
+        let Ok(port) = u16::try_from(port) else {
+            return Ok(());
+        };
+        let mut providers = config.clone();
+        if let Some(fragments) = fragments {
+            let content = fragments
+                .read_all()
+                .map_err(|e| format!("Failed to read provider files: {}", e))?;
+            providers
+                .sections
+                .extend(StunnelConfig::parse(&content).sections);
+        }
+        check_accept_port(&providers, name, port)
+    };
+    match change.ok_or_else(|| "Change is empty".to_string())? {
+        config_change::Change::AddProvider(provider) => {
+            let provider = normalize_provider(provider, |_| true).map_err(invalid_provider)?;
+            if config.section(&provider.name).is_some() || in_fragment(&provider.name) {
+                return Err(format!(
+                    "Provider {} already exists in config",
+                    provider.name
+                ));
+            }
+            check_port(&provider.name, provider.accept_port)?;
+            Ok(append_to_config(
+                content,
+                &render_provider_section(&provider),
+            ))
+        }
+        config_change::Change::UpdateProvider(update) => {
+            let name = update.provider_name;
+            let provider = update
+                .provider
+                .ok_or_else(|| format!("Provider is required to update {}", name))?;
+            let paths = update_mask_paths(update.update_mask)?;
+            let provider =
+                normalize_provider(provider, |field| paths.iter().any(|path| path == field))
+                    .map_err(invalid_provider)?;
+            let section = config
+                .section(&name)
+                .ok_or_else(|| missing_provider(&name, in_fragment(&name)))?;
+            if paths.iter().any(|path| path == "accept_port") {
+                check_port(&name, provider.accept_port)?;
+            }
+            let updates = provider_section_updates(section, &provider, &paths)?;
+            Ok(update_section(content, section, &updates))
+        }
+        config_change::Change::RemoveProvider(name) => {
+            let section = config
+                .section(&name)
+                .ok_or_else(|| missing_provider(&name, in_fragment(&name)))?;
+            Ok(remove_section(content, section))
+        }
+        config_change::Change::SetGlobalOption(option) => {
+            let key = option.key.trim();
+            if key.is_empty() || key.contains(['=', '[', ']', ';', '#', '\n', '\r']) {
+                return Err(format!("Invalid global option name: {:?}", option.key));
+            }
+            if option.value.contains(['\n', '\r']) {
+                return Err(format!("Value of global option {} spans lines", key));
+            }
+            let value = option.value.trim();
+            let value = (!value.is_empty()).then(|| value.to_string());
+            Ok(update_globals(content, &[(key, value)]))
+        }
+    }
+}
+
+// Helper: convert the issues of a validation report for a response.
+fn proto_validation_errors(report: &Report) -> Vec<ValidationError> {
+    report
+        .issues
+        .iter()
+        .map(|issue| ValidationError {
+            line: issue.line.unwrap_or(0),
+            message: issue.message.clone(),
+            validator: issue.validator.clone(),
+            severity: lint_severity(issue.severity) as i32,
+            blocking: issue.blocking,
+        })
+        .collect()
+}
+
+// Helper: explain why provider `name` cannot be edited in the main config.
+fn missing_provider(name: &str, in_fragment: bool) -> String {
+    if in_fragment {
+        format!(
+            "Provider {} is defined in its own provider file, which ApplyChanges cannot edit",
+            name
+        )
+    } else {
+        format!("Provider {} not found in config", name)
+    }
+}
+
+// Streamed by WatchCertificateChanges and RemoveProviderWithProgress.
+type CertificateChangeStream =
+    std::pin::Pin<Box<dyn Stream<Item = Result<CertificateChangeEvent, Status>> + Send>>;
+type RemoveProviderProgressStream =
+    std::pin::Pin<Box<dyn Stream<Item = Result<RemoveProviderProgress, Status>> + Send>>;
+
+// The RPC handlers, each acting on this instance. The StunnelManager impl
+// below routes every call to the instance its request names.
+impl StunnelServer {
+    async fn reload_config(
+        &self,
+        request: Request<ReloadRequest>,
+    ) -> Result<Response<ReloadResponse>, Status> {
+        if request.get_ref().background {
+            let operation_id = self
+                .start_in_background("ReloadConfig", request, |server, mut request, _| {
+                    request.get_mut().background = false;
+                    async move {
+                        let response = StunnelManager::reload_config(&server, request)
+                            .await?
+                            .into_inner();
+                        Ok(outcome(
+                            response.success,
+                            response.message.clone(),
+                            OperationResult::Reload(response),
+                        ))
+                    }
+                })
+                .map_err(Status::internal)?;
+            return Ok(Response::new(ReloadResponse {
+                success: true,
+                message: format!("Reload running as operation {}", operation_id),
+                pid: 0,
+                ready: false,
+                services: vec![],
+                outcome: ReloadOutcome::Unknown as i32,
+                log_errors: vec![],
+                operation_id,
+            }));
+        }
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let config_path = self
+            .resolve_config_path(req.config_path)
+            .map_err(Status::permission_denied)?;
+
+        // Validate only if requested
+        if req.validate_only {
+            match self.validate_file(&config_path) {
+                Ok(_) => {
+                    return Ok(Response::new(ReloadResponse {
+                        success: true,
+                        message: "Configuration is valid".to_string(),
+                        pid: 0,
+                        ready: false,
+                        services: vec![],
+                        outcome: ReloadOutcome::Unknown as i32,
+                        log_errors: vec![],
+                        operation_id: String::new(),
+                    }));
+                }
+                Err(e) => {
+                    return Ok(Response::new(ReloadResponse {
+                        success: false,
+                        message: format!("Config validation failed: {}", e),
+                        pid: 0,
+                        ready: false,
+                        services: vec![],
+                        outcome: ReloadOutcome::Unknown as i32,
+                        log_errors: vec![],
+                        operation_id: String::new(),
+                    }));
+                }
+            }
+        }
+
+        // Reload the running instance, or start one if none is running
+        let mut reload = None;
+        let started = match self.stunnel_pid().map_err(|e| e.to_string()) {
+            Ok(pid) if process_running(pid) => {
+                // stunnel only logs whether it accepted the config, so note
+                // where its log ends before signalling it
+                let mut cursor = fs::read_to_string(&config_path)
+                    .ok()
+                    .and_then(|content| logs::log_path(&StunnelConfig::parse(&content)))
+                    .map(LogCursor::at_end);
+                let signalled = self
+                    .reload_process(pid)
+                    .map_err(|e| format!("Failed to reload stunnel: {}", e));
+                match (signalled, cursor.as_mut()) {
+                    (Err(e), _) => Err(e),
+                    (Ok(()), None) => Ok((
+                        pid,
+                        "Reload signal sent; stunnel has no log file to confirm the outcome"
+                            .to_string(),
+                    )),
+                    (Ok(()), Some(cursor)) => {
+                        let result = logs::wait_for_reload(cursor, logs::RELOAD_TIMEOUT).await;
+                        if result.outcome == logs::ReloadOutcome::Rejected {
+                            return Ok(Response::new(ReloadResponse {
+                                success: false,
+                                message: format!(
+                                    "stunnel rejected the configuration and kept the previous one: {}",
+                                    if result.errors.is_empty() {
+                                        "see its log for details".to_string()
+                                    } else {
+                                        result.errors.join("; ")
+                                    }
+                                ),
+                                pid,
+                                ready: false,
+                                services: vec![],
+                                outcome: ReloadOutcome::Rejected as i32,
+                                log_errors: result.errors,
+                                operation_id: String::new(),
+                            }));
+                        }
+                        let message = match result.outcome {
+                            logs::ReloadOutcome::Applied => {
+                                "Configuration reloaded successfully".to_string()
+                            }
+                            _ => format!(
+                                "Reload signal sent, but stunnel did not log the outcome within {}s",
+                                logs::RELOAD_TIMEOUT.as_secs()
+                            ),
+                        };
+                        reload = Some(result);
+                        Ok((pid, message))
+                    }
+                }
+            }
+            // PID file exists but process not running - start new instance
+            Ok(_) => self
+                .start_process(&config_path)
+                .await?
+                .map(|pid| {
+                    (
+                        pid,
+                        "Stunnel restarted successfully (stale pid)".to_string(),
+                    )
+                })
+                .map_err(|e| format!("Failed to start stunnel after stale pid: {}", e)),
+            Err(e) => {
+                info!("Starting new stunnel instance: {}", e);
+                self.start_process(&config_path)
+                    .await?
+                    .map(|pid| (pid, "Stunnel started successfully".to_string()))
+                    .map_err(|e| format!("Failed to start stunnel: {}", e))
+            }
+        };
+        let (pid, message) = match started {
+            Ok(started) => started,
+            Err(message) => {
+                return Ok(Response::new(ReloadResponse {
+                    success: false,
+                    message,
+                    pid: 0,
+                    ready: false,
+                    services: vec![],
+                    outcome: ReloadOutcome::Unknown as i32,
+                    log_errors: vec![],
+                    operation_id: String::new(),
+                }));
+            }
+        };
+        let (outcome, log_errors) = match reload {
+            Some(result) => (proto_reload_outcome(result.outcome), result.errors),
+            None => (ReloadOutcome::Unknown, vec![]),
+        };
+
+        let services = self.wait_for_services(&config_path).await;
+        let (ready, message) = match readiness::summary(&services, self.readiness_timeout) {
+            None => (true, message),
+            Some(summary) => (false, format!("{}, but {}", message, summary)),
+        };
+        Ok(Response::new(ReloadResponse {
+            success: ready,
+            message,
+            pid,
+            ready,
+            services: services.into_iter().map(proto_readiness).collect(),
+            outcome: outcome as i32,
+            log_errors,
+            operation_id: String::new(),
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        let located = self.backend.locate();
+        let running = located
+            .as_ref()
+            .ok()
+            .map(|located| located.pid)
+            .filter(|&pid| process_running(pid));
+        let stunnel_version = self.stunnel_release(running).await?;
+        match located {
+            Ok(backend::Located { pid, discovered }) => {
+                let connections = self.instance_connections();
+                Ok(Response::new(StatusResponse {
+                    is_running: running.is_some(),
+                    pid,
+                    config_path: self.config_path.clone(),
+                    active_connections: connections,
+                    pid_discovered: discovered,
+                    supervisor: self.proto_supervisor(),
+                    process_backend: self.backend.name().to_string(),
+                    usage: running.and_then(|pid| self.proto_usage(pid)),
+                    restart_count: self.usage.starts(),
+                    stunnel_version,
+                }))
+            }
+            Err(_) => Ok(Response::new(StatusResponse {
+                is_running: false,
+                pid: 0,
+                config_path: self.config_path.clone(),
+                active_connections: vec![],
+                pid_discovered: false,
+                supervisor: self.proto_supervisor(),
+                process_backend: self.backend.name().to_string(),
+                usage: None,
+                restart_count: self.usage.starts(),
+                stunnel_version,
+            })),
+        }
+    }
+
+    async fn update_config(
+        &self,
+        request: Request<UpdateConfigRequest>,
+    ) -> Result<Response<UpdateConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let config_path = self
+            .resolve_config_path(req.config_path)
+            .map_err(Status::permission_denied)?;
+        self.check_version(&config_path, &req.expected_version)
+            .map_err(Status::aborted)?;
+
+        let config_content = if req.expand_env {
+            match expand_env_vars(&req.config_content) {
+                Ok(expanded) => expanded,
+                Err(missing) => {
+                    return Ok(Response::new(UpdateConfigResponse {
+                        success: false,
+                        message: format!("Undefined environment variables: {}", missing.join(", ")),
+                        config_version: String::new(),
+                        updated_config: String::new(),
+                        validation_errors: vec![],
+                    }));
+                }
+            }
+        } else {
+            req.config_content
+        };
+        if let Err(message) = check_no_placeholders(&config_content) {
+            return Ok(Response::new(UpdateConfigResponse {
+                success: false,
+                message,
+                config_version: String::new(),
+                updated_config: String::new(),
+                validation_errors: vec![],
+            }));
+        }
+
+        if req.dry_run {
+            let report = self.validation.validate(&config_content);
+            let message = if report.is_valid() {
+                "Dry run: configuration is valid and would be written".to_string()
+            } else {
+                format!("Invalid configuration: {}", report.summary())
+            };
+            return Ok(Response::new(redact::apply(
+                redact,
+                UpdateConfigResponse {
+                    success: report.is_valid(),
+                    message,
+                    config_version: String::new(),
+                    updated_config: config_content,
+                    validation_errors: proto_validation_errors(&report),
+                },
+            )));
+        }
+
+        let _writing = match self.begin_write() {
+            Ok(writing) => writing,
+            Err(message) => {
+                return Ok(Response::new(UpdateConfigResponse {
+                    success: false,
+                    message,
+                    config_version: String::new(),
+                    updated_config: String::new(),
+                    validation_errors: vec![],
+                }));
+            }
+        };
+        let previous = fs::read_to_string(&config_path).unwrap_or_default();
+
+        // Validate new config
+        let report = self.validation.validate(&config_content);
+        if !report.is_valid() {
+            return Ok(Response::new(UpdateConfigResponse {
+                success: false,
+                message: format!("Invalid configuration: {}", report.summary()),
+                config_version: String::new(),
+                updated_config: String::new(),
+                validation_errors: proto_validation_errors(&report),
+            }));
+        }
+
+        // Backup existing config
+        if let Err(e) = backup_file(&config_path, &self.backup_policy, self.secrets.as_deref()) {
+            return Ok(Response::new(UpdateConfigResponse {
+                success: false,
+                message: format!("Failed to backup config: {}", e),
+                config_version: String::new(),
+                updated_config: String::new(),
+                validation_errors: vec![],
+            }));
+        }
+
+        // Write new config atomically
+        if let Err(e) = atomic_write(&config_path, &config_content) {
+            // Attempt to restore the previous content if write partially failed
+            let _ = atomic_write(&config_path, &previous);
+            return Ok(Response::new(UpdateConfigResponse {
+                success: false,
+                message: format!("Failed to write config: {}", e),
+                config_version: String::new(),
+                updated_config: String::new(),
+                validation_errors: vec![],
+            }));
+        }
+
+        self.record_revision(
+            &config_path,
+            "UpdateConfig",
+            &caller,
+            &previous,
+            &config_content,
+        );
+
+        Ok(Response::new(UpdateConfigResponse {
+            success: true,
+            message: "Configuration updated successfully".to_string(),
+            config_version: self.version_of(&config_path),
+            updated_config: String::new(),
+            validation_errors: vec![],
+        }))
+    }
+
+    async fn generate_config(
+        &self,
+        request: Request<GenerateConfigRequest>,
+    ) -> Result<Response<GenerateConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let req = request.into_inner();
+        let tls_profile = tls_profile(req.tls_profile).map_err(Status::invalid_argument)?;
+        let providers = normalize_providers(req.providers).map_err(Status::invalid_argument)?;
+        let mut config_content = String::new();
+
+        // Global settings
+        config_content.push_str("; Stunnel configuration generated by Rust gRPC server\n");
+        config_content.push_str(&format!("; Generated at: {}\n\n", Utc::now().to_rfc3339()));
+
+        if req.foreground {
+            config_content.push_str("foreground = yes\n");
+        }
+
+        let debug_level = req.debug_level.unwrap_or(7);
+        if debug_level > 7 {
+            return Err(Status::invalid_argument(
+                "debug_level must be between 0 and 7",
+            ));
+        }
+        if req.engine.is_empty() && !req.engine_ctrl.is_empty() {
+            return Err(Status::invalid_argument("engine_ctrl requires engine"));
+        }
+        if req.syslog_facility.is_empty() {
+            config_content.push_str(&format!("debug = {}\n", debug_level));
+        } else {
+            config_content.push_str(&format!(
+                "debug = {}.{}\n",
+                req.syslog_facility, debug_level
+            ));
+        }
+        for (key, value) in [
+            ("output", &req.output),
+            ("setuid", &req.setuid),
+            ("setgid", &req.setgid),
+            ("chroot", &req.chroot),
+            ("compression", &req.compression),
+            ("service", &req.service),
+        ] {
+            if !value.is_empty() {
+                config_content.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+        // engineCtrl lines apply to the engine loaded just before them
+        if !req.engine.is_empty() {
+            config_content.push_str(&format!("engine = {}\n", req.engine));
+            for ctrl in &req.engine_ctrl {
+                config_content.push_str(&format!("engineCtrl = {}\n", ctrl));
+            }
+        }
+        for socket_option in &req.socket_options {
+            config_content.push_str(&format!("socket = {}\n", socket_option));
+        }
+        if let Some(taskbar) = req.taskbar {
+            config_content.push_str(&format!("taskbar = {}\n", yes_no(taskbar)));
+        }
+
+        let pid_file = if !req.pid_file.is_empty() {
+            req.pid_file
+        } else {
+            "/var/run/stunnel.pid".to_string()
+        };
+        config_content.push_str(&format!("pid = {}\n", pid_file));
+
+        if !req.cert_path.is_empty() {
+            config_content.push_str(&format!("cert = {}\n", req.cert_path));
+        }
+        if !req.key_path.is_empty() {
+            config_content.push_str(&format!("key = {}\n", req.key_path));
+        }
+        if !req.ca_path.is_empty() {
+            config_content.push_str(&format!("CAfile = {}\n", req.ca_path));
+        }
+
+        // Global TLS settings apply to every service
+        if let Some(profile) = tls_profile {
+            config_content.push('\n');
+            config_content.push_str(&profile.render());
+        }
+
+        config_content.push('\n');
+
+        // Add each provider as a service
+        for provider in providers {
+            config_content.push_str(render_provider_section(&provider).trim_start());
+            config_content.push('\n');
+        }
+
+        if req.expand_env {
+            config_content = match expand_env_vars(&config_content) {
+                Ok(expanded) => expanded,
+                Err(missing) => {
+                    return Ok(Response::new(redact::apply(
+                        redact,
+                        GenerateConfigResponse {
+                            success: false,
+                            message: format!(
+                                "Undefined environment variables: {}",
+                                missing.join(", ")
+                            ),
+                            config_content: String::new(),
+                            config_path: String::new(),
+                        },
+                    )));
+                }
+            };
+        }
+        if let Err(message) = check_no_placeholders(&config_content) {
+            return Ok(Response::new(redact::apply(
+                redact,
+                GenerateConfigResponse {
+                    success: false,
+                    message,
+                    config_content: String::new(),
+                    config_path: String::new(),
+                },
+            )));
+        }
+
+        let _writing = match self.begin_write() {
+            Ok(writing) => writing,
+            Err(message) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    GenerateConfigResponse {
+                        success: false,
+                        message,
+                        config_content: String::new(),
+                        config_path: String::new(),
+                    },
+                )));
+            }
+        };
+
+        // Write to file atomically
+        if let Err(e) = atomic_write(&self.config_path, &config_content) {
+            return Ok(Response::new(redact::apply(
+                redact,
+                GenerateConfigResponse {
+                    success: false,
+                    message: format!("Failed to write config file: {}", e),
+                    config_content: String::new(),
+                    config_path: String::new(),
+                },
+            )));
+        }
+
+        // Validate the generated config; it is kept even if validation fails
+        self.warn_if_invalid(&config_content);
+
+        Ok(Response::new(redact::apply(
+            redact,
+            GenerateConfigResponse {
+                success: true,
+                message: "Configuration generated successfully".to_string(),
+                config_content: config_content.clone(),
+                config_path: self.config_path.clone(),
+            },
+        )))
+    }
+
+    async fn add_provider(
+        &self,
+        request: Request<AddProviderRequest>,
+    ) -> Result<Response<AddProviderResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let mut provider = req
+            .provider
+            .ok_or_else(|| Status::invalid_argument("Provider is required"))?;
+        self.check_version(&self.config_path, &req.expected_version)
+            .map_err(Status::aborted)?;
+
+        // An omitted accept port is allocated from the configured range
+        if let Some(range) = self.accept_port_range.filter(|_| provider.accept_port == 0) {
+            match self.allocate_accept_port(range) {
+                Ok(port) => provider.accept_port = i32::from(port),
+                Err(message) => {
+                    return Ok(Response::new(AddProviderResponse {
+                        success: false,
+                        message,
+                        updated_config: String::new(),
+                        config_version: String::new(),
+                        validation_errors: vec![],
+                        accept_port: 0,
+                    }));
+                }
+            }
+        }
+        let provider = normalize_provider(provider, |_| true)
+            .map_err(|problems| Status::invalid_argument(invalid_provider(problems)))?;
+        self.check_connect_hosts(connect_hosts(&provider, |_| true))
+            .await
+            .map_err(|problems| {
+                Status::invalid_argument(format!("Invalid provider: {}", problems))
+            })?;
+        if req.probe_backend {
+            let probes = probe_backends(&provider, probe::DEFAULT_TIMEOUT).await;
+            if let Some(message) = unreachable_backends(&probes) {
+                return Ok(Response::new(AddProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    validation_errors: vec![],
+                    accept_port: 0,
+                }));
+            }
+        }
+
+        if req.dry_run {
+            let checked = self
+                .preview_provider_section(&provider.name, &render_provider_section(&provider))
+                .and_then(|(written, loaded)| Ok((written, self.dry_run_managed(&loaded)?)));
+            let (success, message, updated_config, validation_errors) = match checked {
+                Ok((written, report)) if report.is_valid() => (
+                    true,
+                    format!("Dry run: provider {} would be added", provider.name),
+                    written,
+                    proto_validation_errors(&report),
+                ),
+                Ok((written, report)) => (
+                    false,
+                    format!("Invalid configuration: {}", report.summary()),
+                    written,
+                    proto_validation_errors(&report),
+                ),
+                Err(message) => (false, message, String::new(), vec![]),
+            };
+            return Ok(Response::new(redact::apply(
+                redact,
+                AddProviderResponse {
+                    success,
+                    message,
+                    updated_config,
+                    config_version: String::new(),
+                    validation_errors,
+                    accept_port: provider.accept_port,
+                },
+            )));
+        }
+
+        match self.add_provider_section(
+            &provider.name,
+            &render_provider_section(&provider),
+            "AddProvider",
+            &caller,
+        ) {
+            Ok(updated_config) => {
+                // Apply immediately if requested
+                if req.apply_immediately {
+                    self.reload_if_running();
+                }
+                Ok(Response::new(redact::apply(
+                    redact,
+                    AddProviderResponse {
+                        success: true,
+                        message: format!("Provider {} added successfully", provider.name),
+                        updated_config,
+                        config_version: self.version_of(&self.config_path),
+                        validation_errors: vec![],
+                        accept_port: provider.accept_port,
+                    },
+                )))
+            }
+            Err(message) => Ok(Response::new(redact::apply(
+                redact,
+                AddProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    validation_errors: vec![],
+                    accept_port: 0,
+                },
+            ))),
+        }
+    }
+
+    async fn remove_provider(
+        &self,
+        request: Request<RemoveProviderRequest>,
+    ) -> Result<Response<RemoveProviderResponse>, Status> {
+        if request.get_ref().background {
+            let operation_id = self
+                .start_in_background("RemoveProvider", request, |server, request, handle| {
+                    let (metadata, extensions, req) = request.into_parts();
+                    let drain = req.drain && !req.dry_run;
+                    let drain_port = drain
+                        .then(|| server.drain_port(&req.provider_name))
+                        .flatten();
+                    let timeout = drain_timeout(req.drain_timeout_secs);
+                    // The removal runs as a foreground call; draining is
+                    // tracked, and can be cancelled, here
+                    let removal = RemoveProviderRequest {
+                        apply_immediately: req.apply_immediately || req.drain,
+                        drain: false,
+                        background: false,
+                        ..req
+                    };
+                    async move {
+                        let removal = Request::from_parts(metadata, extensions, removal);
+                        let mut response = StunnelManager::remove_provider(&server, removal)
+                            .await?
+                            .into_inner();
+                        if drain && response.success {
+                            handle.set_progress("Waiting for connections to close");
+                            let progress = |status: &drain::DrainStatus| {
+                                handle.set_progress(format!(
+                                    "{} connection(s) open after {}s",
+                                    status.open_connections,
+                                    status.elapsed.as_secs()
+                                ));
+                            };
+                            let cancelled = tokio::select! {
+                                _ = drain_removed(&mut response, drain_port, timeout, progress) => false,
+                                _ = handle.cancelled() => true,
+                            };
+                            if cancelled {
+                                response.message =
+                                    format!("{}; draining cancelled", response.message);
+                                return Ok(Outcome {
+                                    state: operations::OperationState::Cancelled,
+                                    message: response.message.clone(),
+                                    result: OperationResult::RemoveProvider(response),
+                                });
+                            }
+                        }
+                        Ok(outcome(
+                            response.success,
+                            response.message.clone(),
+                            OperationResult::RemoveProvider(response),
+                        ))
+                    }
+                })
+                .map_err(Status::internal)?;
+            return Ok(Response::new(RemoveProviderResponse {
+                success: true,
+                message: format!("Removal running as operation {}", operation_id),
+                updated_config: String::new(),
+                config_version: String::new(),
+                validation_errors: vec![],
+                drained: false,
+                open_connections: 0,
+                operation_id,
+            }));
+        }
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let name = req.provider_name;
+
+        if name.trim().is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RemoveProviderResponse {
+                    success: false,
+                    message: "provider_name is required".to_string(),
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    validation_errors: vec![],
+                    drained: false,
+                    open_connections: 0,
+                    operation_id: String::new(),
+                },
+            )));
+        }
+        self.check_version(&self.config_path, &req.expected_version)
+            .map_err(Status::aborted)?;
+        // Draining needs the accept port, which is gone with the section
+        let drain_port = (req.drain && !req.dry_run)
+            .then(|| self.drain_port(&name))
+            .flatten();
+
+        // A provider with its own file is removed by deleting that file
+        if let Some(fragments) = self.fragments.as_ref().filter(|f| f.contains(&name)) {
+            if req.dry_run {
+                return Ok(Response::new(RemoveProviderResponse {
+                    success: true,
+                    message: format!("Dry run: provider file for {} would be deleted", name),
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    validation_errors: vec![],
+                    drained: false,
+                    open_connections: 0,
+                    operation_id: String::new(),
+                }));
+            }
+            let removed = fragments
+                .fragment_path(&name)
+                .and_then(|path| Ok((fragments.remove(&name)?, path)));
+            let (previous, path) = match removed {
+                Ok(removed) => removed,
+                Err(e) => {
+                    return Ok(Response::new(redact::apply(
+                        redact,
+                        RemoveProviderResponse {
+                            success: false,
+                            message: format!("Failed to remove provider file: {}", e),
+                            updated_config: String::new(),
+                            config_version: String::new(),
+                            validation_errors: vec![],
+                            drained: false,
+                            open_connections: 0,
+                            operation_id: String::new(),
+                        },
+                    )));
+                }
+            };
+            self.record_revision(
+                &path.to_string_lossy(),
+                "RemoveProvider",
+                &caller,
+                &previous,
+                "",
+            );
+            if req.apply_immediately || req.drain {
+                self.reload_if_running();
+            }
+            let mut response = RemoveProviderResponse {
+                success: true,
+                message: format!("Provider {} removed successfully", name),
+                updated_config: String::new(),
+                config_version: self.version_of(&self.config_path),
+                validation_errors: vec![],
+                drained: false,
+                open_connections: 0,
+                operation_id: String::new(),
+            };
+            if req.drain {
+                drain_removed(
+                    &mut response,
+                    drain_port,
+                    drain_timeout(req.drain_timeout_secs),
+                    |_| {},
+                )
+                .await;
+            }
+            return Ok(Response::new(redact::apply(redact, response)));
+        }
+
+        // Read existing config
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RemoveProviderResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                        config_version: String::new(),
+                        validation_errors: vec![],
+                        drained: false,
+                        open_connections: 0,
+                        operation_id: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&existing_config);
+        let updated_config = match config.section(&name) {
+            Some(section) => remove_section(&existing_config, section),
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RemoveProviderResponse {
+                        success: false,
+                        message: format!("Provider {} not found in config", name),
+                        updated_config: existing_config,
+                        config_version: String::new(),
+                        validation_errors: vec![],
+                        drained: false,
+                        open_connections: 0,
+                        operation_id: String::new(),
+                    },
+                )));
+            }
+        };
+
+        if req.dry_run {
+            let (success, message, validation_errors) = match self.dry_run_managed(&updated_config)
+            {
+                Ok(report) if report.is_valid() => (
+                    true,
+                    format!("Dry run: provider {} would be removed", name),
+                    proto_validation_errors(&report),
+                ),
+                Ok(report) => (
+                    false,
+                    format!("Invalid configuration: {}", report.summary()),
+                    proto_validation_errors(&report),
+                ),
+                Err(message) => (false, message, vec![]),
+            };
+            return Ok(Response::new(redact::apply(
+                redact,
+                RemoveProviderResponse {
+                    success,
+                    message,
+                    updated_config,
+                    config_version: String::new(),
+                    validation_errors,
+                    drained: false,
+                    open_connections: 0,
+                    operation_id: String::new(),
+                },
+            )));
+        }
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "RemoveProvider", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RemoveProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    validation_errors: vec![],
+                    drained: false,
+                    open_connections: 0,
+                    operation_id: String::new(),
+                },
+            )));
+        }
+
+        // Apply immediately if requested; draining needs stunnel to stop accepting first
+        if req.apply_immediately || req.drain {
+            self.reload_if_running();
+        }
+
+        let mut response = RemoveProviderResponse {
+            success: true,
+            message: format!("Provider {} removed successfully", name),
+            updated_config,
+            config_version: self.version_of(&self.config_path),
+            validation_errors: vec![],
+            drained: false,
+            open_connections: 0,
+            operation_id: String::new(),
+        };
+        if req.drain {
+            drain_removed(
+                &mut response,
+                drain_port,
+                drain_timeout(req.drain_timeout_secs),
+                |_| {},
+            )
+            .await;
+        }
+        Ok(Response::new(redact::apply(redact, response)))
+    }
+
+    async fn remove_provider_with_progress(
+        &self,
+        request: Request<RemoveProviderRequest>,
+    ) -> Result<Response<RemoveProviderProgressStream>, Status> {
+        let (metadata, extensions, req) = request.into_parts();
+        let drain_port = (req.drain && !req.dry_run)
+            .then(|| self.drain_port(&req.provider_name))
+            .flatten();
+        let timeout = drain_timeout(req.drain_timeout_secs);
+        let drain = req.drain && !req.dry_run;
+
+        // The removal itself runs as RemoveProvider does; draining is streamed
+        let removal = RemoveProviderRequest {
+            apply_immediately: req.apply_immediately || req.drain,
+            drain: false,
+            background: false,
+            ..req
+        };
+        let removed = self
+            .remove_provider(Request::from_parts(metadata, extensions, removal))
+            .await?
+            .into_inner();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let drain = drain && removed.success;
+        let _ = tx.send(Ok(RemoveProviderProgress {
+            event: Some(remove_provider_progress::Event::Removed(removed)),
+        }));
+        if drain {
+            tokio::spawn(async move {
+                let progress = |status: &drain::DrainStatus, done: bool| {
+                    let _ = tx.send(Ok(RemoveProviderProgress {
+                        event: Some(remove_provider_progress::Event::Drain(
+                            proto_drain_progress(status, done),
+                        )),
+                    }));
+                };
+                let status = match drain_port {
+                    Some(port) => {
+                        drain::drain(port, timeout, |status| {
+                            if !status.drained() && status.elapsed < timeout {
+                                progress(status, false);
+                            }
+                        })
+                        .await
+                    }
+                    None => drain::DrainStatus {
+                        open_connections: 0,
+                        elapsed: Duration::ZERO,
+                    },
+                };
+                progress(&status, true);
+            });
+        }
+        Ok(Response::new(Box::pin(
+            tokio_stream::wrappers::UnboundedReceiverStream::new(rx),
+        )))
+    }
+
+    async fn stop_stunnel(
+        &self,
+        request: Request<StopRequest>,
+    ) -> Result<Response<StopResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let timeout = stop_timeout(req.timeout_secs);
+
+        let pid = match self.stunnel_pid() {
+            Ok(pid) => pid,
+            Err(e) => {
+                // Clean up a PID file left behind by a dead process
+                let message = if Path::new(&self.pid_file).exists() {
+                    match remove_pid_file(&self.pid_file) {
+                        Ok(_) => "Stunnel is not running; removed stale pid file".to_string(),
+                        Err(rm_err) => format!("Failed to remove stale pid file: {}", rm_err),
+                    }
+                } else {
+                    format!("Stunnel is not running: {}", e)
+                };
+                return Ok(Response::new(StopResponse {
+                    success: false,
+                    message,
+                    pid: 0,
+                    exited_cleanly: false,
+                }));
+            }
+        };
+
+        match self.stop_process(pid, timeout).await? {
+            Ok(exited_cleanly) => {
+                if let Some(supervisor) = &self.supervisor {
+                    supervisor.stopped();
+                }
+                if let Err(e) = remove_pid_file(&self.pid_file) {
+                    warn!("Failed to remove pid file {}: {}", self.pid_file, e);
+                }
+                let message = if exited_cleanly {
+                    "Stunnel stopped successfully".to_string()
+                } else {
+                    format!(
+                        "Stunnel did not exit within {}s and was killed",
+                        timeout.as_secs()
+                    )
+                };
+                Ok(Response::new(StopResponse {
+                    success: true,
+                    message,
+                    pid,
+                    exited_cleanly,
+                }))
+            }
+            Err(e) => Ok(Response::new(StopResponse {
+                success: false,
+                message: format!("Failed to stop stunnel: {}", e),
+                pid,
+                exited_cleanly: false,
+            })),
+        }
+    }
+
+    async fn restart_stunnel(
+        &self,
+        request: Request<RestartRequest>,
+    ) -> Result<Response<RestartResponse>, Status> {
+        if request.get_ref().background {
+            let operation_id = self
+                .start_in_background("RestartStunnel", request, |server, mut request, _| {
+                    request.get_mut().background = false;
+                    async move {
+                        let response = StunnelManager::restart_stunnel(&server, request)
+                            .await?
+                            .into_inner();
+                        Ok(outcome(
+                            response.success,
+                            response.message.clone(),
+                            OperationResult::Restart(response),
+                        ))
+                    }
+                })
+                .map_err(Status::internal)?;
+            return Ok(Response::new(RestartResponse {
+                success: true,
+                message: format!("Restart running as operation {}", operation_id),
+                old_pid: 0,
+                pid: 0,
+                ready: false,
+                services: vec![],
+                operation_id,
+            }));
+        }
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let config_path = self
+            .resolve_config_path(req.config_path)
+            .map_err(Status::permission_denied)?;
+
+        // Refuse to touch the running instance if the new config would not load
+        if let Err(e) = self.validate_file(&config_path) {
+            return Ok(Response::new(RestartResponse {
+                success: false,
+                message: format!("Config validation failed, stunnel left running: {}", e),
+                old_pid: 0,
+                pid: 0,
+                ready: false,
+                services: vec![],
+                operation_id: String::new(),
+            }));
+        }
+
+        let old_pid = match self.stunnel_pid().ok() {
+            Some(pid) => {
+                if let Err(e) = self
+                    .stop_process(pid, stop_timeout(req.timeout_secs))
+                    .await?
+                {
+                    return Ok(Response::new(RestartResponse {
+                        success: false,
+                        message: format!("Failed to stop stunnel: {}", e),
+                        old_pid: pid,
+                        pid: 0,
+                        ready: false,
+                        services: vec![],
+                        operation_id: String::new(),
+                    }));
+                }
+                pid
+            }
+            None => 0,
+        };
+
+        if let Err(e) = remove_pid_file(&self.pid_file) {
+            warn!("Failed to remove pid file {}: {}", self.pid_file, e);
+        }
+
+        let pid = match self.start_process(&config_path).await? {
+            Ok(pid) => pid,
+            Err(e) => {
+                return Ok(Response::new(RestartResponse {
+                    success: false,
+                    message: format!("Stunnel stopped but failed to start: {}", e),
+                    old_pid,
+                    pid: 0,
+                    ready: false,
+                    services: vec![],
+                    operation_id: String::new(),
+                }));
+            }
+        };
+
+        let services = self.wait_for_services(&config_path).await;
+        let (ready, message) = match readiness::summary(&services, self.readiness_timeout) {
+            None => (true, "Stunnel restarted successfully".to_string()),
+            Some(summary) => (false, format!("Stunnel restarted, but {}", summary)),
+        };
+        Ok(Response::new(RestartResponse {
+            success: ready,
+            message,
+            old_pid,
+            pid,
+            ready,
+            services: services.into_iter().map(proto_readiness).collect(),
+            operation_id: String::new(),
+        }))
+    }
+
+    async fn start_stunnel(
+        &self,
+        request: Request<StartRequest>,
+    ) -> Result<Response<StartResponse>, Status> {
+        if request.get_ref().background {
+            let operation_id = self
+                .start_in_background("StartStunnel", request, |server, mut request, _| {
+                    request.get_mut().background = false;
+                    async move {
+                        let response = StunnelManager::start_stunnel(&server, request)
+                            .await?
+                            .into_inner();
+                        Ok(outcome(
+                            response.success,
+                            response.message.clone(),
+                            OperationResult::Start(response),
+                        ))
+                    }
+                })
+                .map_err(Status::internal)?;
+            return Ok(Response::new(StartResponse {
+                success: true,
+                message: format!("Start running as operation {}", operation_id),
+                pid: 0,
+                ready: false,
+                services: vec![],
+                operation_id,
+                attempts: 0,
+                attempt_errors: vec![],
+            }));
+        }
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        if matches!(req.debug_level, Some(level) if level > 7) {
+            return Err(Status::invalid_argument(
+                "debug_level must be between 0 and 7",
+            ));
+        }
+        let config_path = self
+            .resolve_config_path(req.config_path)
+            .map_err(Status::permission_denied)?;
+
+        if let Ok(pid) = self.stunnel_pid() {
+            if process_running(pid) {
+                return Ok(Response::new(StartResponse {
+                    success: false,
+                    message: format!("Stunnel is already running with PID {}", pid),
+                    pid,
+                    ready: false,
+                    services: vec![],
+                    operation_id: String::new(),
+                    attempts: 0,
+                    attempt_errors: vec![],
+                }));
+            }
+        }
+
+        let existing_config = match fs::read_to_string(&config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(StartResponse {
+                    success: false,
+                    message: format!("Failed to read config: {}", e),
+                    pid: 0,
+                    ready: false,
+                    services: vec![],
+                    operation_id: String::new(),
+                    attempts: 0,
+                    attempt_errors: vec![],
+                }));
+            }
+        };
+
+        // Startup options apply to this run only: stunnel reads them from a
+        // copy of the config, so the config and later runs are left alone
+        let overrides = (req.foreground || req.debug_level.is_some()).then(|| LaunchOverrides {
+            config_path: config_path.clone(),
+            foreground: req.foreground,
+            debug_level: req.debug_level,
+        });
+        if let Some(overrides) = &overrides {
+            let report = self.validation.validate(&overrides.apply(&existing_config));
+            if !report.is_valid() {
+                return Ok(Response::new(StartResponse {
+                    success: false,
+                    message: format!("Invalid configuration: {}", report.summary()),
+                    pid: 0,
+                    ready: false,
+                    services: vec![],
+                    operation_id: String::new(),
+                    attempts: 0,
+                    attempt_errors: vec![],
+                }));
+            }
+        }
+        self.clear_launch_overrides();
+        *self
+            .launch_overrides
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = overrides;
+
+        let result = self
+            .start_retry
+            .run(|_| async {
+                self.start_process(&config_path)
+                    .await
+                    .unwrap_or_else(|status| Err(status.message().to_string()))
+            })
+            .await;
+
+        let (pid, attempts) = match result {
+            Ok(started) => started,
+            Err(e) => {
+                self.clear_launch_overrides();
+                return Ok(Response::new(StartResponse {
+                    success: false,
+                    message: format!("Failed to start stunnel: {}", e),
+                    pid: 0,
+                    ready: false,
+                    services: vec![],
+                    operation_id: String::new(),
+                    attempts: u32::try_from(e.errors.len()).unwrap_or(u32::MAX),
+                    attempt_errors: e.history(),
+                }));
+            }
+        };
+
+        let services = self.wait_for_services(&config_path).await;
+        let retried = match attempts {
+            1 => String::new(),
+            attempts => format!(" on attempt {}", attempts),
+        };
+        let (ready, message) = match readiness::summary(&services, self.readiness_timeout) {
+            None => (true, format!("Stunnel started successfully{}", retried)),
+            Some(summary) => (
+                false,
+                format!("Stunnel started{}, but {}", retried, summary),
+            ),
+        };
+        Ok(Response::new(StartResponse {
+            success: ready,
+            message,
+            pid,
+            ready,
+            services: services.into_iter().map(proto_readiness).collect(),
+            operation_id: String::new(),
+            attempts,
+            attempt_errors: vec![],
+        }))
+    }
+
+    async fn list_providers(
+        &self,
+        request: Request<ListProvidersRequest>,
+    ) -> Result<Response<ListProvidersResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let content = match self.read_providers_config() {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    ListProvidersResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        providers: vec![],
+                        config_version: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let providers: Vec<Provider> = StunnelConfig::parse(&content)
+            .sections
+            .iter()
+            .map(provider_from_section)
+            .collect();
+
+        Ok(Response::new(redact::apply(
+            redact,
+            ListProvidersResponse {
+                success: true,
+                message: format!("Found {} provider(s)", providers.len()),
+                providers,
+                config_version: config_version(&content),
+            },
+        )))
+    }
+
+    async fn get_provider(
+        &self,
+        request: Request<GetProviderRequest>,
+    ) -> Result<Response<GetProviderResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let name = request.into_inner().provider_name;
+
+        if name.trim().is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                GetProviderResponse {
+                    success: false,
+                    message: "provider_name is required".to_string(),
+                    provider: None,
+                    options: vec![],
+                    config_version: String::new(),
+                },
+            )));
+        }
+
+        let content = match self.read_providers_config() {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    GetProviderResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        provider: None,
+                        options: vec![],
+                        config_version: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&content);
+        let section = match config.section(&name) {
+            Some(section) => section,
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    GetProviderResponse {
+                        success: false,
+                        message: format!("Provider {} not found in config", name),
+                        provider: None,
+                        options: vec![],
+                        config_version: String::new(),
+                    },
+                )));
+            }
+        };
+
+        Ok(Response::new(redact::apply(
+            redact,
+            GetProviderResponse {
+                success: true,
+                message: format!("Provider {} found", name),
+                provider: Some(provider_from_section(section)),
+                options: proto_options(&section.options),
+                config_version: config_version(&content),
+            },
+        )))
+    }
+
+    async fn update_provider(
+        &self,
+        request: Request<UpdateProviderRequest>,
+    ) -> Result<Response<UpdateProviderResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let name = req.provider_name;
+        let provider = req
+            .provider
+            .ok_or_else(|| Status::invalid_argument("Provider is required"))?;
+
+        if name.trim().is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                UpdateProviderResponse {
+                    success: false,
+                    message: "provider_name is required".to_string(),
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        let paths = update_mask_paths(req.update_mask).map_err(Status::invalid_argument)?;
+        let provider = normalize_provider(provider, |field| paths.iter().any(|path| path == field))
+            .map_err(|problems| Status::invalid_argument(invalid_provider(problems)))?;
+        self.check_connect_hosts(connect_hosts(&provider, |field| {
+            paths.iter().any(|path| path == field)
+        }))
+        .await
+        .map_err(|problems| Status::invalid_argument(format!("Invalid provider: {}", problems)))?;
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UpdateProviderResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&existing_config);
+        let section = match config.section(&name) {
+            Some(section) => section,
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UpdateProviderResponse {
+                        success: false,
+                        message: format!("Provider {} not found in config", name),
+                        updated_config: existing_config,
+                    },
+                )));
+            }
+        };
+        let updates = provider_section_updates(section, &provider, &paths)
+            .map_err(Status::invalid_argument)?;
+        if paths.iter().any(|path| path == "accept_port") {
+            let checked = self
+                .read_providers_config()
+                .map_err(|e| format!("Failed to read provider files: {}", e))
+                .and_then(|content| {
+                    let port = u16::try_from(provider.accept_port).unwrap_or_default();
+                    check_accept_port(&StunnelConfig::parse(&content), &name, port)
+                });
+            if let Err(message) = checked {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UpdateProviderResponse {
+                        success: false,
+                        message,
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        }
+        let updated_config = update_section(&existing_config, section, &updates);
+
+        if req.probe_backend {
+            let updated = StunnelConfig::parse(&updated_config)
+                .section(&name)
+                .map(provider_from_section)
+                .unwrap_or_default();
+            let probes = probe_backends(&updated, probe::DEFAULT_TIMEOUT).await;
+            if let Some(message) = unreachable_backends(&probes) {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UpdateProviderResponse {
+                        success: false,
+                        message,
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        }
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "UpdateProvider", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                UpdateProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            UpdateProviderResponse {
+                success: true,
+                message: format!("Provider {} updated successfully", name),
+                updated_config,
+            },
+        )))
+    }
+
+    async fn disable_provider(
+        &self,
+        request: Request<DisableProviderRequest>,
+    ) -> Result<Response<DisableProviderResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let name = req.provider_name;
+
+        if name.trim().is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                DisableProviderResponse {
+                    success: false,
+                    message: "provider_name is required".to_string(),
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    DisableProviderResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&existing_config);
+        let updated_config = match config.section(&name) {
+            Some(section) => disable_section(&existing_config, section),
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    DisableProviderResponse {
+                        success: false,
+                        message: format!("Provider {} not found in config", name),
+                        updated_config: existing_config,
+                    },
+                )));
+            }
+        };
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "DisableProvider", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                DisableProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            DisableProviderResponse {
+                success: true,
+                message: format!("Provider {} disabled successfully", name),
+                updated_config,
+            },
+        )))
+    }
+
+    async fn enable_provider(
+        &self,
+        request: Request<EnableProviderRequest>,
+    ) -> Result<Response<EnableProviderResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let name = req.provider_name;
+
+        if name.trim().is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                EnableProviderResponse {
+                    success: false,
+                    message: "provider_name is required".to_string(),
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    EnableProviderResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        // Re-enabling must not shadow a section added under the same name meanwhile
+        if StunnelConfig::parse(&existing_config)
+            .section(&name)
+            .is_some()
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                EnableProviderResponse {
+                    success: false,
+                    message: format!("Provider {} is already enabled", name),
+                    updated_config: existing_config,
+                },
+            )));
+        }
+
+        let updated_config = match enable_section(&existing_config, &name) {
+            Some(content) => content,
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    EnableProviderResponse {
+                        success: false,
+                        message: format!("Disabled provider {} not found in config", name),
+                        updated_config: existing_config,
+                    },
+                )));
+            }
+        };
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "EnableProvider", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                EnableProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            EnableProviderResponse {
+                success: true,
+                message: format!("Provider {} enabled successfully", name),
+                updated_config,
+            },
+        )))
+    }
+
+    async fn rename_provider(
+        &self,
+        request: Request<RenameProviderRequest>,
+    ) -> Result<Response<RenameProviderResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let old_name = req.old_name.trim().to_string();
+        let new_name = req.new_name.trim().to_string();
+
+        if old_name.is_empty() || new_name.is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RenameProviderResponse {
+                    success: false,
+                    message: "old_name and new_name are required".to_string(),
+                    updated_config: String::new(),
+                },
+            )));
+        }
+        if new_name.contains(['[', ']']) {
+            return Err(Status::invalid_argument(
+                "new_name must not contain '[' or ']'",
+            ));
+        }
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RenameProviderResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&existing_config);
+        if config.section(&new_name).is_some() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RenameProviderResponse {
+                    success: false,
+                    message: format!("Provider {} already exists in config", new_name),
+                    updated_config: existing_config,
+                },
+            )));
+        }
+        let updated_config = match config.section(&old_name) {
+            Some(section) => rename_section(&existing_config, section, &new_name),
+            None => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RenameProviderResponse {
+                        success: false,
+                        message: format!("Provider {} not found in config", old_name),
+                        updated_config: existing_config,
+                    },
+                )));
+            }
+        };
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "RenameProvider", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RenameProviderResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            RenameProviderResponse {
+                success: true,
+                message: format!("Provider {} renamed to {}", old_name, new_name),
+                updated_config,
+            },
+        )))
+    }
+
+    async fn add_providers(
+        &self,
+        request: Request<AddProvidersRequest>,
+    ) -> Result<Response<AddProvidersResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.providers.is_empty() {
+            return Err(Status::invalid_argument(
+                "At least one provider is required",
+            ));
+        }
+        let providers = normalize_providers(req.providers).map_err(Status::invalid_argument)?;
+        let hosts = providers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, provider)| {
+                connect_hosts(provider, |_| true)
+                    .into_iter()
+                    .map(move |(field, host)| (format!("providers[{}].{}", i, field), host))
+            })
+            .collect();
+        self.check_connect_hosts(hosts).await.map_err(|problems| {
+            Status::invalid_argument(format!("Invalid providers: {}", problems))
+        })?;
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    AddProvidersResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                        conflicts: vec![],
+                    },
+                )));
+            }
+        };
+
+        // Reject the whole batch if any name clashes with the config, a provider
+        // file, or another entry
+        let config = match self.read_providers_config() {
+            Ok(content) => StunnelConfig::parse(&content),
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    AddProvidersResponse {
+                        success: false,
+                        message: format!("Failed to read provider files: {}", e),
+                        updated_config: String::new(),
+                        conflicts: vec![],
+                    },
+                )));
+            }
+        };
+        let mut seen = HashSet::new();
+        let mut conflicts = Vec::new();
+        for provider in &providers {
+            if config.section(&provider.name).is_some() || !seen.insert(provider.name.as_str()) {
+                conflicts.push(provider.name.clone());
+            }
+        }
+        if !conflicts.is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                AddProvidersResponse {
+                    success: false,
+                    message: format!(
+                        "No providers added; conflicting names: {}",
+                        conflicts.join(", ")
+                    ),
+                    updated_config: String::new(),
+                    conflicts,
+                },
+            )));
+        }
+
+        // Accept ports must not clash with the config, the host or earlier entries
+        let mut taken = config.clone();
+        let mut port_conflicts = Vec::new();
+        for provider in &providers {
+            let new_section = render_provider_section(provider);
+            if let Ok(port) = u16::try_from(provider.accept_port) {
+                if let Err(e) = check_accept_port(&taken, &provider.name, port) {
+                    port_conflicts.push(format!("{}: {}", provider.name, e));
+                    conflicts.push(provider.name.clone());
+                }
+            }
+            taken
+                .sections
+                .extend(StunnelConfig::parse(&new_section).sections);
+        }
+        if !conflicts.is_empty() {
+            return Ok(Response::new(redact::apply(
+                redact,
+                AddProvidersResponse {
+                    success: false,
+                    message: format!(
+                        "No providers added; conflicting accept ports: {}",
+                        port_conflicts.join("; ")
+                    ),
+                    updated_config: String::new(),
+                    conflicts,
+                },
+            )));
+        }
+
+        let mut updated_config = existing_config.clone();
+        for provider in &providers {
+            let new_section = render_provider_section(provider);
+            updated_config = append_to_config(&updated_config, &new_section);
+        }
+
+        // Backup and write new config atomically
+        if let Err(message) = self.write_managed_config(&updated_config, "AddProviders", &caller) {
+            return Ok(Response::new(redact::apply(
+                redact,
+                AddProvidersResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    conflicts: vec![],
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            AddProvidersResponse {
+                success: true,
+                message: format!("{} provider(s) added successfully", providers.len()),
+                updated_config,
+                conflicts: vec![],
+            },
+        )))
+    }
+
+    async fn get_config(
+        &self,
+        request: Request<GetConfigRequest>,
+    ) -> Result<Response<GetConfigResponse>, Status> {
+        let redact = self.should_redact(&request);
+        // The providers directory is read alongside, so the parsed view is
+        // the one read_providers_config gives the rest of the server
+        let read = fs::read_to_string(&self.config_path).and_then(|content| {
+            let fragments = match &self.fragments {
+                Some(fragments) => fragments.read_each()?,
+                None => Vec::new(),
+            };
+            Ok((content, fragments))
+        });
+        let (raw_config, fragments) = match read {
+            Ok(read) => read,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    GetConfigResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        config_path: self.config_path.clone(),
+                        ..Default::default()
+                    },
+                )));
+            }
+        };
+
+        let mut merged = raw_config.clone();
+        for (_, content) in &fragments {
+            if !merged.is_empty() && !merged.ends_with('\n') {
+                merged.push('\n');
+            }
+            merged.push_str(content);
+        }
+        let config = StunnelConfig::parse(&merged);
+        Ok(Response::new(redact::apply(
+            redact,
+            GetConfigResponse {
+                success: true,
+                message: "Configuration read successfully".to_string(),
+                config_path: self.config_path.clone(),
+                global_options: proto_options(&config.globals),
+                providers: config.sections.iter().map(provider_from_section).collect(),
+                raw_config,
+                config_version: self.version_of(&self.config_path),
+                fragments: fragments
+                    .into_iter()
+                    .map(|(path, content)| ConfigFragment {
+                        path: path.display().to_string(),
+                        content,
+                    })
+                    .collect(),
+            },
+        )))
+    }
+
+    async fn validate_config_content(
+        &self,
+        request: Request<ValidateConfigContentRequest>,
+    ) -> Result<Response<ValidateConfigContentResponse>, Status> {
+        let content = request.into_inner().config_content;
+
+        // Key mismatches and broken chains are reported alongside stunnel's
+        // own errors, for the files GetCertificateInfo could read only
+        let parsed = StunnelConfig::parse(&content);
+        let (pairs, chains, unchecked) = self.checkable_certificates(&parsed);
+        let certificate_error = |message, blocking| ValidationError {
+            line: 0,
+            message,
+            validator: "certificates".to_string(),
+            severity: if blocking {
+                LintSeverity::Error
+            } else {
+                LintSeverity::Warning
+            } as i32,
+            blocking,
+        };
+        let certificate_errors: Vec<ValidationError> = certs::check_key_pairs(&pairs)
+            .into_iter()
+            .chain(certs::check_chains(&chains))
+            .chain(
+                Some(certs::check_key_permissions(&pairs))
+                    .filter(|_| self.key_permission_policy == KeyPermissionPolicy::Enforce)
+                    .into_iter()
+                    .flatten(),
+            )
+            .map(|message| certificate_error(message, true))
+            .collect();
+
+        let report = self.validation.validate(&content);
+        let valid = report.is_valid() && certificate_errors.is_empty();
+        let errors: Vec<ValidationError> = proto_validation_errors(&report)
+            .into_iter()
+            .chain(certificate_errors)
+            .chain(
+                unchecked
+                    .into_iter()
+                    .map(|message| certificate_error(message, false)),
+            )
+            .collect();
+
+        // A blocking validator that could not run leaves the verdict unknown
+        if let Some(failure) = report.failures.iter().find(|failure| failure.blocking) {
+            return Ok(Response::new(ValidateConfigContentResponse {
+                success: false,
+                message: format!(
+                    "Failed to run config validation: {} could not run: {}",
+                    failure.validator, failure.error
+                ),
+                valid: false,
+                errors,
+            }));
+        }
+
+        let blocking = errors.iter().filter(|error| error.blocking).count();
+        let message = if valid && errors.is_empty() {
+            "Configuration is valid".to_string()
+        } else if valid {
+            format!(
+                "Configuration is valid with {} advisory issue(s)",
+                errors.len()
+            )
+        } else {
+            format!("Configuration has {} error(s)", blocking)
+        };
+        Ok(Response::new(ValidateConfigContentResponse {
+            success: true,
+            message,
+            valid,
+            errors,
+        }))
+    }
+
+    async fn diff_config(
+        &self,
+        request: Request<DiffConfigRequest>,
+    ) -> Result<Response<DiffConfigResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let change = request
+            .into_inner()
+            .change
+            .ok_or_else(|| Status::invalid_argument("config_content or provider is required"))?;
+
+        let current_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    DiffConfigResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        ..Default::default()
+                    },
+                )));
+            }
+        };
+        let current = StunnelConfig::parse(&current_config);
+
+        // A provider change is previewed as a full update if it exists, otherwise as an add
+        let proposed_config = match change {
+            diff_config_request::Change::ConfigContent(content) => content,
+            diff_config_request::Change::Provider(provider) => {
+                // An existing provider keeps its name, as with UpdateProvider
+                let exists = current.section(provider.name.trim()).is_some();
+                let provider = normalize_provider(provider, |field| field != "name" || !exists)
+                    .map_err(|problems| Status::invalid_argument(invalid_provider(problems)))?;
+                match current.section(&provider.name) {
+                    Some(section) => {
+                        let all_fields: Vec<String> = UPDATABLE_PROVIDER_FIELDS
+                            .iter()
+                            .map(|field| field.to_string())
+                            .collect();
+                        let updates = provider_section_updates(section, &provider, &all_fields)
+                            .map_err(Status::invalid_argument)?;
+                        update_section(&current_config, section, &updates)
+                    }
+                    None => append_to_config(&current_config, &render_provider_section(&provider)),
+                }
+            }
+        };
+
+        let changes = section_changes(&current, &StunnelConfig::parse(&proposed_config));
+        let unified_diff = unified_diff(
+            &current_config,
+            &proposed_config,
+            &self.config_path,
+            "proposed",
+        );
+        let message = if unified_diff.is_empty() {
+            "No changes".to_string()
+        } else {
+            format!(
+                "{} added, {} removed, {} modified section(s)",
+                changes.added.len(),
+                changes.removed.len(),
+                changes.modified.len()
+            )
+        };
+
+        Ok(Response::new(redact::apply(
+            redact,
+            DiffConfigResponse {
+                success: true,
+                message,
+                unified_diff,
+                added_sections: changes.added,
+                removed_sections: changes.removed,
+                modified_sections: changes.modified,
+                globals_changed: changes.globals_changed,
+            },
+        )))
+    }
+
+    async fn list_backups(
+        &self,
+        _request: Request<ListBackupsRequest>,
+    ) -> Result<Response<ListBackupsResponse>, Status> {
+        match backup::list_backups(&self.config_path) {
+            Ok(backups) => Ok(Response::new(ListBackupsResponse {
+                success: true,
+                message: format!("Found {} backup(s)", backups.len()),
+                backups: backups
+                    .into_iter()
+                    .map(|backup| Backup {
+                        id: backup.id,
+                        path: backup.path,
+                        created_at: backup.created_at.to_rfc3339(),
+                        size_bytes: backup.size_bytes,
+                    })
+                    .collect(),
+            })),
+            Err(e) => Ok(Response::new(ListBackupsResponse {
+                success: false,
+                message: format!("Failed to list backups: {}", e),
+                backups: vec![],
+            })),
+        }
+    }
+
+    async fn restore_backup(
+        &self,
+        request: Request<RestoreBackupRequest>,
+    ) -> Result<Response<RestoreBackupResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.backup_id.trim().is_empty() {
+            return Err(Status::invalid_argument("backup_id is required"));
+        }
+
+        let restored_config =
+            match read_backup(&self.config_path, &req.backup_id, self.secrets.as_deref()) {
+                Ok(content) => content,
+                Err(e) => {
+                    return Ok(Response::new(redact::apply(
+                        redact,
+                        RestoreBackupResponse {
+                            success: false,
+                            message: format!("Failed to read backup: {}", e),
+                            restored_config: String::new(),
+                        },
+                    )));
+                }
+            };
+
+        // The current config is itself backed up first, so a restore can be undone
+        if let Err(message) = self.write_managed_config(&restored_config, "RestoreBackup", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RestoreBackupResponse {
+                    success: false,
+                    message,
+                    restored_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            RestoreBackupResponse {
+                success: true,
+                message: format!("Backup {} restored successfully", req.backup_id),
+                restored_config,
+            },
+        )))
+    }
+
+    async fn prune_backups(
+        &self,
+        request: Request<PruneBackupsRequest>,
+    ) -> Result<Response<PruneBackupsResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+
+        // Without explicit limits, apply the server's configured retention policy
+        let policy = if req.keep_count == 0 && req.older_than_days == 0 {
+            self.backup_policy
+        } else {
+            RetentionPolicy {
+                max_count: (req.keep_count > 0).then_some(req.keep_count as usize),
+                max_age: (req.older_than_days > 0)
+                    .then(|| chrono::Duration::days(i64::from(req.older_than_days))),
+            }
+        };
+
+        match backup::prune_backups(&self.config_path, &policy) {
+            Ok(removed_ids) => Ok(Response::new(PruneBackupsResponse {
+                success: true,
+                message: format!("Removed {} backup(s)", removed_ids.len()),
+                removed_ids,
+            })),
+            Err(e) => Ok(Response::new(PruneBackupsResponse {
+                success: false,
+                message: format!("Failed to prune backups: {}", e),
+                removed_ids: vec![],
+            })),
+        }
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<GetHistoryRequest>,
+    ) -> Result<Response<GetHistoryResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let limit = match request.into_inner().limit {
+            0 => DEFAULT_HISTORY_LIMIT,
+            limit => limit,
+        };
+
+        let result = self.history_store().and_then(|store| {
+            store
+                .list(&self.config_path, limit)
+                .map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(revisions) => Ok(Response::new(redact::apply(
+                redact,
+                GetHistoryResponse {
+                    success: true,
+                    message: format!("Found {} revision(s)", revisions.len()),
+                    revisions: revisions
+                        .into_iter()
+                        .map(|revision| proto_revision(revision, false))
+                        .collect(),
+                },
+            ))),
+            Err(e) => Ok(Response::new(redact::apply(
+                redact,
+                GetHistoryResponse {
+                    success: false,
+                    message: format!("Failed to read history: {}", e),
+                    revisions: vec![],
+                },
+            ))),
+        }
+    }
+
+    async fn get_revision(
+        &self,
+        request: Request<GetRevisionRequest>,
+    ) -> Result<Response<GetRevisionResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let revision_id = request.into_inner().revision_id;
+        if revision_id <= 0 {
+            return Err(Status::invalid_argument("revision_id is required"));
+        }
+
+        let result = self
+            .history_store()
+            .and_then(|store| store.get(revision_id).map_err(|e| e.to_string()))
+            .map(|revision| revision.filter(|r| self.may_read_revision(&r.config_path)));
+        match result {
+            Ok(Some(revision)) => Ok(Response::new(redact::apply(
+                redact,
+                GetRevisionResponse {
+                    success: true,
+                    message: format!("Found revision {}", revision_id),
+                    revision: Some(proto_revision(revision, true)),
+                },
+            ))),
+            Ok(None) => Ok(Response::new(redact::apply(
+                redact,
+                GetRevisionResponse {
+                    success: false,
+                    message: format!("Revision {} not found", revision_id),
+                    revision: None,
+                },
+            ))),
+            Err(e) => Ok(Response::new(redact::apply(
+                redact,
+                GetRevisionResponse {
+                    success: false,
+                    message: format!("Failed to read history: {}", e),
+                    revision: None,
+                },
+            ))),
+        }
+    }
+
+    async fn rollback_revision(
+        &self,
+        request: Request<RollbackRevisionRequest>,
+    ) -> Result<Response<RollbackRevisionResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let store = match self.history_store() {
+            Ok(store) => store,
+            Err(message) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RollbackRevisionResponse {
+                        success: false,
+                        message,
+                        restored_revision_id: 0,
+                        restored_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        let lookup = match req.target {
+            Some(rollback_revision_request::Target::RevisionId(id)) if id > 0 => store
+                .get(id)
+                .map(|revision| revision.filter(|r| r.config_path == self.config_path)),
+            Some(rollback_revision_request::Target::Generations(generations))
+                if generations > 0 =>
+            {
+                // The latest revision is the current config, so skip past it
+                store
+                    .list(&self.config_path, generations + 1)
+                    .map(|revisions| revisions.into_iter().nth(generations as usize))
+            }
+            _ => {
+                return Err(Status::invalid_argument(
+                    "a positive revision_id or generations is required",
+                ));
+            }
+        };
+
+        let revision = match lookup {
+            Ok(Some(revision)) => revision,
+            Ok(None) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RollbackRevisionResponse {
+                        success: false,
+                        message: "Requested revision not found in history".to_string(),
+                        restored_revision_id: 0,
+                        restored_config: String::new(),
+                    },
+                )));
+            }
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RollbackRevisionResponse {
+                        success: false,
+                        message: format!("Failed to read history: {}", e),
+                        restored_revision_id: 0,
+                        restored_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        if let Err(message) =
+            self.write_managed_config(&revision.content, "RollbackRevision", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RollbackRevisionResponse {
+                    success: false,
+                    message,
+                    restored_revision_id: 0,
+                    restored_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            RollbackRevisionResponse {
+                success: true,
+                message: format!("Rolled back to revision {}", revision.id),
+                restored_revision_id: revision.id,
+                restored_config: revision.content,
+            },
+        )))
+    }
+
+    async fn rollback_to_commit(
+        &self,
+        request: Request<RollbackToCommitRequest>,
+    ) -> Result<Response<RollbackToCommitResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.commit.trim().is_empty() {
+            return Err(Status::invalid_argument("commit is required"));
+        }
+
+        let Some(git) = &self.git else {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RollbackToCommitResponse {
+                    success: false,
+                    message: "Git versioning is not enabled (set GIT_VERSIONING=true)".to_string(),
+                    commit: String::new(),
+                    restored_config: String::new(),
+                },
+            )));
+        };
+
+        let restored = git.resolve(req.commit.trim()).and_then(|commit| {
+            let content = git.file_at(&commit, &self.config_path)?;
+            Ok((commit, content))
+        });
+        let (commit, restored_config) = match restored {
+            Ok(restored) => restored,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    RollbackToCommitResponse {
+                        success: false,
+                        message: format!("Failed to read config at commit: {}", e),
+                        commit: String::new(),
+                        restored_config: String::new(),
+                    },
+                )));
+            }
+        };
+
+        if let Err(message) =
+            self.write_managed_config(&restored_config, "RollbackToCommit", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                RollbackToCommitResponse {
+                    success: false,
+                    message,
+                    commit: String::new(),
+                    restored_config: String::new(),
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            RollbackToCommitResponse {
+                success: true,
+                message: format!("Config restored from commit {}", commit),
+                commit,
+                restored_config,
+            },
+        )))
+    }
+
+    async fn export_config(
+        &self,
+        request: Request<ExportConfigRequest>,
+    ) -> Result<Response<ExportConfigResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let format =
+            structured_format(request.into_inner().format).map_err(Status::invalid_argument)?;
+
+        let raw_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(ExportConfigResponse {
+                    success: false,
+                    message: format!("Failed to read config: {}", e),
+                    content: String::new(),
+                }));
+            }
+        };
+
+        // Masked before conversion, since JSON and YAML are not line-based
+        let raw_config = if redact {
+            redact_config(&raw_config)
+        } else {
+            raw_config
+        };
+        let structured = StructuredConfig::from_config(&StunnelConfig::parse(&raw_config));
+        match structured.serialize(format) {
+            Ok(content) => Ok(Response::new(ExportConfigResponse {
+                success: true,
+                message: format!(
+                    "Exported {} provider(s) as {}",
+                    structured.providers.len(),
+                    format
+                ),
+                content,
+            })),
+            Err(e) => Ok(Response::new(ExportConfigResponse {
+                success: false,
+                message: format!("Failed to export config: {}", e),
+                content: String::new(),
+            })),
+        }
+    }
+
+    async fn import_config(
+        &self,
+        request: Request<ImportConfigRequest>,
+    ) -> Result<Response<ImportConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let format = structured_format(req.format).map_err(Status::invalid_argument)?;
+        let structured = StructuredConfig::deserialize(&req.content, format)
+            .map_err(|e| Status::invalid_argument(format!("Invalid {} document: {}", format, e)))?;
+        let updated_config = structured.render();
+
+        // Reject content with blocking issues; validators that cannot run only warn
+        let report = self.validation.validate(&updated_config);
+        if report.has_blocking_issues() {
+            let errors = proto_validation_errors(&report);
+            return Ok(Response::new(redact::apply(
+                redact,
+                ImportConfigResponse {
+                    success: false,
+                    message: format!(
+                        "Imported configuration has {} error(s)",
+                        errors.iter().filter(|error| error.blocking).count()
+                    ),
+                    updated_config,
+                    errors,
+                },
+            )));
+        }
+        for failure in &report.failures {
+            warn!(
+                "Validator {} could not run: {}",
+                failure.validator,
+                redact_config(&failure.error)
+            );
+        }
+
+        // The current config is backed up before being replaced
+        if let Err(message) = self.write_managed_config(&updated_config, "ImportConfig", &caller) {
+            return Ok(Response::new(redact::apply(
+                redact,
+                ImportConfigResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    errors: vec![],
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            ImportConfigResponse {
+                success: true,
+                message: format!(
+                    "Imported {} global option(s) and {} provider(s)",
+                    structured.globals.len(),
+                    structured.providers.len()
+                ),
+                updated_config,
+                errors: vec![],
+            },
+        )))
+    }
+
+    async fn register_template(
+        &self,
+        request: Request<RegisterTemplateRequest>,
+    ) -> Result<Response<RegisterTemplateResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let req = request.into_inner();
+        if req.name.trim().is_empty() {
+            return Err(Status::invalid_argument("name is required"));
+        }
+        if !StunnelConfig::parse(&req.content).sections.is_empty() {
+            return Err(Status::invalid_argument(
+                "Templates hold a section body and cannot contain section headers",
+            ));
+        }
+
+        match self.templates.register(&req.name, &req.content) {
+            Ok(()) => Ok(Response::new(redact::apply(
+                redact,
+                RegisterTemplateResponse {
+                    success: true,
+                    message: format!("Template {} registered successfully", req.name),
+                    template: Some(proto_template(req.name, req.content)),
+                },
+            ))),
+            Err(e) => Ok(Response::new(redact::apply(
+                redact,
+                RegisterTemplateResponse {
+                    success: false,
+                    message: format!("Failed to register template: {}", e),
+                    template: None,
+                },
+            ))),
+        }
+    }
+
+    async fn list_templates(
+        &self,
+        request: Request<ListTemplatesRequest>,
+    ) -> Result<Response<ListTemplatesResponse>, Status> {
+        let redact = self.should_redact(&request);
+        let templates: Vec<ProviderTemplate> = self
+            .templates
+            .list()
+            .into_iter()
+            .map(|(name, content)| proto_template(name, content))
+            .collect();
+
+        Ok(Response::new(redact::apply(
+            redact,
+            ListTemplatesResponse {
+                success: true,
+                message: format!("Found {} template(s)", templates.len()),
+                templates,
+            },
+        )))
+    }
+
+    async fn add_provider_from_template(
+        &self,
+        request: Request<AddProviderFromTemplateRequest>,
+    ) -> Result<Response<AddProviderFromTemplateResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let name = req.provider_name.trim().to_string();
+        if name.is_empty() || name.contains(['[', ']']) {
+            return Err(Status::invalid_argument(
+                "provider_name is required and cannot contain brackets",
+            ));
+        }
+        // A value spanning lines could smuggle extra options or sections into the config
+        if let Some((key, _)) = req
+            .vars
+            .iter()
+            .find(|(_, value)| value.contains(['\n', '\r']))
+        {
+            return Err(Status::invalid_argument(format!(
+                "Variable {} cannot span multiple lines",
+                key
+            )));
+        }
+
+        let Some(template) = self.templates.get(&req.template) else {
+            return Ok(Response::new(redact::apply(
+                redact,
+                AddProviderFromTemplateResponse {
+                    success: false,
+                    message: format!("Template {} not found", req.template),
+                    updated_config: String::new(),
+                    missing_vars: vec![],
+                },
+            )));
+        };
+
+        let mut vars = req.vars;
+        vars.insert("name".to_string(), name.clone());
+        let body = match templates::render(&template, &vars) {
+            Ok(body) => body,
+            Err(missing_vars) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    AddProviderFromTemplateResponse {
+                        success: false,
+                        message: format!("Missing template variables: {}", missing_vars.join(", ")),
+                        updated_config: String::new(),
+                        missing_vars,
+                    },
+                )));
+            }
+        };
+
+        let mut new_section = format!("\n; {} service\n[{}]\n", name, name);
+        new_section.push_str(body.trim_matches('\n'));
+        new_section.push('\n');
+
+        match self.add_provider_section(&name, &new_section, "AddProviderFromTemplate", &caller) {
+            Ok(updated_config) => {
+                if req.apply_immediately {
+                    self.reload_if_running();
+                }
+                Ok(Response::new(redact::apply(
+                    redact,
+                    AddProviderFromTemplateResponse {
+                        success: true,
+                        message: format!("Provider {} added from template {}", name, req.template),
+                        updated_config,
+                        missing_vars: vec![],
+                    },
+                )))
+            }
+            Err(message) => Ok(Response::new(redact::apply(
+                redact,
+                AddProviderFromTemplateResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    missing_vars: vec![],
+                },
+            ))),
+        }
+    }
+
+    async fn lint_config(
+        &self,
+        request: Request<LintConfigRequest>,
+    ) -> Result<Response<LintConfigResponse>, Status> {
+        let mut content = request.into_inner().config_content;
+        if content.is_empty() {
+            content = match fs::read_to_string(&self.config_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    return Ok(Response::new(LintConfigResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        findings: vec![],
+                    }));
+                }
+            };
+        }
+
+        let findings = lint(&StunnelConfig::parse(&content));
+        Ok(Response::new(LintConfigResponse {
+            success: true,
+            message: format!("Found {} issue(s)", findings.len()),
+            findings: findings.into_iter().map(proto_finding).collect(),
+        }))
+    }
+
+    async fn set_debug_level(
+        &self,
+        request: Request<SetDebugLevelRequest>,
+    ) -> Result<Response<SetDebugLevelResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.level > 7 {
+            return Err(Status::invalid_argument("level must be between 0 and 7"));
+        }
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(SetDebugLevelResponse {
+                    success: false,
+                    message: format!("Failed to read existing config: {}", e),
+                    previous_level: String::new(),
+                    reloaded: false,
+                }));
+            }
+        };
+
+        // Keep the syslog facility of `debug = facility.level`
+        let previous_level = get_global_option(&existing_config, "debug").unwrap_or_default();
+        let value = match previous_level.rsplit_once('.') {
+            Some((facility, _)) => format!("{}.{}", facility, req.level),
+            None => req.level.to_string(),
+        };
+        let updated_config = set_global_option(&existing_config, "debug", &value);
+
+        if let Err(message) = self.write_managed_config(&updated_config, "SetDebugLevel", &caller) {
+            return Ok(Response::new(SetDebugLevelResponse {
+                success: false,
+                message,
+                previous_level,
+                reloaded: false,
+            }));
+        }
+
+        // The level set here replaces one StartStunnel gave for this run
+        if let Some(overrides) = self
+            .launch_overrides
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+        {
+            overrides.debug_level = None;
+        }
+        let reloaded = match self.stunnel_pid() {
+            Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+            _ => false,
+        };
+
+        Ok(Response::new(SetDebugLevelResponse {
+            success: true,
+            message: if reloaded {
+                format!("Debug level set to {} and stunnel reloaded", value)
+            } else {
+                format!("Debug level set to {}; stunnel is not running", value)
+            },
+            previous_level,
+            reloaded,
+        }))
+    }
+
+    async fn upload_crl(
+        &self,
+        request: Request<UploadCrlRequest>,
+    ) -> Result<Response<UploadCrlResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if !req.crl_content.contains(CRL_PEM_HEADER) {
+            return Err(Status::invalid_argument(
+                "crl_content must be a PEM-encoded X509 CRL",
+            ));
+        }
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UploadCrlResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        path: String::new(),
+                        updated_config: String::new(),
+                        reloaded: false,
+                    },
+                )));
+            }
+        };
+
+        let config = StunnelConfig::parse(&existing_config);
+        let section = if req.provider_name.is_empty() {
+            None
+        } else {
+            match config.section(&req.provider_name) {
+                Some(section) => Some(section),
+                None => {
+                    return Ok(Response::new(redact::apply(
+                        redact,
+                        UploadCrlResponse {
+                            success: false,
+                            message: format!("Provider {} not found in config", req.provider_name),
+                            path: String::new(),
+                            updated_config: existing_config,
+                            reloaded: false,
+                        },
+                    )));
+                }
+            }
+        };
+        let current_path = match section {
+            Some(section) => section.get("CRLfile"),
+            None => config.global("CRLfile"),
+        };
+        let path = match (req.path.is_empty(), current_path) {
+            (false, _) => req.path.clone(),
+            (true, Some(current)) => current.to_string(),
+            (true, None) => {
+                return Err(Status::invalid_argument(
+                    "path is required when no CRLfile is configured",
+                ));
+            }
+        };
+
+        if let Err(e) = atomic_write(&path, &req.crl_content) {
+            return Ok(Response::new(redact::apply(
+                redact,
+                UploadCrlResponse {
+                    success: false,
+                    message: format!("Failed to write CRL file: {}", e),
+                    path,
+                    updated_config: existing_config,
+                    reloaded: false,
+                },
+            )));
+        }
+
+        // Point CRLfile at the new file unless it already does
+        let mut updated_config = existing_config.clone();
+        if current_path != Some(path.as_str()) {
+            updated_config = match section {
+                Some(section) => update_section(
+                    &existing_config,
+                    section,
+                    &[("CRLfile", Some(path.clone()))],
+                ),
+                None => set_global_option(&existing_config, "CRLfile", &path),
+            };
+            if let Err(message) = self.write_managed_config(&updated_config, "UploadCrl", &caller) {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    UploadCrlResponse {
+                        success: false,
+                        message,
+                        path,
+                        updated_config: existing_config,
+                        reloaded: false,
+                    },
+                )));
+            }
+        }
+
+        let reloaded = match self.stunnel_pid() {
+            Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+            _ => false,
+        };
+
+        Ok(Response::new(redact::apply(
+            redact,
+            UploadCrlResponse {
+                success: true,
+                message: format!("CRL written to {}", path),
+                path,
+                updated_config,
+                reloaded,
+            },
+        )))
+    }
+
+    async fn get_certificate_info(
+        &self,
+        request: Request<GetCertificateInfoRequest>,
+    ) -> Result<Response<GetCertificateInfoResponse>, Status> {
+        let req = request.into_inner();
+        let path = match (req.path.is_empty(), req.provider_name.is_empty()) {
+            (false, true) => self
+                .resolve_certificate_path(&req.path)
+                .map_err(Status::permission_denied)?,
+            (true, false) => match self.provider_cert_path(&req.provider_name) {
+                Ok(path) => path,
+                Err(message) => {
+                    return Ok(Response::new(GetCertificateInfoResponse {
+                        success: false,
+                        message,
+                        path: String::new(),
+                        certificates: vec![],
+                    }));
+                }
+            },
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Exactly one of path or provider_name is required",
+                ));
+            }
+        };
+
+        match certs::read_certificates(&path) {
+            Ok(certificates) => Ok(Response::new(GetCertificateInfoResponse {
+                success: true,
+                message: format!("Found {} certificate(s)", certificates.len()),
+                path,
+                certificates: certificates.into_iter().map(proto_certificate).collect(),
+            })),
+            Err(message) => Ok(Response::new(GetCertificateInfoResponse {
+                success: false,
+                message,
+                path,
+                certificates: vec![],
+            })),
+        }
+    }
+
+    async fn get_certificate_status(
+        &self,
+        request: Request<GetCertificateStatusRequest>,
+    ) -> Result<Response<GetCertificateStatusResponse>, Status> {
+        let req = request.into_inner();
+        let warning_days = self.expiry.warning_days();
+
+        let report = match self.expiry.latest() {
+            Some(report) if !req.refresh => report,
+            _ => match self.read_providers_config() {
+                Ok(content) => self.expiry.check(&StunnelConfig::parse(&content)),
+                Err(e) => {
+                    return Ok(Response::new(GetCertificateStatusResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        checked_at: String::new(),
+                        warning_days,
+                        certificates: vec![],
+                    }));
+                }
+            },
+        };
+
+        let expiring = report
+            .certificates
+            .iter()
+            .filter(|status| status.is_expiring(warning_days))
+            .count();
+        Ok(Response::new(GetCertificateStatusResponse {
+            success: true,
+            message: format!(
+                "{} certificate file(s), {} expiring within {} days",
+                report.certificates.len(),
+                expiring,
+                warning_days
+            ),
+            checked_at: report.checked_at.to_rfc3339(),
+            warning_days,
+            certificates: report
+                .certificates
+                .into_iter()
+                .map(|status| proto_certificate_status(status, warning_days))
+                .collect(),
+        }))
+    }
+
+    async fn generate_csr(
+        &self,
+        request: Request<GenerateCsrRequest>,
+    ) -> Result<Response<GenerateCsrResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let key_type = key_type(req.key_type).map_err(Status::invalid_argument)?;
+        let store = match self.cert_store() {
+            Ok(store) => store,
+            Err(message) => {
+                return Ok(Response::new(GenerateCsrResponse {
+                    success: false,
+                    message,
+                    key_path: String::new(),
+                    csr_pem: String::new(),
+                }));
+            }
+        };
+
+        let subject = Subject {
+            common_name: req.common_name,
+            organization: req.organization,
+            organizational_unit: req.organizational_unit,
+            locality: req.locality,
+            state: req.state,
+            country: req.country,
+        };
+        match store.generate_csr(&req.name, key_type, &subject, &req.sans, req.overwrite) {
+            Ok((key_path, csr_pem)) => Ok(Response::new(GenerateCsrResponse {
+                success: true,
+                message: format!("Generated key {} and CSR", key_path.display()),
+                key_path: key_path.to_string_lossy().into_owned(),
+                csr_pem,
+            })),
+            Err(message) => Ok(Response::new(GenerateCsrResponse {
+                success: false,
+                message,
+                key_path: String::new(),
+                csr_pem: String::new(),
+            })),
+        }
+    }
+
+    async fn upload_certificate(
+        &self,
+        request: Request<UploadCertificateRequest>,
+    ) -> Result<Response<UploadCertificateResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        if req.cert_pem.is_empty() {
+            return Err(Status::invalid_argument("cert_pem is required"));
+        }
+        let failure = |message: String| {
+            Response::new(UploadCertificateResponse {
+                success: false,
+                message,
+                cert_path: String::new(),
+                key_path: String::new(),
+                chain_path: String::new(),
+            })
+        };
+        let store = match self.cert_store() {
+            Ok(store) => store,
+            Err(message) => return Ok(failure(message)),
+        };
+
+        let stored = match store.store(
+            &req.name,
+            &req.cert_pem,
+            Some(req.key_pem.as_str()).filter(|pem| !pem.is_empty()),
+            Some(req.chain_pem.as_str()).filter(|pem| !pem.is_empty()),
+            req.overwrite,
+        ) {
+            Ok(stored) => stored,
+            Err(message) => return Ok(failure(message)),
+        };
+
+        let display = |path: Option<&Path>| {
+            path.map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        Ok(Response::new(UploadCertificateResponse {
+            success: true,
+            message: format!("Stored certificate {}", stored.cert_path.display()),
+            cert_path: display(Some(&stored.cert_path)),
+            key_path: display(stored.key_path.as_deref()),
+            chain_path: display(stored.chain_path.as_deref()),
+        }))
+    }
+
+    async fn list_certificates(
+        &self,
+        _request: Request<ListCertificatesRequest>,
+    ) -> Result<Response<ListCertificatesResponse>, Status> {
+        let content = match self.read_providers_config() {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(ListCertificatesResponse {
+                    success: false,
+                    message: format!("Failed to read config: {}", e),
+                    certificates: vec![],
+                }));
+            }
+        };
+
+        match certstore::inventory(&StunnelConfig::parse(&content), self.cert_store.as_ref()) {
+            Ok(entries) => Ok(Response::new(ListCertificatesResponse {
+                success: true,
+                message: format!("Found {} certificate file(s)", entries.len()),
+                certificates: entries.into_iter().map(proto_certificate_entry).collect(),
+            })),
+            Err(message) => Ok(Response::new(ListCertificatesResponse {
+                success: false,
+                message,
+                certificates: vec![],
+            })),
+        }
+    }
+
+    async fn import_pkcs12(
+        &self,
+        request: Request<ImportPkcs12Request>,
+    ) -> Result<Response<ImportPkcs12Response>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.bundle.is_empty() {
+            return Err(Status::invalid_argument("bundle is required"));
+        }
+        let failure = |message: String| {
+            Response::new(redact::apply(
+                redact,
+                ImportPkcs12Response {
+                    success: false,
+                    message,
+                    cert_path: String::new(),
+                    key_path: String::new(),
+                    chain_path: String::new(),
+                    updated_config: String::new(),
+                },
+            ))
+        };
+        let store = match self.cert_store() {
+            Ok(store) => store,
+            Err(message) => return Ok(failure(message)),
+        };
+
+        // Check the provider exists before writing any files
+        let existing_config = if req.provider_name.is_empty() {
+            None
+        } else {
+            let content = match fs::read_to_string(&self.config_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    return Ok(failure(format!("Failed to read existing config: {}", e)));
+                }
+            };
+            if StunnelConfig::parse(&content)
+                .section(&req.provider_name)
+                .is_none()
+            {
+                return Ok(failure(format!(
+                    "Provider {} not found in config",
+                    req.provider_name
+                )));
+            }
+            Some(content)
+        };
+
+        let stored =
+            match store.import_pkcs12(&req.name, &req.bundle, &req.passphrase, req.overwrite) {
+                Ok(stored) => stored,
+                Err(message) => return Ok(failure(message)),
+            };
+        let cert_path = stored.cert_path.to_string_lossy().into_owned();
+        // A provider pointed at a missing key would fail on the next reload
+        let Some(key_path) = stored
+            .key_path
+            .map(|path| path.to_string_lossy().into_owned())
+        else {
+            return Ok(failure("PKCS#12 bundle holds no private key".to_string()));
+        };
+        let chain_path = stored
+            .chain_path
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut updated_config = String::new();
+        if let Some(existing_config) = existing_config {
+            let config = StunnelConfig::parse(&existing_config);
+            if let Some(section) = config.section(&req.provider_name) {
+                updated_config = update_section(
+                    &existing_config,
+                    section,
+                    &[
+                        ("cert", Some(cert_path.clone())),
+                        ("key", Some(key_path.clone())),
+                    ],
+                );
+                if let Err(message) =
+                    self.write_managed_config(&updated_config, "ImportPkcs12", &caller)
+                {
+                    return Ok(failure(message));
+                }
+                if req.apply_immediately {
+                    self.reload_if_running();
+                }
+            }
+        }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            ImportPkcs12Response {
+                success: true,
+                message: format!("Imported PKCS#12 bundle as {}", cert_path),
+                cert_path,
+                key_path,
+                chain_path,
+                updated_config,
+            },
+        )))
+    }
+
+    async fn verify_key_pair(
+        &self,
+        request: Request<VerifyKeyPairRequest>,
+    ) -> Result<Response<VerifyKeyPairResponse>, Status> {
+        let req = request.into_inner();
+        let pairs = if req.provider_name.is_empty() {
+            if req.cert_path.is_empty() {
+                return Err(Status::invalid_argument(
+                    "cert_path or provider_name is required",
+                ));
+            }
+            let key = if req.key_path.is_empty() {
+                req.cert_path.clone()
+            } else {
+                req.key_path
+            };
+            let cert = self
+                .resolve_certificate_path(&req.cert_path)
+                .map_err(Status::permission_denied)?;
+            let key = self
+                .resolve_certificate_path(&key)
+                .map_err(Status::permission_denied)?;
+            vec![(cert, key)]
+        } else {
+            let content = match self.read_providers_config() {
+                Ok(content) => content,
+                Err(e) => {
+                    return Ok(Response::new(VerifyKeyPairResponse {
+                        success: false,
+                        message: format!("Failed to read config: {}", e),
+                        matches: false,
+                        cert_path: String::new(),
+                        key_path: String::new(),
+                    }));
+                }
+            };
+            certs::key_pairs(&StunnelConfig::parse(&content))
+                .into_iter()
+                .filter(|pair| pair.section.as_deref() == Some(req.provider_name.as_str()))
+                .map(|pair| (pair.cert, pair.key))
+                .collect()
+        };
+
+        let Some((cert_path, key_path)) = pairs.into_iter().next() else {
+            return Ok(Response::new(VerifyKeyPairResponse {
+                success: false,
+                message: format!(
+                    "Provider {} has no certificate and key file configured",
+                    req.provider_name
+                ),
+                matches: false,
+                cert_path: String::new(),
+                key_path: String::new(),
+            }));
+        };
+
+        let (matches, message) = match certs::verify_key_pair(&cert_path, &key_path) {
+            Ok(()) => (true, "Private key matches the certificate".to_string()),
+            Err(message) => (false, message),
+        };
+        Ok(Response::new(VerifyKeyPairResponse {
+            success: true,
+            message,
+            matches,
+            cert_path,
+            key_path,
+        }))
+    }
+
+    async fn verify_chain(
+        &self,
+        request: Request<VerifyChainRequest>,
+    ) -> Result<Response<VerifyChainResponse>, Status> {
+        let req = request.into_inner();
+        let non_empty = |value: String| Some(value).filter(|v| !v.is_empty());
+        let failure = |message: String| {
+            Response::new(VerifyChainResponse {
+                success: false,
+                message,
+                valid: false,
+                cert_path: String::new(),
+                ca_file: String::new(),
+                ca_path: String::new(),
+            })
+        };
+
+        let check = if req.provider_name.is_empty() {
+            if req.cert_path.is_empty() {
+                return Err(Status::invalid_argument(
+                    "cert_path or provider_name is required",
+                ));
+            }
+            let resolve = |path: String| self.resolve_certificate_path(&path);
+            certs::ChainCheck {
+                section: None,
+                cert: resolve(req.cert_path).map_err(Status::permission_denied)?,
+                ca_file: non_empty(req.ca_file)
+                    .map(resolve)
+                    .transpose()
+                    .map_err(Status::permission_denied)?,
+                ca_path: non_empty(req.ca_path)
+                    .map(resolve)
+                    .transpose()
+                    .map_err(Status::permission_denied)?,
+            }
+        } else {
+            let content = match self.read_providers_config() {
+                Ok(content) => content,
+                Err(e) => return Ok(failure(format!("Failed to read config: {}", e))),
+            };
+            match certs::chain_checks(&StunnelConfig::parse(&content))
+                .into_iter()
+                .find(|check| check.section.as_deref() == Some(req.provider_name.as_str()))
+            {
+                Some(check) => check,
+                None => {
+                    return Ok(failure(format!(
+                        "Provider {} has no certificate with a CAfile or CApath configured",
+                        req.provider_name
+                    )))
+                }
+            }
+        };
+        if check.ca_file.is_none() && check.ca_path.is_none() {
+            return Err(Status::invalid_argument("ca_file or ca_path is required"));
+        }
+
+        let (valid, message) = match certs::verify_chain(
+            &check.cert,
+            check.ca_file.as_deref(),
+            check.ca_path.as_deref(),
+        ) {
+            Ok(()) => (true, "Certificate chain is valid".to_string()),
+            Err(message) => (false, message),
+        };
+        Ok(Response::new(VerifyChainResponse {
+            success: true,
+            message,
+            valid,
+            cert_path: check.cert,
+            ca_file: check.ca_file.unwrap_or_default(),
+            ca_path: check.ca_path.unwrap_or_default(),
+        }))
+    }
+
+    async fn enable_acme(
+        &self,
+        request: Request<EnableAcmeRequest>,
+    ) -> Result<Response<EnableAcmeResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.provider_name.is_empty() {
+            return Err(Status::invalid_argument("provider_name is required"));
+        }
+        if req.domains.is_empty() {
+            return Err(Status::invalid_argument("at least one domain is required"));
+        }
+        let failure = |message: String| {
+            Response::new(redact::apply(
+                redact,
+                EnableAcmeResponse {
+                    success: false,
+                    message,
+                    cert_path: String::new(),
+                    key_path: String::new(),
+                    not_after: String::new(),
+                    updated_config: String::new(),
+                    reloaded: false,
+                },
+            ))
+        };
+        let acme = match self.acme() {
+            Ok(acme) => acme,
+            Err(message) => return Ok(failure(message)),
+        };
+
+        // Check the provider exists before placing an order
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => return Ok(failure(format!("Failed to read existing config: {}", e))),
+        };
+        if StunnelConfig::parse(&existing_config)
+            .section(&req.provider_name)
+            .is_none()
+        {
+            return Ok(failure(format!(
+                "Provider {} not found in config",
+                req.provider_name
+            )));
+        }
+
+        let issued = match acme.issue(&req.provider_name, &req.domains).await {
+            Ok(issued) => issued,
+            Err(message) => return Ok(failure(message)),
+        };
+        if let Err(message) = acme.register(&req.provider_name, &req.domains) {
+            return Ok(failure(message));
+        }
+        let cert_path = issued.cert_path.to_string_lossy().into_owned();
+        let key_path = issued.key_path.to_string_lossy().into_owned();
+
+        // Re-read the config, since issuance can take a while
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => return Ok(failure(format!("Failed to read existing config: {}", e))),
+        };
+        let config = StunnelConfig::parse(&existing_config);
+        let Some(section) = config.section(&req.provider_name) else {
+            return Ok(failure(format!(
+                "Provider {} was removed while the certificate was issued",
+                req.provider_name
+            )));
+        };
+        let updated_config = update_section(
+            &existing_config,
+            section,
+            &[
+                ("cert", Some(cert_path.clone())),
+                ("key", Some(key_path.clone())),
+            ],
+        );
+        if let Err(message) = self.write_managed_config(&updated_config, "EnableAcme", &caller) {
+            return Ok(failure(message));
+        }
+        let reloaded = match self.stunnel_pid() {
+            Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+            _ => false,
+        };
+
+        Ok(Response::new(redact::apply(
+            redact,
+            EnableAcmeResponse {
+                success: true,
+                message: format!(
+                    "Issued certificate for {} and enabled ACME renewal",
+                    req.domains.join(", ")
+                ),
+                cert_path,
+                key_path,
+                not_after: issued.not_after.to_rfc3339(),
+                updated_config,
+                reloaded,
+            },
+        )))
+    }
+
+    async fn disable_acme(
+        &self,
+        request: Request<DisableAcmeRequest>,
+    ) -> Result<Response<DisableAcmeResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        if req.provider_name.is_empty() {
+            return Err(Status::invalid_argument("provider_name is required"));
+        }
+        let result = self
+            .acme()
+            .and_then(|acme| acme.unregister(&req.provider_name));
+        let (success, message) = match result {
+            Ok(true) => (
+                true,
+                format!(
+                    "ACME renewal disabled for {}; its certificate files are kept",
+                    req.provider_name
+                ),
+            ),
+            Ok(false) => (
+                false,
+                format!("Provider {} is not ACME-managed", req.provider_name),
+            ),
+            Err(message) => (false, message),
+        };
+        Ok(Response::new(DisableAcmeResponse { success, message }))
+    }
+
+    async fn renew_acme_certificates(
+        &self,
+        request: Request<RenewAcmeCertificatesRequest>,
+    ) -> Result<Response<RenewAcmeCertificatesResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let results = match self.acme() {
+            Ok(acme) => acme.renew_due(req.force).await,
+            Err(message) => Err(message),
+        };
+        let results = match results {
+            Ok(results) => results,
+            Err(message) => {
+                return Ok(Response::new(RenewAcmeCertificatesResponse {
+                    success: false,
+                    message,
+                    renewals: vec![],
+                    reloaded: false,
+                }));
+            }
+        };
+
+        let renewed = results.iter().filter(|result| result.renewed).count();
+        let failed = results
+            .iter()
+            .filter(|result| result.error.is_some())
+            .count();
+        let reloaded = renewed > 0
+            && match self.stunnel_pid() {
+                Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+                _ => false,
+            };
+        Ok(Response::new(RenewAcmeCertificatesResponse {
+            success: failed == 0,
+            message: format!(
+                "Renewed {} of {} ACME certificate(s), {} failed",
+                renewed,
+                results.len(),
+                failed
+            ),
+            renewals: results.into_iter().map(proto_acme_renewal).collect(),
+            reloaded,
+        }))
+    }
+
+    async fn bind_vault_certificate(
+        &self,
+        request: Request<BindVaultCertificateRequest>,
+    ) -> Result<Response<BindVaultCertificateResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.provider_name.is_empty() {
+            return Err(Status::invalid_argument("provider_name is required"));
+        }
+        let Some(source) = req.source else {
+            return Err(Status::invalid_argument("source is required"));
+        };
+        let source = vault_source(source).map_err(Status::invalid_argument)?;
+        let failure = |message: String| {
+            Response::new(redact::apply(
+                redact,
+                BindVaultCertificateResponse {
+                    success: false,
+                    message,
+                    cert_path: String::new(),
+                    key_path: String::new(),
+                    not_after: String::new(),
+                    updated_config: String::new(),
+                    reloaded: false,
+                },
+            ))
+        };
+        let vault = match self.vault() {
+            Ok(vault) => vault,
+            Err(message) => return Ok(failure(message)),
+        };
+
+        // Check the provider exists before fetching anything
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => return Ok(failure(format!("Failed to read existing config: {}", e))),
+        };
+        if StunnelConfig::parse(&existing_config)
+            .section(&req.provider_name)
+            .is_none()
+        {
+            return Ok(failure(format!(
+                "Provider {} not found in config",
+                req.provider_name
+            )));
+        }
+
+        let fetched = match vault.bind(&req.provider_name, source).await {
+            Ok(fetched) => fetched,
+            Err(message) => return Ok(failure(message)),
+        };
+        let cert_path = fetched.cert_path.to_string_lossy().into_owned();
+        let key_path = fetched.key_path.to_string_lossy().into_owned();
+
+        let config = StunnelConfig::parse(&existing_config);
+        let Some(section) = config.section(&req.provider_name) else {
+            return Ok(failure(format!(
+                "Provider {} not found in config",
+                req.provider_name
+            )));
+        };
+        let updated_config = update_section(
+            &existing_config,
+            section,
+            &[
+                ("cert", Some(cert_path.clone())),
+                ("key", Some(key_path.clone())),
+            ],
+        );
+        if let Err(message) =
+            self.write_managed_config(&updated_config, "BindVaultCertificate", &caller)
+        {
+            return Ok(failure(message));
+        }
+        let reloaded = req.apply_immediately
+            && match self.stunnel_pid() {
+                Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+                _ => false,
+            };
+
+        Ok(Response::new(redact::apply(
+            redact,
+            BindVaultCertificateResponse {
+                success: true,
+                message: format!(
+                    "Provider {} now uses its certificate from Vault",
+                    req.provider_name
+                ),
+                cert_path,
+                key_path,
+                not_after: fetched.not_after.to_rfc3339(),
+                updated_config,
+                reloaded,
+            },
+        )))
+    }
+
+    async fn unbind_vault_certificate(
+        &self,
+        request: Request<UnbindVaultCertificateRequest>,
+    ) -> Result<Response<UnbindVaultCertificateResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        if req.provider_name.is_empty() {
+            return Err(Status::invalid_argument("provider_name is required"));
+        }
+        let result = match self.vault() {
+            Ok(vault) => vault.unbind(&req.provider_name).await,
+            Err(message) => Err(message),
+        };
+        let (success, message) = match result {
+            Ok(true) => (
+                true,
+                format!(
+                    "Vault refresh disabled for {}; its certificate files are kept",
+                    req.provider_name
+                ),
+            ),
+            Ok(false) => (
+                false,
+                format!("Provider {} is not bound to Vault", req.provider_name),
+            ),
+            Err(message) => (false, message),
+        };
+        Ok(Response::new(UnbindVaultCertificateResponse {
+            success,
+            message,
+        }))
+    }
+
+    async fn refresh_vault_certificates(
+        &self,
+        request: Request<RefreshVaultCertificatesRequest>,
+    ) -> Result<Response<RefreshVaultCertificatesResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let req = request.into_inner();
+        let results = match self.vault() {
+            Ok(vault) => vault.refresh_due(req.force).await,
+            Err(message) => Err(message),
+        };
+        let results = match results {
+            Ok(results) => results,
+            Err(message) => {
+                return Ok(Response::new(RefreshVaultCertificatesResponse {
+                    success: false,
+                    message,
+                    refreshes: vec![],
+                    reloaded: false,
+                }));
+            }
+        };
+
+        let refreshed = results.iter().filter(|result| result.refreshed).count();
+        let failed = results
+            .iter()
+            .filter(|result| result.error.is_some())
+            .count();
+        let reloaded = refreshed > 0
+            && match self.stunnel_pid() {
+                Ok(pid) if process_running(pid) => self.reload_process(pid).is_ok(),
+                _ => false,
+            };
+        Ok(Response::new(RefreshVaultCertificatesResponse {
+            success: failed == 0,
+            message: format!(
+                "Refreshed {} of {} Vault certificate(s), {} failed",
+                refreshed,
+                results.len(),
+                failed
+            ),
+            refreshes: results.into_iter().map(proto_vault_refresh).collect(),
+            reloaded,
+        }))
+    }
+
+    async fn watch_certificate_changes(
+        &self,
+        _request: Request<WatchCertificateChangesRequest>,
+    ) -> Result<Response<CertificateChangeStream>, Status> {
+        // Subscribers that fall behind skip the changes they missed; the
+        // stream ends when the manager shuts down
+        let changes = BroadcastStream::new(self.cert_changes.subscribe())
+            .filter_map(|change| change.ok())
+            .map(|change| Some(Ok(proto_certificate_change(change))));
+        let shutdown = WatchStream::new(self.shutting_down.subscribe())
+            .filter(|shutting_down| *shutting_down)
+            .map(|_| None);
+        let stream = changes.merge(shutdown).map_while(|event| event);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn set_maintenance_mode(
+        &self,
+        request: Request<SetMaintenanceModeRequest>,
+    ) -> Result<Response<SetMaintenanceModeResponse>, Status> {
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if self.namespace.is_some() {
+            return Ok(Response::new(SetMaintenanceModeResponse {
+                success: false,
+                message: format!(
+                    "Maintenance mode applies to the whole manager; set it on the {} instance",
+                    DEFAULT_INSTANCE
+                ),
+                state: Some(proto_maintenance(self.maintenance.current())),
+            }));
+        }
+        let message = if req.enabled {
+            self.maintenance.enable(req.reason.trim(), &caller);
+            info!(%caller, "Maintenance mode enabled: {}", req.reason.trim());
+            "Maintenance mode enabled; changes are refused until it is disabled".to_string()
+        } else if self.maintenance.disable() {
+            info!(%caller, "Maintenance mode disabled");
+            "Maintenance mode disabled".to_string()
+        } else {
+            "Maintenance mode was not enabled".to_string()
+        };
+        Ok(Response::new(SetMaintenanceModeResponse {
+            success: true,
+            message,
+            state: Some(proto_maintenance(self.maintenance.current())),
+        }))
+    }
+
+    async fn get_journal_logs(
+        &self,
+        request: Request<GetJournalLogsRequest>,
+    ) -> Result<Response<GetJournalLogsResponse>, Status> {
+        let req = request.into_inner();
+        let query = JournalQuery {
+            lines: req.lines,
+            since: Some(req.since.trim().to_string()).filter(|since| !since.is_empty()),
+            priority: Some(req.priority.trim().to_string()).filter(|priority| !priority.is_empty()),
+        };
+        let unit = self.journal_unit.clone();
+        if unit.is_empty() {
+            return Ok(Response::new(GetJournalLogsResponse {
+                success: false,
+                message: format!(
+                    "Instance {} does not run as a systemd unit",
+                    self.instance_id
+                ),
+                unit,
+                entries: vec![],
+            }));
+        }
+        let entries = {
+            let unit = unit.clone();
+            tokio::task::spawn_blocking(move || journal::query(&unit, &query))
+                .await
+                .map_err(|e| Status::internal(format!("Journal task failed: {}", e)))?
+        };
+        match entries {
+            Ok(entries) => Ok(Response::new(GetJournalLogsResponse {
+                success: true,
+                message: format!("Read {} journal entries of {}", entries.len(), unit),
+                unit,
+                entries: entries
+                    .into_iter()
+                    .map(|entry| JournalEntry {
+                        timestamp: entry.timestamp.to_rfc3339(),
+                        priority: u32::from(entry.priority),
+                        message: entry.message,
+                        pid: entry.pid.unwrap_or(0),
+                    })
+                    .collect(),
+            })),
+            Err(e) => Ok(Response::new(GetJournalLogsResponse {
+                success: false,
+                message: e,
+                unit,
+                entries: vec![],
+            })),
+        }
+    }
+
+    async fn get_traffic_stats(
+        &self,
+        request: Request<GetTrafficStatsRequest>,
+    ) -> Result<Response<GetTrafficStatsResponse>, Status> {
+        let req = request.into_inner();
+        // Catch up with the log rather than wait for the follower
+        let followed = self.follow_traffic();
+        let providers = self
+            .traffic
+            .totals()
+            .into_iter()
+            .filter(|(name, _)| req.provider_name.is_empty() || *name == req.provider_name)
+            .map(|(name, totals)| ProviderTraffic {
+                provider_name: name,
+                connections: totals.connections,
+                bytes_to_tls: totals.bytes_to_tls,
+                bytes_to_socket: totals.bytes_to_socket,
+                last_closed_at: totals
+                    .last_closed_at
+                    .map(|at| at.to_rfc3339())
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        Ok(Response::new(GetTrafficStatsResponse {
+            success: followed.is_ok(),
+            message: match followed {
+                Ok(()) => format!("{} provider(s) with closed connections", providers.len()),
+                Err(e) => e,
+            },
+            since: self.traffic.since().to_rfc3339(),
+            providers,
+        }))
+    }
+
+    async fn query_connections(
+        &self,
+        request: Request<QueryConnectionsRequest>,
+    ) -> Result<Response<QueryConnectionsResponse>, Status> {
+        let req = request.into_inner();
+        let result = self.connection_log().and_then(|log| {
+            let query = ConnectionQuery {
+                instance: self.instance_id.clone(),
+                service: Some(req.provider_name.clone()).filter(|name| !name.is_empty()),
+                since: parse_time_bound("since", &req.since)?,
+                until: parse_time_bound("until", &req.until)?,
+                limit: match req.limit {
+                    0 => DEFAULT_CONNECTION_LIMIT,
+                    limit => limit.min(MAX_CONNECTION_LIMIT),
+                },
+            };
+            // Catch up with the log so recent connections are included
+            let _ = self.follow_traffic();
+            log.query(&query)
+                .map_err(|e| format!("Failed to read connection history: {}", e))
+        });
+        match result {
+            Ok(records) => Ok(Response::new(QueryConnectionsResponse {
+                success: true,
+                message: format!("Found {} connection(s)", records.len()),
+                connections: records.into_iter().map(proto_connection).collect(),
+            })),
+            Err(e) => Ok(Response::new(QueryConnectionsResponse {
+                success: false,
+                message: e,
+                connections: vec![],
+            })),
+        }
+    }
+
+    async fn get_top_talkers(
+        &self,
+        request: Request<GetTopTalkersRequest>,
+    ) -> Result<Response<GetTopTalkersResponse>, Status> {
+        let req = request.into_inner();
+        let result = self.connection_log().and_then(|log| {
+            let order = match TalkerOrder::from_i32(req.order_by) {
+                Some(TalkerOrder::Connections) => connlog::TalkerOrder::Connections,
+                Some(TalkerOrder::Bytes) => connlog::TalkerOrder::Bytes,
+                None => return Err(format!("Unknown talker order: {}", req.order_by)),
+            };
+            let query = ConnectionQuery {
+                instance: self.instance_id.clone(),
+                service: Some(req.provider_name.clone()).filter(|name| !name.is_empty()),
+                since: parse_time_bound("since", &req.since)?,
+                until: parse_time_bound("until", &req.until)?,
+                limit: match req.limit {
+                    0 => DEFAULT_TALKER_LIMIT,
+                    limit => limit.min(MAX_TALKER_LIMIT),
+                },
+            };
+            // Catch up with the log so recent connections are included
+            let _ = self.follow_traffic();
+            log.top_talkers(&query, order)
+                .map_err(|e| format!("Failed to read connection history: {}", e))
+        });
+        match result {
+            Ok(talkers) => {
+                let mut providers: Vec<ProviderTalkers> = Vec::new();
+                for talker in talkers {
+                    if providers
+                        .last()
+                        .is_none_or(|last| last.provider_name != talker.service)
+                    {
+                        providers.push(ProviderTalkers {
+                            provider_name: talker.service.clone(),
+                            talkers: vec![],
+                        });
+                    }
+                    if let Some(provider) = providers.last_mut() {
+                        provider.talkers.push(proto_talker(talker));
+                    }
+                }
+                Ok(Response::new(GetTopTalkersResponse {
+                    success: true,
+                    message: format!("{} provider(s) with connections", providers.len()),
+                    providers,
+                }))
+            }
+            Err(e) => Ok(Response::new(GetTopTalkersResponse {
+                success: false,
+                message: e,
+                providers: vec![],
+            })),
+        }
+    }
+
+    async fn get_version(
+        &self,
+        _request: Request<GetVersionRequest>,
+    ) -> Result<Response<GetVersionResponse>, Status> {
+        let build = version::build_info();
+        let manager = ManagerVersion {
+            version: build.version.to_string(),
+            git_commit: build.git_commit.to_string(),
+            target: build.target.to_string(),
+            profile: build.profile.to_string(),
+            features: build
+                .features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+        };
+        let stunnel = tokio::task::spawn_blocking(version::stunnel_version)
+            .await
+            .map_err(|e| Status::internal(format!("Version task failed: {}", e)))?;
+        match stunnel {
+            Ok(stunnel) => Ok(Response::new(GetVersionResponse {
+                success: true,
+                message: format!("stunnel {}, manager {}", stunnel.version, build.version),
+                stunnel: Some(StunnelVersion {
+                    fips: stunnel.fips(),
+                    version: stunnel.version,
+                    platform: stunnel.platform,
+                    compiled_openssl: stunnel.compiled_openssl,
+                    running_openssl: stunnel.running_openssl,
+                    threading: stunnel.threading,
+                    sockets: stunnel.sockets,
+                    tls: stunnel.tls,
+                    auth: stunnel.auth,
+                }),
+                manager: Some(manager),
+            })),
+            Err(e) => Ok(Response::new(GetVersionResponse {
+                success: false,
+                message: e,
+                stunnel: None,
+                manager: Some(manager),
+            })),
+        }
+    }
+
+    async fn get_maintenance_mode(
+        &self,
+        _request: Request<GetMaintenanceModeRequest>,
+    ) -> Result<Response<GetMaintenanceModeResponse>, Status> {
+        Ok(Response::new(GetMaintenanceModeResponse {
+            state: Some(proto_maintenance(self.maintenance.current())),
+        }))
+    }
+
+    async fn apply_changes(
+        &self,
+        request: Request<ApplyChangesRequest>,
+    ) -> Result<Response<ApplyChangesResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        if req.changes.is_empty() {
+            return Err(Status::invalid_argument("At least one change is required"));
+        }
+        self.check_version(&self.config_path, &req.expected_version)
+            .map_err(Status::aborted)?;
+
+        let existing_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    ApplyChangesResponse {
+                        success: false,
+                        message: format!("Failed to read existing config: {}", e),
+                        updated_config: String::new(),
+                        config_version: String::new(),
+                        failed_index: None,
+                    },
+                )));
+            }
+        };
+
+        // Every change is applied in memory first, so a failing one leaves
+        // the config untouched
+        let count = req.changes.len();
+        let mut updated_config = existing_config;
+        for (index, change) in req.changes.into_iter().enumerate() {
+            let hosts = match &change.change {
+                Some(config_change::Change::AddProvider(provider)) => {
+                    connect_hosts(provider, |_| true)
+                }
+                Some(config_change::Change::UpdateProvider(update)) => {
+                    let paths = update_mask_paths(update.update_mask.clone()).unwrap_or_default();
+                    update
+                        .provider
+                        .as_ref()
+                        .map(|provider| {
+                            connect_hosts(provider, |field| paths.iter().any(|path| path == field))
+                        })
+                        .unwrap_or_default()
+                }
+                _ => vec![],
+            };
+            if let Err(e) = self.check_connect_hosts(hosts).await {
+                return Ok(Response::new(redact::apply(
+                    redact,
+                    ApplyChangesResponse {
+                        success: false,
+                        message: format!("Change {} failed, nothing applied: {}", index, e),
+                        updated_config: String::new(),
+                        config_version: String::new(),
+                        failed_index: u32::try_from(index).ok(),
+                    },
+                )));
+            }
+            match apply_config_change(&updated_config, change.change, self.fragments.as_ref()) {
+                Ok(content) => updated_config = content,
+                Err(e) => {
+                    return Ok(Response::new(redact::apply(
+                        redact,
+                        ApplyChangesResponse {
+                            success: false,
+                            message: format!("Change {} failed, nothing applied: {}", index, e),
+                            updated_config: String::new(),
+                            config_version: String::new(),
+                            failed_index: u32::try_from(index).ok(),
+                        },
+                    )));
+                }
+            }
+        }
+
+        if let Err(message) = self.write_validated_config(&updated_config, "ApplyChanges", &caller)
+        {
+            return Ok(Response::new(redact::apply(
+                redact,
+                ApplyChangesResponse {
+                    success: false,
+                    message,
+                    updated_config: String::new(),
+                    config_version: String::new(),
+                    failed_index: None,
+                },
+            )));
+        }
+
+        if req.apply_immediately {
+            self.reload_if_running();
         }
+
+        Ok(Response::new(redact::apply(
+            redact,
+            ApplyChangesResponse {
+                success: true,
+                message: format!("{} change(s) applied", count),
+                updated_config,
+                config_version: self.version_of(&self.config_path),
+                failed_index: None,
+            },
+        )))
     }
 
-    async fn update_config(
+    async fn stage_config(
         &self,
-        request: Request<UpdateConfigRequest>,
-    ) -> Result<Response<UpdateConfigResponse>, Status> {
+        request: Request<StageConfigRequest>,
+    ) -> Result<Response<StageConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let redact = self.should_redact(&request);
+        let caller = caller_identity(&request);
         let req = request.into_inner();
-        let config_path = if req.config_path.is_empty() {
-            self.config_path.clone()
+        self.check_version(&self.config_path, &req.expected_version)
+            .map_err(Status::aborted)?;
+
+        let config_content = if req.expand_env {
+            match expand_env_vars(&req.config_content) {
+                Ok(expanded) => expanded,
+                Err(missing) => {
+                    return Ok(Response::new(StageConfigResponse {
+                        success: false,
+                        message: format!("Undefined environment variables: {}", missing.join(", ")),
+                        token: String::new(),
+                        errors: vec![],
+                        diff: String::new(),
+                        base_version: String::new(),
+                    }));
+                }
+            }
         } else {
-            req.config_path
+            req.config_content
         };
 
-        // Backup existing config
-        let backup_path = match backup_file(&config_path) {
-            Ok(path) => path,
+        let current_config = match fs::read_to_string(&self.config_path) {
+            Ok(content) => content,
             Err(e) => {
-                return Ok(Response::new(UpdateConfigResponse {
+                return Ok(Response::new(StageConfigResponse {
                     success: false,
-                    message: format!("Failed to backup config: {}", e),
+                    message: format!("Failed to read existing config: {}", e),
+                    token: String::new(),
+                    errors: vec![],
+                    diff: String::new(),
+                    base_version: String::new(),
                 }));
             }
         };
 
-        // Write new config atomically
-        if let Err(e) = atomic_write(&config_path, &req.config_content) {
-            // Attempt to restore from backup if write partially failed
-            let _ = fs::copy(&backup_path, &config_path);
-            return Ok(Response::new(UpdateConfigResponse {
+        // Refuse candidates that CommitConfig would refuse, so an approved
+        // change goes live
+        let checked = check_no_placeholders(&config_content)
+            .and_then(|_| check_new_certificates(&current_config, &config_content))
+            .and_then(|_| self.check_key_permissions(&config_content));
+        if let Err(message) = checked {
+            return Ok(Response::new(StageConfigResponse {
                 success: false,
-                message: format!("Failed to write config: {}", e),
+                message,
+                token: String::new(),
+                errors: vec![],
+                diff: String::new(),
+                base_version: String::new(),
+            }));
+        }
+        let report = self.validation.validate(&config_content);
+        if !report.is_valid() {
+            return Ok(Response::new(StageConfigResponse {
+                success: false,
+                message: format!("Configuration failed validation: {}", report.summary()),
+                token: String::new(),
+                errors: proto_validation_errors(&report),
+                diff: String::new(),
+                base_version: String::new(),
             }));
         }
 
-        // Validate new config
-        if let Err(e) = validate_stunnel_conf_path(&config_path) {
-            // Restore backup
-            match fs::copy(&backup_path, &config_path) {
-                Ok(_) => {
-                    return Ok(Response::new(UpdateConfigResponse {
-                        success: false,
-                        message: format!("Invalid configuration: {}. Restored previous config.", e),
-                    }));
-                }
-                Err(copy_err) => {
-                    // Log restoration error and return failure
-                    eprintln!(
-                        "Failed to restore backup after validation error: {}",
-                        copy_err
-                    );
-                    return Ok(Response::new(UpdateConfigResponse {
-                        success: false,
-                        message: format!(
-                            "Invalid configuration: {}. Failed to restore backup: {}",
-                            e, copy_err
-                        ),
-                    }));
-                }
+        let base_version = self.version_of(&self.config_path);
+        let staged =
+            secrets::seal_text(self.secrets.as_deref(), &config_content).and_then(|sealed| {
+                self.staging
+                    .stage(&sealed, &base_version, &caller)
+                    .map_err(|e| format!("Failed to stage config: {}", e))
+            });
+        let staged = match staged {
+            Ok(staged) => staged,
+            Err(message) => {
+                return Ok(Response::new(StageConfigResponse {
+                    success: false,
+                    message,
+                    token: String::new(),
+                    errors: vec![],
+                    diff: String::new(),
+                    base_version: String::new(),
+                }));
             }
-        }
+        };
 
-        Ok(Response::new(UpdateConfigResponse {
-            success: true,
-            message: "Configuration updated successfully".to_string(),
-        }))
+        Ok(Response::new(redact::apply(
+            redact,
+            StageConfigResponse {
+                success: true,
+                message: "Configuration staged; commit it with CommitConfig".to_string(),
+                token: staged.token,
+                errors: vec![],
+                diff: unified_diff(&current_config, &config_content, "current", "staged"),
+                base_version,
+            },
+        )))
     }
 
-    async fn generate_config(
+    async fn commit_config(
         &self,
-        request: Request<GenerateConfigRequest>,
-    ) -> Result<Response<GenerateConfigResponse>, Status> {
+        request: Request<CommitConfigRequest>,
+    ) -> Result<Response<CommitConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let caller = caller_identity(&request);
         let req = request.into_inner();
-        let mut config_content = String::new();
-
-        // Global settings
-        config_content.push_str("; Stunnel configuration generated by Rust gRPC server\n");
-        config_content.push_str(&format!("; Generated at: {}\n\n", Utc::now().to_rfc3339()));
-
-        if req.foreground {
-            config_content.push_str("foreground = yes\n");
+        if req.token.is_empty() {
+            return Err(Status::invalid_argument("token is required"));
         }
 
-        config_content.push_str("debug = 7\n");
-
-        let pid_file = if !req.pid_file.is_empty() {
-            req.pid_file
-        } else {
-            "/var/run/stunnel.pid".to_string()
+        let staged = match self.staging.get(&req.token) {
+            Some(staged) => staged,
+            None => {
+                return Ok(Response::new(CommitConfigResponse {
+                    success: false,
+                    message: format!("No config is staged under token {}", req.token),
+                    config_version: String::new(),
+                }));
+            }
         };
-        config_content.push_str(&format!("pid = {}\n", pid_file));
-
-        if !req.cert_path.is_empty() {
-            config_content.push_str(&format!("cert = {}\n", req.cert_path));
-        }
-        if !req.key_path.is_empty() {
-            config_content.push_str(&format!("key = {}\n", req.key_path));
-        }
-        if !req.ca_path.is_empty() {
-            config_content.push_str(&format!("CAfile = {}\n", req.ca_path));
-        }
+        self.check_version(&self.config_path, &staged.base_version)
+            .map_err(Status::aborted)?;
 
-        config_content.push('\n');
-
-        // Add each provider as a service
-        for provider in req.providers {
-            config_content.push_str(&format!("; {} service\n", provider.name));
-            config_content.push_str(&format!("[{}]\n", provider.name));
-
-            if provider.is_client {
-                config_content.push_str("client = yes\n");
+        let config_content = match secrets::open_text(self.secrets.as_deref(), &staged.content) {
+            Ok(content) => content,
+            Err(e) => {
+                return Ok(Response::new(CommitConfigResponse {
+                    success: false,
+                    message: format!("Failed to read staged config: {}", e),
+                    config_version: String::new(),
+                }));
             }
+        };
 
-            config_content.push_str(&format!("accept = :::{}\n", provider.accept_port));
-            config_content.push_str(&format!(
-                "connect = {}:{}\n",
-                provider.connect_host, provider.connect_port
-            ));
-            config_content.push('\n');
-        }
-
-        // Write to file atomically
-        if let Err(e) = atomic_write(&self.config_path, &config_content) {
-            return Ok(Response::new(GenerateConfigResponse {
+        let committer = format!("{} (staged by {})", caller, staged.staged_by);
+        if let Err(message) =
+            self.write_validated_config(&config_content, "CommitConfig", &committer)
+        {
+            return Ok(Response::new(CommitConfigResponse {
                 success: false,
-                message: format!("Failed to write config file: {}", e),
-                config_content: String::new(),
-                config_path: String::new(),
+                message,
+                config_version: String::new(),
             }));
         }
+        if let Err(e) = self.staging.discard(&staged.token) {
+            warn!("Failed to remove committed staged config: {}", e);
+        }
 
-        // Validate the generated config (skip if stunnel not available)
-        if let Err(e) = validate_stunnel_conf_path(&self.config_path) {
-            println!(
-                "Warning: Config validation failed (stunnel may not be installed): {}",
-                e
-            );
-            // Continue anyway - config is generated
+        if req.apply_immediately {
+            self.reload_if_running();
         }
 
-        Ok(Response::new(GenerateConfigResponse {
+        Ok(Response::new(CommitConfigResponse {
             success: true,
-            message: "Configuration generated successfully".to_string(),
-            config_content: config_content.clone(),
-            config_path: self.config_path.clone(),
+            message: "Staged configuration committed".to_string(),
+            config_version: self.version_of(&self.config_path),
         }))
     }
 
-    async fn add_provider(
+    async fn discard_config(
         &self,
-        request: Request<AddProviderRequest>,
-    ) -> Result<Response<AddProviderResponse>, Status> {
+        request: Request<DiscardConfigRequest>,
+    ) -> Result<Response<DiscardConfigResponse>, Status> {
+        let _mutation = self.lock_mutations().await;
+        let token = request.into_inner().token;
+        if token.is_empty() {
+            return Err(Status::invalid_argument("token is required"));
+        }
+
+        let (success, message) = match self.staging.discard(&token) {
+            Ok(true) => (true, "Staged configuration discarded".to_string()),
+            Ok(false) => (false, format!("No config is staged under token {}", token)),
+            Err(e) => (false, format!("Failed to discard staged config: {}", e)),
+        };
+        Ok(Response::new(DiscardConfigResponse { success, message }))
+    }
+
+    async fn probe_backend(
+        &self,
+        request: Request<ProbeBackendRequest>,
+    ) -> Result<Response<ProbeBackendResponse>, Status> {
         let req = request.into_inner();
-        let provider = req
-            .provider
-            .ok_or_else(|| Status::invalid_argument("Provider is required"))?;
+        if req.provider_name.trim().is_empty() {
+            return Err(Status::invalid_argument("provider_name is required"));
+        }
+        let timeout = match req.timeout_ms {
+            0 => probe::DEFAULT_TIMEOUT,
+            ms => Duration::from_millis(u64::from(ms)),
+        };
 
-        // Read existing config
-        let existing_config = match fs::read_to_string(&self.config_path) {
+        let content = match self.read_providers_config() {
             Ok(content) => content,
             Err(e) => {
-                return Ok(Response::new(AddProviderResponse {
+                return Ok(Response::new(ProbeBackendResponse {
                     success: false,
-                    message: format!("Failed to read existing config: {}", e),
-                    updated_config: String::new(),
+                    message: format!("Failed to read config: {}", e),
+                    reachable: false,
+                    probes: vec![],
                 }));
             }
         };
-
-        // Check if provider already exists
-        if existing_config.contains(&format!("[{}]", provider.name)) {
-            return Ok(Response::new(AddProviderResponse {
+        let config = StunnelConfig::parse(&content);
+        let (provider, settings) = match config.section(&req.provider_name) {
+            Some(section) => (
+                provider_from_section(section),
+                tls_settings(&config, section),
+            ),
+            None => {
+                return Ok(Response::new(ProbeBackendResponse {
+                    success: false,
+                    message: format!("Provider {} not found in config", req.provider_name),
+                    reachable: false,
+                    probes: vec![],
+                }));
+            }
+        };
+        if !provider.exec.is_empty() {
+            return Ok(Response::new(ProbeBackendResponse {
                 success: false,
-                message: format!("Provider {} already exists in config", provider.name),
-                updated_config: String::new(),
+                message: format!(
+                    "Provider {} runs a program and has no backend to probe",
+                    req.provider_name
+                ),
+                reachable: false,
+                probes: vec![],
             }));
         }
-
-        // Add new provider section
-        let mut new_section = String::new();
-        new_section.push_str(&format!("\n; {} service\n", provider.name));
-        new_section.push_str(&format!("[{}]\n", provider.name));
-
-        if provider.is_client {
-            new_section.push_str("client = yes\n");
+        if req.tls_handshake && !provider.is_client {
+            return Ok(Response::new(ProbeBackendResponse {
+                success: false,
+                message: format!(
+                    "Provider {} is not in client mode; its backend does not speak TLS",
+                    req.provider_name
+                ),
+                reachable: false,
+                probes: vec![],
+            }));
         }
 
-        new_section.push_str(&format!("accept = :::{}\n", provider.accept_port));
-        new_section.push_str(&format!(
-            "connect = {}:{}\n",
-            provider.connect_host, provider.connect_port
-        ));
-
-        // If global cert/CAfile/verify are present in existing config, copy them into the new service
-        let mut cert_line: Option<String> = None;
-        let mut cafile_line: Option<String> = None;
-        // let mut verify_line: Option<String> = None;
-
-        for line in existing_config.lines() {
-            let trimmed = line.trim();
-            if cert_line.is_none() && trimmed.starts_with("cert =") {
-                cert_line = Some(trimmed.to_string());
-            } else if cafile_line.is_none() && trimmed.starts_with("CAfile =") {
-                cafile_line = Some(trimmed.to_string());
+        let probes = probe_backends(&provider, timeout).await;
+        let reachable = probes.iter().filter(|probe| probe.reachable()).count();
+        let mut message = match (reachable, probes.len()) {
+            (0, _) => format!("Backend of {} is unreachable", req.provider_name),
+            (reachable, total) if reachable == total => {
+                format!("Backend of {} is reachable", req.provider_name)
             }
-            // if cert_line.is_some() && cafile_line.is_some() && verify_line.is_some() {
-            //     break;
-            // }
-        }
+            (reachable, total) => format!(
+                "{} of {} connect targets of {} are reachable",
+                reachable, total, req.provider_name
+            ),
+        };
 
-        if let Some(line) = cert_line {
-            new_section.push_str(&line);
-            new_section.push('\n');
+        let mut results = Vec::with_capacity(probes.len());
+        let (mut completed, mut valid) = (0, 0);
+        for (probe, (host, port)) in probes.into_iter().zip(connect_targets(&provider)) {
+            let reachable = probe.reachable();
+            let mut result = proto_probe(probe);
+            if req.tls_handshake && reachable {
+                let handshake = probe::handshake(
+                    host,
+                    u16::try_from(port).unwrap_or_default(),
+                    &settings,
+                    timeout,
+                )
+                .await;
+                completed += usize::from(handshake.completed());
+                valid += usize::from(handshake.certificate_valid());
+                result.tls = Some(proto_handshake(handshake));
+            }
+            results.push(result);
         }
-        if let Some(line) = cafile_line {
-            new_section.push_str(&line);
-            new_section.push('\n');
+        if req.tls_handshake && reachable > 0 {
+            message.push_str(&format!(
+                "; TLS handshake completed with {} of {} reachable targets, {} with a valid certificate",
+                completed, reachable, valid
+            ));
         }
+        Ok(Response::new(ProbeBackendResponse {
+            success: true,
+            message,
+            reachable: reachable > 0,
+            probes: results,
+        }))
+    }
 
-        // Ensure there's exactly one newline between existing content and new section
-        let updated_config = if existing_config.ends_with('\n') {
-            format!("{}{}", existing_config, new_section)
-        } else {
-            format!("{}\n{}", existing_config, new_section)
-        };
+    async fn get_operation(
+        &self,
+        request: Request<GetOperationRequest>,
+    ) -> Result<Response<GetOperationResponse>, Status> {
+        let req = request.into_inner();
+        Ok(Response::new(
+            match self.operations.get(&req.operation_id) {
+                Some(operation) => GetOperationResponse {
+                    success: true,
+                    message: String::new(),
+                    operation: Some(proto_operation(operation)),
+                },
+                None => GetOperationResponse {
+                    success: false,
+                    message: format!("Operation {} not found", req.operation_id),
+                    operation: None,
+                },
+            },
+        ))
+    }
 
-        // Backup and write new config atomically
-        if let Err(e) = backup_file(&self.config_path) {
-            return Ok(Response::new(AddProviderResponse {
+    async fn list_operations(
+        &self,
+        _request: Request<ListOperationsRequest>,
+    ) -> Result<Response<ListOperationsResponse>, Status> {
+        Ok(Response::new(ListOperationsResponse {
+            operations: self
+                .operations
+                .list()
+                .into_iter()
+                .map(proto_operation)
+                .collect(),
+        }))
+    }
+
+    async fn cancel_operation(
+        &self,
+        request: Request<CancelOperationRequest>,
+    ) -> Result<Response<CancelOperationResponse>, Status> {
+        let caller = caller_identity(&request);
+        let req = request.into_inner();
+        let Some(operation) = self.operations.cancel(&req.operation_id) else {
+            return Ok(Response::new(CancelOperationResponse {
                 success: false,
-                message: format!("Failed to backup config: {}", e),
-                updated_config: String::new(),
+                message: format!("Operation {} not found", req.operation_id),
+                operation: None,
             }));
-        }
+        };
+        let (success, message) = if operation.state.is_finished() {
+            (
+                false,
+                format!("Operation {} has already finished", operation.id),
+            )
+        } else {
+            info!(operation = %operation.id, %caller, "Cancellation requested");
+            (
+                true,
+                format!(
+                    "Cancellation requested; operation {} stops at its next safe point",
+                    operation.id
+                ),
+            )
+        };
+        Ok(Response::new(CancelOperationResponse {
+            success,
+            message,
+            operation: Some(proto_operation(operation)),
+        }))
+    }
 
-        if let Err(e) = atomic_write(&self.config_path, &updated_config) {
-            return Ok(Response::new(AddProviderResponse {
+    async fn create_instance(
+        &self,
+        request: Request<CreateInstanceRequest>,
+    ) -> Result<Response<CreateInstanceResponse>, Status> {
+        let caller = caller_identity(&request);
+        let scope = request.extensions().get::<NamespaceScope>().cloned();
+        let req = request.into_inner();
+        let instance_id = req.instance_id.clone();
+        if !req.systemd_unit.is_empty() {
+            if !req.namespace.trim().is_empty() {
+                return Err(Status::invalid_argument(
+                    "Instances in a namespace are started directly and take no systemd_unit",
+                ));
+            }
+            check_systemd_unit(&instance_id, &req.systemd_unit)
+                .map_err(Status::invalid_argument)?;
+        }
+        match self.register_instance(req, scope.as_ref()) {
+            Ok(server) => {
+                info!(instance = %instance_id, %caller, "Created instance");
+                Ok(Response::new(CreateInstanceResponse {
+                    success: true,
+                    message: format!("Created instance {}", instance_id),
+                    instance: Some(server.proto_instance(&instance_id)),
+                }))
+            }
+            Err(e) => Ok(Response::new(CreateInstanceResponse {
                 success: false,
-                message: format!("Failed to write updated config: {}", e),
-                updated_config: String::new(),
-            }));
+                message: e,
+                instance: None,
+            })),
         }
+    }
 
-        // Validate new config (skip if stunnel not available)
-        if let Err(e) = validate_stunnel_conf_path(&self.config_path) {
-            println!(
-                "Warning: Config validation failed (stunnel may not be installed): {}",
-                e
-            );
-            // Continue anyway - config is written
+    async fn list_instances(
+        &self,
+        request: Request<ListInstancesRequest>,
+    ) -> Result<Response<ListInstancesResponse>, Status> {
+        let mut instances = Vec::new();
+        if in_scope(&request, None) {
+            instances.push(self.proto_instance(DEFAULT_INSTANCE));
         }
-
-        // Apply immediately if requested
-        if req.apply_immediately {
-            if let Ok(pid) = get_stunnel_pid(&self.pid_file) {
-                // only reload if process exists
-                if process_running(pid) {
-                    let _ = reload_stunnel(pid);
-                }
+        for (id, spec) in self.instances.list() {
+            if !in_scope(&request, spec.namespace.as_deref()) {
+                continue;
+            }
+            if let Ok(server) = self.instance(&id) {
+                instances.push(server.proto_instance(&id));
             }
         }
-
-        Ok(Response::new(AddProviderResponse {
-            success: true,
-            message: format!("Provider {} added successfully", provider.name),
-            updated_config,
-        }))
+        Ok(Response::new(ListInstancesResponse { instances }))
     }
 
-    async fn remove_provider(
+    async fn delete_instance(
         &self,
-        request: Request<RemoveProviderRequest>,
-    ) -> Result<Response<RemoveProviderResponse>, Status> {
+        request: Request<DeleteInstanceRequest>,
+    ) -> Result<Response<DeleteInstanceResponse>, Status> {
+        let caller = caller_identity(&request);
+        let scope = request.extensions().get::<NamespaceScope>().cloned();
         let req = request.into_inner();
-        let name = req.provider_name;
-
-        if name.trim().is_empty() {
-            return Ok(Response::new(RemoveProviderResponse {
+        if req.instance_id.is_empty() || req.instance_id == DEFAULT_INSTANCE {
+            return Ok(Response::new(DeleteInstanceResponse {
                 success: false,
-                message: "provider_name is required".to_string(),
-                updated_config: String::new(),
+                message: format!("The {} instance cannot be deleted", DEFAULT_INSTANCE),
             }));
         }
-
-        // Read existing config
-        let existing_config = match fs::read_to_string(&self.config_path) {
-            Ok(content) => content,
+        let server = match self.instance(&req.instance_id) {
+            Ok(server)
+                if scope
+                    .as_ref()
+                    .is_none_or(|scope| scope.allows(server.namespace.as_deref())) =>
+            {
+                server
+            }
+            Ok(_) => {
+                return Ok(Response::new(DeleteInstanceResponse {
+                    success: false,
+                    message: format!("Instance {} not found", req.instance_id),
+                }));
+            }
             Err(e) => {
-                return Ok(Response::new(RemoveProviderResponse {
+                return Ok(Response::new(DeleteInstanceResponse {
                     success: false,
-                    message: format!("Failed to read existing config: {}", e),
-                    updated_config: String::new(),
+                    message: e,
                 }));
             }
         };
 
-        if !existing_config.contains(&format!("[{}]", name)) {
-            return Ok(Response::new(RemoveProviderResponse {
+        // A stunnel that is not running has nothing to stop
+        let mut message = format!("Deleted instance {}", req.instance_id);
+        if req.stop_stunnel {
+            let stopped = server
+                .stop_stunnel(Request::new(StopRequest {
+                    timeout_secs: req.timeout_secs,
+                    instance_id: req.instance_id.clone(),
+                }))
+                .await?
+                .into_inner();
+            if stopped.success {
+                message = format!("{}; stopped stunnel (PID {})", message, stopped.pid);
+            } else if stopped.pid != 0 {
+                return Ok(Response::new(DeleteInstanceResponse {
+                    success: false,
+                    message: stopped.message,
+                }));
+            }
+        }
+
+        if let Err(e) = self.instances.remove(&req.instance_id) {
+            return Ok(Response::new(DeleteInstanceResponse {
                 success: false,
-                message: format!("Provider {} not found in config", name),
-                updated_config: existing_config,
+                message: e,
             }));
         }
+        self.instance_servers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&req.instance_id);
+        server.begin_shutdown();
+        info!(instance = %req.instance_id, %caller, "Deleted instance");
+        Ok(Response::new(DeleteInstanceResponse {
+            success: true,
+            message,
+        }))
+    }
+}
 
-        // Remove the section lines from the config
-        let mut result_lines: Vec<String> = Vec::new();
-        let lines: Vec<&str> = existing_config.lines().collect();
-        let mut i: usize = 0;
-        let target_header = format!("[{}]", name);
-        let target_comment = format!("; {} service", name);
-        let mut skipping = false;
-
-        while i < lines.len() {
-            let line = lines[i];
-            let trimmed_start = line.trim_start();
+// Implements StunnelManager by handing each routed RPC to the server of the
+// instance named by its request's `instance_id`; local RPCs manage the
+// instances themselves. Every RPC runs in its own span.
+macro_rules! route_to_instances {
+    (
+        routed { $($routed:ident($routed_req:ty) -> $routed_resp:ty;)* }
+        streaming { $($streaming:ident($streaming_req:ty) -> $streaming_resp:ty;)* }
+        local { $($local:ident($local_req:ty) -> $local_resp:ty;)* }
+    ) => {
+        #[tonic::async_trait]
+        impl StunnelManager for StunnelServer {
+            type WatchCertificateChangesStream = CertificateChangeStream;
+            type RemoveProviderWithProgressStream = RemoveProviderProgressStream;
 
-            // If line is a pure comment, keep it and skip header detection on it
-            if trimmed_start.starts_with(';') {
-                // If we're not in skipping mode, preserve comment lines
-                if !skipping {
-                    result_lines.push(line.to_string());
+            $(
+                async fn $routed(
+                    &self,
+                    request: Request<$routed_req>,
+                ) -> Result<Response<$routed_resp>, Status> {
+                    let span = telemetry::rpc_span(stringify!($routed), request.metadata());
+                    let logged = self.payload_logger(stringify!($routed), &request);
+                    let result = async {
+                        let id = request.get_ref().instance_id.clone();
+                        let instance = self.instance(&id).map_err(Status::not_found)?;
+                        Span::current().record("instance", instance.instance_id.as_str());
+                        if !in_scope(&request, instance.namespace.as_deref()) {
+                            return Err(out_of_scope(&id));
+                        }
+                        instance.$routed(request).await
+                    }
+                    .instrument(span.clone())
+                    .await;
+                    telemetry::record_result(&span, &result);
+                    if let Some(log) = logged {
+                        let response = result.as_ref().ok().map(|r| accesslog::payload(r.get_ref()));
+                        span.in_scope(|| log(response.as_deref()));
+                    }
+                    result
                 }
-                i += 1;
-                continue;
-            }
+            )*
 
-            let trimmed = line.trim();
-            let is_section_header = trimmed.starts_with('[') && trimmed.ends_with(']');
-
-            if !skipping && trimmed == target_header {
-                // If previous pushed line is the comment for this service, remove it
-                if let Some(last) = result_lines.last() {
-                    if last.trim() == target_comment {
-                        let _ = result_lines.pop();
+            // Like routed calls, but only the request of these is logged
+            $(
+                async fn $streaming(
+                    &self,
+                    request: Request<$streaming_req>,
+                ) -> Result<Response<$streaming_resp>, Status> {
+                    let span = telemetry::rpc_span(stringify!($streaming), request.metadata());
+                    if let Some(log) = self.payload_logger(stringify!($streaming), &request) {
+                        span.in_scope(|| log(None));
+                    }
+                    let result = async {
+                        let id = request.get_ref().instance_id.clone();
+                        let instance = self.instance(&id).map_err(Status::not_found)?;
+                        Span::current().record("instance", instance.instance_id.as_str());
+                        if !in_scope(&request, instance.namespace.as_deref()) {
+                            return Err(out_of_scope(&id));
+                        }
+                        instance.$streaming(request).await
                     }
+                    .instrument(span.clone())
+                    .await;
+                    telemetry::record_result(&span, &result);
+                    result
                 }
-                // Start skipping from this header line
-                skipping = true;
-                i += 1;
-                continue;
-            }
+            )*
 
-            if skipping {
-                // Stop skipping when the next section header begins
-                if is_section_header {
-                    skipping = false;
-                    // Do not consume this header here; loop will handle it without skipping
-                    continue;
-                } else {
-                    i += 1;
-                    continue;
+            $(
+                async fn $local(
+                    &self,
+                    request: Request<$local_req>,
+                ) -> Result<Response<$local_resp>, Status> {
+                    let span = telemetry::rpc_span(stringify!($local), request.metadata());
+                    let logged = self.payload_logger(stringify!($local), &request);
+                    let result = StunnelServer::$local(self, request)
+                        .instrument(span.clone())
+                        .await;
+                    telemetry::record_result(&span, &result);
+                    if let Some(log) = logged {
+                        let response = result.as_ref().ok().map(|r| accesslog::payload(r.get_ref()));
+                        span.in_scope(|| log(response.as_deref()));
+                    }
+                    result
                 }
-            }
-
-            result_lines.push(line.to_string());
-            i += 1;
+            )*
         }
+    };
+}
 
-        let updated_config = if result_lines.is_empty() {
-            String::new()
-        } else {
-            // Ensure final newline
-            let mut s = result_lines.join("\n");
-            if !s.ends_with('\n') {
-                s.push('\n');
-            }
-            s
-        };
+route_to_instances! {
+    routed {
+        reload_config(ReloadRequest) -> ReloadResponse;
+        get_status(StatusRequest) -> StatusResponse;
+        update_config(UpdateConfigRequest) -> UpdateConfigResponse;
+        generate_config(GenerateConfigRequest) -> GenerateConfigResponse;
+        add_provider(AddProviderRequest) -> AddProviderResponse;
+        remove_provider(RemoveProviderRequest) -> RemoveProviderResponse;
+        stop_stunnel(StopRequest) -> StopResponse;
+        restart_stunnel(RestartRequest) -> RestartResponse;
+        start_stunnel(StartRequest) -> StartResponse;
+        list_providers(ListProvidersRequest) -> ListProvidersResponse;
+        get_provider(GetProviderRequest) -> GetProviderResponse;
+        update_provider(UpdateProviderRequest) -> UpdateProviderResponse;
+        disable_provider(DisableProviderRequest) -> DisableProviderResponse;
+        enable_provider(EnableProviderRequest) -> EnableProviderResponse;
+        rename_provider(RenameProviderRequest) -> RenameProviderResponse;
+        add_providers(AddProvidersRequest) -> AddProvidersResponse;
+        get_config(GetConfigRequest) -> GetConfigResponse;
+        validate_config_content(ValidateConfigContentRequest) -> ValidateConfigContentResponse;
+        diff_config(DiffConfigRequest) -> DiffConfigResponse;
+        list_backups(ListBackupsRequest) -> ListBackupsResponse;
+        restore_backup(RestoreBackupRequest) -> RestoreBackupResponse;
+        prune_backups(PruneBackupsRequest) -> PruneBackupsResponse;
+        get_history(GetHistoryRequest) -> GetHistoryResponse;
+        get_revision(GetRevisionRequest) -> GetRevisionResponse;
+        rollback_revision(RollbackRevisionRequest) -> RollbackRevisionResponse;
+        rollback_to_commit(RollbackToCommitRequest) -> RollbackToCommitResponse;
+        export_config(ExportConfigRequest) -> ExportConfigResponse;
+        import_config(ImportConfigRequest) -> ImportConfigResponse;
+        register_template(RegisterTemplateRequest) -> RegisterTemplateResponse;
+        list_templates(ListTemplatesRequest) -> ListTemplatesResponse;
+        add_provider_from_template(AddProviderFromTemplateRequest) -> AddProviderFromTemplateResponse;
+        lint_config(LintConfigRequest) -> LintConfigResponse;
+        set_debug_level(SetDebugLevelRequest) -> SetDebugLevelResponse;
+        upload_crl(UploadCrlRequest) -> UploadCrlResponse;
+        get_certificate_info(GetCertificateInfoRequest) -> GetCertificateInfoResponse;
+        get_certificate_status(GetCertificateStatusRequest) -> GetCertificateStatusResponse;
+        generate_csr(GenerateCsrRequest) -> GenerateCsrResponse;
+        upload_certificate(UploadCertificateRequest) -> UploadCertificateResponse;
+        list_certificates(ListCertificatesRequest) -> ListCertificatesResponse;
+        import_pkcs12(ImportPkcs12Request) -> ImportPkcs12Response;
+        verify_key_pair(VerifyKeyPairRequest) -> VerifyKeyPairResponse;
+        verify_chain(VerifyChainRequest) -> VerifyChainResponse;
+        enable_acme(EnableAcmeRequest) -> EnableAcmeResponse;
+        disable_acme(DisableAcmeRequest) -> DisableAcmeResponse;
+        renew_acme_certificates(RenewAcmeCertificatesRequest) -> RenewAcmeCertificatesResponse;
+        bind_vault_certificate(BindVaultCertificateRequest) -> BindVaultCertificateResponse;
+        unbind_vault_certificate(UnbindVaultCertificateRequest) -> UnbindVaultCertificateResponse;
+        refresh_vault_certificates(RefreshVaultCertificatesRequest) -> RefreshVaultCertificatesResponse;
+        set_maintenance_mode(SetMaintenanceModeRequest) -> SetMaintenanceModeResponse;
+        get_maintenance_mode(GetMaintenanceModeRequest) -> GetMaintenanceModeResponse;
+        apply_changes(ApplyChangesRequest) -> ApplyChangesResponse;
+        stage_config(StageConfigRequest) -> StageConfigResponse;
+        commit_config(CommitConfigRequest) -> CommitConfigResponse;
+        discard_config(DiscardConfigRequest) -> DiscardConfigResponse;
+        probe_backend(ProbeBackendRequest) -> ProbeBackendResponse;
+        get_journal_logs(GetJournalLogsRequest) -> GetJournalLogsResponse;
+        get_version(GetVersionRequest) -> GetVersionResponse;
+        get_traffic_stats(GetTrafficStatsRequest) -> GetTrafficStatsResponse;
+        query_connections(QueryConnectionsRequest) -> QueryConnectionsResponse;
+        get_top_talkers(GetTopTalkersRequest) -> GetTopTalkersResponse;
+        get_operation(GetOperationRequest) -> GetOperationResponse;
+        list_operations(ListOperationsRequest) -> ListOperationsResponse;
+        cancel_operation(CancelOperationRequest) -> CancelOperationResponse;
+    }
+    streaming {
+        remove_provider_with_progress(RemoveProviderRequest) -> RemoveProviderProgressStream;
+        watch_certificate_changes(WatchCertificateChangesRequest) -> CertificateChangeStream;
+    }
+    local {
+        create_instance(CreateInstanceRequest) -> CreateInstanceResponse;
+        list_instances(ListInstancesRequest) -> ListInstancesResponse;
+        delete_instance(DeleteInstanceRequest) -> DeleteInstanceResponse;
+    }
+}
 
-        // Backup and write new config atomically
-        if let Err(e) = backup_file(&self.config_path) {
-            return Ok(Response::new(RemoveProviderResponse {
-                success: false,
-                message: format!("Failed to backup config: {}", e),
-                updated_config: String::new(),
-            }));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn validate_config_content_does_not_open_files_outside_the_allowed_set() {
+        let dir =
+            std::env::temp_dir().join(format!("stunnel-space-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("stunnel.conf");
+        fs::write(
+            &config_path,
+            "[web]\naccept = 8443\nconnect = 127.0.0.1:80\n",
+        )
+        .unwrap();
+        let secret = dir.join("secret.txt");
+        fs::write(&secret, "not a certificate\n").unwrap();
+        let server = StunnelServer::new(
+            config_path.display().to_string(),
+            dir.join("stunnel.pid").display().to_string(),
+        );
+
+        let content = format!(
+            "[web]\naccept = 8443\nconnect = 127.0.0.1:80\ncert = {0}\nkey = {0}\nCAfile = {0}\n",
+            secret.display()
+        );
+        let response = server
+            .validate_config_content(Request::new(ValidateConfigContentRequest {
+                config_content: content,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let certificates: Vec<&ValidationError> = response
+            .errors
+            .iter()
+            .filter(|error| error.validator == "certificates")
+            .collect();
+        assert!(!certificates.is_empty());
+        for error in certificates {
+            assert!(!error.blocking, "{}", error.message);
+            assert!(error.message.contains("Not checked"), "{}", error.message);
+            assert!(!error.message.contains("No certificate found"));
+            assert!(!error.message.contains("Failed to read"));
         }
+    }
 
-        if let Err(e) = atomic_write(&self.config_path, &updated_config) {
-            return Ok(Response::new(RemoveProviderResponse {
-                success: false,
-                message: format!("Failed to write updated config: {}", e),
-                updated_config: String::new(),
-            }));
+    fn provider(name: &str) -> Provider {
+        Provider {
+            name: name.to_string(),
+            accept_port: 8443,
+            connect_host: "backend.internal".to_string(),
+            connect_port: 443,
+            ..Default::default()
         }
+    }
+
+    #[test]
+    fn normalize_provider_trims_fields() {
+        let mut untrimmed = provider(" web ");
+        untrimmed.cert = " /etc/stunnel/web.pem\n".to_string();
+        let normalized = normalize_provider(untrimmed, |_| true).unwrap();
+        assert_eq!(normalized.name, "web");
+        assert_eq!(normalized.cert, "/etc/stunnel/web.pem");
+    }
 
-        // Validate new config (skip if stunnel not available)
-        if let Err(e) = validate_stunnel_conf_path(&self.config_path) {
-            println!(
-                "Warning: Config validation failed (stunnel may not be installed): {}",
-                e
+    #[test]
+    fn normalize_provider_rejects_invalid_fields() {
+        let mut invalid = provider("../web");
+        invalid.accept_port = 0;
+        invalid.key = "/etc/web.key\rexec = /bin/sh".to_string();
+        invalid.options = vec!["NO_SSLv3 NO_TLSv1".to_string()];
+        let errors = normalize_provider(invalid, |_| true).unwrap_err();
+        for field in ["name:", "accept_port:", "key:", "options[0]:"] {
+            assert!(
+                errors.iter().any(|error| error.starts_with(field)),
+                "{} not in {:?}",
+                field,
+                errors
             );
-            // Continue anyway - config is written
         }
+    }
 
-        // Apply immediately if requested
-        if req.apply_immediately {
-            if let Ok(pid) = get_stunnel_pid(&self.pid_file) {
-                if process_running(pid) {
-                    let _ = reload_stunnel(pid);
-                }
-            }
-        }
+    #[test]
+    fn normalize_provider_checks_only_selected_fields() {
+        let mut partial = provider("");
+        partial.accept_port = 0;
+        assert!(normalize_provider(partial.clone(), |field| field == "cert").is_ok());
+        assert_eq!(
+            normalize_provider(partial, |field| field == "accept_port").unwrap_err(),
+            ["accept_port: 0 is not between 1 and 65535"]
+        );
+    }
 
-        Ok(Response::new(RemoveProviderResponse {
-            success: true,
-            message: format!("Provider {} removed successfully", name),
-            updated_config,
-        }))
+    #[tokio::test]
+    async fn get_config_includes_provider_fragments() {
+        let dir =
+            std::env::temp_dir().join(format!("stunnel-space-get-config-{}", std::process::id()));
+        let providers_dir = dir.join("conf.d");
+        fs::create_dir_all(&providers_dir).unwrap();
+        let config_path = dir.join("stunnel.conf");
+        let main = format!(
+            "pid = /run/stunnel.pid\ninclude = {}\n",
+            providers_dir.display()
+        );
+        fs::write(&config_path, &main).unwrap();
+        fs::write(
+            providers_dir.join("web.conf"),
+            "[web]\naccept = 8443\nconnect = 127.0.0.1:80\n",
+        )
+        .unwrap();
+        let server = StunnelServer::new(
+            config_path.display().to_string(),
+            dir.join("stunnel.pid").display().to_string(),
+        )
+        .with_providers_dir(FragmentDir::open(&providers_dir.display().to_string()).unwrap());
+
+        let response = server
+            .get_config(Request::new(GetConfigRequest::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(response.success, "{}", response.message);
+        assert_eq!(response.raw_config, main);
+        assert_eq!(response.fragments.len(), 1);
+        assert!(response.fragments[0].path.ends_with("web.conf"));
+        let names: Vec<&str> = response.providers.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["web"]);
     }
 }
@@ -0,0 +1,332 @@
+//! TCP socket statistics from the kernel.
+//!
+//! On Linux the `sock_diag` netlink interface (`INET_DIAG`) reports each
+//! TCP socket's queues and the kernel's `tcp_info` for it: bytes sent and
+//! received, and the smoothed round-trip time. Where
+//! netlink sockets are refused, e.g. by a seccomp profile, the same figures
+//! are read from `ss -tine`.
+
+use std::net::{IpAddr, SocketAddr};
+use std::process::Command;
+use std::time::Duration;
+
+/// One established TCP socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpSocketStats {
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    /// Inode, matching the `socket:[inode]` links in `/proc/<pid>/fd`.
+    pub inode: u64,
+    /// Bytes of data sent, not counting retransmissions.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Smoothed round-trip time.
+    pub rtt: Duration,
+    /// Bytes sent but not yet acknowledged.
+    pub send_queue: u32,
+    /// Bytes received but not yet read.
+    pub receive_queue: u32,
+}
+
+/// Lists the established TCP sockets on this host, IPv4 and IPv6, through
+/// sock_diag or, failing that, `ss`.
+///
+/// # Errors
+///
+/// Returns an error if neither works, e.g. on a host other than Linux.
+pub fn established_sockets() -> Result<Vec<TcpSocketStats>, String> {
+    netlink::established_sockets().or_else(|netlink_error| {
+        ss_established_sockets().map_err(|ss_error| format!("{}; {}", netlink_error, ss_error))
+    })
+}
+
+// Runs `ss`, headerless, with tcp_info and inodes.
+fn ss_established_sockets() -> Result<Vec<TcpSocketStats>, String> {
+    let output = Command::new("ss")
+        .args(["-Htine", "state", "established"])
+        .output()
+        .map_err(|e| format!("Failed to run ss: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ss failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_ss(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the output of `ss -Htine state established`: a line per socket
+/// with its queues, addresses and inode, followed by an indented line of
+/// tcp_info.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::sockdiag::parse_ss;
+///
+/// let output = "0      512   10.0.0.1:443   10.0.0.2:51234 ino:280591 sk:1 <->\n\
+///     \t cubic rtt:1.5/0.75 bytes_sent:4608 bytes_retrans:512 bytes_acked:4097 \
+///     bytes_received:2048 segs_out:9\n";
+/// let sockets = parse_ss(output);
+/// assert_eq!(sockets[0].local.to_string(), "10.0.0.1:443");
+/// assert_eq!(sockets[0].inode, 280591);
+/// assert_eq!(sockets[0].send_queue, 512);
+/// assert_eq!(sockets[0].bytes_sent, 4096);
+/// assert_eq!(sockets[0].rtt.as_micros(), 1500);
+/// ```
+pub fn parse_ss(output: &str) -> Vec<TcpSocketStats> {
+    let mut sockets = Vec::new();
+    let mut current: Option<TcpSocketStats> = None;
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            let Some(socket) = current.as_mut() else {
+                continue;
+            };
+            // ss leaves out counters that are zero
+            let mut retransmitted = 0;
+            for (name, value) in line
+                .split_whitespace()
+                .filter_map(|word| word.split_once(':'))
+            {
+                match name {
+                    "bytes_sent" => socket.bytes_sent = value.parse().unwrap_or(0),
+                    "bytes_retrans" => retransmitted = value.parse().unwrap_or(0),
+                    "bytes_received" => socket.bytes_received = value.parse().unwrap_or(0),
+                    // Smoothed RTT and its variance, in milliseconds
+                    "rtt" => {
+                        socket.rtt = value
+                            .split('/')
+                            .next()
+                            .and_then(|ms| ms.parse::<f64>().ok())
+                            .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+                            .unwrap_or_default();
+                    }
+                    _ => {}
+                }
+            }
+            socket.bytes_sent = socket.bytes_sent.saturating_sub(retransmitted);
+            continue;
+        }
+        sockets.extend(current.take());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let [receive_queue, send_queue, local, remote, rest @ ..] = words.as_slice() else {
+            continue;
+        };
+        current = (|| {
+            Some(TcpSocketStats {
+                local: ss_address(local)?,
+                remote: ss_address(remote)?,
+                inode: rest
+                    .iter()
+                    .find_map(|word| word.strip_prefix("ino:")?.parse().ok())
+                    .unwrap_or(0),
+                bytes_sent: 0,
+                bytes_received: 0,
+                rtt: Duration::ZERO,
+                send_queue: send_queue.parse().ok()?,
+                receive_queue: receive_queue.parse().ok()?,
+            })
+        })();
+    }
+    sockets.extend(current);
+    sockets
+}
+
+// Parses an address as ss prints it: `127.0.0.1:443`, `[::1]:443` or, for a
+// socket bound to an interface, `127.0.0.1%lo:443`.
+fn ss_address(address: &str) -> Option<SocketAddr> {
+    let (ip, port) = address.rsplit_once(':')?;
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    let ip = ip.split_once('%').map_or(ip, |(ip, _)| ip);
+    Some(SocketAddr::new(
+        unmapped(ip.parse().ok()?),
+        port.parse().ok()?,
+    ))
+}
+
+// Shows IPv4 peers of IPv6 sockets as IPv4.
+fn unmapped(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod netlink {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::os::unix::io::RawFd;
+    use std::time::Duration;
+
+    use nix::errno::Errno;
+    use nix::sys::socket::{
+        self, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+    };
+    use nix::unistd;
+
+    use super::{unmapped, TcpSocketStats};
+
+    // From linux/netlink.h, linux/sock_diag.h and linux/inet_diag.h.
+    const NLMSG_ERROR: u16 = 2;
+    const NLMSG_DONE: u16 = 3;
+    const NLM_F_REQUEST: u16 = 0x1;
+    const NLM_F_DUMP: u16 = 0x300;
+    const SOCK_DIAG_BY_FAMILY: u16 = 20;
+    const INET_DIAG_INFO: u16 = 2;
+    const TCP_ESTABLISHED: u32 = 1;
+    const IPPROTO_TCP: u8 = 6;
+    const NLMSG_HEADER_LEN: usize = 16;
+    const INET_DIAG_REQ_LEN: usize = 56;
+    const INET_DIAG_MSG_LEN: usize = 72;
+
+    // Offsets into struct tcp_info.
+    const TCPI_RTT: usize = 68;
+    const TCPI_BYTES_ACKED: usize = 120;
+    const TCPI_BYTES_RECEIVED: usize = 128;
+    const TCPI_BYTES_SENT: usize = 200;
+    const TCPI_BYTES_RETRANS: usize = 208;
+
+    // Large enough for any one datagram of a dump.
+    const RECEIVE_BUFFER_LEN: usize = 64 * 1024;
+
+    pub fn established_sockets() -> Result<Vec<TcpSocketStats>, String> {
+        let fd = socket::socket(
+            AddressFamily::Netlink,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkSockDiag,
+        )
+        .map_err(|e| format!("Failed to open sock_diag socket: {}", e))?;
+        let sockets = dump(fd, AddressFamily::Inet).and_then(|mut sockets| {
+            sockets.extend(dump(fd, AddressFamily::Inet6)?);
+            Ok(sockets)
+        });
+        let _ = unistd::close(fd);
+        sockets
+    }
+
+    // Asks for the established TCP sockets of `family`, with tcp_info, and
+    // reads the replies up to the end of the dump.
+    fn dump(fd: RawFd, family: AddressFamily) -> Result<Vec<TcpSocketStats>, String> {
+        let mut request = Vec::with_capacity(NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN);
+        // struct nlmsghdr: length, type, flags, sequence number, port
+        request.extend(((NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN) as u32).to_ne_bytes());
+        request.extend(SOCK_DIAG_BY_FAMILY.to_ne_bytes());
+        request.extend((NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+        request.extend(1u32.to_ne_bytes());
+        request.extend(0u32.to_ne_bytes());
+        // struct inet_diag_req_v2: family, protocol, extensions wanted,
+        // padding and states, then an all-zero socket id matching any
+        request.extend([family as u8, IPPROTO_TCP, 1 << (INET_DIAG_INFO - 1), 0]);
+        request.extend((1u32 << TCP_ESTABLISHED).to_ne_bytes());
+        request.resize(NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN, 0);
+        socket::sendto(fd, &request, &NetlinkAddr::new(0, 0), MsgFlags::empty())
+            .map_err(|e| format!("Failed to query sock_diag: {}", e))?;
+
+        let mut sockets = Vec::new();
+        let mut buffer = vec![0u8; RECEIVE_BUFFER_LEN];
+        loop {
+            let len = socket::recv(fd, &mut buffer, MsgFlags::empty())
+                .map_err(|e| format!("Failed to read sock_diag reply: {}", e))?;
+            let mut messages = &buffer[..len];
+            while messages.len() >= NLMSG_HEADER_LEN {
+                let message_len = u32_at(messages, 0) as usize;
+                if message_len < NLMSG_HEADER_LEN || message_len > messages.len() {
+                    return Err("Malformed sock_diag reply".to_string());
+                }
+                match u16::from_ne_bytes([messages[4], messages[5]]) {
+                    NLMSG_DONE => return Ok(sockets),
+                    NLMSG_ERROR => {
+                        let errno = i32::from_ne_bytes(
+                            messages[NLMSG_HEADER_LEN..NLMSG_HEADER_LEN + 4]
+                                .try_into()
+                                .unwrap_or_default(),
+                        );
+                        return Err(format!(
+                            "sock_diag refused the query: {}",
+                            Errno::from_i32(-errno)
+                        ));
+                    }
+                    _ => sockets.extend(parse_socket(&messages[NLMSG_HEADER_LEN..message_len])),
+                }
+                // Messages are padded to 4 bytes
+                messages = &messages[((message_len + 3) & !3).min(messages.len())..];
+            }
+        }
+    }
+
+    // Parses a struct inet_diag_msg and the attributes after it.
+    fn parse_socket(message: &[u8]) -> Option<TcpSocketStats> {
+        if message.len() < INET_DIAG_MSG_LEN {
+            return None;
+        }
+        // The socket id holds the ports and addresses in network byte order
+        let ip = |offset: usize| -> Option<IpAddr> {
+            match message[0] {
+                family if family == AddressFamily::Inet as u8 => {
+                    let octets: [u8; 4] = message[offset..offset + 4].try_into().ok()?;
+                    Some(IpAddr::V4(Ipv4Addr::from(octets)))
+                }
+                family if family == AddressFamily::Inet6 as u8 => {
+                    let octets: [u8; 16] = message[offset..offset + 16].try_into().ok()?;
+                    Some(unmapped(IpAddr::V6(Ipv6Addr::from(octets))))
+                }
+                _ => None,
+            }
+        };
+        let port = |offset: usize| u16::from_be_bytes([message[offset], message[offset + 1]]);
+        let mut socket = TcpSocketStats {
+            local: SocketAddr::new(ip(8)?, port(4)),
+            remote: SocketAddr::new(ip(24)?, port(6)),
+            inode: u64::from(u32_at(message, 68)),
+            bytes_sent: 0,
+            bytes_received: 0,
+            rtt: Duration::ZERO,
+            send_queue: u32_at(message, 60),
+            receive_queue: u32_at(message, 56),
+        };
+
+        // struct rtattr: length, type, then the payload, padded to 4 bytes
+        let mut attributes = &message[INET_DIAG_MSG_LEN..];
+        while attributes.len() >= 4 {
+            let len = u16::from_ne_bytes([attributes[0], attributes[1]]) as usize;
+            if len < 4 || len > attributes.len() {
+                break;
+            }
+            if u16::from_ne_bytes([attributes[2], attributes[3]]) == INET_DIAG_INFO {
+                let info = &attributes[4..len];
+                if info.len() >= TCPI_BYTES_RECEIVED + 8 {
+                    socket.rtt = Duration::from_micros(u64::from(u32_at(info, TCPI_RTT)));
+                    socket.bytes_received = u64_at(info, TCPI_BYTES_RECEIVED);
+                    // Before Linux 4.19 only acknowledged bytes are counted,
+                    // including the SYN of connections stunnel opened
+                    socket.bytes_sent = if info.len() >= TCPI_BYTES_RETRANS + 8 {
+                        u64_at(info, TCPI_BYTES_SENT)
+                            .saturating_sub(u64_at(info, TCPI_BYTES_RETRANS))
+                    } else {
+                        u64_at(info, TCPI_BYTES_ACKED)
+                    };
+                }
+            }
+            attributes = &attributes[((len + 3) & !3).min(attributes.len())..];
+        }
+        Some(socket)
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap_or_default())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap_or_default())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod netlink {
+    use super::TcpSocketStats;
+
+    pub fn established_sockets() -> Result<Vec<TcpSocketStats>, String> {
+        Err("sock_diag is only available on Linux".to_string())
+    }
+}
@@ -0,0 +1,170 @@
+//! Staged config changes awaiting approval.
+//!
+//! StageConfig validates a candidate config and parks it under a random
+//! token instead of applying it, so a CI pipeline can prepare a change, a
+//! human can review the returned diff, and CommitConfig flips it live in a
+//! separate step (or DiscardConfig drops it). A staged candidate records the
+//! version of the config it was prepared against and is only committed if
+//! the config has not changed since.
+
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Extension of staged candidate files in the staging directory.
+pub const STAGED_EXTENSION: &str = "json";
+
+// Random bytes in a staging token.
+const TOKEN_LEN: usize = 16;
+
+/// A candidate config waiting to be committed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagedConfig {
+    pub token: String,
+    pub content: String,
+    /// Version of the config the candidate was staged against.
+    pub base_version: String,
+    pub staged_by: String,
+    pub staged_at: DateTime<Utc>,
+}
+
+/// Staged candidates, optionally persisted to a directory so they survive
+/// a restart.
+#[derive(Debug, Default)]
+pub struct StagingArea {
+    dir: Option<PathBuf>,
+    staged: RwLock<HashMap<String, StagedConfig>>,
+}
+
+impl StagingArea {
+    /// Creates a staging area that keeps candidates in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a staging area persisted to `dir`, loading every candidate in
+    /// it.
+    ///
+    /// The directory is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a candidate cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::staging::StagingArea;
+    ///
+    /// let staging = StagingArea::open("/var/lib/stunnel-space/staged")
+    ///     .expect("Failed to load staged configs");
+    /// ```
+    pub fn open(dir: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut staged = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(STAGED_EXTENSION) {
+                continue;
+            }
+            let candidate: StagedConfig = serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid staged config {}: {}", path.display(), e),
+                    )
+                })?;
+            staged.insert(candidate.token.clone(), candidate);
+        }
+
+        Ok(Self {
+            dir: Some(PathBuf::from(dir)),
+            staged: RwLock::new(staged),
+        })
+    }
+
+    /// Stages `content`, prepared against config version `base_version`,
+    /// under a new random token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no token can be generated or the candidate cannot
+    /// be persisted.
+    pub fn stage(
+        &self,
+        content: &str,
+        base_version: &str,
+        staged_by: &str,
+    ) -> io::Result<StagedConfig> {
+        let mut bytes = [0u8; TOKEN_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| io::Error::other("Failed to generate a token"))?;
+        let candidate = StagedConfig {
+            token: bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            content: content.to_string(),
+            base_version: base_version.to_string(),
+            staged_by: staged_by.to_string(),
+            staged_at: Utc::now(),
+        };
+
+        if let Some(dir) = &self.dir {
+            let json = serde_json::to_string_pretty(&candidate).map_err(io::Error::other)?;
+            let path = dir.join(format!("{}.{}", candidate.token, STAGED_EXTENSION));
+            let tmp_path = dir.join(format!(".{}.tmp.{}", candidate.token, std::process::id()));
+            {
+                // Candidates may hold secrets, like the live config
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&tmp_path)?;
+                file.write_all(json.as_bytes())?;
+                file.sync_all()?;
+            }
+            fs::rename(&tmp_path, &path)?;
+        }
+
+        self.staged
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(candidate.token.clone(), candidate.clone());
+        Ok(candidate)
+    }
+
+    /// Returns the candidate staged under `token`.
+    pub fn get(&self, token: &str) -> Option<StagedConfig> {
+        self.staged
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token)
+            .cloned()
+    }
+
+    /// Drops the candidate staged under `token`, returning whether there
+    /// was one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if its file cannot be removed.
+    pub fn discard(&self, token: &str) -> io::Result<bool> {
+        let mut staged = self.staged.write().unwrap_or_else(|e| e.into_inner());
+        if !staged.contains_key(token) {
+            return Ok(false);
+        }
+        if let Some(dir) = &self.dir {
+            match fs::remove_file(dir.join(format!("{}.{}", token, STAGED_EXTENSION))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        staged.remove(token);
+        Ok(true)
+    }
+}
@@ -0,0 +1,202 @@
+//! Pushing metrics to StatsD.
+//!
+//! Where nothing scrapes `/metrics`, the same samples (see
+//! [`crate::metrics`]) can be sent on an interval to a StatsD server over
+//! UDP, with labels as DogStatsD tags:
+//!
+//! ```text
+//! stunnel_provider_active_connections:3|g|#instance:default,provider:web,accept_port:443
+//! stunnel_provider_connections:12|c|#instance:default,provider:web,accept_port:443
+//! ```
+//!
+//! Gauges are sent as they are. StatsD adds counters up itself, so each
+//! push sends how much a counter grew since the previous one; the first
+//! sends everything counted since the manager started. The Datadog agent,
+//! Telegraf's `statsd` input and `statsd_exporter` understand the tags; a
+//! plain StatsD server may not.
+//!
+//! UDP is fire-and-forget: samples sent while the server is down are lost,
+//! counters included.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::metrics::{Kind, Labels, MetricSet};
+
+/// How often metrics are pushed when no interval is configured.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Largest datagram sent, so that one fits in an Ethernet frame; lines are
+// batched into datagrams up to this size, and a longer line goes alone.
+const MAX_PACKET_BYTES: usize = 1432;
+
+/// Sends metric samples to one StatsD server.
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    target: SocketAddr,
+    prefix: String,
+    // The value of each counter at the previous push, by name and tags.
+    counters: Mutex<HashMap<(String, String), f64>>,
+}
+
+impl StatsdSink {
+    /// Resolves `address`, a `host:port`, and opens a socket to send to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `address` does not resolve or no socket can be
+    /// opened.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::statsd::StatsdSink;
+    ///
+    /// let sink = StatsdSink::connect("127.0.0.1:8125")
+    ///     .expect("Failed to open StatsD socket")
+    ///     .with_prefix("edge.");
+    /// ```
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let target = address
+            .to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve StatsD address {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("StatsD address {} resolves to nothing", address))?;
+        let local: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)
+            .and_then(|socket| socket.connect(target).map(|()| socket))
+            .map_err(|e| format!("Failed to open StatsD socket to {}: {}", target, e))?;
+        Ok(Self {
+            socket,
+            target,
+            prefix: String::new(),
+            counters: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Prepends `prefix` to every metric name, e.g. `edge.` for
+    /// `edge.stunnel_provider_connections`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Where samples are sent.
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    /// The lines a push of `metrics` sends, remembering counter values for
+    /// the next push. A counter lower than at the previous push restarted,
+    /// so its whole value is sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::metrics::MetricSet;
+    /// use stunnel_space::statsd::StatsdSink;
+    ///
+    /// let sink = StatsdSink::connect("127.0.0.1:8125").unwrap();
+    /// let labels = vec![("provider", "web".to_string())];
+    /// let mut metrics = MetricSet::new();
+    /// metrics.add("stunnel_provider_connections", labels.clone(), 3.0);
+    /// assert_eq!(sink.lines(&metrics), ["stunnel_provider_connections:3|c|#provider:web"]);
+    /// let mut metrics = MetricSet::new();
+    /// metrics.add("stunnel_provider_connections", labels, 5.0);
+    /// assert_eq!(sink.lines(&metrics), ["stunnel_provider_connections:2|c|#provider:web"]);
+    /// ```
+    pub fn lines(&self, metrics: &MetricSet) -> Vec<String> {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        metrics
+            .samples()
+            .map(|(name, kind, labels, value)| {
+                let name = format!("{}{}", self.prefix, name);
+                let tags = tags(labels);
+                let (value, kind) = match kind {
+                    Kind::Gauge => (value, "g"),
+                    Kind::Counter => {
+                        let previous = counters
+                            .insert((name.clone(), tags.clone()), value)
+                            .unwrap_or(0.0);
+                        (
+                            if value < previous {
+                                value
+                            } else {
+                                value - previous
+                            },
+                            "c",
+                        )
+                    }
+                };
+                let mut line = format!("{}:{}|{}", name, value, kind);
+                if !tags.is_empty() {
+                    let _ = write!(line, "|#{}", tags);
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Sends `metrics`, batching lines into as few datagrams as fit.
+    /// Returns how many lines were sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a datagram cannot be sent, e.g. because the
+    /// previous one was refused. Counters are still remembered, so their
+    /// growth until then is lost.
+    pub fn push(&self, metrics: &MetricSet) -> Result<usize, String> {
+        let lines = self.lines(metrics);
+        let mut packet = String::new();
+        for line in &lines {
+            if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_BYTES {
+                self.send(&packet)?;
+                packet.clear();
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(line);
+        }
+        if !packet.is_empty() {
+            self.send(&packet)?;
+        }
+        Ok(lines.len())
+    }
+
+    fn send(&self, packet: &str) -> Result<(), String> {
+        self.socket
+            .send(packet.as_bytes())
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send metrics to StatsD at {}: {}", self.target, e))
+    }
+}
+
+// Labels as DogStatsD tags, `name:value` joined by commas. Labels without a
+// value are left out, and characters that separate tags or fields in the
+// line are replaced.
+fn tags(labels: &Labels) -> String {
+    labels
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| {
+            let value: String = value
+                .chars()
+                .map(|c| match c {
+                    ',' | '|' | '#' | '\n' => '_',
+                    c => c,
+                })
+                .collect();
+            format!("{}:{}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,181 @@
+//! Structured (JSON/YAML) representation of a stunnel configuration.
+//!
+//! Globals and providers are exported as ordered lists of `key`/`value`
+//! options, so repeated options such as `include` and their order survive a
+//! round trip. This is the format external tooling such as CMDBs,
+//! Terraform or Ansible consumes, and the desired-state format accepted on
+//! import.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::parser::{self, StunnelConfig};
+
+/// Serialization formats supported for structured configs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Json => write!(f, "JSON"),
+            Format::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// A single `key = value` option.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructuredOption {
+    pub key: String,
+    pub value: String,
+}
+
+/// A `[name]` service section and its options, in file order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructuredProvider {
+    pub name: String,
+    #[serde(default)]
+    pub options: Vec<StructuredOption>,
+}
+
+/// Globals and providers of a stunnel configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructuredConfig {
+    #[serde(default)]
+    pub globals: Vec<StructuredOption>,
+    #[serde(default)]
+    pub providers: Vec<StructuredProvider>,
+}
+
+impl StructuredConfig {
+    /// Builds the structured form of a parsed config.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::parser::StunnelConfig;
+    /// use stunnel_space::structured::{Format, StructuredConfig};
+    ///
+    /// let config = StunnelConfig::parse("debug = 5\n[web]\naccept = 443\n");
+    /// let json = StructuredConfig::from_config(&config).serialize(Format::Json).unwrap();
+    /// assert!(json.contains("\"name\": \"web\""));
+    /// ```
+    pub fn from_config(config: &StunnelConfig) -> Self {
+        Self {
+            globals: structured_options(&config.globals),
+            providers: config
+                .sections
+                .iter()
+                .map(|section| StructuredProvider {
+                    name: section.name.clone(),
+                    options: structured_options(&section.options),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses a structured config document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document is malformed or describes options
+    /// or provider names that cannot be written to a stunnel config.
+    pub fn deserialize(content: &str, format: Format) -> Result<Self, String> {
+        let config: Self = match format {
+            Format::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+            Format::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?,
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Renders the config as stunnel configuration content.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::structured::{Format, StructuredConfig};
+    ///
+    /// let yaml = "globals:\n  - {key: debug, value: '5'}\nproviders:\n  - name: web\n    options:\n      - {key: accept, value: '443'}\n";
+    /// let config = StructuredConfig::deserialize(yaml, Format::Yaml).unwrap();
+    /// assert_eq!(config.render(), "debug = 5\n\n; web service\n[web]\naccept = 443\n");
+    /// ```
+    pub fn render(&self) -> String {
+        let mut content = String::new();
+        for option in &self.globals {
+            content.push_str(&format!("{} = {}\n", option.key, option.value));
+        }
+        for provider in &self.providers {
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(&format!(
+                "; {} service\n[{}]\n",
+                provider.name, provider.name
+            ));
+            for option in &provider.options {
+                content.push_str(&format!("{} = {}\n", option.key, option.value));
+            }
+        }
+        content
+    }
+
+    // Rejects anything that would not survive being written as config lines.
+    fn check(&self) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for provider in &self.providers {
+            let name = provider.name.trim();
+            if name.is_empty() || name != provider.name || name.contains(['[', ']', '\n', '\r']) {
+                return Err(format!("Invalid provider name: {:?}", provider.name));
+            }
+            if !names.insert(name) {
+                return Err(format!("Duplicate provider name: {}", name));
+            }
+        }
+
+        let options = self
+            .globals
+            .iter()
+            .chain(self.providers.iter().flat_map(|provider| &provider.options));
+        for option in options {
+            let key = option.key.trim();
+            if key.is_empty()
+                || key.starts_with([';', '#', '['])
+                || key.contains(['=', '\n', '\r'])
+                || option.value.contains(['\n', '\r'])
+            {
+                return Err(format!(
+                    "Invalid option: {:?} = {:?}",
+                    option.key, option.value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes the config in the given format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    pub fn serialize(&self, format: Format) -> Result<String, String> {
+        match format {
+            Format::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::to_string(self).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn structured_options(options: &[parser::ConfigOption]) -> Vec<StructuredOption> {
+    options
+        .iter()
+        .map(|option| StructuredOption {
+            key: option.key.clone(),
+            value: option.value.clone(),
+        })
+        .collect()
+}
@@ -0,0 +1,175 @@
+//! Supervision of the stunnel process.
+//!
+//! stunnel has no supervisor of its own: if it crashes, its tunnels stay
+//! down until someone notices. With supervision enabled the manager checks
+//! on stunnel periodically and starts it again when it has died without
+//! being stopped through the API, backing off exponentially while it keeps
+//! crashing so a broken config does not cause a restart storm.
+//!
+//! Only a stunnel the manager has seen running is restarted; one that was
+//! never started, or was stopped with StopStunnel, is left alone.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::retry::RetryPolicy;
+
+/// How often stunnel is checked when no interval is configured.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+// Delay before the first restart after a crash, doubled for each crash or
+// failed restart in a row.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+// How long a restarted stunnel must keep running before its crashes stop
+// counting towards the backoff.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// What the supervisor knows about stunnel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SupervisorState {
+    /// PID of stunnel while it runs.
+    pub pid: Option<i32>,
+    /// Whether stunnel should be running: set once it is seen running,
+    /// cleared when it is stopped through the API.
+    pub expected_running: bool,
+    /// Times stunnel died without being stopped.
+    pub crash_count: u32,
+    /// Times the supervisor started stunnel again.
+    pub restart_count: u32,
+    pub last_crash_reason: Option<String>,
+    pub last_crash_at: Option<DateTime<Utc>>,
+    /// Why the latest restart failed, until one succeeds.
+    pub last_restart_error: Option<String>,
+    /// When the next restart is due, while stunnel is down.
+    pub next_restart_at: Option<DateTime<Utc>>,
+    // Crashes and failed restarts in a row, for the backoff
+    failures: u32,
+    restarted_at: Option<DateTime<Utc>>,
+}
+
+/// Supervision state, shared by every clone.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    state: Arc<Mutex<SupervisorState>>,
+    backoff: RetryPolicy,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            backoff: RetryPolicy {
+                attempts: 1,
+                initial_backoff: INITIAL_BACKOFF,
+            },
+        }
+    }
+}
+
+impl Supervisor {
+    /// Creates a supervisor that has not seen stunnel yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a snapshot of the supervision state.
+    pub fn state(&self) -> SupervisorState {
+        self.lock().clone()
+    }
+
+    /// Records that stunnel is running as `pid`.
+    pub fn observe_running(&self, pid: i32) {
+        let mut state = self.lock();
+        let now = Utc::now();
+        state.pid = Some(pid);
+        state.expected_running = true;
+        state.next_restart_at = None;
+        let stable = state
+            .restarted_at
+            .is_none_or(|restarted| elapsed_since(restarted, now) >= STABLE_AFTER);
+        if stable {
+            state.failures = 0;
+        }
+    }
+
+    /// Records that stunnel was stopped on purpose, so it is not restarted.
+    pub fn stopped(&self) {
+        let mut state = self.lock();
+        state.pid = None;
+        state.expected_running = false;
+        state.next_restart_at = None;
+        state.failures = 0;
+    }
+
+    /// Records that stunnel is not running and returns whether it should be
+    /// restarted now. If it was running before, this is a crash, described
+    /// by `reason` from the PID it had.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::supervisor::Supervisor;
+    ///
+    /// let supervisor = Supervisor::new();
+    /// supervisor.observe_running(4242);
+    /// let due = supervisor.observe_down(|pid| format!("PID {} exited", pid));
+    /// assert!(!due); // after a crash, the first restart waits a second
+    /// let state = supervisor.state();
+    /// assert_eq!(state.crash_count, 1);
+    /// assert_eq!(state.last_crash_reason.as_deref(), Some("PID 4242 exited"));
+    /// ```
+    pub fn observe_down(&self, reason: impl FnOnce(i32) -> String) -> bool {
+        let mut state = self.lock();
+        if !state.expected_running {
+            return false;
+        }
+        let now = Utc::now();
+        if let Some(pid) = state.pid.take() {
+            let reason = reason(pid);
+            tracing::error!(pid, "Stunnel crashed: {}", reason);
+            state.crash_count += 1;
+            state.last_crash_reason = Some(reason);
+            state.last_crash_at = Some(now);
+            self.back_off(&mut state, now);
+        }
+        state.next_restart_at.is_none_or(|due| now >= due)
+    }
+
+    /// Records that stunnel was restarted as `pid`.
+    pub fn restarted(&self, pid: i32) {
+        let mut state = self.lock();
+        let now = Utc::now();
+        state.pid = Some(pid);
+        state.restart_count += 1;
+        state.restarted_at = Some(now);
+        state.last_restart_error = None;
+        state.next_restart_at = None;
+    }
+
+    /// Records that restarting stunnel failed with `error`; the next attempt
+    /// waits longer.
+    pub fn restart_failed(&self, error: impl Into<String>) {
+        let mut state = self.lock();
+        state.last_restart_error = Some(error.into());
+        self.back_off(&mut state, Utc::now());
+    }
+
+    fn back_off(&self, state: &mut SupervisorState, now: DateTime<Utc>) {
+        state.failures += 1;
+        let delay = chrono::Duration::from_std(self.backoff.backoff(state.failures))
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        state.next_restart_at = Some(now + delay);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SupervisorState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn elapsed_since(then: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (now - then).to_std().unwrap_or_default()
+}
@@ -0,0 +1,153 @@
+//! systemd service notifications.
+//!
+//! Under a `Type=notify` unit, systemd waits for the manager to report
+//! `READY=1` before starting units ordered after it, restarts it if
+//! `WATCHDOG=1` keepalives stop arriving within `WatchdogSec`, and shows
+//! the latest `STATUS=` line in `systemctl status`. Messages are datagrams
+//! sent to the socket named by `NOTIFY_SOCKET`, as described in
+//! sd_notify(3); outside systemd that variable is unset and nothing is sent.
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Longest time between refreshes of the status line.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Sends notifications to the systemd service manager.
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// Connects to the socket named by `NOTIFY_SOCKET`, or returns `None`
+    /// when not run by systemd with `Type=notify`. A name starting with `@`
+    /// is in the abstract namespace.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket address is invalid or no socket can
+    /// be created.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::systemd::Notifier;
+    ///
+    /// if let Some(notifier) = Notifier::from_env().unwrap() {
+    ///     notifier.ready("Serving gRPC on 127.0.0.1:50055").unwrap();
+    /// }
+    /// ```
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let path = path.to_string_lossy().into_owned();
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        Ok(Some(Self {
+            socket: UnixDatagram::unbound()?,
+            addr,
+        }))
+    }
+
+    /// Sends raw `KEY=value` assignments, one per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be sent.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.addr)?;
+        Ok(())
+    }
+
+    /// Reports that startup has finished, with a status line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be sent.
+    pub fn ready(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("READY=1\nSTATUS={}", single_line(status)))
+    }
+
+    /// Replaces the status line shown by `systemctl status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be sent.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", single_line(status)))
+    }
+
+    /// Sends a watchdog keepalive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be sent.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    /// Reports that the manager is shutting down.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datagram cannot be sent.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    /// Spawns a task sending watchdog keepalives when the watchdog is on,
+    /// and refreshing the status line from `status` when it changes.
+    pub fn spawn_keepalive(
+        self: Arc<Self>,
+        status: impl Fn() -> String + Send + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        let watchdog = watchdog_interval();
+        let period = watchdog.map_or(STATUS_INTERVAL, |interval| interval.min(STATUS_INTERVAL));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            let mut reported = String::new();
+            loop {
+                ticker.tick().await;
+                if watchdog.is_some() {
+                    if let Err(e) = self.watchdog() {
+                        tracing::warn!("Failed to send systemd watchdog keepalive: {}", e);
+                    }
+                }
+                let current = status();
+                if current != reported {
+                    if let Err(e) = self.status(&current) {
+                        tracing::warn!("Failed to send systemd status: {}", e);
+                    }
+                    reported = current;
+                }
+            }
+        })
+    }
+}
+
+/// Returns how often to send watchdog keepalives: half of the `WatchdogSec`
+/// systemd passes in `WATCHDOG_USEC`, or `None` if the watchdog is off or
+/// meant for another process according to `WATCHDOG_PID`.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.trim().parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec) / 2)
+}
+
+// A STATUS value ends at the first line break.
+fn single_line(status: &str) -> String {
+    status.replace(['\n', '\r'], " ")
+}
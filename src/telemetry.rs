@@ -0,0 +1,129 @@
+//! Exporting the manager's spans over OTLP.
+//!
+//! Every StunnelManager RPC runs in an `rpc` span, and the steps that take
+//! time inside it — validation, backups, writes, reloads, starting and
+//! stopping stunnel — in child spans, so a trace in Jaeger or any other
+//! OTLP backend shows where a slow call spent its time. A caller that sends
+//! a W3C `traceparent` header gets the RPC's spans in its own trace.
+//!
+//! Spans are only exported when an OTLP endpoint is configured; otherwise
+//! they just give log events their context.
+
+use opentelemetry::propagation::Extractor;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::{self as sdktrace, Tracer};
+use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use tonic::metadata::{KeyRef, MetadataMap};
+use tonic::Status;
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Service name spans are exported under when none is configured.
+pub const DEFAULT_SERVICE_NAME: &str = "stunnel-space";
+
+// The gRPC service every `rpc` span belongs to.
+const RPC_SERVICE: &str = "vfxstunnel.StunnelManager";
+
+/// Starts exporting spans in batches to the OTLP/gRPC collector at
+/// `endpoint`, e.g. `http://localhost:4317`, as `service_name`. Must be
+/// called from within the Tokio runtime, which the exporter runs on.
+///
+/// # Errors
+///
+/// Returns an error if the exporter cannot be set up, e.g. because
+/// `endpoint` is not a valid URI.
+pub fn otlp_tracer(endpoint: &str, service_name: &str) -> Result<Tracer, String> {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            sdktrace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .map_err(|e| format!("Failed to set up OTLP exporter for {}: {}", endpoint, e))
+}
+
+/// Exports the spans still buffered. Blocks until the exporter is done.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// The span an RPC handler runs in, named after the gRPC method, e.g.
+/// `UpdateConfig` for `update_config`. If `metadata` carries a
+/// `traceparent`, the span joins the caller's trace.
+pub fn rpc_span(handler: &str, metadata: &MetadataMap) -> Span {
+    let method = method_name(handler);
+    let span = tracing::info_span!(
+        "rpc",
+        otel.name = %format!("{}/{}", RPC_SERVICE, method),
+        otel.kind = "server",
+        rpc.system = "grpc",
+        rpc.service = RPC_SERVICE,
+        rpc.method = %method,
+        instance = Empty,
+        rpc.grpc.status_code = Empty,
+        otel.status_code = Empty,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(metadata))
+    });
+    span.set_parent(parent);
+    span
+}
+
+/// Records on `span` how the RPC it covers ended.
+pub fn record_result<T>(span: &Span, result: &Result<T, Status>) {
+    match result {
+        Ok(_) => {
+            span.record("rpc.grpc.status_code", 0);
+        }
+        Err(status) => {
+            span.record("rpc.grpc.status_code", status.code() as i32);
+            span.record("otel.status_code", "ERROR");
+        }
+    }
+}
+
+/// The gRPC method a handler implements: `update_config` is `UpdateConfig`.
+pub fn method_name(handler: &str) -> String {
+    handler
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+// Reads propagation headers from gRPC request metadata.
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                KeyRef::Ascii(key) => Some(key.as_str()),
+                KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,180 @@
+//! Named provider templates.
+//!
+//! A template is the body of a service section with `{{ var }}`
+//! placeholders, for example an "https-terminator" template holding the
+//! `accept`, `connect` and certificate options every such service shares.
+//! Rendering a template with a set of variables stamps out a consistent
+//! section without sending every field each time. The provider name is
+//! always available as `{{ name }}`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Extension of template files in the templates directory.
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Registered provider templates, optionally persisted to a directory.
+#[derive(Debug, Default)]
+pub struct TemplateStore {
+    dir: Option<PathBuf>,
+    templates: RwLock<BTreeMap<String, String>>,
+}
+
+impl TemplateStore {
+    /// Creates a store that keeps templates in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a store persisted to `dir`, loading every `*.tmpl` file in it.
+    ///
+    /// The directory is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or a template cannot be read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stunnel_space::templates::TemplateStore;
+    ///
+    /// let store = TemplateStore::open("/etc/stunnel-space/templates")
+    ///     .expect("Failed to load templates");
+    /// ```
+    pub fn open(dir: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut templates = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(TEMPLATE_EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                templates.insert(name.to_string(), fs::read_to_string(&path)?);
+            }
+        }
+
+        Ok(Self {
+            dir: Some(PathBuf::from(dir)),
+            templates: RwLock::new(templates),
+        })
+    }
+
+    /// Registers (or replaces) the template `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` cannot be used as a file name, or the
+    /// template cannot be persisted.
+    pub fn register(&self, name: &str, content: &str) -> io::Result<()> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Template name {:?} cannot be used as a file name", name),
+            ));
+        }
+
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+            let tmp_path = dir.join(format!(".{}.tmp.{}", name, std::process::id()));
+            {
+                let mut file = fs::File::create(&tmp_path)?;
+                file.write_all(content.as_bytes())?;
+                file.sync_all()?;
+            }
+            fs::rename(&tmp_path, &path)?;
+        }
+
+        self.templates
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), content.to_string());
+        Ok(())
+    }
+
+    /// Returns the content of template `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.templates
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// Returns every registered template as `(name, content)`, sorted by name.
+    pub fn list(&self) -> Vec<(String, String)> {
+        self.templates
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, content)| (name.clone(), content.clone()))
+            .collect()
+    }
+}
+
+/// Returns the distinct placeholder names used in `template`, in order of
+/// first appearance.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some((name, after)) = next_placeholder(rest) {
+        if !names.iter().any(|existing| existing == name) {
+            names.push(name.to_string());
+        }
+        rest = after;
+    }
+    names
+}
+
+/// Replaces every `{{ var }}` placeholder in `template` with its value.
+///
+/// # Errors
+///
+/// Returns the names of any placeholders without a value in `vars`.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use stunnel_space::templates::render;
+///
+/// let vars = HashMap::from([("port".to_string(), "443".to_string())]);
+/// assert_eq!(render("accept = {{ port }}", &vars).unwrap(), "accept = 443");
+/// assert_eq!(render("cert = {{cert}}", &vars).unwrap_err(), vec!["cert"]);
+/// ```
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, Vec<String>> {
+    let missing: Vec<String> = placeholders(template)
+        .into_iter()
+        .filter(|name| !vars.contains_key(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        match next_placeholder(&rest[start..]) {
+            Some((name, after)) => {
+                rendered.push_str(&rest[..start]);
+                rendered.push_str(&vars[name]);
+                rest = after;
+            }
+            None => break,
+        }
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+// Finds the first `{{ name }}` placeholder, returning its trimmed name and
+// the text after it.
+fn next_placeholder(text: &str) -> Option<(&str, &str)> {
+    let start = text.find("{{")?;
+    let end = text[start..].find("}}")? + start;
+    Some((text[start + 2..end].trim(), &text[end + 2..]))
+}
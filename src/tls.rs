@@ -0,0 +1,91 @@
+//! TLS settings for generated stunnel configurations.
+//!
+//! Profiles follow Mozilla's server side TLS recommendations (guidelines
+//! version 5.7) and expand into the `sslVersionMin`, `ciphers`,
+//! `ciphersuites` and `options` lines stunnel understands, replacing
+//! stunnel's more permissive defaults.
+
+use std::fmt;
+
+// TLS 1.3 suites shared by every profile.
+const TLS13_CIPHERSUITES: &str =
+    "TLS_AES_128_GCM_SHA256:TLS_AES_256_GCM_SHA384:TLS_CHACHA20_POLY1305_SHA256";
+
+const INTERMEDIATE_CIPHERS: &str = "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:\
+ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:ECDHE-ECDSA-CHACHA20-POLY1305:\
+ECDHE-RSA-CHACHA20-POLY1305:DHE-RSA-AES128-GCM-SHA256:DHE-RSA-AES256-GCM-SHA384:\
+DHE-RSA-CHACHA20-POLY1305";
+
+const OLD_CIPHERS: &str = "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:\
+ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:ECDHE-ECDSA-CHACHA20-POLY1305:\
+ECDHE-RSA-CHACHA20-POLY1305:DHE-RSA-AES128-GCM-SHA256:DHE-RSA-AES256-GCM-SHA384:\
+DHE-RSA-CHACHA20-POLY1305:ECDHE-ECDSA-AES128-SHA256:ECDHE-RSA-AES128-SHA256:\
+ECDHE-ECDSA-AES128-SHA:ECDHE-RSA-AES128-SHA:ECDHE-ECDSA-AES256-SHA384:ECDHE-RSA-AES256-SHA384:\
+ECDHE-ECDSA-AES256-SHA:ECDHE-RSA-AES256-SHA:DHE-RSA-AES128-SHA256:DHE-RSA-AES256-SHA256:\
+AES128-GCM-SHA256:AES256-GCM-SHA384:AES128-SHA256:AES256-SHA256:AES128-SHA:AES256-SHA:\
+DES-CBC3-SHA";
+
+/// Mozilla server side TLS profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsProfile {
+    /// TLS 1.3 only, for clients that all support it.
+    Modern,
+    /// TLS 1.2 and 1.3 with forward-secret AEAD ciphers; the general-purpose default.
+    Intermediate,
+    /// TLS 1.0 and later, only for very old clients.
+    Old,
+}
+
+impl TlsProfile {
+    /// Returns the config options implementing this profile, in the order
+    /// they should be written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::tls::TlsProfile;
+    ///
+    /// let options = TlsProfile::Modern.options();
+    /// assert!(options.contains(&("sslVersionMin", "TLSv1.3")));
+    /// ```
+    pub fn options(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            TlsProfile::Modern => vec![
+                ("sslVersionMin", "TLSv1.3"),
+                ("ciphersuites", TLS13_CIPHERSUITES),
+                ("options", "NO_RENEGOTIATION"),
+            ],
+            TlsProfile::Intermediate => vec![
+                ("sslVersionMin", "TLSv1.2"),
+                ("ciphers", INTERMEDIATE_CIPHERS),
+                ("ciphersuites", TLS13_CIPHERSUITES),
+                ("options", "NO_RENEGOTIATION"),
+            ],
+            TlsProfile::Old => vec![
+                ("sslVersionMin", "TLSv1"),
+                ("ciphers", OLD_CIPHERS),
+                ("ciphersuites", TLS13_CIPHERSUITES),
+                ("options", "CIPHER_SERVER_PREFERENCE"),
+            ],
+        }
+    }
+
+    /// Renders the profile as config lines, preceded by a comment naming it.
+    pub fn render(&self) -> String {
+        let mut content = format!("; Mozilla {} TLS profile\n", self);
+        for (key, value) in self.options() {
+            content.push_str(&format!("{} = {}\n", key, value));
+        }
+        content
+    }
+}
+
+impl fmt::Display for TlsProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsProfile::Modern => write!(f, "modern"),
+            TlsProfile::Intermediate => write!(f, "intermediate"),
+            TlsProfile::Old => write!(f, "old"),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Per-service traffic totals from stunnel's log.
+//!
+//! At `debug = 5` (notice, stunnel's default) or higher, stunnel logs each
+//! connection it accepts and, when the connection ends, how much it
+//! forwarded each way:
+//!
+//! ```text
+//! 2024.01.31 12:00:00 LOG5[7]: Service [backend-a] accepted connection from 10.0.0.2:51234
+//! 2024.01.31 12:00:09 LOG5[7]: Connection closed: 5120 byte(s) sent to TLS, 880 byte(s) sent to socket
+//! ```
+//!
+//! The closing line names no service, so the connection number in brackets
+//! ties it to the line that accepted it. [`TrafficAccounting`] follows the
+//! log file and adds up the closed connections per service, and reports
+//! each connection opened or closed as a [`ConnectionEvent`] for
+//! [`crate::connlog`] to keep. It also counts the configs stunnel logged
+//! applying or rejecting, whichever call site sent the reload signal.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::logs::{parse_line, reload_outcome, LogCursor, LogLine, ReloadOutcome};
+
+/// How often the log is read for new lines in the background.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Connections tracked between their accepted and closed lines. Connections
+// whose close was never logged, e.g. because stunnel was killed, are
+// forgotten once there are this many.
+const MAX_OPEN_CONNECTIONS: usize = 100_000;
+
+/// What the connections of one service forwarded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServiceTraffic {
+    /// Connections closed.
+    pub connections: u64,
+    /// Bytes sent over TLS, i.e. read from the plaintext side.
+    pub bytes_to_tls: u64,
+    /// Bytes sent to the plaintext socket, i.e. read over TLS.
+    pub bytes_to_socket: u64,
+    pub last_closed_at: Option<DateTime<Utc>>,
+}
+
+/// A connection stunnel logged accepting or closing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionEvent {
+    /// stunnel's number for the connection, which restarts with stunnel.
+    pub connection: String,
+    pub service: String,
+    /// Address the connection came from.
+    pub peer: String,
+    pub opened_at: DateTime<Utc>,
+    /// Set once the connection has closed.
+    pub closed: Option<ClosedConnection>,
+}
+
+/// How a connection ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClosedConnection {
+    pub closed_at: DateTime<Utc>,
+    pub bytes_to_tls: u64,
+    pub bytes_to_socket: u64,
+}
+
+/// Configs stunnel logged loading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReloadCounts {
+    /// Configs applied, at start or on reload.
+    pub applied: u64,
+    /// Reloaded configs rejected, leaving the previous one in place.
+    pub rejected: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    since: DateTime<Utc>,
+    cursor: Option<LogCursor>,
+    // Each open connection, by connection number.
+    open: HashMap<String, ConnectionEvent>,
+    services: BTreeMap<String, ServiceTraffic>,
+    reloads: ReloadCounts,
+}
+
+/// Cumulative traffic per service of one stunnel. Clones share totals.
+#[derive(Debug, Clone)]
+pub struct TrafficAccounting {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for TrafficAccounting {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                since: Utc::now(),
+                cursor: None,
+                open: HashMap::new(),
+                services: BTreeMap::new(),
+                reloads: ReloadCounts::default(),
+            })),
+        }
+    }
+}
+
+impl TrafficAccounting {
+    /// Starts counting from now.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// When counting started; the totals cover connections closed since.
+    pub fn since(&self) -> DateTime<Utc> {
+        self.state().since
+    }
+
+    /// Records the lines logged to `path` since the last call, returning
+    /// the connections they open and close. The first call for a path
+    /// starts at its end, since earlier connections may already have been
+    /// counted under another path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log exists but cannot be read.
+    pub fn follow(&self, path: &Path) -> io::Result<Vec<ConnectionEvent>> {
+        let mut state = self.state();
+        if state
+            .cursor
+            .as_ref()
+            .is_none_or(|cursor| cursor.path() != path)
+        {
+            state.cursor = Some(LogCursor::at_end(path));
+            state.open.clear();
+        }
+        let lines = match state.cursor.as_mut() {
+            Some(cursor) => cursor.read_new()?,
+            None => return Ok(vec![]),
+        };
+        Ok(lines
+            .iter()
+            .filter_map(|line| parse_line(line))
+            .filter_map(|line| record(&mut state, &line))
+            .collect())
+    }
+
+    /// Records one log line, returning the connection it opens or closes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::logs::parse_line;
+    /// use stunnel_space::traffic::TrafficAccounting;
+    ///
+    /// let traffic = TrafficAccounting::new();
+    /// let opened = traffic.record(&parse_line(
+    ///     "LOG5[7]: Service [backend-a] accepted connection from 10.0.0.2:51234",
+    /// ).unwrap());
+    /// assert_eq!(opened.unwrap().peer, "10.0.0.2:51234");
+    /// let closed = traffic.record(&parse_line(
+    ///     "LOG5[7]: Connection closed: 5120 byte(s) sent to TLS, 880 byte(s) sent to socket",
+    /// ).unwrap());
+    /// assert_eq!(closed.unwrap().closed.unwrap().bytes_to_socket, 880);
+    /// let totals = traffic.totals();
+    /// assert_eq!(totals["backend-a"].connections, 1);
+    /// assert_eq!(totals["backend-a"].bytes_to_tls, 5120);
+    /// assert_eq!(totals["backend-a"].bytes_to_socket, 880);
+    /// ```
+    pub fn record(&self, line: &LogLine) -> Option<ConnectionEvent> {
+        record(&mut self.state(), line)
+    }
+
+    /// The totals so far, by service.
+    pub fn totals(&self) -> BTreeMap<String, ServiceTraffic> {
+        self.state().services.clone()
+    }
+
+    /// The configs loaded so far.
+    pub fn reloads(&self) -> ReloadCounts {
+        self.state().reloads
+    }
+}
+
+fn record(state: &mut State, line: &LogLine) -> Option<ConnectionEvent> {
+    let at = line.logged_at.unwrap_or_else(Utc::now);
+    match reload_outcome(line) {
+        Some(ReloadOutcome::Applied) => state.reloads.applied += 1,
+        Some(ReloadOutcome::Rejected) => state.reloads.rejected += 1,
+        _ => {}
+    }
+    if let Some((service, peer)) = accepted(&line.message) {
+        if state.open.len() >= MAX_OPEN_CONNECTIONS {
+            state.open.clear();
+        }
+        let event = ConnectionEvent {
+            connection: line.thread.clone(),
+            service: service.to_string(),
+            peer: peer.to_string(),
+            opened_at: at,
+            closed: None,
+        };
+        state.open.insert(line.thread.clone(), event.clone());
+        return Some(event);
+    }
+    let (bytes_to_tls, bytes_to_socket) = transferred(&line.message)?;
+    let mut event = state.open.remove(&line.thread)?;
+    let totals = state.services.entry(event.service.clone()).or_default();
+    totals.connections += 1;
+    totals.bytes_to_tls += bytes_to_tls;
+    totals.bytes_to_socket += bytes_to_socket;
+    totals.last_closed_at = Some(at);
+    event.closed = Some(ClosedConnection {
+        closed_at: at,
+        bytes_to_tls,
+        bytes_to_socket,
+    });
+    Some(event)
+}
+
+// The service and peer of `Service [name] accepted connection from
+// <address>`.
+fn accepted(message: &str) -> Option<(&str, &str)> {
+    let (service, rest) = message.strip_prefix("Service [")?.split_once(']')?;
+    let peer = rest.strip_prefix(" accepted connection from ")?;
+    Some((service, peer))
+}
+
+// The byte counts of `Connection closed: X byte(s) sent to TLS, Y byte(s)
+// sent to socket`, or of the same line with `reset` for a reset connection.
+fn transferred(message: &str) -> Option<(u64, u64)> {
+    let counts = message
+        .strip_prefix("Connection closed: ")
+        .or_else(|| message.strip_prefix("Connection reset: "))?;
+    let (to_tls, to_socket) = counts.split_once(" byte(s) sent to TLS, ")?;
+    let to_socket = to_socket.strip_suffix(" byte(s) sent to socket")?;
+    Some((to_tls.parse().ok()?, to_socket.parse().ok()?))
+}
@@ -0,0 +1,116 @@
+//! Resource use of the stunnel process, as GetStatus reports it.
+//!
+//! Memory and CPU time are read from the host on each call (see
+//! [`crate::utils::process_stats`]). CPU use is the CPU time consumed
+//! between two calls over the time between them, so a tracker remembers the
+//! previous sample; the first call, or one for a new PID, reports the
+//! average since stunnel started instead.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::platform::ProcessStats;
+
+/// Samples closer together than this reuse the previous CPU figure, since
+/// CPU time is only counted in clock ticks.
+pub const MIN_CPU_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What GetStatus reports about a running stunnel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    pub started_at: DateTime<Utc>,
+    pub uptime: Duration,
+    pub rss_bytes: u64,
+    /// 100 is one core.
+    pub cpu_percent: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CpuSample {
+    pid: i32,
+    at: Instant,
+    cpu_seconds: f64,
+    percent: f64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    starts: u32,
+    cpu: Option<CpuSample>,
+    release: Option<(i32, String)>,
+}
+
+/// Tracks one instance's stunnel across GetStatus calls. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    state: Arc<Mutex<State>>,
+}
+
+impl UsageTracker {
+    /// Creates a tracker that has not seen stunnel yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records that the manager started stunnel.
+    pub fn record_start(&self) {
+        self.state().starts += 1;
+    }
+
+    /// Times the manager started stunnel since it started itself.
+    pub fn starts(&self) -> u32 {
+        self.state().starts
+    }
+
+    /// Works out the usage of `pid` from `stats`, read just now.
+    pub fn sample(&self, pid: i32, stats: &ProcessStats) -> Usage {
+        let now = Instant::now();
+        let uptime = (Utc::now() - stats.started_at).to_std().unwrap_or_default();
+        let mut state = self.state();
+        let previous = state.cpu.filter(|sample| sample.pid == pid);
+        let cpu_percent = match previous {
+            Some(sample) if now.duration_since(sample.at) < MIN_CPU_INTERVAL => sample.percent,
+            Some(sample) => {
+                let used = (stats.cpu_seconds - sample.cpu_seconds).max(0.0);
+                used / now.duration_since(sample.at).as_secs_f64() * 100.0
+            }
+            None if uptime.is_zero() => 0.0,
+            None => stats.cpu_seconds / uptime.as_secs_f64() * 100.0,
+        };
+        if previous.is_none_or(|sample| now.duration_since(sample.at) >= MIN_CPU_INTERVAL) {
+            state.cpu = Some(CpuSample {
+                pid,
+                at: now,
+                cpu_seconds: stats.cpu_seconds,
+                percent: cpu_percent,
+            });
+        }
+        Usage {
+            started_at: stats.started_at,
+            uptime,
+            rss_bytes: stats.rss_bytes,
+            cpu_percent,
+        }
+    }
+
+    /// The stunnel release remembered for `pid`, if any. A binary cannot
+    /// change under a running process, so it is read once per PID.
+    pub fn release(&self, pid: i32) -> Option<String> {
+        self.state()
+            .release
+            .as_ref()
+            .filter(|(known, _)| *known == pid)
+            .map(|(_, release)| release.clone())
+    }
+
+    /// Remembers the stunnel release of `pid`.
+    pub fn remember_release(&self, pid: i32, release: String) {
+        self.state().release = Some((pid, release));
+    }
+}
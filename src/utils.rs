@@ -4,12 +4,22 @@
 //! including PID management, configuration validation, connection monitoring,
 //! and process lifecycle management.
 
+use crate::parser::{update_globals, StunnelConfig};
+use crate::platform;
+pub use crate::platform::{ListeningSocket, ProcessStats};
 use crate::stunnel::Connection;
 use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
+use std::collections::VecDeque;
 use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Reads the PID from a file and verifies the process is running.
 ///
@@ -43,45 +53,121 @@ pub fn get_stunnel_pid(pid_file: &str) -> Result<i32, Box<dyn std::error::Error>
     }
 }
 
-/// Retrieves active stunnel connections using netstat.
+/// Retrieves active stunnel connections.
 ///
-/// This function parses the output of `netstat -tnp` to find active TCP
-/// connections associated with stunnel processes.
+/// On Linux this matches the TCP sockets the kernel reports through
+/// sock_diag against the file descriptors of stunnel processes, which needs
+/// root when stunnel runs as another user, and fills in byte counts,
+/// round-trip times and queues; macOS and the BSDs use their own socket
+/// listers and report addresses only. See [`crate::platform`].
 ///
 /// # Returns
 ///
 /// A vector of `Connection` objects representing active stunnel connections.
-/// Returns an empty vector if netstat fails or no connections are found.
+/// Returns an empty vector if they cannot be listed or none are found.
+pub fn get_active_connections() -> Vec<Connection> {
+    platform::current().stunnel_connections()
+}
+
+/// Whether a process with this PID exists.
+pub fn process_running(pid: i32) -> bool {
+    platform::current().process_running(pid)
+}
+
+/// Lists the TCP sockets listening on this host, IPv4 and IPv6, from
+/// `/proc/net/tcp` and `/proc/net/tcp6`, or with the system's socket lister
+/// on macOS and the BSDs.
 ///
-/// # Note
+/// # Example
 ///
-/// This function requires `netstat` to be installed and may require
-/// root/sudo privileges to see process information.
-pub fn get_active_connections() -> Vec<Connection> {
-    let mut connections = Vec::new();
-
-    // Run netstat to get TCP connections
-    let output = Command::new("netstat").args(["-tnp"]).output();
-
-    if let Ok(output) = output {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        for line in stdout.lines() {
-            if line.contains("stunnel") {
-                let fields: Vec<&str> = line.split_whitespace().collect();
-                if fields.len() >= 5 {
-                    connections.push(Connection {
-                        service_name: String::new(),
-                        local_address: fields[3].to_string(),
-                        remote_address: fields[4].to_string(),
-                        bytes_sent: 0,
-                        bytes_received: 0,
-                    });
-                }
-            }
-        }
-    }
+/// ```no_run
+/// use stunnel_space::utils::listening_sockets;
+///
+/// let in_use = listening_sockets().iter().any(|socket| socket.port == 443);
+/// println!("Port 443 in use: {}", in_use);
+/// ```
+pub fn listening_sockets() -> Vec<ListeningSocket> {
+    platform::current().listening_sockets()
+}
 
-    connections
+/// Counts the established TCP connections, IPv4 and IPv6, whose local port
+/// is `port`, i.e. the connections accepted on it that are still open.
+pub fn established_connections(port: u16) -> usize {
+    platform::current().established_connections(port)
+}
+
+/// Finds the process listening on TCP `port`, returning its PID and
+/// command name.
+///
+/// Returns `None` if no process visible to this one listens on it; without
+/// root, other users' processes cannot be inspected.
+pub fn port_owner(port: u16) -> Option<(i32, String)> {
+    platform::current().port_owner(port)
+}
+
+/// Finds the running stunnel started with `config_path`, or with its launch
+/// copy (see [`launch_config_path`]): a process named `stunnel` or
+/// `stunnel4` with that argument, found in `/proc` on Linux or
+/// with `ps` elsewhere. If several match, the most recently started one is
+/// returned.
+///
+/// Returns `None` if none is found; without root, other users' processes
+/// may not be visible.
+///
+/// # Example
+///
+/// ```no_run
+/// use stunnel_space::utils::find_stunnel_process;
+///
+/// if let Some(pid) = find_stunnel_process("/etc/stunnel/stunnel.conf") {
+///     println!("stunnel is running as PID {}", pid);
+/// }
+/// ```
+pub fn find_stunnel_process(config_path: &str) -> Option<i32> {
+    let platform = platform::current();
+    platform
+        .find_stunnel_process(config_path)
+        .or_else(|| platform.find_stunnel_process(&launch_config_path(config_path)))
+}
+
+/// The copy of `config_path` stunnel is started from when StartStunnel
+/// gives startup options for one run: a hidden file beside it.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::utils::launch_config_path;
+///
+/// assert_eq!(
+///     launch_config_path("/etc/stunnel/stunnel.conf"),
+///     "/etc/stunnel/.stunnel.conf.launch"
+/// );
+/// ```
+pub fn launch_config_path(config_path: &str) -> String {
+    let path = Path::new(config_path);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.launch", name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads when process `pid` started and the memory and CPU time it uses.
+///
+/// Returns `None` if the process does not exist or cannot be inspected.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::utils::process_stats;
+///
+/// let stats = process_stats(std::process::id() as i32).unwrap();
+/// assert!(stats.rss_bytes > 0);
+/// ```
+pub fn process_stats(pid: i32) -> Option<ProcessStats> {
+    platform::current().process_stats(pid)
 }
 
 /// Validates a stunnel configuration file.
@@ -107,6 +193,7 @@ pub fn get_active_connections() -> Vec<Connection> {
 ///     Err(e) => eprintln!("Invalid configuration: {}", e),
 /// }
 /// ```
+#[tracing::instrument]
 pub fn validate_stunnel_conf_path(config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let output = Command::new("stunnel")
         .args(["-fd", "0", "-test", config_path])
@@ -120,27 +207,95 @@ pub fn validate_stunnel_conf_path(config_path: &str) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-/// Creates a backup copy of a file.
-///
-/// Copies the specified file to `{original_path}.backup` if it exists.
-///
-/// # Arguments
+/// A single problem reported by `stunnel -test`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// 1-based line number in the configuration, when stunnel reports one.
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+/// Validates stunnel configuration content without touching any live config.
 ///
-/// * `path` - Path to the file to backup
+/// The content is written to a private temporary file, checked with
+/// `stunnel -test`, and the file is removed again.
 ///
 /// # Returns
 ///
-/// Returns the path to the backup file on success.
+/// Returns an empty vector if the configuration is valid, or the issues
+/// stunnel reported otherwise.
 ///
 /// # Errors
 ///
-/// Returns an error if the file copy operation fails.
-pub fn backup_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let backup_path = format!("{}.backup", path);
-    if Path::new(path).exists() {
-        fs::copy(path, &backup_path)?;
+/// Returns an error if the temporary file cannot be written or stunnel
+/// cannot be executed.
+#[tracing::instrument(skip_all)]
+pub fn validate_stunnel_conf_content(
+    content: &str,
+) -> Result<Vec<ValidationIssue>, Box<dyn std::error::Error>> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let temp_path = std::env::temp_dir().join(format!(
+        "stunnel-validate-{}-{}.conf",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)?
+        .write_all(content.as_bytes())?;
+    let output = Command::new("stunnel")
+        .args(["-fd", "0", "-test"])
+        .arg(&temp_path)
+        .output();
+    let _ = fs::remove_file(&temp_path);
+    let output = output?;
+
+    if output.status.success() {
+        return Ok(Vec::new());
     }
-    Ok(backup_path)
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut issues = parse_validation_output(&format!("{}{}", stderr, stdout));
+    if issues.is_empty() {
+        issues.push(ValidationIssue {
+            line: None,
+            message: format!("Config validation failed: {}", stderr.trim()),
+        });
+    }
+    Ok(issues)
+}
+
+/// Extracts `[!]` error lines from stunnel output.
+///
+/// Handles both `file:LINE: message` and `Line LINE: message` forms used by
+/// different stunnel versions.
+pub fn parse_validation_output(output: &str) -> Vec<ValidationIssue> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("[!]"))
+        .map(|message| {
+            let message = message.trim();
+            ValidationIssue {
+                line: find_line_number(message),
+                message: message.to_string(),
+            }
+        })
+        .collect()
+}
+
+// Finds the first `:N:` or `Line N:` reference in a stunnel error message.
+fn find_line_number(message: &str) -> Option<u32> {
+    if let Some(rest) = message.strip_prefix("Line ") {
+        return rest.split(':').next()?.trim().parse().ok();
+    }
+    message
+        .split(':')
+        .skip(1)
+        .find_map(|part| part.trim().parse().ok())
 }
 
 /// Sends a SIGHUP signal to reload stunnel configuration.
@@ -156,13 +311,29 @@ pub fn backup_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
 ///
 /// Returns an error if the signal cannot be sent (e.g., process doesn't exist
 /// or insufficient permissions).
+#[tracing::instrument]
 pub fn reload_stunnel(pid: i32) -> Result<(), Box<dyn std::error::Error>> {
     signal::kill(Pid::from_raw(pid), Signal::SIGHUP)?;
     Ok(())
 }
 
+// How long a newly started stunnel must stay up before it counts as started.
+const STARTUP_GRACE: Duration = Duration::from_millis(500);
+
+// How long a daemonized stunnel may take to write its PID file.
+const DAEMON_PID_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Output lines kept from a starting stunnel for the error if it fails.
+const CAPTURED_LINES: usize = 50;
+
 /// Starts a new stunnel process with the specified configuration.
 ///
+/// stunnel exits at once when, for example, a certificate path is wrong, so
+/// the process is given a short grace period: if it fails within it, the
+/// error carries what it wrote to stdout and stderr. A launcher that exits
+/// successfully within it has daemonized, and the daemon's PID is read from
+/// the `pid` file the config names or, failing that, found in `/proc`.
+///
 /// # Arguments
 ///
 /// * `config_path` - Path to the stunnel configuration file to use
@@ -173,10 +344,359 @@ pub fn reload_stunnel(pid: i32) -> Result<(), Box<dyn std::error::Error>> {
 ///
 /// # Errors
 ///
-/// Returns an error if stunnel fails to start or if the stunnel binary
-/// is not found in PATH.
+/// Returns an error if stunnel fails to start, if the stunnel binary
+/// is not found in PATH, or if the PID of a daemonized stunnel cannot be
+/// found.
+#[tracing::instrument]
 pub fn start_stunnel(config_path: &str) -> Result<i32, Box<dyn std::error::Error>> {
-    let child = Command::new("stunnel").arg(config_path).spawn()?;
+    let (mut child, output) = spawn_stunnel(config_path)?;
+    let pid = child.id() as i32;
+
+    let deadline = Instant::now() + STARTUP_GRACE;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(startup_failure(status, &output).into());
+            }
+            let pid_file = fs::read_to_string(config_path)
+                .ok()
+                .and_then(|content| get_global_option(&content, "pid"));
+            return daemon_pid(config_path, pid_file.as_deref(), DAEMON_PID_TIMEOUT);
+        }
+        if Instant::now() >= deadline {
+            thread::spawn(move || child.wait());
+            return Ok(pid);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Starts stunnel and returns the PID of the long-running process.
+///
+/// In foreground mode the spawned child is the stunnel process itself: it
+/// must survive a short grace period, and is reaped on a background thread
+/// once it exits. Otherwise stunnel forks into the background, so this waits
+/// for the launcher to exit and then reads the daemon PID from `pid_file`,
+/// or finds the daemon in `/proc` if the file does not appear.
+/// Either way, a failure to start includes what stunnel wrote to stdout and
+/// stderr.
+///
+/// # Arguments
+///
+/// * `config_path` - Path to the stunnel configuration file to use
+/// * `pid_file` - PID file the configuration tells stunnel to write
+/// * `foreground` - Whether the configuration runs stunnel in the foreground
+/// * `timeout` - How long to wait for the daemon to write its PID file
+///
+/// # Errors
+///
+/// Returns an error if stunnel cannot be spawned, exits with a failure
+/// status, or no running PID appears in `pid_file` before `timeout`.
+#[tracing::instrument]
+pub fn start_stunnel_detached(
+    config_path: &str,
+    pid_file: &str,
+    foreground: bool,
+    timeout: Duration,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (mut child, output) = spawn_stunnel(config_path)?;
+
+    if foreground {
+        let pid = child.id() as i32;
+        let deadline = Instant::now() + STARTUP_GRACE.min(timeout);
+        while Instant::now() < deadline {
+            if let Some(status) = child.try_wait()? {
+                return Err(startup_failure(status, &output).into());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        thread::spawn(move || child.wait());
+        return Ok(pid);
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(startup_failure(status, &output).into());
+            }
+            break;
+        }
+        if Instant::now() >= deadline {
+            return Err("Timed out waiting for stunnel to daemonize".into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    daemon_pid(
+        config_path,
+        Some(pid_file),
+        deadline.saturating_duration_since(Instant::now()),
+    )
+}
+
+// Waits up to `timeout` for a daemonized stunnel to write a running PID to
+// `pid_file`, or to show up in /proc, since a config without a `pid` option,
+// or with one the manager cannot read, leaves no file to wait for.
+fn daemon_pid(
+    config_path: &str,
+    pid_file: Option<&str>,
+    timeout: Duration,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(pid) = pid_file.and_then(|pid_file| get_stunnel_pid(pid_file).ok()) {
+            return Ok(pid);
+        }
+        if let Some(pid) = find_stunnel_process(config_path) {
+            return Ok(pid);
+        }
+        if Instant::now() >= deadline {
+            return Err(match pid_file {
+                Some(pid_file) => format!("stunnel did not write a running PID to {}", pid_file),
+                None => {
+                    "stunnel daemonized, but no running stunnel process was found for its config"
+                        .to_string()
+                }
+            }
+            .into());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
 
-    Ok(child.id() as i32)
+// What a starting stunnel writes to stdout and stderr, read on background
+// threads.
+struct StartupOutput {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    readers: Vec<thread::JoinHandle<()>>,
+}
+
+impl StartupOutput {
+    // The last lines written, once the pipes of an exited process are drained.
+    // A forked daemon can hold them open, so the wait is bounded.
+    fn collect(&self) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while !self.readers.iter().all(|reader| reader.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let lines = self
+            .lines
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        lines
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// Spawns stunnel with its stdout and stderr piped. Each line is logged with
+// stunnel's PID, stderr as warnings, and the last few are kept for startup
+// errors.
+fn spawn_stunnel(config_path: &str) -> io::Result<(Child, StartupOutput)> {
+    let mut child = Command::new("stunnel")
+        .arg(config_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    let lines = Arc::new(Mutex::new(VecDeque::new()));
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(forward_output(
+            stdout,
+            Arc::clone(&lines),
+            move |line| tracing::info!(target: "stunnel", pid, "{}", line),
+        ));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(forward_output(
+            stderr,
+            Arc::clone(&lines),
+            move |line| tracing::warn!(target: "stunnel", pid, "{}", line),
+        ));
+    }
+    Ok((child, StartupOutput { lines, readers }))
+}
+
+// Reads `pipe` until stunnel closes it, on its own thread since a daemon may
+// keep it open long after startup.
+fn forward_output(
+    pipe: impl io::Read + Send + 'static,
+    lines: Arc<Mutex<VecDeque<String>>>,
+    log: impl Fn(&str) + Send + 'static,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+            log(&line);
+            let mut lines = lines
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if lines.len() == CAPTURED_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    })
+}
+
+// Describes a stunnel that exited while starting, with its output.
+fn startup_failure(status: ExitStatus, output: &StartupOutput) -> String {
+    let lines = output.collect();
+    if lines.is_empty() {
+        format!("stunnel exited during startup: {}", status)
+    } else {
+        format!(
+            "stunnel exited during startup: {}: {}",
+            status,
+            lines.join("; ")
+        )
+    }
+}
+
+/// Returns the value of a global (pre-section) option from config content.
+///
+/// Option names are compared case-insensitively, matching stunnel.
+pub fn get_global_option(content: &str, key: &str) -> Option<String> {
+    StunnelConfig::parse(content)
+        .global(key)
+        .map(str::to_string)
+}
+
+/// Sets a global (pre-section) option in config content.
+///
+/// Replaces the first existing assignment of `key` in the global section, or
+/// appends a new `key = value` line after the last global option otherwise.
+pub fn set_global_option(content: &str, key: &str, value: &str) -> String {
+    update_globals(content, &[(key, Some(value.to_string()))])
+}
+
+/// Expands `${VAR}` references in config content from the process environment.
+///
+/// This lets one config template carry cert paths, hosts and ports that
+/// differ between environments. A `$` not followed by `{` is left as is.
+///
+/// # Errors
+///
+/// Returns the names of every referenced variable that is unset (or not
+/// valid Unicode), so all of them can be reported at once.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::utils::expand_env_vars;
+///
+/// std::env::set_var("STUNNEL_CERT_DIR", "/etc/ssl/prod");
+/// assert_eq!(
+///     expand_env_vars("cert = ${STUNNEL_CERT_DIR}/server.pem").unwrap(),
+///     "cert = /etc/ssl/prod/server.pem"
+/// );
+/// ```
+pub fn expand_env_vars(content: &str) -> Result<String, Vec<String>> {
+    let mut expanded = String::with_capacity(content.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        expanded.push_str(&rest[..start]);
+        match std::env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => {
+                if !missing.iter().any(|existing| existing == name) {
+                    missing.push(name.to_string());
+                }
+            }
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+
+    if missing.is_empty() {
+        Ok(expanded)
+    } else {
+        Err(missing)
+    }
+}
+
+/// Stops a running stunnel process, escalating to SIGKILL if needed.
+///
+/// Sends SIGTERM and polls the process until it exits or `timeout` elapses,
+/// at which point SIGKILL is sent.
+///
+/// # Arguments
+///
+/// * `pid` - Process ID of the stunnel instance to stop
+/// * `timeout` - How long to wait for a graceful exit before sending SIGKILL
+///
+/// # Returns
+///
+/// Returns `Ok(true)` if the process exited after SIGTERM, or `Ok(false)` if
+/// it had to be killed.
+///
+/// # Errors
+///
+/// Returns an error if the signals cannot be sent (e.g., insufficient permissions).
+#[tracing::instrument]
+pub fn stop_stunnel(pid: i32, timeout: Duration) -> Result<bool, Box<dyn std::error::Error>> {
+    signal::kill(Pid::from_raw(pid), Signal::SIGTERM)?;
+
+    if wait_for_exit(pid, timeout) {
+        return Ok(true);
+    }
+
+    match signal::kill(Pid::from_raw(pid), Signal::SIGKILL) {
+        // The process may have exited between the last poll and the kill
+        Ok(_) | Err(nix::errno::Errno::ESRCH) => {}
+        Err(e) => return Err(e.into()),
+    }
+    wait_for_exit(pid, Duration::from_secs(1));
+    Ok(false)
+}
+
+/// Removes a PID file, treating an already-missing file as success.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but cannot be removed.
+pub fn remove_pid_file(pid_file: &str) -> io::Result<()> {
+    match fs::remove_file(pid_file) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Polls until the process is gone (or a zombie) or the timeout expires.
+fn wait_for_exit(pid: i32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if !is_process_alive(pid) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// A zombie still answers signal 0, so also check its state in /proc when available.
+pub(crate) fn is_process_alive(pid: i32) -> bool {
+    if signal::kill(Pid::from_raw(pid), None).is_err() {
+        return false;
+    }
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat
+            .rsplit(')')
+            .next()
+            .map(|rest| !rest.trim_start().starts_with('Z'))
+            .unwrap_or(true),
+        Err(_) => true,
+    }
 }
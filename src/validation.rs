@@ -0,0 +1,437 @@
+//! Config validation pipeline.
+//!
+//! Every config the manager validates runs through a [`ValidationPipeline`]:
+//! an ordered list of [`ConfigValidator`]s such as `stunnel -test`, a native
+//! syntax check, the security linter, or an organization's own policies.
+//! Each validator is either blocking, so its errors reject the config, or
+//! advisory, so its findings are only reported. A validator's severity can
+//! be overridden, for example to report every lint finding as an error.
+//!
+//! The built-in validators are selected with `VALIDATORS`, a comma-separated
+//! list of `name[:blocking|advisory[:info|warning|error]]`, and custom ones
+//! are added with [`ValidationPipeline::with_validator`].
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::lint::{self, Severity};
+use crate::parser::StunnelConfig;
+use crate::utils::validate_stunnel_conf_content;
+
+/// Validators run when `VALIDATORS` is unset.
+pub const DEFAULT_VALIDATORS: &str = "stunnel,parser,lint:advisory";
+
+/// A problem a validator found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// 1-based line in the validated content, when known.
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+/// A check run on candidate config content.
+pub trait ConfigValidator: Send + Sync {
+    /// Short name identifying the validator in reports and `VALIDATORS`.
+    fn name(&self) -> &str;
+
+    /// Checks config `content`, which is already parsed as `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the check could not run, e.g. because a tool it
+    /// needs is not installed.
+    fn validate(&self, content: &str, config: &StunnelConfig) -> Result<Vec<Problem>, String>;
+}
+
+/// Whether a validator's errors reject a config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enforcement {
+    /// Errors reject the config.
+    Blocking,
+    /// Findings are reported but never reject the config.
+    Advisory,
+}
+
+impl FromStr for Enforcement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "blocking" => Ok(Enforcement::Blocking),
+            "advisory" => Ok(Enforcement::Advisory),
+            other => Err(format!(
+                "Unknown validator mode: {} (expected blocking or advisory)",
+                other
+            )),
+        }
+    }
+}
+
+// Parses a severity override from `VALIDATORS`.
+fn parse_severity(s: &str) -> Result<Severity, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "info" => Ok(Severity::Info),
+        "warning" => Ok(Severity::Warning),
+        "error" => Ok(Severity::Error),
+        other => Err(format!(
+            "Unknown severity: {} (expected info, warning or error)",
+            other
+        )),
+    }
+}
+
+/// A problem found by a pipeline stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub validator: String,
+    pub severity: Severity,
+    /// Whether the issue rejects the config: an error from a blocking
+    /// validator.
+    pub blocking: bool,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {}): {}", self.validator, line, self.message),
+            None => write!(f, "{}: {}", self.validator, self.message),
+        }
+    }
+}
+
+/// A validator that could not run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub validator: String,
+    pub blocking: bool,
+    pub error: String,
+}
+
+/// Outcome of running a pipeline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Whether any issue rejects the config.
+    pub fn has_blocking_issues(&self) -> bool {
+        self.issues.iter().any(|issue| issue.blocking)
+    }
+
+    /// Whether the config passed: no blocking issues, and every blocking
+    /// validator ran.
+    pub fn is_valid(&self) -> bool {
+        !self.has_blocking_issues() && !self.failures.iter().any(|failure| failure.blocking)
+    }
+
+    /// Describes what rejects the config, or every problem if nothing does.
+    pub fn summary(&self) -> String {
+        let blocking: Vec<String> = self
+            .issues
+            .iter()
+            .filter(|issue| issue.blocking)
+            .map(Issue::to_string)
+            .chain(
+                self.failures
+                    .iter()
+                    .filter(|failure| failure.blocking)
+                    .map(|failure| {
+                        format!("{} could not run: {}", failure.validator, failure.error)
+                    }),
+            )
+            .collect();
+        if !blocking.is_empty() {
+            return blocking.join("; ");
+        }
+        self.issues
+            .iter()
+            .map(Issue::to_string)
+            .chain(
+                self.failures.iter().map(|failure| {
+                    format!("{} could not run: {}", failure.validator, failure.error)
+                }),
+            )
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+#[derive(Clone)]
+struct Stage {
+    validator: Arc<dyn ConfigValidator>,
+    enforcement: Enforcement,
+    severity: Option<Severity>,
+}
+
+/// Validators run in order on candidate configs.
+#[derive(Clone)]
+pub struct ValidationPipeline {
+    stages: Vec<Stage>,
+}
+
+impl fmt::Debug for ValidationPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.stages.iter().map(|stage| {
+                (
+                    stage.validator.name().to_string(),
+                    stage.enforcement,
+                    stage.severity,
+                )
+            }))
+            .finish()
+    }
+}
+
+impl Default for ValidationPipeline {
+    /// The [`DEFAULT_VALIDATORS`] pipeline.
+    fn default() -> Self {
+        Self::new()
+            .with_validator(StunnelBinary, Enforcement::Blocking, None)
+            .with_validator(Syntax, Enforcement::Blocking, None)
+            .with_validator(Lint, Enforcement::Advisory, None)
+    }
+}
+
+impl ValidationPipeline {
+    /// Creates a pipeline without validators, which accepts everything.
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    /// Builds a pipeline of built-in validators from a `VALIDATORS` list
+    /// such as `stunnel,parser,lint:advisory:warning`. Validators are
+    /// blocking unless marked advisory, and keep their own severities
+    /// unless one is given.
+    ///
+    /// The built-in validators are `stunnel` (`stunnel -test`), `parser`
+    /// (lines stunnel cannot parse) and `lint` (the security linter).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown validator, mode or severity, or a
+    /// validator listed twice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stunnel_space::validation::ValidationPipeline;
+    ///
+    /// let pipeline = ValidationPipeline::from_spec("parser,lint:blocking").unwrap();
+    /// let report = pipeline.validate("sslVersion = SSLv3\n[web]\naccept 443\n");
+    /// assert!(!report.is_valid());
+    /// assert!(report
+    ///     .issues
+    ///     .iter()
+    ///     .any(|issue| issue.validator == "parser" && issue.line == Some(3)));
+    /// assert!(report
+    ///     .issues
+    ///     .iter()
+    ///     .any(|issue| issue.validator == "lint" && issue.blocking));
+    /// ```
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let mut pipeline = Self::new();
+        let mut seen = HashSet::new();
+        for entry in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let mut parts = entry.split(':');
+            let name = parts.next().unwrap_or_default().trim();
+            let enforcement = parts
+                .next()
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or(Enforcement::Blocking);
+            let severity = parts.next().map(parse_severity).transpose()?;
+            if parts.next().is_some() {
+                return Err(format!(
+                    "Invalid validator {} (expected name[:mode[:severity]])",
+                    entry
+                ));
+            }
+            if !seen.insert(name.to_string()) {
+                return Err(format!("Validator {} is listed twice", name));
+            }
+            pipeline = match name {
+                "stunnel" => pipeline.with_validator(StunnelBinary, enforcement, severity),
+                "parser" => pipeline.with_validator(Syntax, enforcement, severity),
+                "lint" => pipeline.with_validator(Lint, enforcement, severity),
+                other => {
+                    return Err(format!(
+                        "Unknown validator: {} (expected stunnel, parser or lint)",
+                        other
+                    ))
+                }
+            };
+        }
+        Ok(pipeline)
+    }
+
+    /// Appends `validator`. `severity`, if set, replaces the severity of
+    /// every problem it reports.
+    pub fn with_validator(
+        mut self,
+        validator: impl ConfigValidator + 'static,
+        enforcement: Enforcement,
+        severity: Option<Severity>,
+    ) -> Self {
+        self.stages.push(Stage {
+            validator: Arc::new(validator),
+            enforcement,
+            severity,
+        });
+        self
+    }
+
+    /// Returns the names of the validators, in order.
+    pub fn validators(&self) -> Vec<&str> {
+        self.stages
+            .iter()
+            .map(|stage| stage.validator.name())
+            .collect()
+    }
+
+    /// Runs every validator on `content`, each in its own span.
+    #[tracing::instrument(skip_all)]
+    pub fn validate(&self, content: &str) -> Report {
+        let config = StunnelConfig::parse(content);
+        let mut report = Report::default();
+        for stage in &self.stages {
+            let blocking = stage.enforcement == Enforcement::Blocking;
+            let name = stage.validator.name().to_string();
+            let result = tracing::info_span!("validator", validator = %name)
+                .in_scope(|| stage.validator.validate(content, &config));
+            match result {
+                Ok(problems) => report.issues.extend(problems.into_iter().map(|problem| {
+                    let severity = stage.severity.unwrap_or(problem.severity);
+                    Issue {
+                        validator: name.clone(),
+                        severity,
+                        blocking: blocking && severity == Severity::Error,
+                        line: problem.line,
+                        message: problem.message,
+                    }
+                })),
+                Err(error) => report.failures.push(Failure {
+                    validator: name,
+                    blocking,
+                    error,
+                }),
+            }
+        }
+        report
+    }
+}
+
+/// Checks the config with `stunnel -test`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StunnelBinary;
+
+impl ConfigValidator for StunnelBinary {
+    fn name(&self) -> &str {
+        "stunnel"
+    }
+
+    fn validate(&self, content: &str, _config: &StunnelConfig) -> Result<Vec<Problem>, String> {
+        let issues = validate_stunnel_conf_content(content).map_err(|e| e.to_string())?;
+        Ok(issues
+            .into_iter()
+            .map(|issue| Problem {
+                severity: Severity::Error,
+                line: issue.line,
+                message: issue.message,
+            })
+            .collect())
+    }
+}
+
+/// Flags lines stunnel cannot parse, without needing stunnel installed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Syntax;
+
+impl ConfigValidator for Syntax {
+    fn name(&self) -> &str {
+        "parser"
+    }
+
+    fn validate(&self, content: &str, _config: &StunnelConfig) -> Result<Vec<Problem>, String> {
+        let mut problems = Vec::new();
+        let mut sections = HashSet::new();
+        for (index, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            let problem = |severity, message: String| Problem {
+                severity,
+                line: u32::try_from(index + 1).ok(),
+                message,
+            };
+            if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = trimmed.strip_prefix('[') {
+                match header.strip_suffix(']').map(str::trim) {
+                    None => problems.push(problem(
+                        Severity::Error,
+                        format!("Unterminated section header: {}", trimmed),
+                    )),
+                    Some("") => problems.push(problem(
+                        Severity::Error,
+                        "Section header has no name".to_string(),
+                    )),
+                    Some(name) if !sections.insert(name.to_string()) => problems.push(problem(
+                        Severity::Warning,
+                        format!("Section [{}] is defined more than once", name),
+                    )),
+                    Some(_) => {}
+                }
+                continue;
+            }
+            match trimmed.split_once('=') {
+                None => problems.push(problem(
+                    Severity::Error,
+                    format!("Expected option = value: {}", trimmed),
+                )),
+                Some((key, _)) if key.trim().is_empty() => problems.push(problem(
+                    Severity::Error,
+                    format!("Option has no name: {}", trimmed),
+                )),
+                Some(_) => {}
+            }
+        }
+        Ok(problems)
+    }
+}
+
+/// Reports the security linter's findings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lint;
+
+impl ConfigValidator for Lint {
+    fn name(&self) -> &str {
+        "lint"
+    }
+
+    fn validate(&self, _content: &str, config: &StunnelConfig) -> Result<Vec<Problem>, String> {
+        Ok(lint::lint(config)
+            .into_iter()
+            .map(|finding| Problem {
+                severity: finding.severity,
+                line: None,
+                message: match finding.section {
+                    Some(section) => {
+                        format!("[{}] {} ({})", section, finding.message, finding.code)
+                    }
+                    None => format!("{} ({})", finding.message, finding.code),
+                },
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,389 @@
+//! HashiCorp Vault as a source of certificates and keys.
+//!
+//! A provider bound to Vault gets its certificate and key from either the
+//! PKI secrets engine, which issues a fresh certificate for a role, or a
+//! KV version 2 secret holding PEM fields. The material is written to the
+//! managed certificates directory as `<provider>.crt`/`<provider>.key`
+//! (mode 600) so it never passes through gRPC payloads. PKI certificates are
+//! reissued once less than a third of their lifetime remains; KV secrets are
+//! re-read on every refresh and rewritten when they change.
+//!
+//! Bindings are kept in `vault.json` in the managed directory.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+use crate::certs;
+use crate::certstore::{self, CertStore, CERT_EXTENSION, CHAIN_EXTENSION};
+
+/// File in the managed directory listing Vault-backed providers.
+pub const REGISTRY_FILE: &str = "vault.json";
+
+/// Default KV field names.
+pub const DEFAULT_CERT_FIELD: &str = "certificate";
+pub const DEFAULT_KEY_FIELD: &str = "private_key";
+pub const DEFAULT_CHAIN_FIELD: &str = "ca_chain";
+
+/// Where a provider's certificate and key come from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "engine", rename_all = "lowercase")]
+pub enum VaultSource {
+    /// A certificate issued by `<mount>/issue/<role>`.
+    Pki {
+        mount: String,
+        role: String,
+        common_name: String,
+        #[serde(default)]
+        alt_names: Vec<String>,
+        /// Requested lifetime, e.g. `72h`; empty uses the role's default.
+        #[serde(default)]
+        ttl: String,
+    },
+    /// PEM fields of the KV v2 secret `<mount>/data/<path>`.
+    Kv {
+        mount: String,
+        path: String,
+        cert_field: String,
+        key_field: String,
+        /// Optional field holding the CA chain.
+        chain_field: String,
+    },
+}
+
+/// A provider bound to Vault, with the state of its current material.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultBinding {
+    pub source: VaultSource,
+    /// When the material was last written.
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Expiry of the stored certificate.
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+/// Paths and expiry of material written from Vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedCertificate {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Whether the files on disk changed.
+    pub changed: bool,
+    pub not_after: DateTime<Utc>,
+}
+
+/// Outcome of one provider's refresh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshResult {
+    pub provider: String,
+    /// Whether new material was written.
+    pub refreshed: bool,
+    pub not_after: Option<DateTime<Utc>>,
+    /// Why the refresh failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Fetches and refreshes Vault-backed certificates.
+#[derive(Debug)]
+pub struct VaultManager {
+    address: String,
+    token: String,
+    namespace: Option<String>,
+    store: CertStore,
+    http: reqwest::Client,
+    // Serializes writes to the registry and the managed files
+    lock: Mutex<()>,
+}
+
+impl VaultManager {
+    /// Creates a manager talking to the Vault server at `address` with
+    /// `token`, storing material in `store`.
+    pub fn new(address: &str, token: String, store: CertStore) -> Self {
+        Self {
+            address: address.trim_end_matches('/').to_string(),
+            token,
+            namespace: None,
+            store,
+            http: reqwest::Client::new(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Sends requests to Vault Enterprise namespace `namespace`.
+    pub fn with_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    /// Returns the Vault-backed providers, keyed by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry exists but cannot be read.
+    pub fn bindings(&self) -> Result<BTreeMap<String, VaultBinding>, String> {
+        let path = self.registry_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid Vault registry {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(format!(
+                "Failed to read Vault registry {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Fetches material for `provider` from `source`, writes it to the
+    /// managed directory and records the binding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Vault rejects the request, the response lacks a
+    /// certificate or key, or the files cannot be written.
+    pub async fn bind(
+        &self,
+        provider: &str,
+        source: VaultSource,
+    ) -> Result<FetchedCertificate, String> {
+        let _guard = self.lock.lock().await;
+        let fetched = self.fetch(provider, &source).await?;
+        let mut bindings = self.bindings()?;
+        bindings.insert(
+            provider.to_string(),
+            VaultBinding {
+                source,
+                fetched_at: Some(Utc::now()),
+                not_after: Some(fetched.not_after),
+            },
+        );
+        self.save_bindings(&bindings)?;
+        Ok(fetched)
+    }
+
+    /// Stops refreshing `provider`, leaving its files in place. Returns
+    /// whether it was bound.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be read or written.
+    pub async fn unbind(&self, provider: &str) -> Result<bool, String> {
+        let _guard = self.lock.lock().await;
+        let mut bindings = self.bindings()?;
+        if bindings.remove(provider).is_none() {
+            return Ok(false);
+        }
+        self.save_bindings(&bindings)?;
+        Ok(true)
+    }
+
+    /// Refreshes every binding that is due: PKI certificates with less than a
+    /// third of their lifetime left, and all KV secrets. `force` reissues
+    /// PKI certificates regardless.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the registry cannot be read or written.
+    pub async fn refresh_due(&self, force: bool) -> Result<Vec<RefreshResult>, String> {
+        let _guard = self.lock.lock().await;
+        let mut bindings = self.bindings()?;
+        let now = Utc::now();
+        let mut results = Vec::new();
+        for (provider, binding) in bindings.iter_mut() {
+            let due = match (&binding.source, binding.fetched_at, binding.not_after) {
+                (VaultSource::Pki { .. }, Some(fetched_at), Some(not_after)) => {
+                    force || now > not_after - (not_after - fetched_at) / 3
+                }
+                _ => true,
+            };
+            if !due {
+                results.push(RefreshResult {
+                    provider: provider.clone(),
+                    refreshed: false,
+                    not_after: binding.not_after,
+                    error: None,
+                });
+                continue;
+            }
+
+            let result = match self.fetch(provider, &binding.source).await {
+                Ok(fetched) => {
+                    if fetched.changed {
+                        binding.fetched_at = Some(now);
+                        binding.not_after = Some(fetched.not_after);
+                    }
+                    RefreshResult {
+                        provider: provider.clone(),
+                        refreshed: fetched.changed,
+                        not_after: Some(fetched.not_after),
+                        error: None,
+                    }
+                }
+                Err(e) => RefreshResult {
+                    provider: provider.clone(),
+                    refreshed: false,
+                    not_after: binding.not_after,
+                    error: Some(e),
+                },
+            };
+            results.push(result);
+        }
+        self.save_bindings(&bindings)?;
+        Ok(results)
+    }
+
+    // Reads material from Vault and writes it if it differs from the files
+    // on disk.
+    async fn fetch(
+        &self,
+        provider: &str,
+        source: &VaultSource,
+    ) -> Result<FetchedCertificate, String> {
+        let (cert, key, chain) = match source {
+            VaultSource::Pki {
+                mount,
+                role,
+                common_name,
+                alt_names,
+                ttl,
+            } => {
+                let mut body = json!({ "common_name": common_name });
+                if !alt_names.is_empty() {
+                    body["alt_names"] = json!(alt_names.join(","));
+                }
+                if !ttl.is_empty() {
+                    body["ttl"] = json!(ttl);
+                }
+                let data = self
+                    .request(
+                        reqwest::Method::POST,
+                        &format!("{}/issue/{}", mount, role),
+                        Some(&body),
+                    )
+                    .await?;
+                let data = &data["data"];
+                let chain = match &data["ca_chain"] {
+                    Value::Array(chain) => chain
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|pem| format!("{}\n", pem.trim_end()))
+                        .collect(),
+                    _ => String::new(),
+                };
+                (
+                    string_field(data, "certificate")?,
+                    string_field(data, "private_key")?,
+                    chain,
+                )
+            }
+            VaultSource::Kv {
+                mount,
+                path,
+                cert_field,
+                key_field,
+                chain_field,
+            } => {
+                let data = self
+                    .request(
+                        reqwest::Method::GET,
+                        &format!("{}/data/{}", mount, path),
+                        None,
+                    )
+                    .await?;
+                let data = &data["data"]["data"];
+                let chain = data[chain_field.as_str()]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                (
+                    string_field(data, cert_field)?,
+                    string_field(data, key_field)?,
+                    chain,
+                )
+            }
+        };
+
+        let not_after = certs::parse_certificates(cert.as_bytes())?
+            .first()
+            .map(|leaf| leaf.not_after)
+            .ok_or_else(|| "Vault returned no certificate".to_string())?;
+        let cert_path = self.store.file_path(provider, CERT_EXTENSION)?;
+        let key_path = self.store.key_path(provider)?;
+        let chain_path = self.store.file_path(provider, CHAIN_EXTENSION)?;
+        let unchanged = fs::read_to_string(&cert_path).ok().as_deref() == Some(cert.as_str())
+            && fs::read_to_string(&key_path).ok().as_deref() == Some(key.as_str())
+            && (chain.is_empty()
+                || fs::read_to_string(&chain_path).ok().as_deref() == Some(chain.as_str()));
+        if !unchanged {
+            let chain = Some(chain.as_str()).filter(|chain| !chain.is_empty());
+            self.store.store(provider, &cert, Some(&key), chain, true)?;
+        }
+        Ok(FetchedCertificate {
+            cert_path,
+            key_path,
+            changed: !unchanged,
+            not_after,
+        })
+    }
+
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, String> {
+        let path = path.trim_matches('/');
+        let url = format!("{}/v1/{}", self.address, path);
+        let mut request = self
+            .http
+            .request(method, &url)
+            .header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Vault request to {} failed: {}", path, e))?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            // Vault explains failures in an `errors` array; never echo secrets
+            let errors: Vec<&str> = body["errors"]
+                .as_array()
+                .map(|errors| errors.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            return Err(format!(
+                "Vault request to {} failed ({}): {}",
+                path,
+                status,
+                errors.join("; ")
+            ));
+        }
+        Ok(body)
+    }
+
+    fn registry_path(&self) -> PathBuf {
+        self.store.dir().join(REGISTRY_FILE)
+    }
+
+    fn save_bindings(&self, bindings: &BTreeMap<String, VaultBinding>) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(bindings)
+            .map_err(|e| format!("Failed to serialize Vault registry: {}", e))?;
+        certstore::write_file(&self.registry_path(), content, certstore::CERT_FILE_MODE)
+    }
+}
+
+fn string_field(data: &Value, field: &str) -> Result<String, String> {
+    data[field]
+        .as_str()
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| format!("{}\n", value.trim_end()))
+        .ok_or_else(|| format!("Vault response has no {} field", field))
+}
@@ -0,0 +1,160 @@
+//! Versions of stunnel and of the manager itself.
+//!
+//! `stunnel -version` prints the release, the OpenSSL it was built with and
+//! the one it runs with, and the features compiled in, e.g.
+//!
+//! ```text
+//! stunnel 5.72 on x86_64-pc-linux-gnu platform
+//! Compiled with OpenSSL 3.0.13 30 Jan 2024
+//! Running  with OpenSSL 3.0.13 30 Jan 2024
+//! Threading:PTHREAD Sockets:POLL,IPv6,SYSTEMD TLS:ENGINE,FIPS,OCSP,PSK,SNI Auth:LIBWRAP
+//! ```
+//!
+//! followed by the default global and service options. Tooling can check
+//! these before relying on a feature, e.g. FIPS mode or PSK.
+
+use std::process::Command;
+
+/// What `stunnel -version` reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StunnelVersion {
+    /// Release, e.g. `5.72`.
+    pub version: String,
+    /// Target the binary was built for, e.g. `x86_64-pc-linux-gnu`.
+    pub platform: String,
+    /// OpenSSL version stunnel was compiled against.
+    pub compiled_openssl: String,
+    /// OpenSSL version loaded at run time.
+    pub running_openssl: String,
+    /// Thread model, e.g. `PTHREAD`, `FORK` or `UCONTEXT`.
+    pub threading: String,
+    /// Socket features, e.g. `IPv6` and `SYSTEMD`.
+    pub sockets: Vec<String>,
+    /// TLS features, e.g. `FIPS`, `OCSP`, `PSK` and `SNI`.
+    pub tls: Vec<String>,
+    /// Authentication features, e.g. `LIBWRAP`.
+    pub auth: Vec<String>,
+}
+
+impl StunnelVersion {
+    /// Whether stunnel was built with FIPS support.
+    pub fn fips(&self) -> bool {
+        self.tls.iter().any(|feature| feature == "FIPS")
+    }
+}
+
+/// How the running manager was built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Crate version, e.g. `0.1.0`.
+    pub version: &'static str,
+    /// Short Git commit the binary was built from, or `unknown`.
+    pub git_commit: &'static str,
+    /// Target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub target: &'static str,
+    /// `release` or `debug`.
+    pub profile: &'static str,
+    /// Cargo features compiled in.
+    pub features: Vec<&'static str>,
+}
+
+/// Returns how the running manager was built.
+pub fn build_info() -> BuildInfo {
+    let mut features = Vec::new();
+    if cfg!(feature = "systemd") {
+        features.push("systemd");
+    }
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("STUNNEL_SPACE_GIT_COMMIT"),
+        target: env!("STUNNEL_SPACE_TARGET"),
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        features,
+    }
+}
+
+/// Runs `stunnel -version` and parses what it prints.
+///
+/// # Errors
+///
+/// Returns an error if stunnel cannot be run or its output is not
+/// recognized.
+pub fn stunnel_version() -> Result<StunnelVersion, String> {
+    let output = Command::new("stunnel")
+        .arg("-version")
+        .output()
+        .map_err(|e| format!("Failed to run stunnel: {}", e))?;
+    // stunnel prints the version to stderr, and some builds exit with 1
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stderr),
+        String::from_utf8_lossy(&output.stdout)
+    );
+    parse_version(&text).ok_or_else(|| {
+        format!(
+            "Unrecognized stunnel -version output: {}",
+            text.lines().next().unwrap_or_default()
+        )
+    })
+}
+
+/// Parses the output of `stunnel -version`, or returns `None` if it does
+/// not start with a version line.
+///
+/// # Example
+///
+/// ```
+/// use stunnel_space::version::parse_version;
+///
+/// let output = "stunnel 5.72 on x86_64-pc-linux-gnu platform\n\
+///     Compiled with OpenSSL 3.0.13 30 Jan 2024\n\
+///     Running  with OpenSSL 3.0.13 30 Jan 2024\n\
+///     Threading:PTHREAD Sockets:POLL,IPv6 TLS:ENGINE,FIPS,OCSP,PSK,SNI\n";
+/// let version = parse_version(output).unwrap();
+/// assert_eq!(version.version, "5.72");
+/// assert_eq!(version.running_openssl, "3.0.13");
+/// assert_eq!(version.threading, "PTHREAD");
+/// assert!(version.fips());
+/// ```
+pub fn parse_version(output: &str) -> Option<StunnelVersion> {
+    let mut version = StunnelVersion::default();
+    let mut found = false;
+    for line in output.lines().map(str::trim) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["stunnel", release, "on", platform, ..] => {
+                version.version = release.to_string();
+                version.platform = platform.to_string();
+                found = true;
+            }
+            ["Compiled", "with", "OpenSSL", openssl, ..] => {
+                version.compiled_openssl = openssl.to_string();
+            }
+            ["Running", "with", "OpenSSL", openssl, ..] => {
+                version.running_openssl = openssl.to_string();
+            }
+            _ if line.starts_with("Threading:") => {
+                for (name, values) in words.iter().filter_map(|word| word.split_once(':')) {
+                    let values: Vec<String> = values
+                        .split(',')
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    match name {
+                        "Threading" => version.threading = values.join(","),
+                        "Sockets" => version.sockets = values,
+                        "TLS" => version.tls = values,
+                        "Auth" => version.auth = values,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found.then_some(version)
+}
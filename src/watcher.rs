@@ -0,0 +1,185 @@
+//! Watching certificate and key files for replacement.
+//!
+//! Rotation tools such as certbot replace files by writing a new file and
+//! renaming it over the old one, or by repointing a symlink, so the parent
+//! directories are watched with inotify rather than the files themselves.
+//! Changes arriving close together are reported as one batch.
+
+use chrono::{DateTime, Utc};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::parser::{ConfigOption, StunnelConfig};
+
+// Options whose values are files stunnel reads at startup and on reload.
+const WATCHED_OPTIONS: [&str; 4] = ["cert", "key", "CAfile", "CRLfile"];
+
+// How often the inotify descriptor is checked for events.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Default time to wait for related changes before acting on a batch.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// A batch of watched files that changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateChange {
+    pub paths: Vec<String>,
+    /// Sections referencing a changed file; `global` for the global section.
+    pub providers: Vec<String>,
+    pub detected_at: DateTime<Utc>,
+    /// Whether stunnel was reloaded in response.
+    pub reloaded: bool,
+}
+
+/// Returns every certificate, key, CA and CRL file `config` references.
+pub fn watched_files(config: &StunnelConfig) -> BTreeSet<PathBuf> {
+    let is_watched = |option: &&ConfigOption| {
+        WATCHED_OPTIONS
+            .iter()
+            .any(|key| option.key.eq_ignore_ascii_case(key))
+    };
+    config
+        .globals
+        .iter()
+        .chain(config.sections.iter().flat_map(|section| &section.options))
+        .filter(is_watched)
+        .map(|option| PathBuf::from(&option.value))
+        .collect()
+}
+
+/// Returns the sections of `config` that reference `path` in a watched
+/// option, or `"global"` if the global section does.
+pub fn referencing_sections(config: &StunnelConfig, path: &Path) -> Vec<String> {
+    let references = |options: &[ConfigOption]| {
+        options.iter().any(|option| {
+            WATCHED_OPTIONS
+                .iter()
+                .any(|key| option.key.eq_ignore_ascii_case(key))
+                && Path::new(&option.value) == path
+        })
+    };
+    let mut sections = Vec::new();
+    if references(&config.globals) {
+        sections.push("global".to_string());
+    }
+    sections.extend(
+        config
+            .sections
+            .iter()
+            .filter(|section| references(&section.options))
+            .map(|section| section.name.clone()),
+    );
+    sections
+}
+
+/// Watches a set of files through inotify watches on their directories.
+#[derive(Debug)]
+pub struct FileWatcher {
+    inotify: Inotify,
+    directories: HashMap<WatchDescriptor, PathBuf>,
+    files: BTreeSet<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Creates a watcher watching nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if inotify is unavailable.
+    pub fn new() -> io::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        Ok(Self {
+            inotify,
+            directories: HashMap::new(),
+            files: BTreeSet::new(),
+        })
+    }
+
+    /// Replaces the watched set with `files`. Directories that no longer
+    /// hold a watched file stop being watched; directories that cannot be
+    /// watched, e.g. because they do not exist, are skipped.
+    pub fn watch(&mut self, files: BTreeSet<PathBuf>) {
+        let wanted: BTreeSet<PathBuf> = files.iter().map(|file| parent_dir(file)).collect();
+        self.directories.retain(|descriptor, directory| {
+            let keep = wanted.contains(directory);
+            if !keep {
+                let _ = self.inotify.rm_watch(*descriptor);
+            }
+            keep
+        });
+        for directory in wanted {
+            if self
+                .directories
+                .values()
+                .any(|watched| *watched == directory)
+            {
+                continue;
+            }
+            let flags = AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_MOVED_TO
+                | AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_DELETE;
+            if let Ok(descriptor) = self.inotify.add_watch(&directory, flags) {
+                self.directories.insert(descriptor, directory);
+            }
+        }
+        self.files = files;
+    }
+
+    /// Blocks until a watched file changes, then waits `debounce` for
+    /// related changes and returns every watched file that changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading inotify events fails.
+    pub fn wait_for_changes(&mut self, debounce: Duration) -> io::Result<BTreeSet<PathBuf>> {
+        loop {
+            let mut changed = self.read_changes()?;
+            if changed.is_empty() {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            thread::sleep(debounce);
+            changed.extend(self.read_changes()?);
+            return Ok(changed);
+        }
+    }
+
+    // Drains pending events, keeping those naming a watched file.
+    fn read_changes(&mut self) -> io::Result<BTreeSet<PathBuf>> {
+        let mut changed = BTreeSet::new();
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) => return Ok(changed),
+                Err(e) => return Err(e.into()),
+            };
+            for event in events {
+                let (Some(directory), Some(name)) =
+                    (self.directories.get(&event.wd), event.name.as_ref())
+                else {
+                    continue;
+                };
+                if let Some(file) = self
+                    .files
+                    .iter()
+                    .find(|file| parent_dir(file) == *directory && file.file_name() == Some(name))
+                {
+                    changed.insert(file.clone());
+                }
+            }
+        }
+    }
+}
+
+fn parent_dir(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}